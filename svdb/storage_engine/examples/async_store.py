@@ -0,0 +1,29 @@
+# Store and read back files from asyncio without blocking the event loop
+#
+# Build the extension with the python-async feature first:
+#
+#     maturin develop --features python-async
+#     python examples/async_store.py
+
+import asyncio
+import tempfile
+
+from svdb_core import SvdbEngine
+
+
+async def main():
+    with tempfile.TemporaryDirectory() as path:
+        engine = SvdbEngine(path)
+
+        blobs = [bytes([i]) * 1024 * 1024 for i in range(8)]
+        hashes = await asyncio.gather(*(engine.store_async(blob) for blob in blobs))
+
+        for blob, hash_val in zip(blobs, hashes):
+            assert await engine.retrieve_async(hash_val) == blob
+        print(f"stored and read back {len(hashes)} files")
+
+        engine.close()
+
+
+if __name__ == "__main__":
+    asyncio.run(main())
@@ -0,0 +1,32 @@
+// Time storing a large chunked file
+//
+// Compare the serial and parallel chunk write paths with
+//
+//     cargo run --release --example store_large
+//     cargo run --release --example store_large --features parallel
+//
+// An optional argument sets the size in MiB (default 1024).
+//
+// No reference timings are published; throughput depends on the core count,
+// so note it (e.g. from `nproc`) with any results.
+
+use std::time::Instant;
+
+use svdb_core::{HashAlgorithm, StorageEngine};
+
+fn main() -> svdb_core::Result<()> {
+    let mib: usize = std::env::args().nth(1)
+        .and_then(|arg| arg.parse().ok())
+        .unwrap_or(1024);
+    let data: Vec<u8> = (0..mib * 1024 * 1024).map(|i| (i % 251) as u8 ^ (i >> 20) as u8).collect();
+
+    let dir = tempfile::tempdir()?;
+    let engine = StorageEngine::new(dir.path())?;
+
+    let start = Instant::now();
+    let hash = engine.store_with_options(&data, HashAlgorithm::Blake3, 1024 * 1024)?;
+    let elapsed = start.elapsed();
+
+    println!("stored {} MiB as {} in {:.2?} ({:.0} MiB/s)", mib, hash, elapsed, mib as f64 / elapsed.as_secs_f64());
+    Ok(())
+}
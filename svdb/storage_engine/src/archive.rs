@@ -0,0 +1,119 @@
+// Store archives
+//
+// A whole store written out as one portable file: a magic header, then every
+// record as its raw key and value, then an end marker with the record count.
+// Records keep their `meta:`/`chunk:` prefixes, so restoring routes each one
+// to its column family without the archive recording families itself. Values
+// are copied as stored, so an archive of an encrypted store stays encrypted.
+
+use std::io::{Read, Write};
+
+use crate::records::{is_record_key, Records};
+use crate::{Result, StorageError};
+
+const MAGIC: &[u8; 8] = b"SVDBARC\x01";
+
+/// Key length that marks the end of the records
+const END_MARKER: u32 = u32::MAX;
+
+/// Records written per batch while restoring
+const RESTORE_BATCH: usize = 1000;
+
+/// Write every record in a consistent snapshot of `records`, returning the bytes written
+pub(crate) fn write_archive<W: Write>(records: &Records, mut writer: W) -> Result<u64> {
+    writer.write_all(MAGIC)?;
+    let mut written = MAGIC.len() as u64;
+    let mut count = 0u64;
+
+    records.for_each_in_snapshot(|key, value| {
+        writer.write_all(&(key.len() as u32).to_le_bytes())?;
+        writer.write_all(&(value.len() as u64).to_le_bytes())?;
+        writer.write_all(key)?;
+        writer.write_all(value)?;
+        written += 12 + key.len() as u64 + value.len() as u64;
+        count += 1;
+        Ok(())
+    })?;
+
+    writer.write_all(&END_MARKER.to_le_bytes())?;
+    writer.write_all(&count.to_le_bytes())?;
+    writer.flush()?;
+    Ok(written + 12)
+}
+
+/// Restore every record from an archive, returning how many were restored
+///
+/// Records are written in batches as they are read, so a damaged archive
+/// can leave part of its records behind.
+pub(crate) fn read_archive<R: Read>(records: &Records, mut reader: R) -> Result<u64> {
+    let mut magic = [0u8; 8];
+    read_exact(&mut reader, &mut magic)?;
+    if &magic != MAGIC {
+        return Err(StorageError::SerializationError("not an SVDB archive".to_string()));
+    }
+
+    let mut batch = records.batch();
+    let mut pending = 0;
+    let mut count = 0u64;
+    loop {
+        let mut key_len = [0u8; 4];
+        read_exact(&mut reader, &mut key_len)?;
+        let key_len = u32::from_le_bytes(key_len);
+
+        let mut value_len = [0u8; 8];
+        read_exact(&mut reader, &mut value_len)?;
+        let value_len = u64::from_le_bytes(value_len);
+
+        if key_len == END_MARKER {
+            if value_len != count {
+                return Err(StorageError::SerializationError(format!(
+                    "archive lists {} records but holds {}", value_len, count
+                )));
+            }
+            break;
+        }
+
+        // Lengths come from the file, so buffers grow with the bytes actually
+        // read rather than being sized up front
+        let key = read_record_part(&mut reader, key_len as u64)?;
+        let value = read_record_part(&mut reader, value_len)?;
+        // A key naming another namespace or an internal record directly
+        // would restore outside this store
+        if !is_record_key(&key) {
+            return Err(StorageError::SerializationError(format!(
+                "archive holds a key outside the store: {}", String::from_utf8_lossy(&key)
+            )));
+        }
+
+        batch.put(&key, value);
+        pending += 1;
+        count += 1;
+        if pending >= RESTORE_BATCH {
+            records.write(std::mem::replace(&mut batch, records.batch()))?;
+            pending = 0;
+        }
+    }
+
+    records.write(batch)?;
+    Ok(count)
+}
+
+fn read_record_part<R: Read>(reader: &mut R, len: u64) -> Result<Vec<u8>> {
+    let mut part = Vec::new();
+    reader.take(len).read_to_end(&mut part)?;
+    if part.len() as u64 != len {
+        return Err(truncated());
+    }
+    Ok(part)
+}
+
+fn read_exact<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<()> {
+    reader.read_exact(buf).map_err(|e| match e.kind() {
+        std::io::ErrorKind::UnexpectedEof => truncated(),
+        _ => StorageError::IOError(e),
+    })
+}
+
+fn truncated() -> StorageError {
+    StorageError::SerializationError("archive is truncated".to_string())
+}
@@ -0,0 +1,112 @@
+// Async wrapper around `StorageEngine`
+//
+// RocksDB calls block, so every operation is moved onto tokio's blocking pool
+// with `spawn_blocking`. The engine is shared through an `Arc`, so all blocking
+// tasks use the same database handle and cache.
+
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::{FileMetadata, HashAlgorithm, Result, StorageConfig, StorageEngine, StoreOptions};
+
+/// Storage engine for async code
+///
+/// Cloning is cheap and clones share the underlying engine.
+#[derive(Clone)]
+pub struct AsyncStorageEngine {
+    engine: Arc<StorageEngine>,
+}
+
+impl AsyncStorageEngine {
+    /// Open a database with default settings
+    pub async fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::open_with_config(path, StorageConfig::default()).await
+    }
+
+    /// Open a database with explicit configuration
+    pub async fn open_with_config<P: AsRef<Path>>(path: P, config: StorageConfig) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let engine = run_blocking(move || StorageEngine::with_config(path, config)).await?;
+        Ok(Self::from(engine))
+    }
+
+    /// The wrapped synchronous engine
+    pub fn engine(&self) -> &Arc<StorageEngine> {
+        &self.engine
+    }
+
+    pub async fn store(&self, data: Vec<u8>) -> Result<String> {
+        let engine = self.engine.clone();
+        run_blocking(move || engine.store(&data)).await
+    }
+
+    pub async fn store_with_options(&self, data: Vec<u8>, algorithm: HashAlgorithm, chunk_size: usize) -> Result<String> {
+        let engine = self.engine.clone();
+        run_blocking(move || engine.store_with_options(&data, algorithm, chunk_size)).await
+    }
+
+    pub async fn store_with(&self, data: Vec<u8>, options: StoreOptions) -> Result<String> {
+        let engine = self.engine.clone();
+        run_blocking(move || engine.store_with(&data, &options)).await
+    }
+
+    pub async fn retrieve(&self, hash: &str) -> Result<Vec<u8>> {
+        let engine = self.engine.clone();
+        let hash = hash.to_string();
+        run_blocking(move || engine.retrieve(&hash)).await
+    }
+
+    pub async fn retrieve_verified(&self, hash: &str) -> Result<Vec<u8>> {
+        let engine = self.engine.clone();
+        let hash = hash.to_string();
+        run_blocking(move || engine.retrieve_verified(&hash)).await
+    }
+
+    pub async fn get_metadata(&self, hash: &str) -> Result<Option<FileMetadata>> {
+        let engine = self.engine.clone();
+        let hash = hash.to_string();
+        run_blocking(move || engine.get_metadata(&hash)).await
+    }
+
+    pub async fn size_of(&self, hash: &str) -> Result<usize> {
+        let engine = self.engine.clone();
+        let hash = hash.to_string();
+        run_blocking(move || engine.size_of(&hash)).await
+    }
+
+    pub async fn exists(&self, hash: &str) -> Result<bool> {
+        let engine = self.engine.clone();
+        let hash = hash.to_string();
+        run_blocking(move || engine.exists(&hash)).await
+    }
+
+    pub async fn delete(&self, hash: &str) -> Result<bool> {
+        let engine = self.engine.clone();
+        let hash = hash.to_string();
+        run_blocking(move || engine.delete(&hash)).await
+    }
+}
+
+impl From<StorageEngine> for AsyncStorageEngine {
+    fn from(engine: StorageEngine) -> Self {
+        AsyncStorageEngine {
+            engine: Arc::new(engine),
+        }
+    }
+}
+
+impl From<Arc<StorageEngine>> for AsyncStorageEngine {
+    fn from(engine: Arc<StorageEngine>) -> Self {
+        AsyncStorageEngine { engine }
+    }
+}
+
+/// Run a blocking engine call on tokio's blocking pool
+async fn run_blocking<T, F>(f: F) -> Result<T>
+where
+    F: FnOnce() -> Result<T> + Send + 'static,
+    T: Send + 'static,
+{
+    // A panicking or cancelled task surfaces as an IO error
+    tokio::task::spawn_blocking(f).await.map_err(std::io::Error::from)?
+}
@@ -0,0 +1,104 @@
+// File cache
+//
+// Whole files kept in memory by hash, bounded by their total size. Each read
+// or insert stamps the entry with a fresh tick, and the entry with the oldest
+// tick is evicted first once the contents pass the capacity. A second map from
+// tick to hash keeps finding that entry cheap.
+
+use std::collections::{BTreeMap, HashMap};
+
+pub(crate) struct FileCache {
+    entries: HashMap<String, Entry>,
+    /// Hash of each entry by the tick it was last used at
+    order: BTreeMap<u64, String>,
+    next_tick: u64,
+    bytes: usize,
+    capacity: usize,
+}
+
+struct Entry {
+    data: Vec<u8>,
+    tick: u64,
+}
+
+impl FileCache {
+    /// An empty cache holding at most `capacity` bytes of file contents
+    pub(crate) fn new(capacity: usize) -> Self {
+        FileCache {
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+            next_tick: 0,
+            bytes: 0,
+            capacity,
+        }
+    }
+
+    pub(crate) fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Bytes of file contents held
+    #[cfg(test)]
+    pub(crate) fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    /// A cached file's contents, marking it most recently used
+    pub(crate) fn get(&mut self, hash: &str) -> Option<&Vec<u8>> {
+        let tick = self.tick();
+        let entry = self.entries.get_mut(hash)?;
+        let hash = self.order.remove(&entry.tick).expect("every entry has a tick");
+        self.order.insert(tick, hash);
+        entry.tick = tick;
+        Some(&entry.data)
+    }
+
+    #[cfg(test)]
+    pub(crate) fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub(crate) fn contains_key(&self, hash: &str) -> bool {
+        self.entries.contains_key(hash)
+    }
+
+    /// Cache a file, evicting the least recently used files until it fits
+    ///
+    /// A file larger than the whole capacity is not cached, and evicts nothing.
+    pub(crate) fn insert(&mut self, hash: String, data: Vec<u8>) {
+        self.remove(&hash);
+        if data.len() > self.capacity {
+            return;
+        }
+
+        while self.bytes + data.len() > self.capacity {
+            let Some((_, oldest)) = self.order.pop_first() else { break };
+            if let Some(evicted) = self.entries.remove(&oldest) {
+                self.bytes -= evicted.data.len();
+            }
+        }
+
+        let tick = self.tick();
+        self.bytes += data.len();
+        self.order.insert(tick, hash.clone());
+        self.entries.insert(hash, Entry { data, tick });
+    }
+
+    pub(crate) fn remove(&mut self, hash: &str) {
+        if let Some(entry) = self.entries.remove(hash) {
+            self.order.remove(&entry.tick);
+            self.bytes -= entry.data.len();
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.entries.clear();
+        self.order.clear();
+        self.bytes = 0;
+    }
+
+    fn tick(&mut self) -> u64 {
+        self.next_tick += 1;
+        self.next_tick
+    }
+}
@@ -0,0 +1,82 @@
+// Content-defined chunking
+//
+// A gear-based rolling hash in the style of FastCDC. Boundaries depend only on
+// the bytes around them, so inserting or removing data only moves the chunk
+// boundaries next to the edit and later chunks keep their hashes.
+
+/// Random per-byte values mixed into the rolling hash
+const GEAR: [u64; 256] = gear_table();
+
+const fn gear_table() -> [u64; 256] {
+    // splitmix64 with a fixed seed so boundaries are stable across builds
+    let mut table = [0u64; 256];
+    let mut state: u64 = 0x5356_4442_4344_4321;
+    let mut i = 0;
+    while i < 256 {
+        state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        table[i] = z ^ (z >> 31);
+        i += 1;
+    }
+    table
+}
+
+/// Mask selecting the top `bits` bits of the rolling hash
+fn high_bits_mask(bits: u32) -> u64 {
+    let bits = bits.clamp(1, 63);
+    !0u64 << (64 - bits)
+}
+
+/// Split `data` into content-defined chunks, returning the chunk lengths
+///
+/// Chunks are never shorter than `min_size` (except the last one) or longer
+/// than `max_size`. A stricter mask is used before `avg_size` and a looser one
+/// after it, which keeps chunk sizes clustered around the average.
+pub(crate) fn chunk_lengths(data: &[u8], min_size: usize, avg_size: usize, max_size: usize) -> Vec<usize> {
+    let bits = avg_size.max(2).next_power_of_two().trailing_zeros();
+    let mask_strict = high_bits_mask(bits + 1);
+    let mask_loose = high_bits_mask(bits - 1);
+
+    let mut lengths = Vec::new();
+    let mut offset = 0;
+
+    while offset < data.len() {
+        let len = find_cut(&data[offset..], min_size, avg_size, max_size, mask_strict, mask_loose);
+        lengths.push(len);
+        offset += len;
+    }
+
+    lengths
+}
+
+/// Length of the next chunk at the start of `data`
+fn find_cut(data: &[u8], min_size: usize, avg_size: usize, max_size: usize, mask_strict: u64, mask_loose: u64) -> usize {
+    if data.len() <= min_size {
+        return data.len();
+    }
+
+    let end = data.len().min(max_size);
+    let normal = avg_size.min(end);
+    let mut hash: u64 = 0;
+    let mut i = min_size;
+
+    while i < normal {
+        hash = (hash << 1).wrapping_add(GEAR[data[i] as usize]);
+        if hash & mask_strict == 0 {
+            return i + 1;
+        }
+        i += 1;
+    }
+
+    while i < end {
+        hash = (hash << 1).wrapping_add(GEAR[data[i] as usize]);
+        if hash & mask_loose == 0 {
+            return i + 1;
+        }
+        i += 1;
+    }
+
+    end
+}
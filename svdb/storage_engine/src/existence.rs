@@ -0,0 +1,94 @@
+// Existence filter
+//
+// A Bloom filter over the hashes of every file in a namespace, so `exists` can
+// answer most lookups for absent files without touching RocksDB. It is filled
+// by a key scan when the store is opened and by every write of a file record
+// after that, through any handle on the namespace, since they all share
+// one filter. A Bloom filter can't forget, so deleted files keep their bits
+// and fall through to the database until the store is next opened; the filter
+// only ever errs towards "maybe", never hiding a stored file.
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::{ExistenceFilterConfig, ExistenceFilterMetrics};
+
+pub(crate) struct ExistenceFilter {
+    config: ExistenceFilterConfig,
+    bits: Vec<AtomicU64>,
+    /// Bit positions probed per hash
+    probes: u32,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    false_positives: AtomicU64,
+}
+
+impl ExistenceFilter {
+    /// An empty filter sized for `config.expected_files` at `config.false_positive_rate`
+    pub(crate) fn new(config: ExistenceFilterConfig) -> Self {
+        let files = config.expected_files.max(1) as f64;
+        let rate = config.false_positive_rate.clamp(1e-9, 0.5);
+        let ln2 = std::f64::consts::LN_2;
+
+        let bit_count = (-files * rate.ln() / (ln2 * ln2)).ceil().max(64.0) as usize;
+        let probes = ((bit_count as f64 / files) * ln2).round().clamp(1.0, 32.0) as u32;
+
+        ExistenceFilter {
+            config,
+            bits: (0..bit_count.div_ceil(64)).map(|_| AtomicU64::new(0)).collect(),
+            probes,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            false_positives: AtomicU64::new(0),
+        }
+    }
+
+    pub(crate) fn config(&self) -> ExistenceFilterConfig {
+        self.config
+    }
+
+    pub(crate) fn insert(&self, hash: &[u8]) {
+        for (word, mask) in self.positions(hash) {
+            self.bits[word].fetch_or(mask, Ordering::Relaxed);
+        }
+    }
+
+    /// Whether `hash` may be stored, counting the answer as a hit or a miss
+    pub(crate) fn may_contain(&self, hash: &[u8]) -> bool {
+        let found = self.positions(hash)
+            .all(|(word, mask)| self.bits[word].load(Ordering::Relaxed) & mask != 0);
+        let counter = if found { &self.hits } else { &self.misses };
+        counter.fetch_add(1, Ordering::Relaxed);
+        found
+    }
+
+    /// Count a hit the database then found no file for
+    pub(crate) fn record_false_positive(&self) {
+        self.false_positives.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn metrics(&self) -> ExistenceFilterMetrics {
+        ExistenceFilterMetrics {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            false_positives: self.false_positives.load(Ordering::Relaxed),
+            bits: self.bits.len() as u64 * 64,
+        }
+    }
+
+    /// Word index and bit mask of each probe, by double hashing
+    fn positions<'a>(&'a self, hash: &[u8]) -> impl Iterator<Item = (usize, u64)> + 'a {
+        let mut hasher = DefaultHasher::new();
+        hash.hash(&mut hasher);
+        let first = hasher.finish();
+        0xa5u8.hash(&mut hasher);
+        let second = hasher.finish() | 1;
+
+        let bit_count = self.bits.len() as u64 * 64;
+        (0..self.probes as u64).map(move |i| {
+            let bit = first.wrapping_add(i.wrapping_mul(second)) % bit_count;
+            ((bit / 64) as usize, 1u64 << (bit % 64))
+        })
+    }
+}
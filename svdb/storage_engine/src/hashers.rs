@@ -0,0 +1,91 @@
+// Pluggable hash functions
+//
+// The built-in algorithms are the `HashAlgorithm` variants. Any other hash,
+// e.g. an in-house one, can be registered with an engine as a `FileHasher`
+// under its own name, which is recorded in the file's metadata like a
+// built-in algorithm name. A custom hasher digests a whole value in one call,
+// so it is only used for files stored whole, and never with the engine's hash
+// key.
+
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use crate::{calculate_hash_with_algorithm, HashAlgorithm, Result, StorageError};
+
+/// A named hash function producing hex digests
+pub trait FileHasher: Send + Sync {
+    /// Name recorded in the metadata of files hashed with it
+    fn name(&self) -> &str;
+
+    /// The lowercase hex digest of `data`
+    fn hash(&self, data: &[u8]) -> String;
+}
+
+impl FileHasher for HashAlgorithm {
+    fn name(&self) -> &str {
+        self.as_str()
+    }
+
+    fn hash(&self, data: &[u8]) -> String {
+        calculate_hash_with_algorithm(data, *self)
+    }
+}
+
+/// The hashers an engine knows by name: the built-ins plus any registered ones
+#[derive(Clone, Default)]
+pub struct HasherRegistry {
+    custom: HashMap<String, Arc<dyn FileHasher>>,
+}
+
+impl HasherRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a hasher under its `name`
+    ///
+    /// Names of built-in algorithms, names containing `:` (which separates an
+    /// identifier's algorithm prefix) and names already registered are
+    /// rejected, since swapping the hash behind a name would orphan every file
+    /// stored under it.
+    pub fn register(&mut self, hasher: Arc<dyn FileHasher>) -> Result<()> {
+        let name = hasher.name();
+        if name.is_empty() || name.contains(':') {
+            return Err(StorageError::InvalidAlgorithm(format!("invalid hasher name {:?}", name)));
+        }
+        if HashAlgorithm::from_str(name).is_ok() {
+            return Err(StorageError::InvalidAlgorithm(format!("{:?} is a built-in algorithm", name)));
+        }
+        if self.custom.contains_key(name) {
+            return Err(StorageError::InvalidAlgorithm(format!("a hasher named {:?} is already registered", name)));
+        }
+
+        self.custom.insert(name.to_string(), hasher);
+        Ok(())
+    }
+
+    /// Look a hasher up by name
+    ///
+    /// Built-in names and their aliases resolve as in `HashAlgorithm::from_str`;
+    /// registered names must match exactly.
+    pub fn from_str(&self, name: &str) -> Result<Arc<dyn FileHasher>> {
+        match HashAlgorithm::from_str(name) {
+            Ok(algorithm) => Ok(Arc::new(algorithm)),
+            Err(e) => self.custom(name).cloned().ok_or(e),
+        }
+    }
+
+    /// A registered hasher, never a built-in one
+    pub(crate) fn custom(&self, name: &str) -> Option<&Arc<dyn FileHasher>> {
+        self.custom.get(name)
+    }
+}
+
+impl std::fmt::Debug for HasherRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HasherRegistry")
+            .field("custom", &self.custom.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
@@ -1,20 +1,101 @@
+// pyo3 0.20 expands `#[new]` into impls that newer compilers flag as non-local
+#![allow(non_local_definitions)]
+
 use pyo3::prelude::*;
-use pyo3::types::PyBytes;
+use pyo3::buffer::PyBuffer;
+use pyo3::types::{PyBytes, PyDict, PyList};
 use thiserror::Error;
-use std::path::Path;
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Cursor, Read, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 use std::sync::Arc;
-use std::collections::HashMap;
-use std::sync::Mutex;
-use rocksdb::{DB, Options};
-use blake2::{Blake2b512, Digest as Blake2Digest};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Mutex, MutexGuard, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::Duration;
+use rocksdb::{BlockBasedOptions, Cache, DBCompressionType, Options};
+use blake2::{Blake2b512, Blake2bMac512, Blake2bVar, Digest as Blake2Digest};
 use sha3::Keccak256;
-use digest::Digest;
+use sha2::Sha256;
+use digest::{Digest, Mac, Update, VariableOutput};
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+
+mod archive;
+mod cache;
+mod chunking;
+mod existence;
+mod hashers;
+mod merkle;
+mod metadata_format;
+mod records;
+mod sharded;
+
+pub use hashers::{FileHasher, HasherRegistry};
+pub use merkle::verify_chunk_proof;
+pub use sharded::ShardedStorageEngine;
+
+use cache::FileCache;
+use records::{RecordBatch, Records};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+#[cfg(feature = "async")]
+mod async_engine;
+
+#[cfg(feature = "async")]
+pub use async_engine::AsyncStorageEngine;
 
 // Constants
-const DEFAULT_CHUNK_SIZE: usize = 1024 * 1024; // 1MB chunks
+pub const DEFAULT_CHUNK_SIZE: usize = 1024 * 1024; // 1MB chunks
+/// `store` chunks files larger than this into `DEFAULT_CHUNK_SIZE` chunks
+/// unless `StorageConfig::auto_chunk_threshold` says otherwise
+pub const AUTO_CHUNK_THRESHOLD: usize = 64 * 1024 * 1024;
+/// Largest value stored whole unless `StorageConfig::max_value_bytes` says otherwise
+pub const DEFAULT_MAX_VALUE_BYTES: usize = 256 * 1024 * 1024;
+/// Bytes of file contents the in-memory cache holds unless `StorageConfig::cache_capacity` says otherwise
+pub const DEFAULT_CACHE_CAPACITY: usize = 256 * 1024 * 1024;
+/// Smallest nonzero chunk size accepted by `store_with_options`
+pub const MIN_CHUNK_SIZE: usize = 1024;
+/// Shortest digest, in bytes, accepted for algorithms with variable output
+pub const MIN_OUTPUT_LEN: usize = 16;
+/// Longest digest, in bytes, accepted for algorithms with variable output
+pub const MAX_OUTPUT_LEN: usize = 64;
 const HASH_ALGORITHM_BLAKE3: &str = "blake3";
 const HASH_ALGORITHM_BLAKE2B: &str = "blake2b";
 const HASH_ALGORITHM_KECCAK: &str = "keccak256";
+const HASH_ALGORITHM_SHA256: &str = "sha256";
+const COMPRESSION_NONE: &str = "none";
+const COMPRESSION_ZSTD: &str = "zstd";
+const ZSTD_LEVEL: i32 = 3;
+
+// Content-addressed chunk bodies may be shared by files stored with different
+// options, so each body starts with a tag naming its own codec
+const CHUNK_CODEC_RAW: u8 = 0;
+const CHUNK_CODEC_ZSTD: u8 = 1;
+
+/// Domain separation tag for chunked file hashes
+const CHUNKED_HASH_DOMAIN: &[u8] = b"svdb:chunked-file:v1";
+
+/// Inputs at least this large are hashed across the rayon pool with the
+/// `parallel` feature; Blake3 only, the other algorithms are inherently serial
+pub const PARALLEL_HASH_THRESHOLD: usize = 128 * 1024;
+
+/// Size of the reads `store_reader` hashes as it goes
+const READ_BLOCK_SIZE: usize = 64 * 1024;
+
+/// Most bytes reserved up front for a reassembled file
+///
+/// The recorded size can't be trusted until the chunks are read, so a
+/// tampered record must not be able to demand a huge allocation.
+const MAX_REASSEMBLY_RESERVE: usize = 64 * 1024 * 1024;
+
+/// Records rewritten per write batch by `migrate_metadata`
+const METADATA_MIGRATION_BATCH: usize = 1000;
+
+const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
 
 #[derive(Error, Debug)]
 pub enum StorageError {
@@ -35,26 +116,107 @@ pub enum StorageError {
     
     #[error("Chunking error: {0}")]
     ChunkingError(String),
+    
+    #[error("Corrupt data{}: expected hash {expected}, got {actual}", .chunk.map(|i| format!(" in chunk {}", i)).unwrap_or_default())]
+    CorruptData {
+        expected: String,
+        actual: String,
+        chunk: Option<usize>,
+    },
+    
+    #[error("Invalid compression: {0}")]
+    InvalidCompression(String),
+    
+    #[error("Invalid durability {0:?}: expected buffered, wal or fsync")]
+    InvalidDurability(String),
+    
+    #[error("Encryption error: {0}")]
+    EncryptionError(String),
+    
+    #[error("Hash is pinned: {0}")]
+    Pinned(String),
+    
+    #[error("Store is read-only: {0}")]
+    ReadOnly(String),
+    
+    #[error("Inconsistent metadata for {hash}: {reason}")]
+    InconsistentMetadata {
+        hash: String,
+        reason: String,
+    },
+    
+    #[error("Invalid namespace {0:?}: names must be non-empty and may not contain '/' or NUL")]
+    InvalidNamespace(String),
+    
+    #[error("Database at {0} is locked by another process; close the other engine or share one open engine instead of reopening per call")]
+    DatabaseLocked(String),
+    
+    #[error("Database is corrupt: {0}")]
+    DatabaseCorrupt(String),
+    
+    #[error("Permission denied opening database at {0}")]
+    PermissionDenied(String),
+    
+    #[error("No database at {0}")]
+    DatabaseNotFound(String),
+    
+    #[error("Value of {size} bytes exceeds the {limit}-byte limit for values stored whole; store it with a chunk size")]
+    ValueTooLarge {
+        size: usize,
+        limit: usize,
+    },
+    
+    #[error("Write did not finish within {0:?}")]
+    Timeout(Duration),
+    
+    #[error("Invalid hash {0:?}: identifiers may not contain '/', and chunk hashes must be hex")]
+    InvalidHash(String),
+    
+    #[error("Alias not found: {0}")]
+    AliasNotFound(String),
+    
+    #[error("Invalid alias {0:?}: names must be non-empty and may not contain '/'")]
+    InvalidAlias(String),
+    
+    #[error("Shard layout mismatch: {0}")]
+    ShardMismatch(String),
+    
+    #[error("Engine configurations don't match: {0}")]
+    ConfigMismatch(String),
+    
+    #[error("Algorithm mismatch: expected {}, found {}", .expected.as_str(), .found.as_str())]
+    AlgorithmMismatch {
+        expected: HashAlgorithm,
+        found: HashAlgorithm,
+    },
 }
 
 pub type Result<T> = std::result::Result<T, StorageError>;
 
 /// Represents the hash algorithm to use
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
 pub enum HashAlgorithm {
+    #[default]
     Blake3,
     Blake2b,
     Keccak256,
+    Sha256,
 }
 
 impl HashAlgorithm {
-    pub fn from_str(s: &str) -> Result<Self> {
-        match s.to_lowercase().as_str() {
-            HASH_ALGORITHM_BLAKE3 => Ok(HashAlgorithm::Blake3),
-            HASH_ALGORITHM_BLAKE2B => Ok(HashAlgorithm::Blake2b),
-            HASH_ALGORITHM_KECCAK => Ok(HashAlgorithm::Keccak256),
-            _ => Err(StorageError::InvalidAlgorithm(s.to_string())),
-        }
+    pub const ALL: [HashAlgorithm; 4] = [
+        HashAlgorithm::Blake3,
+        HashAlgorithm::Blake2b,
+        HashAlgorithm::Keccak256,
+        HashAlgorithm::Sha256,
+    ];
+    
+    fn unsupported(name: &str, reason: &str) -> StorageError {
+        let supported: Vec<&str> = Self::ALL.iter().map(|algorithm| algorithm.as_str()).collect();
+        StorageError::InvalidAlgorithm(format!(
+            "{:?}: {}; supported algorithms are {}",
+            name, reason, supported.join(", "),
+        ))
     }
     
     pub fn as_str(&self) -> &'static str {
@@ -62,348 +224,9295 @@ impl HashAlgorithm {
             HashAlgorithm::Blake3 => HASH_ALGORITHM_BLAKE3,
             HashAlgorithm::Blake2b => HASH_ALGORITHM_BLAKE2B,
             HashAlgorithm::Keccak256 => HASH_ALGORITHM_KECCAK,
+            HashAlgorithm::Sha256 => HASH_ALGORITHM_SHA256,
+        }
+    }
+    
+    /// Digest length in bytes when no output length is requested
+    pub fn default_output_len(&self) -> usize {
+        match self {
+            HashAlgorithm::Blake2b => 64,
+            HashAlgorithm::Blake3 | HashAlgorithm::Keccak256 | HashAlgorithm::Sha256 => 32,
+        }
+    }
+    
+    /// Check that this algorithm can produce `output_len`-byte digests
+    ///
+    /// Blake3 (through its extendable output) and Blake2b take 16 to 64
+    /// bytes; the other algorithms only produce their fixed length.
+    pub fn check_output_len(&self, output_len: usize) -> Result<()> {
+        let supported = match self {
+            HashAlgorithm::Blake3 | HashAlgorithm::Blake2b => MIN_OUTPUT_LEN..=MAX_OUTPUT_LEN,
+            HashAlgorithm::Keccak256 | HashAlgorithm::Sha256 => 32..=32,
+        };
+        if supported.contains(&output_len) {
+            return Ok(());
+        }
+        
+        let lengths = if supported.start() == supported.end() {
+            format!("only {} bytes", supported.start())
+        } else {
+            format!("{} to {} bytes", supported.start(), supported.end())
+        };
+        Err(StorageError::InvalidAlgorithm(format!(
+            "{} cannot produce {}-byte digests; it supports {}", self.as_str(), output_len, lengths
+        )))
+    }
+    
+    /// Algorithms whose hex digest has the same length as `hash`
+    fn candidates_for_hash(hash: &str) -> Vec<Self> {
+        match hash.len() {
+            64 => vec![HashAlgorithm::Blake3, HashAlgorithm::Keccak256, HashAlgorithm::Sha256],
+            128 => vec![HashAlgorithm::Blake2b],
+            _ => Vec::new(),
         }
     }
 }
 
-impl Default for HashAlgorithm {
-    fn default() -> Self {
-        HashAlgorithm::Blake3
+impl FromStr for HashAlgorithm {
+    type Err = StorageError;
+    
+    /// Parse an algorithm name
+    ///
+    /// Case, `-`, `_` and spaces are ignored, and common aliases such as
+    /// `sha-256`, `blake2b-512` and `keccak` are accepted. Names that could
+    /// mean more than one digest, like `blake2`, `sha2` or `sha3-256` (which
+    /// differs from the original Keccak padding used by `keccak256`), are
+    /// rejected rather than guessed.
+    fn from_str(s: &str) -> Result<Self> {
+        let normalized: String = s.chars()
+            .filter(|c| !matches!(c, '-' | '_' | ' '))
+            .collect::<String>()
+            .to_lowercase();
+        
+        match normalized.as_str() {
+            "blake3" | "blake3256" | "b3" => Ok(HashAlgorithm::Blake3),
+            "blake2b" | "blake2b512" => Ok(HashAlgorithm::Blake2b),
+            "keccak256" | "keccak" => Ok(HashAlgorithm::Keccak256),
+            "sha256" | "sha2256" => Ok(HashAlgorithm::Sha256),
+            "blake2" => Err(HashAlgorithm::unsupported(s, "ambiguous between Blake2b and Blake2s")),
+            "sha2" => Err(HashAlgorithm::unsupported(s, "ambiguous between SHA-2 digest sizes")),
+            "sha3" | "sha3256" => Err(HashAlgorithm::unsupported(s, "SHA3-256 is not Keccak256, which uses the original Keccak padding")),
+            _ => Err(HashAlgorithm::unsupported(s, "unknown algorithm")),
+        }
+    }
+}
+
+/// Compression applied to values before they are written
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Compression {
+    #[default]
+    None,
+    Zstd,
+}
+
+impl FromStr for Compression {
+    type Err = StorageError;
+    
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            COMPRESSION_NONE => Ok(Compression::None),
+            COMPRESSION_ZSTD => Ok(Compression::Zstd),
+            _ => Err(StorageError::InvalidCompression(s.to_string())),
+        }
+    }
+}
+
+impl Compression {
+    /// Codec name recorded in metadata, or `None` for uncompressed values
+    pub fn codec(&self) -> Option<&'static str> {
+        match self {
+            Compression::None => None,
+            Compression::Zstd => Some(COMPRESSION_ZSTD),
+        }
+    }
+    
+    fn compress<'a>(&self, data: &'a [u8]) -> Result<Cow<'a, [u8]>> {
+        match self {
+            Compression::None => Ok(Cow::Borrowed(data)),
+            Compression::Zstd => Ok(Cow::Owned(zstd::encode_all(data, ZSTD_LEVEL)?)),
+        }
+    }
+}
+
+/// How far a store must reach towards disk before it returns
+///
+/// Ordered from fastest to safest. `Wal` is what RocksDB does by default and
+/// costs little; `Fsync` adds a disk sync to every store, typically a
+/// millisecond or more, and `Buffered` skips even the log write.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Durability {
+    /// Skip the write-ahead log. Fastest, but a crash of any kind loses
+    /// writes not yet flushed from memory; suits bulk imports that can be
+    /// rerun, followed by `flush`.
+    Buffered,
+    /// Append to the write-ahead log without syncing it. Survives the
+    /// process crashing, but power loss can drop the last few writes.
+    Wal,
+    /// Sync the write-ahead log before returning, so the store survives
+    /// power loss. As `StorageConfig::sync_writes` does for every write.
+    Fsync,
+}
+
+impl FromStr for Durability {
+    type Err = StorageError;
+    
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "buffered" => Ok(Durability::Buffered),
+            "wal" => Ok(Durability::Wal),
+            "fsync" => Ok(Durability::Fsync),
+            _ => Err(StorageError::InvalidDurability(s.to_string())),
+        }
+    }
+}
+
+/// Encode a content-addressed chunk body, prefixed with its codec tag
+fn encode_chunk(chunk: &[u8], compression: Compression) -> Result<Vec<u8>> {
+    let (tag, payload) = match compression {
+        Compression::None => (CHUNK_CODEC_RAW, Cow::Borrowed(chunk)),
+        Compression::Zstd => (CHUNK_CODEC_ZSTD, compression.compress(chunk)?),
+    };
+    
+    let mut body = Vec::with_capacity(payload.len() + 1);
+    body.push(tag);
+    body.extend_from_slice(&payload);
+    Ok(body)
+}
+
+/// Decode a content-addressed chunk body written by `encode_chunk`
+fn decode_chunk(body: Vec<u8>) -> Result<Vec<u8>> {
+    match body.split_first() {
+        Some((&CHUNK_CODEC_RAW, payload)) => Ok(payload.to_vec()),
+        Some((&CHUNK_CODEC_ZSTD, payload)) => Ok(zstd::decode_all(payload)?),
+        Some((tag, _)) => Err(StorageError::InvalidCompression(format!("unknown chunk codec tag {}", tag))),
+        None => Err(StorageError::ChunkingError("empty chunk body".to_string())),
     }
 }
 
+/// Undo the compression recorded for a stored value
+fn decompress(stored: Vec<u8>, codec: Option<&str>) -> Result<Vec<u8>> {
+    match codec {
+        None => Ok(stored),
+        Some(COMPRESSION_ZSTD) => Ok(zstd::decode_all(stored.as_slice())?),
+        Some(other) => Err(StorageError::InvalidCompression(other.to_string())),
+    }
+}
+
+/// Where the chunks of a chunked file live
+///
+/// Metadata written before the layout was recorded uses indexed keys, hence the default.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ChunkLayout {
+    /// `chunk:{file_hash}:{index}`, one copy per file (records written before deduplication)
+    #[default]
+    Indexed,
+    /// `chunk:{chunk_hash}`, shared between files and reference counted
+    ContentAddressed,
+}
+
+/// How a chunked file's hash is derived from its chunk hashes
+///
+/// Metadata written before the scheme was recorded used the joined form, hence the default.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FileHashScheme {
+    /// Hash of the hex chunk hashes joined with `|` (records written before length prefixing)
+    #[default]
+    Joined,
+    /// Hash of a domain tag, the chunk count and chunk size, then each raw
+    /// chunk hash prefixed with its length
+    LengthPrefixed,
+    /// Root of a binary Merkle tree over the chunk hashes, so each chunk can
+    /// be proven part of the file with `chunk_proof`
+    Merkle,
+}
+
 /// Metadata for a stored file
+///
+/// Chunked files have a `meta:{hash}` record. Simple files have an
+/// `info:{hash}` record with no chunks, recording the algorithm, size and
+/// codec; files written by older versions may lack it.
 #[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
 pub struct FileMetadata {
     pub hash: String,
     pub algorithm: String,
+    /// Uncompressed length in bytes
     pub size: usize,
     pub chunk_size: usize,
     pub chunks: Vec<String>,
     pub timestamp: u64,
+    #[serde(default)]
+    pub compression: Option<String>,
+    #[serde(default)]
+    pub chunking: ChunkingStrategy,
+    #[serde(default)]
+    pub layout: ChunkLayout,
+    #[serde(default)]
+    pub hash_scheme: FileHashScheme,
+    /// Unix time after which the file reads as missing and `gc` removes it
+    #[serde(default)]
+    pub expires_at: Option<u64>,
+    /// MIME type or similar, as given by the caller
+    #[serde(default)]
+    pub content_type: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Small caller-defined labels such as the original filename
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub attrs: HashMap<String, String>,
+    /// Digest length in bytes, when not the algorithm's default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_len: Option<usize>,
 }
 
-/// Represents a chunked file
-pub struct ChunkedFile {
-    pub metadata: FileMetadata,
-    pub chunks: Vec<Vec<u8>>,
-}
-
-/// Storage Engine handles storing and retrieving files
-pub struct StorageEngine {
-    db: Arc<DB>,
-    cache: Arc<Mutex<HashMap<String, Vec<u8>>>>,
-}
-
-impl StorageEngine {
-    pub fn new<P: AsRef<Path>>(path: P) -> Result<Self> {
-        let mut opts = Options::default();
-        opts.create_if_missing(true);
-        let db = DB::open(&opts, path)?;
-        
-        Ok(StorageEngine {
-            db: Arc::new(db),
-            cache: Arc::new(Mutex::new(HashMap::new())),
-        })
+impl FileMetadata {
+    /// Check that the chunk list could hold `size` bytes before reading it
+    ///
+    /// Every chunk holds at least one byte, so a file needs at least one
+    /// chunk and at most one per byte. Empty content is always stored whole,
+    /// so a chunked file with no chunks is never written.
+    fn check_chunk_list(&self) -> Result<()> {
+        let count = self.chunks.len();
+        if count == 0 {
+            return Err(self.inconsistent(format!("{} bytes recorded but no chunks", self.size)));
+        }
+        if count > self.size {
+            return Err(self.inconsistent(format!("{} chunks recorded for {} bytes", count, self.size)));
+        }
+        Ok(())
     }
     
-    /// Store a file with default settings (blake3, no chunking)
-    pub fn store(&self, data: &[u8]) -> Result<String> {
-        self.store_with_options(data, HashAlgorithm::Blake3, 0)
+    /// Check that reassembling the chunks gave the recorded size
+    ///
+    /// The chunks were read back, so a mismatch means the data doesn't match
+    /// the file rather than that the record is malformed.
+    fn check_reassembled(&self, actual: usize) -> Result<()> {
+        if actual != self.size {
+            return Err(StorageError::CorruptData {
+                expected: self.hash.clone(),
+                actual: format!("{} bytes instead of {}", actual, self.size),
+                chunk: None,
+            });
+        }
+        Ok(())
     }
     
-    /// Store a file with specified options
-    pub fn store_with_options(&self, data: &[u8], algorithm: HashAlgorithm, chunk_size: usize) -> Result<String> {
-        if chunk_size > 0 && data.len() > chunk_size {
-            // Chunked storage
-            let chunked_file = chunk_data(data, chunk_size, algorithm)?;
-            
-            // Store metadata
-            let metadata_key = format!("meta:{}", chunked_file.metadata.hash);
-            let metadata_bytes = serde_json::to_vec(&chunked_file.metadata)
-                .map_err(|e| StorageError::SerializationError(e.to_string()))?;
-            
-            self.db.put(metadata_key.as_bytes(), &metadata_bytes)?;
-            
-            // Store each chunk
-            for (i, chunk) in chunked_file.chunks.iter().enumerate() {
-                let chunk_key = format!("chunk:{}:{}", chunked_file.metadata.hash, i);
-                self.db.put(chunk_key.as_bytes(), chunk)?;
-            }
-            
-            Ok(chunked_file.metadata.hash)
-        } else {
-            // Simple storage
-            let hash = calculate_hash_with_algorithm(data, algorithm);
-            self.db.put(hash.as_bytes(), data)?;
-            
-            // Update cache
-            let mut cache = self.cache.lock().unwrap();
-            cache.insert(hash.clone(), data.to_vec());
-            
-            Ok(hash)
+    fn inconsistent(&self, reason: String) -> StorageError {
+        StorageError::InconsistentMetadata {
+            hash: self.hash.clone(),
+            reason,
         }
     }
     
-    /// Retrieve a file by its hash
-    pub fn retrieve(&self, hash: &str) -> Result<Vec<u8>> {
-        // Try cache first
-        let cache = self.cache.lock().unwrap();
-        if let Some(data) = cache.get(hash) {
-            return Ok(data.clone());
-        }
-        drop(cache);
-        
-        // Check if this is a chunked file
-        let metadata_key = format!("meta:{}", hash);
-        if let Some(metadata_bytes) = self.db.get(metadata_key.as_bytes())? {
-            // Chunked file - reassemble
-            let metadata: FileMetadata = serde_json::from_slice(&metadata_bytes)
-                .map_err(|e| StorageError::SerializationError(e.to_string()))?;
-            
-            let mut data = Vec::with_capacity(metadata.size);
-            
-            for i in 0..metadata.chunks.len() {
-                let chunk_key = format!("chunk:{}:{}", hash, i);
-                if let Some(chunk) = self.db.get(chunk_key.as_bytes())? {
-                    data.extend_from_slice(&chunk);
-                } else {
-                    return Err(StorageError::ChunkingError(format!("Chunk {} not found", i)));
-                }
-            }
-            
-            // Update cache
-            let mut cache = self.cache.lock().unwrap();
-            cache.insert(hash.to_string(), data.clone());
-            
-            Ok(data)
-        } else {
-            // Simple file
-            match self.db.get(hash.as_bytes())? {
-                Some(data) => {
-                    // Update cache
-                    let mut cache = self.cache.lock().unwrap();
-                    cache.insert(hash.to_string(), data.clone());
-                    Ok(data)
-                },
-                None => Err(StorageError::HashNotFound(hash.to_string())),
-            }
+    /// Database key holding chunk `index` of this file
+    fn chunk_key(&self, index: usize) -> String {
+        match self.layout {
+            ChunkLayout::Indexed => format!("chunk:{}:{}", self.hash, index),
+            ChunkLayout::ContentAddressed => format!("chunk:{}", self.chunks[index]),
         }
     }
 }
 
-/// Chunk data into smaller pieces and hash them
-fn chunk_data(data: &[u8], chunk_size: usize, algorithm: HashAlgorithm) -> Result<ChunkedFile> {
-    // Use default chunk size if specified size is too small
-    let chunk_size = if chunk_size < 1024 { DEFAULT_CHUNK_SIZE } else { chunk_size };
-    
-    let mut chunks = Vec::new();
-    let mut chunk_hashes = Vec::new();
-    
-    // Split the data into chunks
-    for chunk in data.chunks(chunk_size) {
-        let chunk_hash = calculate_hash_with_algorithm(chunk, algorithm);
-        chunk_hashes.push(chunk_hash);
-        chunks.push(chunk.to_vec());
+/// How a file is split into chunks
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ChunkingStrategy {
+    /// Every chunk is exactly `chunk_size` bytes, except the last
+    #[default]
+    Fixed,
+    /// Boundaries are picked by a rolling hash so that edits only disturb
+    /// nearby chunks, which keeps chunk hashes stable for deduplication
+    ContentDefined {
+        min_size: usize,
+        avg_size: usize,
+        max_size: usize,
+    },
+}
+
+impl ChunkingStrategy {
+    /// Content-defined chunking around `avg_size`, bounded to a quarter and four times it
+    pub fn content_defined(avg_size: usize) -> Self {
+        ChunkingStrategy::ContentDefined {
+            min_size: avg_size / 4,
+            avg_size,
+            max_size: avg_size.saturating_mul(4),
+        }
     }
-    
-    // Create a combined hash of all chunks
-    let combined_data = chunk_hashes.join("|").into_bytes();
-    let file_hash = calculate_hash_with_algorithm(&combined_data, algorithm);
-    
-    let metadata = FileMetadata {
-        hash: file_hash.clone(),
-        algorithm: algorithm.as_str().to_string(),
-        size: data.len(),
-        chunk_size,
-        chunks: chunk_hashes,
-        timestamp: std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_secs(),
-    };
-    
-    Ok(ChunkedFile { metadata, chunks })
 }
 
-/// Calculate hash using the default algorithm (blake3)
-pub fn calculate_hash(data: &[u8]) -> String {
-    calculate_hash_with_algorithm(data, HashAlgorithm::Blake3)
+/// Per-call options for `StorageEngine::store_with`
+#[derive(Clone, Debug, Default)]
+pub struct StoreOptions {
+    pub algorithm: HashAlgorithm,
+    /// Chunk size in bytes; 0 disables chunking, otherwise it must be at
+    /// least `MIN_CHUNK_SIZE`
+    pub chunk_size: usize,
+    pub compression: Compression,
+    pub chunking: ChunkingStrategy,
+    /// Seconds until the file expires; `None` keeps it until deleted
+    pub ttl_secs: Option<u64>,
+    pub content_type: Option<String>,
+    pub tags: Vec<String>,
+    /// Labels kept in the file's metadata record, read back with `get_attrs`
+    pub attrs: HashMap<String, String>,
+    /// Digest length in bytes for Blake2b or Blake3; `None` uses the
+    /// algorithm's default. Only files stored whole support other lengths.
+    pub output_len: Option<usize>,
+    /// Hash a chunked file as a Merkle tree over its chunks, so single chunks
+    /// can be verified against the file hash with `chunk_proof`. Gives a
+    /// different hash from the default scheme; files stored whole ignore it.
+    pub merkle: bool,
+    /// Durability of this store; `None` follows `StorageConfig::sync_writes`
+    pub durability: Option<Durability>,
+    /// Hash inputs longer than this with Blake3, whatever `algorithm` says,
+    /// since Blake3 is much faster on large inputs. Only a store-time choice:
+    /// metadata records the algorithm actually used, so retrieval and
+    /// verification never see this setting. `None` always uses `algorithm`.
+    pub auto_blake3_threshold: Option<usize>,
 }
 
-/// Calculate hash using the specified algorithm
-pub fn calculate_hash_with_algorithm(data: &[u8], algorithm: HashAlgorithm) -> String {
-    match algorithm {
-        HashAlgorithm::Blake3 => {
-            let hash = blake3::hash(data);
-            hash.to_hex().to_string()
-        },
-        HashAlgorithm::Blake2b => {
-            let mut hasher = Blake2b512::new();
-            hasher.update(data);
-            let result = hasher.finalize();
-            hex::encode(result)
-        },
-        HashAlgorithm::Keccak256 => {
-            let mut hasher = Keccak256::new();
-            hasher.update(data);
-            let result = hasher.finalize();
-            hex::encode(result)
-        },
+impl StoreOptions {
+    /// The algorithm storing `len` bytes uses, after `auto_blake3_threshold`
+    pub fn algorithm_for(&self, len: usize) -> HashAlgorithm {
+        match self.auto_blake3_threshold {
+            Some(threshold) if len > threshold => HashAlgorithm::Blake3,
+            _ => self.algorithm,
+        }
     }
 }
 
-// Python module
-#[pymodule]
-fn svdb_core(_py: Python, m: &PyModule) -> PyResult<()> {
-    m.add_function(wrap_pyfunction!(py_store_file, m)?)?;
-    m.add_function(wrap_pyfunction!(py_retrieve_file, m)?)?;
-    m.add_function(wrap_pyfunction!(py_calculate_hash, m)?)?;
-    m.add_function(wrap_pyfunction!(py_store_file_with_options, m)?)?;
-    m.add_function(wrap_pyfunction!(py_calculate_hash_with_algorithm, m)?)?;
-    Ok(())
+/// Whether a store wrote new content, from `StorageEngine::store_reporting`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StoreOutcome {
+    /// The content was not stored before, or had expired
+    Written,
+    /// The content was already stored; at most its lifetime, content type
+    /// or tags were updated
+    AlreadyPresent,
 }
 
-// Python bindings
-#[pyfunction]
-fn py_store_file(_py: Python, db_path: &str, py_data: &PyBytes) -> PyResult<String> {
-    let data = py_data.as_bytes();
-    
-    let engine = StorageEngine::new(db_path)
-        .map_err(|e| PyErr::new::<pyo3::exceptions::PyIOError, _>(e.to_string()))?;
-    
-    engine.store(data)
-        .map_err(|e| PyErr::new::<pyo3::exceptions::PyIOError, _>(e.to_string()))
+/// Per-call options for `StorageEngine::retrieve_with`
+#[derive(Clone, Copy, Debug)]
+pub struct RetrieveOptions {
+    /// Check every chunk against its recorded hash as it is read, bypassing
+    /// the cache, as `retrieve_verified` does. A bad chunk is reported by the
+    /// lowest index that fails. Reading in order stops there; with a higher
+    /// `concurrency`, chunks already being read on other threads still are.
+    pub verify: bool,
+    /// Chunks of a chunked file read at once; 1 reads them in order on the
+    /// calling thread
+    pub concurrency: usize,
 }
 
-#[pyfunction]
-fn py_store_file_with_options(
-    _py: Python, 
-    db_path: &str, 
-    py_data: &PyBytes,
-    algorithm: &str,
-    chunk_size: usize
-) -> PyResult<String> {
-    let data = py_data.as_bytes();
-    
-    let algorithm = HashAlgorithm::from_str(algorithm)
-        .map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(e.to_string()))?;
-    
-    let engine = StorageEngine::new(db_path)
-        .map_err(|e| PyErr::new::<pyo3::exceptions::PyIOError, _>(e.to_string()))?;
-    
-    engine.store_with_options(data, algorithm, chunk_size)
-        .map_err(|e| PyErr::new::<pyo3::exceptions::PyIOError, _>(e.to_string()))
+impl Default for RetrieveOptions {
+    fn default() -> Self {
+        RetrieveOptions {
+            verify: false,
+            concurrency: 1,
+        }
+    }
 }
 
-#[pyfunction]
-fn py_retrieve_file(py: Python, db_path: &str, hash: &str) -> PyResult<Py<PyBytes>> {
-    let engine = StorageEngine::new(db_path)
-        .map_err(|e| PyErr::new::<pyo3::exceptions::PyIOError, _>(e.to_string()))?;
-    
-    engine.retrieve(hash)
-        .map(|data| PyBytes::new(py, &data).into())
-        .map_err(|e| PyErr::new::<pyo3::exceptions::PyIOError, _>(e.to_string()))
+/// Represents a chunked file
+pub struct ChunkedFile {
+    pub metadata: FileMetadata,
+    pub chunks: Vec<Vec<u8>>,
 }
 
-#[pyfunction]
-fn py_calculate_hash(_py: Python, py_data: &PyBytes) -> String {
-    let data = py_data.as_bytes();
-    calculate_hash(data)
+/// Iterator over the pieces of a stored file, returned by `StorageEngine::retrieve_stream`
+///
+/// Chunked files are read one chunk per item; simple and cached files are
+/// yielded as a single item.
+pub struct ChunkStream<'a> {
+    engine: &'a StorageEngine,
+    source: StreamSource,
+    next: usize,
+    /// Bytes yielded so far, checked against the recorded size at the end
+    yielded: usize,
 }
 
-#[pyfunction]
-fn py_calculate_hash_with_algorithm(_py: Python, py_data: &PyBytes, algorithm: &str) -> PyResult<String> {
-    let data = py_data.as_bytes();
-    let algo = HashAlgorithm::from_str(algorithm)
-        .map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(e.to_string()))?;
-    
-    Ok(calculate_hash_with_algorithm(data, algo))
+enum StreamSource {
+    Chunked(Box<FileMetadata>),
+    Whole(Option<Vec<u8>>),
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use tempfile::tempdir;
-    
-    #[test]
-    fn test_storage_engine() -> Result<()> {
-        let temp_dir = tempdir()?;
-        let engine = StorageEngine::new(temp_dir.path())?;
-        
-        let test_data = b"Hello, SVDB!";
-        let hash = engine.store(test_data)?;
-        
-        let retrieved = engine.retrieve(&hash)?;
-        assert_eq!(retrieved, test_data);
-        
-        Ok(())
+impl ChunkStream<'_> {
+    /// Length of the whole file, before any of it has been yielded
+    fn total_len(&self) -> u64 {
+        match &self.source {
+            StreamSource::Chunked(metadata) => metadata.size as u64,
+            StreamSource::Whole(data) => data.as_ref().map_or(0, Vec::len) as u64,
+        }
     }
+}
+
+impl Iterator for ChunkStream<'_> {
+    type Item = Result<Vec<u8>>;
     
-    #[test]
-    fn test_calculate_hash() {
-        let data = b"Hello, SVDB!";
-        let hash = calculate_hash(data);
-        assert!(!hash.is_empty());
+    fn next(&mut self) -> Option<Self::Item> {
+        match &mut self.source {
+            StreamSource::Chunked(metadata) => {
+                if self.next >= metadata.chunks.len() {
+                    return None;
+                }
+                let i = self.next;
+                self.next += 1;
+                
+                let chunk = match self.engine.read_chunk(metadata, i) {
+                    Ok(Some(chunk)) => chunk,
+                    Ok(None) => return Some(Err(StorageError::ChunkingError(format!("Chunk {} not found", i)))),
+                    Err(e) => return Some(Err(e)),
+                };
+                self.yielded += chunk.len();
+                
+                // Stop as soon as the chunks overrun the size, and check the
+                // total once the last chunk is in
+                if self.yielded > metadata.size || self.next == metadata.chunks.len() {
+                    if let Err(e) = metadata.check_reassembled(self.yielded) {
+                        self.next = metadata.chunks.len();
+                        return Some(Err(e));
+                    }
+                }
+                Some(Ok(chunk))
+            },
+            // An empty file is an empty stream rather than one empty piece
+            StreamSource::Whole(data) => data.take().filter(|data| !data.is_empty()).map(Ok),
+        }
+    }
+}
+
+/// Tuning knobs applied when opening the database
+///
+/// Fields left as `None` keep RocksDB's own defaults. Write-heavy workloads
+/// usually want a larger `write_buffer_size` and more background jobs;
+/// read-heavy ones benefit most from a bigger block cache.
+#[derive(Clone, Debug, Default)]
+pub struct StorageConfig {
+    /// Block compression RocksDB applies to its data files
+    pub compression: Option<DBCompressionType>,
+    /// Capacity of the RocksDB block cache in bytes
+    pub block_cache_bytes: Option<usize>,
+    /// Size of a single memtable in bytes
+    pub write_buffer_size: Option<usize>,
+    /// Maximum number of concurrent flushes and compactions
+    pub max_background_jobs: Option<i32>,
+    /// Compression `store` and `store_with_options` apply to values
+    pub value_compression: Compression,
+    /// Key for keyed Blake3/Blake2b hashing, giving this engine its own
+    /// address space. Changing the key changes every address, so data stored
+    /// under one key cannot be found, verified or deduplicated under another.
+    pub hash_key: Option<[u8; 32]>,
+    /// Files no larger than this are stored whole whatever chunk size is
+    /// requested. `None` chunks anything larger than the chunk size; a
+    /// threshold below the chunk size has no effect.
+    pub chunk_threshold: Option<usize>,
+    /// Files larger than this are split into `DEFAULT_CHUNK_SIZE` chunks by
+    /// `store`, which names no chunk size, so huge inputs never become one
+    /// giant RocksDB value. `None` uses `AUTO_CHUNK_THRESHOLD`.
+    pub auto_chunk_threshold: Option<usize>,
+    /// Largest value stored whole as a single RocksDB entry. Larger files are
+    /// chunked if a chunk size is given (or picked by `store`), and refused
+    /// with `ValueTooLarge` otherwise. `None` uses `DEFAULT_MAX_VALUE_BYTES`.
+    /// The limit overrides `chunk_threshold`: a file above it is chunked even
+    /// when the threshold says whole, and so gets a chunked file hash rather
+    /// than the content hash a larger threshold used to give it. Chunk sizes
+    /// above the limit are refused, and `store` chunks no larger than it.
+    pub max_value_bytes: Option<usize>,
+    /// Return identifiers of the form `algorithm:hex` from every store, so the
+    /// algorithm can be read off the identifier alone. Lookups accept both
+    /// prefixed and bare hashes either way.
+    pub prefixed_hashes: bool,
+    /// When content is stored again, read back the existing value and check
+    /// it matches before skipping the write, instead of trusting the hash.
+    /// Costs a read per repeated store; a mismatch fails with `CorruptData`.
+    pub verify_existing: bool,
+    /// Wait for each write to reach disk before returning, so a completed
+    /// store survives power loss. Without it a crash keeps every completed
+    /// write, but power loss can drop the last few; `flush` syncs on demand.
+    /// Synced writes are much slower, especially for many small stores.
+    pub sync_writes: bool,
+    /// Keep this engine's records apart from other namespaces in the same
+    /// database, so listing, `gc`, `stats` and deduplication only see its
+    /// own files. `None` is the unnamed namespace older stores use. Names
+    /// must be non-empty and may not contain `/` or a zero byte.
+    pub namespace: Option<String>,
+    /// Skip the in-memory file cache entirely, for workloads that never read
+    /// a file twice. Nothing is cached and the cache lock is never taken;
+    /// RocksDB's own block cache still applies.
+    pub disable_cache: bool,
+    /// Most bytes of file contents the in-memory file cache holds before
+    /// evicting the least recently used files. Files larger than this are
+    /// never cached. `None` uses `DEFAULT_CACHE_CAPACITY`.
+    pub cache_capacity: Option<usize>,
+    /// Try reads and writes again when RocksDB reports a
+    /// transient error (busy, try again or timed out), as can happen during
+    /// compaction stalls under heavy load. `None` tries each call once.
+    /// Corruption, missing records and other errors are never retried.
+    pub retry: Option<RetryPolicy>,
+    /// Keep an in-memory Bloom filter of stored hashes, so `exists` answers
+    /// most lookups for absent files without reading the database. Filled by
+    /// a scan of every file when the store (or a namespace) is opened.
+    pub existence_filter: Option<ExistenceFilterConfig>,
+    /// Fail writes RocksDB hasn't finished within this long with `Timeout`,
+    /// instead of blocking for as long as a write stall lasts. `None` waits.
+    ///
+    /// A `Timeout` does not mean the write failed. It can't be cancelled and
+    /// may still commit afterwards, so treat its outcome as unknown: a store
+    /// that timed out may turn out stored, a delete deleted. The next write
+    /// or `gc` waits for it to land first, and times out in turn if it still
+    /// hasn't.
+    ///
+    /// Writes then run on a shared pool of threads that grows with the
+    /// number of concurrent writes, up to 64, which costs a little per write.
+    /// Time spent waiting for a free thread counts against the timeout.
+    pub write_timeout: Option<Duration>,
+}
+
+/// How transient RocksDB errors are retried, see `StorageConfig::retry`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Tries in all, counting the first
+    pub attempts: u32,
+    /// Wait before the first retry, doubled before each later one up to a
+    /// second, or up to this wait itself if it is longer
+    pub backoff: Duration,
+}
+
+/// Sizing of the filter behind `StorageConfig::existence_filter`
+///
+/// The filter takes about 1.2 bytes per expected file at a 1% false positive
+/// rate. Holding more files than expected raises the rate, but a stored file
+/// is never reported missing.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ExistenceFilterConfig {
+    pub expected_files: usize,
+    /// Fraction of lookups for absent files that still reach the database
+    pub false_positive_rate: f64,
+}
+
+impl Default for ExistenceFilterConfig {
+    fn default() -> Self {
+        ExistenceFilterConfig {
+            expected_files: 1_000_000,
+            false_positive_rate: 0.01,
+        }
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            attempts: 3,
+            backoff: Duration::from_millis(10),
+        }
+    }
+}
+
+impl StorageConfig {
+    fn to_options(&self) -> Options {
+        let mut opts = Options::default();
+        opts.create_if_missing(true);
+        
+        if let Some(compression) = self.compression {
+            opts.set_compression_type(compression);
+        }
+        if let Some(bytes) = self.block_cache_bytes {
+            let cache = Cache::new_lru_cache(bytes);
+            let mut block_opts = BlockBasedOptions::default();
+            block_opts.set_block_cache(&cache);
+            opts.set_block_based_table_factory(&block_opts);
+        }
+        if let Some(size) = self.write_buffer_size {
+            opts.set_write_buffer_size(size);
+        }
+        if let Some(jobs) = self.max_background_jobs {
+            opts.set_max_background_jobs(jobs);
+        }
+        
+        opts
+    }
+}
+
+/// AEAD cipher used for values at rest
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Cipher {
+    ChaCha20Poly1305,
+}
+
+/// Encryption-at-rest settings
+///
+/// Values and chunk bodies are encrypted after compression with a fresh
+/// random nonce, stored in front of the ciphertext. Hashes are still computed
+/// over the plaintext, so addressing and deduplication are unaffected.
+/// Metadata records are not encrypted.
+#[derive(Clone)]
+pub struct EncryptionConfig {
+    pub key: [u8; 32],
+    pub cipher: Cipher,
+}
+
+impl EncryptionConfig {
+    pub fn new(key: [u8; 32]) -> Self {
+        EncryptionConfig {
+            key,
+            cipher: Cipher::ChaCha20Poly1305,
+        }
+    }
+}
+
+impl std::fmt::Debug for EncryptionConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EncryptionConfig")
+            .field("key", &"<redacted>")
+            .field("cipher", &self.cipher)
+            .finish()
+    }
+}
+
+/// Outcome of a `StorageEngine::gc` pass
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GcReport {
+    /// Chunk records examined
+    pub chunks_scanned: usize,
+    /// Chunk records deleted because no metadata referenced them
+    pub chunks_removed: usize,
+    /// Bytes of chunk values deleted
+    pub bytes_reclaimed: u64,
+    /// Chunk reference counts rewritten to match the metadata
+    pub refcounts_repaired: usize,
+    /// Expired files removed
+    pub files_expired: usize,
+}
+
+/// Something wrong with a stored file, found by `StorageEngine::fsck`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FsckProblem {
+    /// A chunk listed in the file's metadata is not stored
+    MissingChunk { index: usize },
+    /// A chunk's contents don't hash to the chunk hash in the metadata
+    ChunkHashMismatch { index: usize, actual: String },
+    /// The file's contents or chunk list don't hash to its address
+    FileHashMismatch { actual: String },
+    /// A simple file has an info record but no value
+    MissingValue,
+    /// A record could not be parsed, decrypted or decompressed
+    Unreadable(String),
+}
+
+/// A problem found with one file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FsckFinding {
+    pub hash: String,
+    pub problem: FsckProblem,
+}
+
+/// Outcome of a `StorageEngine::fsck` scan
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FsckReport {
+    /// Files examined, chunked and simple
+    pub files_checked: usize,
+    /// Chunks read and hashed
+    pub chunks_checked: usize,
+    /// Every problem found, in scan order
+    pub findings: Vec<FsckFinding>,
+}
+
+impl FsckReport {
+    /// Whether the scan found nothing wrong
+    pub fn is_clean(&self) -> bool {
+        self.findings.is_empty()
+    }
+    
+    fn add(&mut self, hash: &str, problem: FsckProblem) {
+        self.findings.push(FsckFinding {
+            hash: hash.to_string(),
+            problem,
+        });
+    }
+}
+
+/// The settings an open engine is running with, returned by `StorageEngine::info`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EngineInfo {
+    /// Directory of the RocksDB database
+    pub path: PathBuf,
+    /// `None` for the unnamed namespace
+    pub namespace: Option<String>,
+    pub read_only: bool,
+    /// Whether the in-memory file cache is in use
+    pub cache_enabled: bool,
+    /// Most bytes of file contents the file cache holds
+    pub cache_capacity: usize,
+    /// Compression `store` applies to values
+    pub compression: Compression,
+    /// Algorithm `store` hashes with
+    pub default_algorithm: HashAlgorithm,
+    /// Chunk size `store` uses for files over `auto_chunk_threshold`
+    pub default_chunk_size: usize,
+    pub auto_chunk_threshold: usize,
+    pub chunk_threshold: Option<usize>,
+    pub max_value_bytes: usize,
+    pub encrypted: bool,
+    /// Whether hashes are keyed with `StorageConfig::hash_key`
+    pub keyed_hashes: bool,
+    pub prefixed_hashes: bool,
+    pub verify_existing: bool,
+    pub sync_writes: bool,
+}
+
+/// Summary of what a store contains, returned by `StorageEngine::stats`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StorageStats {
+    /// Files stored whole under their hash
+    pub simple_files: usize,
+    /// Files stored as chunks
+    pub chunked_files: usize,
+    /// Chunk records, counting shared chunks once
+    pub total_chunks: usize,
+    /// Uncompressed size of every stored file added together
+    pub logical_bytes: u64,
+    /// RocksDB's estimate of the live data on disk, if it reports one,
+    /// covering every namespace in the database
+    pub estimated_live_bytes: Option<u64>,
+}
+
+/// Estimated savings from deduplicating chunk bodies, from `StorageEngine::dedup_report`
+///
+/// Byte counts are of uncompressed chunk content.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DedupReport {
+    /// Chunk records examined, counting shared chunks once
+    pub chunks_scanned: usize,
+    /// Chunk records whose content another record already holds
+    pub duplicate_chunks: usize,
+    /// Bytes held by chunk records today
+    pub current_bytes: u64,
+    /// Bytes left if every distinct chunk content were stored once
+    pub unique_bytes: u64,
+}
+
+impl DedupReport {
+    /// Fraction of `current_bytes` deduplication would free, from 0.0 to 1.0
+    pub fn savings_ratio(&self) -> f64 {
+        if self.current_bytes == 0 {
+            return 0.0;
+        }
+        1.0 - self.unique_bytes as f64 / self.current_bytes as f64
+    }
+}
+
+/// Cache hit and miss counts since the engine was opened, from `StorageEngine::cache_metrics`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheMetrics {
+    /// Reads served from the cache
+    pub hits: u64,
+    /// Reads that had to go to the database, including ones for missing files
+    pub misses: u64,
+    /// Bytes returned from the cache
+    pub bytes_from_cache: u64,
+    /// Bytes read from the database on a miss
+    pub bytes_from_db: u64,
+}
+
+/// Existence filter counts since the engine was opened, from `StorageEngine::existence_filter_metrics`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ExistenceFilterMetrics {
+    /// Lookups the filter passed on to the database
+    pub hits: u64,
+    /// Lookups answered "missing" without reading the database
+    pub misses: u64,
+    /// Hits for which the database held no file
+    pub false_positives: u64,
+    /// Size of the filter in bits
+    pub bits: u64,
+}
+
+/// Receives each cache hit and miss as it happens, e.g. to forward them to Prometheus
+///
+/// Called on the reading thread, so implementations should be cheap.
+pub trait CacheObserver: Send + Sync {
+    /// A read returned `bytes` from the cache
+    fn hit(&self, _bytes: u64) {}
+    
+    /// A read missed the cache and read `bytes` from the database, 0 if the file wasn't found
+    fn miss(&self, _bytes: u64) {}
+}
+
+/// Counters behind `CacheMetrics`, updated without taking a lock
+#[derive(Default)]
+struct CacheCounters {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    bytes_from_cache: AtomicU64,
+    bytes_from_db: AtomicU64,
+}
+
+/// Storage Engine handles storing and retrieving files
+pub struct StorageEngine {
+    db: Arc<Records>,
+    cache: Arc<Mutex<FileCache>>,
+    cache_enabled: bool,
+    compression: Compression,
+    cipher: Option<ChaCha20Poly1305>,
+    hash_key: Option<[u8; 32]>,
+    chunk_threshold: Option<usize>,
+    auto_chunk_threshold: usize,
+    max_value_bytes: usize,
+    prefixed_hashes: bool,
+    read_only: bool,
+    verify_existing: bool,
+    metrics: CacheCounters,
+    cache_observer: Option<Arc<dyn CacheObserver>>,
+    hashers: HasherRegistry,
+    /// Source of the current Unix time for expiry checks
+    clock: fn() -> u64,
+    /// Held shared by writers and exclusively by `gc`, so a collection never
+    /// races the reference-count updates of a store or delete; one per
+    /// namespace, shared by every engine on it
+    write_lock: Arc<RwLock<()>>,
+}
+
+/// Options for opening a `StorageEngine`, from `StorageEngine::builder`
+///
+/// Anything not set keeps the defaults of `StorageEngine::new`. `config`
+/// replaces the whole configuration, so call it before the other setters.
+#[derive(Clone, Debug, Default)]
+pub struct StorageEngineBuilder {
+    config: StorageConfig,
+    encryption: Option<EncryptionConfig>,
+    read_only: bool,
+    must_exist: bool,
+    secondary_path: Option<PathBuf>,
+}
+
+impl StorageEngineBuilder {
+    pub fn config(mut self, config: StorageConfig) -> Self {
+        self.config = config;
+        self
+    }
+    
+    /// Compression `store` and `store_with_options` apply to values
+    pub fn compression(mut self, compression: Compression) -> Self {
+        self.config.value_compression = compression;
+        self
+    }
+    
+    /// Whether to keep the in-memory file cache, see `StorageConfig::disable_cache`
+    pub fn cache(mut self, enabled: bool) -> Self {
+        self.config.disable_cache = !enabled;
+        self
+    }
+    
+    /// Most bytes of file contents the in-memory cache holds, see `StorageConfig::cache_capacity`
+    pub fn cache_capacity(mut self, bytes: usize) -> Self {
+        self.config.cache_capacity = Some(bytes);
+        self
+    }
+    
+    pub fn namespace(mut self, namespace: impl Into<String>) -> Self {
+        self.config.namespace = Some(namespace.into());
+        self
+    }
+    
+    pub fn encryption(mut self, encryption: EncryptionConfig) -> Self {
+        self.encryption = Some(encryption);
+        self
+    }
+    
+    /// Open without write access, as `StorageEngine::open_read_only` does
+    pub fn read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+    
+    /// Open as a secondary instance following the database, keeping its own logs at `path`
+    ///
+    /// See `StorageEngine::open_as_secondary`. Implies `read_only`.
+    pub fn secondary(mut self, path: impl Into<PathBuf>) -> Self {
+        self.secondary_path = Some(path.into());
+        self
+    }
+    
+    /// Whether to create the database if the path holds none, as by default
+    ///
+    /// Without it, opening a missing database fails with `DatabaseNotFound`
+    /// rather than quietly starting an empty store at a mistyped path.
+    pub fn create(mut self, create: bool) -> Self {
+        self.must_exist = !create;
+        self
+    }
+    
+    pub fn open<P: AsRef<Path>>(self, path: P) -> Result<StorageEngine> {
+        StorageEngine::open(path, self)
+    }
+}
+
+impl StorageEngine {
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::with_config(path, StorageConfig::default())
+    }
+    
+    /// Start composing the options for opening a store
+    pub fn builder() -> StorageEngineBuilder {
+        StorageEngineBuilder::default()
+    }
+    
+    /// Open a store whose `store`/`store_with_options` calls compress values by default
+    pub fn with_compression<P: AsRef<Path>>(path: P, compression: Compression) -> Result<Self> {
+        Self::with_config(path, StorageConfig {
+            value_compression: compression,
+            ..Default::default()
+        })
+    }
+    
+    /// Open a store without the in-memory file cache, see `StorageConfig::disable_cache`
+    pub fn without_cache<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::with_config(path, StorageConfig {
+            disable_cache: true,
+            ..Default::default()
+        })
+    }
+    
+    /// Open a store with explicit RocksDB tuning
+    pub fn with_config<P: AsRef<Path>>(path: P, config: StorageConfig) -> Result<Self> {
+        Self::builder().config(config).open(path)
+    }
+    
+    /// Open an existing store for reading only
+    ///
+    /// Every call that would write fails with `StorageError::ReadOnly`, so a
+    /// reader process can never change the store. Reads still use the cache.
+    pub fn open_read_only<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::open_read_only_with_config(path, StorageConfig::default())
+    }
+    
+    /// Open an existing store for reading only, with explicit configuration
+    pub fn open_read_only_with_config<P: AsRef<Path>>(path: P, config: StorageConfig) -> Result<Self> {
+        Self::builder().config(config).read_only(true).open(path)
+    }
+    
+    /// Open a read replica that follows the store at `primary_path`
+    ///
+    /// Unlike a read-only handle, which sees the store as it was when opened,
+    /// a secondary picks up the primary's later writes at each
+    /// `catch_up_with_primary`, without reopening. RocksDB keeps the
+    /// secondary's own logs under `secondary_path`, which must not be shared
+    /// with another secondary. Every write fails with `StorageError::ReadOnly`.
+    pub fn open_as_secondary<P: AsRef<Path>, S: AsRef<Path>>(primary_path: P, secondary_path: S) -> Result<Self> {
+        Self::builder().secondary(secondary_path.as_ref()).open(primary_path)
+    }
+    
+    /// Open a store that must already exist, failing with `DatabaseNotFound` otherwise
+    ///
+    /// `new` creates an empty store wherever it is pointed, so a wrong path
+    /// goes unnoticed until files turn up missing.
+    pub fn open_existing<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::builder().create(false).open(path)
+    }
+    
+    /// Open a store that encrypts every value and chunk body at rest
+    ///
+    /// A store must always be opened with the same key; values written
+    /// without encryption cannot be read back by an encrypting engine and
+    /// vice versa.
+    pub fn with_encryption<P: AsRef<Path>>(path: P, config: StorageConfig, encryption: EncryptionConfig) -> Result<Self> {
+        Self::builder().config(config).encryption(encryption).open(path)
+    }
+    
+    fn open<P: AsRef<Path>>(path: P, options: StorageEngineBuilder) -> Result<Self> {
+        let StorageEngineBuilder { config, encryption, read_only, must_exist, secondary_path } = options;
+        let mut opts = config.to_options();
+        opts.create_if_missing(!must_exist);
+        
+        let read_only = read_only || secondary_path.is_some();
+        let mut db = if let Some(secondary_path) = &secondary_path {
+            Records::open_secondary(&opts, path, secondary_path)?
+        } else if read_only {
+            Records::open_read_only(&opts, path)?
+        } else {
+            Records::open(&opts, path)?
+        };
+        db.set_sync_writes(config.sync_writes);
+        db.set_retry(config.retry);
+        db.set_write_timeout(config.write_timeout);
+        if let Some(namespace) = &config.namespace {
+            db = db.namespaced(namespace)?;
+        }
+        if let Some(filter) = config.existence_filter {
+            db.enable_existence_filter(filter)?;
+        }
+        
+        let cipher = encryption.map(|encryption| match encryption.cipher {
+            Cipher::ChaCha20Poly1305 => ChaCha20Poly1305::new(Key::from_slice(&encryption.key)),
+        });
+        
+        let write_lock = db.write_lock();
+        Ok(StorageEngine {
+            db: Arc::new(db),
+            cache: Arc::new(Mutex::new(FileCache::new(config.cache_capacity.unwrap_or(DEFAULT_CACHE_CAPACITY)))),
+            cache_enabled: !config.disable_cache,
+            compression: config.value_compression,
+            cipher,
+            hash_key: config.hash_key,
+            chunk_threshold: config.chunk_threshold,
+            auto_chunk_threshold: config.auto_chunk_threshold.unwrap_or(AUTO_CHUNK_THRESHOLD),
+            max_value_bytes: config.max_value_bytes.unwrap_or(DEFAULT_MAX_VALUE_BYTES),
+            prefixed_hashes: config.prefixed_hashes,
+            read_only,
+            verify_existing: config.verify_existing,
+            metrics: CacheCounters::default(),
+            cache_observer: None,
+            hashers: HasherRegistry::new(),
+            clock: current_timestamp,
+            write_lock,
+        })
+    }
+    
+    /// Another namespace of the same open database
+    ///
+    /// RocksDB allows one open handle per database, so this is how one
+    /// process reaches several namespaces. The new engine shares the
+    /// database and this engine's settings, but has its own cache.
+    pub fn namespace(&self, namespace: &str) -> Result<StorageEngine> {
+        let db = self.db.namespaced(namespace)?;
+        let write_lock = db.write_lock();
+        Ok(StorageEngine {
+            db: Arc::new(db),
+            cache: Arc::new(Mutex::new(FileCache::new(self.lock_cache().capacity()))),
+            cache_enabled: self.cache_enabled,
+            compression: self.compression,
+            cipher: self.cipher.clone(),
+            hash_key: self.hash_key,
+            chunk_threshold: self.chunk_threshold,
+            auto_chunk_threshold: self.auto_chunk_threshold,
+            max_value_bytes: self.max_value_bytes,
+            prefixed_hashes: self.prefixed_hashes,
+            read_only: self.read_only,
+            verify_existing: self.verify_existing,
+            metrics: CacheCounters::default(),
+            cache_observer: self.cache_observer.clone(),
+            hashers: self.hashers.clone(),
+            clock: self.clock,
+            write_lock,
+        })
+    }
+    
+    /// Store a file with default settings
+    ///
+    /// Uses blake3, and stores the file whole unless it is larger than the
+    /// auto-chunk threshold (64 MiB by default), in which case it is split
+    /// into `DEFAULT_CHUNK_SIZE` chunks. A chunked file's hash is derived from
+    /// its chunk hashes and chunk size, so above the threshold the returned
+    /// hash is not `calculate_hash(data)`, and differs from the hash versions
+    /// that stored such inputs whole gave them.
+    pub fn store(&self, data: &[u8]) -> Result<String> {
+        self.store_with_options(data, HashAlgorithm::Blake3, self.default_chunk_size(data.len()))
+    }
+    
+    /// Chunk size `store` uses for `len` bytes
+    pub(crate) fn default_chunk_size(&self, len: usize) -> usize {
+        if len > self.auto_chunk_threshold.min(self.max_value_bytes) { self.largest_default_chunk_size() } else { 0 }
+    }
+    
+    /// `DEFAULT_CHUNK_SIZE`, or `max_value_bytes` if that is smaller
+    fn largest_default_chunk_size(&self) -> usize {
+        DEFAULT_CHUNK_SIZE.min(self.max_value_bytes)
+    }
+    
+    /// Reject chunk sizes below `MIN_CHUNK_SIZE`, or too large for a chunk to be stored
+    ///
+    /// Each chunk is a single value, so no chunk size may exceed `max_value_bytes`.
+    fn check_chunk_size(&self, chunk_size: usize) -> Result<()> {
+        check_min_chunk_size(chunk_size)?;
+        if chunk_size > self.max_value_bytes {
+            return Err(StorageError::ChunkingError(format!(
+                "chunk size {} exceeds the {}-byte value limit", chunk_size, self.max_value_bytes
+            )));
+        }
+        Ok(())
+    }
+    
+    /// Store a file with specified options, using the engine's default compression
+    ///
+    /// Only data strictly longer than `chunk_size` is chunked: empty input and
+    /// anything up to exactly `chunk_size` bytes are stored whole under their
+    /// content hash, while one byte more gives a full chunk and a 1-byte last
+    /// chunk. The remainder is never folded into the
+    /// chunk before it, as that would change the hashes of content already
+    /// stored and break fixed-size range reads. To avoid small chunked files
+    /// altogether, raise `StorageConfig::chunk_threshold`.
+    pub fn store_with_options(&self, data: &[u8], algorithm: HashAlgorithm, chunk_size: usize) -> Result<String> {
+        self.store_with(data, &StoreOptions {
+            algorithm,
+            chunk_size,
+            compression: self.compression,
+            chunking: ChunkingStrategy::Fixed,
+            ..Default::default()
+        })
+    }
+    
+    /// Store a file that expires `ttl_secs` seconds from now
+    ///
+    /// Once expired the file reads as missing, and the next `gc` removes it.
+    /// Storing the same content again keeps whichever lifetime is longer.
+    pub fn store_with_ttl(&self, data: &[u8], algorithm: HashAlgorithm, chunk_size: usize, ttl_secs: u64) -> Result<String> {
+        self.store_with(data, &StoreOptions {
+            algorithm,
+            chunk_size,
+            compression: self.compression,
+            chunking: ChunkingStrategy::Fixed,
+            ttl_secs: Some(ttl_secs),
+            ..Default::default()
+        })
+    }
+    
+    /// Store a file with a content type and tags, readable through `get_metadata`
+    ///
+    /// Storing the same content again adds to its tags and replaces its
+    /// content type if a new one is given.
+    pub fn store_with_metadata(&self, data: &[u8], algorithm: HashAlgorithm, chunk_size: usize, content_type: Option<&str>, tags: &[&str]) -> Result<String> {
+        self.store_with(data, &StoreOptions {
+            algorithm,
+            chunk_size,
+            compression: self.compression,
+            content_type: content_type.map(str::to_string),
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            ..Default::default()
+        })
+    }
+    
+    /// Store a file with attributes such as its original filename, readable through `get_attrs`
+    ///
+    /// Storing the same content again merges the new attributes over the old.
+    pub fn store_with_attrs(&self, data: &[u8], algorithm: HashAlgorithm, chunk_size: usize, attrs: HashMap<String, String>) -> Result<String> {
+        self.store_with(data, &StoreOptions {
+            algorithm,
+            chunk_size,
+            compression: self.compression,
+            attrs,
+            ..Default::default()
+        })
+    }
+    
+    /// Store a file with explicit per-call options
+    ///
+    /// A chunked file is recorded by one final write of its metadata, so a
+    /// store that fails never leaves a partial file. Without the `parallel`
+    /// feature its new chunk bodies go in that same write and nothing at all
+    /// is left behind. With it they are written concurrently beforehand, and
+    /// a failed store may leave some stored but unreferenced until the next
+    /// `gc` removes them.
+    pub fn store_with(&self, data: &[u8], options: &StoreOptions) -> Result<String> {
+        self.store_reporting(data, options).map(|(hash, _)| hash)
+    }
+    
+    /// Store a file as `store_with` does, also reporting whether its content was new
+    ///
+    /// Lets idempotent ingestion count genuinely new content and skip
+    /// downstream work for repeats. Two concurrent stores of the same new
+    /// content may both report `Written`.
+    pub fn store_reporting(&self, data: &[u8], options: &StoreOptions) -> Result<(String, StoreOutcome)> {
+        let (_, hash, outcome) = self.store_digest(data, options)?;
+        Ok((self.identifier(hash, options.algorithm_for(data.len())), outcome))
+    }
+    
+    /// Store a file, returning its raw digest and bare hex hash with the outcome
+    ///
+    /// A file stored whole is hashed straight to its digest. A chunked file's
+    /// hash is combined from hex chunk hashes, so its digest is decoded from that.
+    fn store_digest(&self, data: &[u8], options: &StoreOptions) -> Result<(Vec<u8>, String, StoreOutcome)> {
+        self.check_writable("store")?;
+        let algorithm = options.algorithm_for(data.len());
+        let chunk_size = options.chunk_size;
+        
+        self.check_chunk_size(chunk_size)?;
+        let expires_at = options.ttl_secs.map(|ttl| (self.clock)().saturating_add(ttl));
+        
+        // The default length is recorded as no length, so it hashes and
+        // stores exactly as if none had been given
+        let output_len = options.output_len.filter(|len| *len != algorithm.default_output_len());
+        if let Some(output_len) = output_len {
+            algorithm.check_output_len(output_len)?;
+            if self.should_chunk(data.len() as u64, chunk_size) {
+                return Err(StorageError::ChunkingError(format!(
+                    "a {}-byte output length is only supported for files stored whole", output_len
+                )));
+            }
+        }
+        
+        let _write_guard = self.write_guard()?;
+        
+        if self.should_chunk(data.len() as u64, chunk_size) {
+            // Chunked storage
+            let mut chunked_file = chunk_data(data, chunk_size, algorithm, &options.chunking, self.hash_key.as_ref())?;
+            chunked_file.metadata.expires_at = expires_at;
+            chunked_file.metadata.content_type = options.content_type.clone();
+            chunked_file.metadata.tags = options.tags.clone();
+            chunked_file.metadata.attrs = options.attrs.clone();
+            if options.merkle {
+                chunked_file.metadata.hash_scheme = FileHashScheme::Merkle;
+                chunked_file.metadata.hash = merkle::root(&chunked_file.metadata.chunks, algorithm, self.hash_key.as_ref())?;
+            }
+            let (hash, outcome) = self.store_chunked(chunked_file, options.compression, options.durability)?;
+            let digest = hex::decode(&hash)
+                .map_err(|e| StorageError::SerializationError(format!("invalid file hash {}: {}", hash, e)))?;
+            Ok((digest, hash, outcome))
+        } else {
+            // Simple storage
+            let digest = self.hash_bytes_with_len(data, algorithm, output_len)?;
+            let hash = hex::encode(&digest);
+            let mut batch = self.db.batch();
+            
+            // The info record tells retrieve how to decode the value and which
+            // algorithm produced the hash
+            let mut info = simple_info(&hash, algorithm, data.len(), options.compression.codec());
+            info.expires_at = expires_at;
+            info.content_type = options.content_type.clone();
+            info.tags = options.tags.clone();
+            info.attrs = options.attrs.clone();
+            info.output_len = output_len;
+            let (info, outcome) = self.stage_simple(&mut batch, data, info, options.compression)?;
+            
+            self.db.write_with(batch, options.durability)?;
+            
+            // Expiring files stay out of the cache so they can't outlive their expiry there
+            if info.expires_at.is_none() {
+                self.cache_insert(&hash, data);
+            } else {
+                self.cache_remove(&hash);
+            }
+            
+            Ok((digest, hash, outcome))
+        }
+    }
+    
+    /// Store a file whole under the hash function registered as `name`
+    ///
+    /// Built-in names behave like `store_with_options` with no chunking.
+    /// Custom hashers ignore the engine's hash key and `prefixed_hashes`, so
+    /// their files are always addressed by the bare digest.
+    pub fn store_with_hasher(&self, data: &[u8], name: &str) -> Result<String> {
+        if let Ok(algorithm) = HashAlgorithm::from_str(name) {
+            return self.store_with_options(data, algorithm, 0);
+        }
+        let hasher = self.hashers.from_str(name)?;
+        self.check_writable("store")?;
+        
+        let hash = hasher.hash(data);
+        if hash.is_empty() || hex::decode(&hash).is_err() {
+            return Err(StorageError::InvalidAlgorithm(format!("hasher {} returned a non-hex digest {:?}", name, hash)));
+        }
+        
+        let _write_guard = self.write_guard()?;
+        let mut batch = self.db.batch();
+        let mut info = simple_info(&hash, HashAlgorithm::default(), data.len(), self.compression.codec());
+        info.algorithm = hasher.name().to_string();
+        let (info, _) = self.stage_simple(&mut batch, data, info, self.compression)?;
+        self.db.write(batch)?;
+        
+        if info.expires_at.is_none() {
+            self.cache_insert(&hash, data);
+        } else {
+            self.cache_remove(&hash);
+        }
+        
+        Ok(hash)
+    }
+    
+    /// Store a file read from disk
+    ///
+    /// Large files are read chunk by chunk as in `store_reader`. Uses
+    /// fixed-size chunking and the engine's default compression.
+    pub fn import<P: AsRef<Path>>(&self, path: P, algorithm: HashAlgorithm, chunk_size: usize) -> Result<String> {
+        self.import_with_progress(path, algorithm, chunk_size, |_, _| {})
+    }
+    
+    /// `import`, calling `progress(bytes_done, bytes_total)` as the file is stored
+    ///
+    /// See `store_reader_with_progress`; the total is the file's size.
+    pub fn import_with_progress<P: AsRef<Path>>(&self, path: P, algorithm: HashAlgorithm, chunk_size: usize, progress: impl FnMut(u64, u64)) -> Result<String> {
+        let file = File::open(path)?;
+        let expected_len = file.metadata()?.len();
+        self.store_reader_with_progress(file, algorithm, chunk_size, expected_len, progress)
+    }
+    
+    /// Store a file read from `reader` without loading it all into memory
+    ///
+    /// Input up to the chunking threshold is buffered and stored as a simple
+    /// file. Longer input is read, hashed and written one chunk at a time, so
+    /// beyond that first read only the current chunk and the chunk hash list
+    /// are held. Gives the same hash as `store_with_options` on the same bytes.
+    pub fn store_reader<R: Read>(&self, reader: R, algorithm: HashAlgorithm, chunk_size: usize) -> Result<String> {
+        self.store_reader_with_progress(reader, algorithm, chunk_size, 0, |_, _| {})
+    }
+    
+    /// `store_reader`, calling `progress(bytes_done, bytes_total)` after each chunk is written
+    ///
+    /// A reader can't tell its length up front, so `expected_len` (e.g. a
+    /// file's size, or 0 if unknown) is reported as the total until the input
+    /// runs past it. The last call always reports the full size as both
+    /// values. Files stored whole get that single call only.
+    pub fn store_reader_with_progress<R: Read>(
+        &self,
+        mut reader: R,
+        algorithm: HashAlgorithm,
+        chunk_size: usize,
+        expected_len: u64,
+        mut progress: impl FnMut(u64, u64)
+    ) -> Result<String> {
+        self.check_writable("store")?;
+        self.check_chunk_size(chunk_size)?;
+        
+        // Read one byte past the threshold to learn whether the input is chunked
+        let mut head = Vec::new();
+        if chunk_size == 0 {
+            reader.read_to_end(&mut head)?;
+        } else {
+            let threshold = self.whole_limit(chunk_size);
+            (&mut reader).take(threshold as u64 + 1).read_to_end(&mut head)?;
+        }
+        
+        if !self.should_chunk(head.len() as u64, chunk_size) {
+            let hash = self.store_with_options(&head, algorithm, chunk_size)?;
+            progress(head.len() as u64, head.len() as u64);
+            return Ok(hash);
+        }
+        
+        // Holding the write lock keeps gc from collecting chunks written here
+        // before the metadata that references them
+        let _write_guard = self.write_guard()?;
+        
+        let mut input = Cursor::new(head).chain(reader);
+        let mut chunk_hashes = Vec::new();
+        let mut size = 0;
+        let mut block = vec![0u8; READ_BLOCK_SIZE.min(chunk_size)];
+        loop {
+            // Hash each block as it arrives rather than rereading the whole chunk
+            let mut chunk = Vec::with_capacity(chunk_size);
+            let mut hasher = Hasher::keyed(algorithm, self.hash_key.as_ref())?;
+            while chunk.len() < chunk_size {
+                let want = block.len().min(chunk_size - chunk.len());
+                let read = input.read(&mut block[..want])?;
+                if read == 0 {
+                    break;
+                }
+                hasher.update(&block[..read]);
+                chunk.extend_from_slice(&block[..read]);
+            }
+            if chunk.is_empty() {
+                break;
+            }
+            
+            let chunk_hash = hasher.finalize();
+            self.write_chunk_body(&chunk_hash, &chunk)?;
+            size += chunk.len();
+            chunk_hashes.push(chunk_hash);
+            progress(size as u64, expected_len.max(size as u64));
+        }
+        
+        let hash = self.record_chunked(&chunk_hashes, chunk_size, size, algorithm)?;
+        if expected_len > size as u64 {
+            progress(size as u64, size as u64);
+        }
+        Ok(self.identifier(hash, algorithm))
+    }
+    
+    /// Start a file whose content arrives in pieces
+    ///
+    /// See `BlobWriter`. The file gets the same hash `store_with_options`
+    /// would give the concatenated pieces.
+    pub fn create_blob(&self, algorithm: HashAlgorithm, chunk_size: usize) -> Result<BlobWriter<'_>> {
+        self.blob_writer(EngineHandle::Borrowed(self), algorithm, chunk_size)
+    }
+    
+    /// Start a file as `create_blob` does, with a writer that holds its own
+    /// reference to the engine instead of borrowing it
+    pub fn create_shared_blob(self: &Arc<Self>, algorithm: HashAlgorithm, chunk_size: usize) -> Result<BlobWriter<'static>> {
+        self.blob_writer(EngineHandle::Shared(Arc::clone(self)), algorithm, chunk_size)
+    }
+    
+    fn blob_writer<'a>(&self, engine: EngineHandle<'a>, algorithm: HashAlgorithm, chunk_size: usize) -> Result<BlobWriter<'a>> {
+        self.check_writable("store")?;
+        self.check_chunk_size(chunk_size)?;
+        
+        let threshold = if chunk_size == 0 {
+            usize::MAX
+        } else {
+            self.whole_limit(chunk_size)
+        };
+        
+        Ok(BlobWriter {
+            engine,
+            algorithm,
+            chunk_size,
+            threshold,
+            buffer: Vec::new(),
+            chunk_hashes: Vec::new(),
+            size: 0,
+            chunking: false,
+        })
+    }
+    
+    /// Write a file to disk chunk by chunk, returning the number of bytes written
+    pub fn export<P: AsRef<Path>>(&self, hash: &str, path: P) -> Result<u64> {
+        self.export_with_progress(hash, path, |_, _| {})
+    }
+    
+    /// `export`, calling `progress(bytes_done, bytes_total)` after each chunk is written
+    pub fn export_with_progress<P: AsRef<Path>>(&self, hash: &str, path: P, progress: impl FnMut(u64, u64)) -> Result<u64> {
+        // Look the file up first so a missing hash doesn't leave an empty file behind
+        let stream = self.retrieve_stream(hash)?;
+        copy_stream(stream, BufWriter::new(File::create(path)?), progress)
+    }
+    
+    /// Store a single chunk under its content hash, for resumable uploads
+    ///
+    /// The chunk is unreferenced until a `commit_chunked` call includes it,
+    /// so a `gc` run before the commit removes it again.
+    pub fn put_chunk(&self, data: &[u8], algorithm: HashAlgorithm) -> Result<String> {
+        self.check_writable("put_chunk")?;
+        let chunk_hash = self.hash(data, algorithm)?;
+        
+        let _write_guard = self.write_guard()?;
+        self.write_chunk_body(&chunk_hash, data)?;
+        
+        Ok(chunk_hash)
+    }
+    
+    /// Finish a resumable upload by recording a file made of previously put chunks
+    ///
+    /// Every chunk must already be stored; otherwise a `ChunkingError` lists the
+    /// missing ones. The chunk size is taken from the first chunk, so chunks
+    /// cut at a fixed size give the same hash `store_with_options` would.
+    /// That includes a single chunk, which like any content no longer than
+    /// the chunk size is stored whole under its content hash. Its contents
+    /// are then stored a second time, as a simple file; the chunk put for it
+    /// is left unreferenced and removed by the next `gc`. An empty list is
+    /// refused: empty content is stored as a simple file.
+    ///
+    /// Chunk hashes must be hex, optionally with an algorithm prefix;
+    /// anything else is refused with `InvalidHash`.
+    pub fn commit_chunked(&self, chunk_hashes: &[String], algorithm: HashAlgorithm) -> Result<String> {
+        self.check_writable("commit_chunked")?;
+        let chunk_hashes = chunk_hashes.iter()
+            .map(|id| bare_chunk_hash(id).map(str::to_string))
+            .collect::<Result<Vec<_>>>()?;
+        if chunk_hashes.is_empty() {
+            return Err(StorageError::ChunkingError("cannot commit a file with no chunks".to_string()));
+        }
+        if let [chunk_hash] = chunk_hashes.as_slice() {
+            let stored = self.db.get(format!("chunk:{}", chunk_hash).as_bytes())?
+                .ok_or_else(|| StorageError::ChunkingError(format!("missing chunks: {}", chunk_hash)))?;
+            return self.store_with_options(&decode_chunk(self.unseal(stored)?)?, algorithm, 0);
+        }
+        
+        let _write_guard = self.write_guard()?;
+        
+        let mut missing = Vec::new();
+        let mut sizes = Vec::with_capacity(chunk_hashes.len());
+        for chunk_hash in &chunk_hashes {
+            match self.db.get(format!("chunk:{}", chunk_hash).as_bytes())? {
+                Some(stored) => sizes.push(decode_chunk(self.unseal(stored)?)?.len()),
+                None => missing.push(chunk_hash.as_str()),
+            }
+        }
+        if !missing.is_empty() {
+            return Err(StorageError::ChunkingError(format!("missing chunks: {}", missing.join(", "))));
+        }
+        
+        let chunk_size = sizes.first().copied().unwrap_or(0);
+        let hash = self.record_chunked(&chunk_hashes, chunk_size, sizes.iter().sum(), algorithm)?;
+        Ok(self.identifier(hash, algorithm))
+    }
+    
+    /// Record a file made of chunks that are already stored, taking references on them
+    ///
+    /// The caller must hold the write lock.
+    fn record_chunked(&self, chunk_hashes: &[String], chunk_size: usize, size: usize, algorithm: HashAlgorithm) -> Result<String> {
+        let hash = combine_chunk_hashes(chunk_hashes, chunk_size, FileHashScheme::LengthPrefixed, algorithm, self.hash_key.as_ref())?;
+        
+        let metadata = FileMetadata {
+            hash: hash.clone(),
+            algorithm: algorithm.as_str().to_string(),
+            size,
+            chunk_size,
+            chunks: chunk_hashes.to_vec(),
+            timestamp: current_timestamp(),
+            compression: self.compression.codec().map(str::to_string),
+            chunking: ChunkingStrategy::Fixed,
+            layout: ChunkLayout::ContentAddressed,
+            hash_scheme: FileHashScheme::LengthPrefixed,
+            expires_at: None,
+            content_type: None,
+            tags: Vec::new(),
+            attrs: HashMap::new(),
+            output_len: None,
+        };
+        self.record_metadata(&metadata)?;
+        
+        Ok(hash)
+    }
+    
+    /// Write a chunked file's metadata and take references on its stored chunks
+    ///
+    /// The caller must hold the write lock.
+    fn record_metadata(&self, metadata: &FileMetadata) -> Result<()> {
+        // Committing the same chunk list twice must not take references twice
+        let metadata_key = format!("meta:{}", metadata.hash);
+        if self.db.get_pinned(metadata_key.as_bytes())?.is_some() {
+            return Ok(());
+        }
+        
+        let mut references: HashMap<&str, u64> = HashMap::new();
+        for chunk_hash in &metadata.chunks {
+            *references.entry(chunk_hash.as_str()).or_insert(0) += 1;
+        }
+        
+        let mut batch = self.db.batch();
+        for (chunk_hash, count) in references {
+            batch.add_to_counter(format!("chunkref:{}", chunk_hash).as_bytes(), count as i64);
+        }
+        batch.put(metadata_key.as_bytes(), serialize_metadata(metadata)?);
+        self.db.write(batch)
+    }
+    
+    /// Copy a file into another engine's store under the same hash
+    ///
+    /// Chunks are read, checked against their recorded hashes and written to
+    /// `dest` one at a time, so a chunked file is never held whole. The
+    /// file's algorithm, hash scheme, lifetime, tags and attrs come along;
+    /// `dest` applies its own compression and encryption. Both engines must
+    /// use the same hash key, since addresses depend on it, or the copy fails
+    /// with `ConfigMismatch`. Copying a file
+    /// `dest` already holds changes nothing.
+    pub fn copy_to(&self, hash: &str, dest: &StorageEngine) -> Result<()> {
+        dest.check_writable("copy_to")?;
+        if self.hash_key != dest.hash_key {
+            return Err(StorageError::ConfigMismatch("engines with different hash keys give different addresses".to_string()));
+        }
+        let hash = bare_hash(hash)?;
+        
+        let _write_guard = dest.write_guard()?;
+        
+        if let Some(metadata) = self.live_metadata(hash)? {
+            metadata.check_chunk_list()?;
+            let algorithm = HashAlgorithm::from_str(&metadata.algorithm)?;
+            
+            for (i, chunk_hash) in metadata.chunks.iter().enumerate() {
+                let chunk = self.read_chunk(&metadata, i)?
+                    .ok_or_else(|| StorageError::ChunkingError(format!("Chunk {} not found", i)))?;
+                let actual = self.hash(&chunk, algorithm)?;
+                if actual != *chunk_hash {
+                    return Err(StorageError::CorruptData {
+                        expected: chunk_hash.clone(),
+                        actual,
+                        chunk: Some(i),
+                    });
+                }
+                dest.write_chunk_body(chunk_hash, &chunk)?;
+            }
+            
+            // Chunks written before deduplication land under their content hash in `dest`
+            return dest.record_metadata(&FileMetadata {
+                compression: dest.compression.codec().map(str::to_string),
+                layout: ChunkLayout::ContentAddressed,
+                ..metadata
+            });
+        }
+        
+        let (data, info) = self.read_simple_with_info(hash)?
+            .ok_or_else(|| StorageError::HashNotFound(hash.to_string()))?;
+        let mut batch = dest.db.batch();
+        match info {
+            Some(info) => {
+                let actual = self.hash_as_recorded(&data, &info)?;
+                if actual != hash {
+                    return Err(StorageError::CorruptData {
+                        expected: hash.to_string(),
+                        actual,
+                        chunk: None,
+                    });
+                }
+                let info = FileMetadata {
+                    compression: dest.compression.codec().map(str::to_string),
+                    ..info
+                };
+                dest.stage_simple(&mut batch, &data, info, dest.compression)?;
+            },
+            // Values written before info records existed were never compressed
+            None => batch.put(hash.as_bytes(), dest.seal(Cow::Borrowed(&data))?),
+        }
+        dest.db.write(batch)
+    }
+    
+    /// Store a file again in fixed chunks of `new_chunk_size`, returning its new hash
+    ///
+    /// A chunked file's hash covers its chunk size and chunk hashes, so the
+    /// hash changes whenever the chunk size does; only rechunking at the same
+    /// size in the current scheme gives the same hash back. Content no longer
+    /// than `new_chunk_size` is stored whole under its plain content hash.
+    ///
+    /// The content is read back verified and held whole while it is stored
+    /// again. Its algorithm, hash scheme, lifetime, content type, tags and
+    /// attrs carry over, and the engine's compression applies. The file under
+    /// the old hash is left in place; delete it once nothing refers to it.
+    pub fn rechunk(&self, hash: &str, new_chunk_size: usize) -> Result<String> {
+        self.check_writable("rechunk")?;
+        if new_chunk_size == 0 {
+            return Err(StorageError::ChunkingError("rechunk needs a chunk size".to_string()));
+        }
+        self.check_chunk_size(new_chunk_size)?;
+        
+        let metadata = self.get_metadata(hash)?
+            .filter(|metadata| !self.is_expired(metadata))
+            .ok_or_else(|| StorageError::HashNotFound(hash.to_string()))?;
+        let data = self.retrieve_verified(hash)?;
+        
+        self.store_with(&data, &StoreOptions {
+            algorithm: HashAlgorithm::from_str(&metadata.algorithm)?,
+            chunk_size: new_chunk_size,
+            compression: self.compression,
+            chunking: ChunkingStrategy::Fixed,
+            ttl_secs: metadata.expires_at.map(|expires_at| expires_at.saturating_sub((self.clock)())),
+            content_type: metadata.content_type,
+            tags: metadata.tags,
+            attrs: metadata.attrs,
+            output_len: metadata.output_len,
+            merkle: metadata.hash_scheme == FileHashScheme::Merkle,
+            durability: None,
+            auto_blake3_threshold: None,
+        })
+    }
+    
+    /// Store many small files in a single write batch
+    ///
+    /// Each item is stored as a simple file (blake3, no chunking) using the
+    /// engine's default compression. Hashes are returned in input order.
+    pub fn store_batch(&self, items: &[&[u8]]) -> Result<Vec<String>> {
+        self.check_writable("store_batch")?;
+        let _write_guard = self.write_guard()?;
+        let mut batch = self.db.batch();
+        let mut hashes = Vec::with_capacity(items.len());
+        let mut cacheable = Vec::with_capacity(items.len());
+        
+        for data in items {
+            let hash = self.hash(data, HashAlgorithm::Blake3)?;
+            let info = simple_info(&hash, HashAlgorithm::Blake3, data.len(), self.compression.codec());
+            let (info, _) = self.stage_simple(&mut batch, data, info, self.compression)?;
+            
+            cacheable.push(info.expires_at.is_none());
+            hashes.push(hash);
+        }
+        
+        self.db.write(batch)?;
+        
+        // Update cache
+        if self.cache_enabled {
+            let mut cache = self.lock_cache();
+            for ((hash, data), cacheable) in hashes.iter().zip(items).zip(cacheable) {
+                if cacheable {
+                    cache.insert(hash.clone(), data.to_vec());
+                }
+            }
+        }
+        
+        Ok(hashes.into_iter().map(|hash| self.identifier(hash, HashAlgorithm::Blake3)).collect())
+    }
+    
+    /// Store a file as `store_with_options` does, returning the raw digest
+    ///
+    /// For callers that embed digests in their own structures and would
+    /// otherwise decode the hex hash straight away.
+    pub fn store_raw(&self, data: &[u8], algorithm: HashAlgorithm, chunk_size: usize) -> Result<Vec<u8>> {
+        let (digest, _, _) = self.store_digest(data, &StoreOptions {
+            algorithm,
+            chunk_size,
+            compression: self.compression,
+            chunking: ChunkingStrategy::Fixed,
+            ..Default::default()
+        })?;
+        Ok(digest)
+    }
+    
+    /// Retrieve a file by its raw digest, as returned by `store_raw` or `calculate_hash_bytes`
+    pub fn retrieve_raw(&self, digest: &[u8]) -> Result<Vec<u8>> {
+        self.retrieve(&hex::encode(digest))
+    }
+    
+    /// Retrieve a file by its hash
+    pub fn retrieve(&self, hash: &str) -> Result<Vec<u8>> {
+        let hash = bare_hash(hash)?;
+        
+        // Try cache first
+        if self.cache_enabled {
+            let mut cache = self.lock_cache();
+            if let Some(data) = cache.get(hash) {
+                self.record_hit(data.len());
+                return Ok(data.clone());
+            }
+        }
+        
+        let result = self.retrieve_from_db(hash, 1, true);
+        self.record_miss(result.as_ref().map_or(0, Vec::len));
+        result
+    }
+    
+    /// Read `len` bytes of a file starting at `offset`
+    ///
+    /// Only the chunks overlapping the range are read. For fixed-size
+    /// chunking the recorded chunk size locates them directly; content-defined
+    /// chunks are walked in order. A range running past the end of the file is
+    /// cut short, so an offset at or beyond the end gives an empty result.
+    pub fn retrieve_range(&self, hash: &str, offset: usize, len: usize) -> Result<Vec<u8>> {
+        let hash = bare_hash(hash)?;
+        
+        if self.cache_enabled {
+            let mut cache = self.lock_cache();
+            if let Some(data) = cache.get(hash) {
+                self.record_hit(len.min(data.len().saturating_sub(offset)));
+                return Ok(slice_range(data, offset, len).to_vec());
+            }
+        }
+        
+        let Some(metadata) = self.live_metadata(hash)? else {
+            // Simple files are stored, and compressed, as one value
+            let data = self.read_simple(hash)?
+                .ok_or_else(|| StorageError::HashNotFound(hash.to_string()))?;
+            return Ok(slice_range(&data, offset, len).to_vec());
+        };
+        
+        let end = offset.saturating_add(len).min(metadata.size);
+        if offset >= end {
+            return Ok(Vec::new());
+        }
+        
+        if metadata.chunking == ChunkingStrategy::Fixed && metadata.chunk_size > 0 {
+            if let Some(data) = self.read_fixed_range(&metadata, offset, end)? {
+                return Ok(data);
+            }
+        }
+        
+        // Walk the chunks, reading only those that overlap the range
+        let mut data = Vec::with_capacity(end - offset);
+        let mut chunk_start = 0;
+        for i in 0..metadata.chunks.len() {
+            if chunk_start >= end {
+                break;
+            }
+            let chunk = self.read_chunk(&metadata, i)?
+                .ok_or_else(|| StorageError::ChunkingError(format!("Chunk {} not found", i)))?;
+            let chunk_end = chunk_start + chunk.len();
+            if chunk_end > offset {
+                data.extend_from_slice(&chunk[offset.saturating_sub(chunk_start)..end.min(chunk_end) - chunk_start]);
+            }
+            chunk_start = chunk_end;
+        }
+        Ok(data)
+    }
+    
+    /// Retrieve a file with explicit per-call options
+    ///
+    /// A `concurrency` above 1 reads the chunks of a chunked file on that many
+    /// threads at once, which helps most when chunk reads wait on disk.
+    pub fn retrieve_with(&self, hash: &str, options: &RetrieveOptions) -> Result<Vec<u8>> {
+        if options.verify {
+            return self.retrieve_verified_with(hash, options.concurrency);
+        }
+        
+        let hash = bare_hash(hash)?;
+        if self.cache_enabled {
+            let mut cache = self.lock_cache();
+            if let Some(data) = cache.get(hash) {
+                self.record_hit(data.len());
+                return Ok(data.clone());
+            }
+        }
+        
+        let result = self.retrieve_from_db(hash, options.concurrency, true);
+        self.record_miss(result.as_ref().map_or(0, Vec::len));
+        result
+    }
+    
+    /// Read a file from the database, caching it unless it expires
+    fn retrieve_from_db(&self, hash: &str, concurrency: usize, cache: bool) -> Result<Vec<u8>> {
+        let (data, cacheable) = self.read_from_db(hash, concurrency)?;
+        
+        // Update cache
+        if cache && cacheable {
+            self.cache_insert(hash, &data);
+        }
+        
+        Ok(data)
+    }
+    
+    /// Read a file from the database, along with whether it may be cached
+    ///
+    /// Files with an expiry are never cached.
+    fn read_from_db(&self, hash: &str, concurrency: usize) -> Result<(Vec<u8>, bool)> {
+        // Check if this is a chunked file
+        if let Some(metadata) = self.live_metadata(hash)? {
+            // Chunked file - reassemble
+            let data = self.read_chunks(&metadata, None, concurrency)?;
+            Ok((data, metadata.expires_at.is_none()))
+        } else {
+            // Simple file
+            match self.read_simple_with_info(hash)? {
+                Some((data, info)) => Ok((data, info.is_none_or(|info| info.expires_at.is_none()))),
+                None => Err(StorageError::HashNotFound(hash.to_string())),
+            }
+        }
+    }
+    
+    /// Retrieve a file straight from the database, neither reading nor filling the cache
+    ///
+    /// For scans and one-shot reads that would otherwise push the working
+    /// set out of the cache. Not counted in `cache_metrics`.
+    pub fn retrieve_uncached(&self, hash: &str) -> Result<Vec<u8>> {
+        let hash = bare_hash(hash)?;
+        self.retrieve_from_db(hash, 1, false)
+    }
+    
+    /// Retrieve many files at once, returning one result per hash in input order
+    ///
+    /// Cached files are served from the cache and simple files are read
+    /// together with a single `multi_get`. Chunked files and anything the
+    /// batch lookup didn't find fall back to `retrieve`. Hits are cached as
+    /// `retrieve` would cache them.
+    pub fn retrieve_batch(&self, hashes: &[String]) -> Vec<Result<Vec<u8>>> {
+        let mut results: Vec<Result<Vec<u8>>> = Vec::with_capacity(hashes.len());
+        let mut pending = Vec::new();
+        
+        let mut cache = self.cache_enabled.then(|| self.lock_cache());
+        for (i, id) in hashes.iter().enumerate() {
+            match bare_hash(id) {
+                Ok(hash) => match cache.as_mut().and_then(|cache| cache.get(hash)) {
+                    Some(data) => {
+                        self.record_hit(data.len());
+                        results.push(Ok(data.clone()));
+                    },
+                    None => {
+                        // Filled in below
+                        results.push(Err(StorageError::HashNotFound(hash.to_string())));
+                        pending.push((i, hash));
+                    },
+                },
+                Err(e) => results.push(Err(e)),
+            }
+        }
+        drop(cache);
+        
+        // Each simple file needs its value and its info record
+        let keys: Vec<Vec<u8>> = pending.iter()
+            .flat_map(|(_, hash)| [hash.as_bytes().to_vec(), format!("info:{}", hash).into_bytes()])
+            .collect();
+        let mut fetched = self.db.multi_get(&keys).into_iter();
+        
+        for (i, hash) in pending {
+            let value = fetched.next().unwrap_or(Ok(None));
+            let info = fetched.next().unwrap_or(Ok(None));
+            
+            results[i] = match (value, info) {
+                (Ok(Some(stored)), Ok(info)) => info
+                    .map(|bytes| metadata_format::decode(&bytes)
+                        .map_err(|e| StorageError::SerializationError(e.to_string())))
+                    .transpose()
+                    .and_then(|info| self.decode_simple(stored, info))
+                    .and_then(|decoded| match decoded {
+                        Some((data, info)) => {
+                            if info.is_none_or(|info| info.expires_at.is_none()) {
+                                self.cache_insert(hash, &data);
+                            }
+                            Ok(data)
+                        },
+                        None => Err(StorageError::HashNotFound(hash.to_string())),
+                    }),
+                // Not a simple file: chunked, or not stored at all
+                (Ok(None), _) => self.retrieve_from_db(hash, 1, true),
+                (Err(e), _) | (_, Err(e)) => Err(e),
+            };
+            self.record_miss(results[i].as_ref().map_or(0, Vec::len));
+        }
+        
+        results
+    }
+    
+    /// Cache hits and misses since the engine was opened
+    pub fn cache_metrics(&self) -> CacheMetrics {
+        CacheMetrics {
+            hits: self.metrics.hits.load(Ordering::Relaxed),
+            misses: self.metrics.misses.load(Ordering::Relaxed),
+            bytes_from_cache: self.metrics.bytes_from_cache.load(Ordering::Relaxed),
+            bytes_from_db: self.metrics.bytes_from_db.load(Ordering::Relaxed),
+        }
+    }
+    
+    /// Report every cache hit and miss to `observer` as well as counting it
+    pub fn set_cache_observer(&mut self, observer: Arc<dyn CacheObserver>) {
+        self.cache_observer = Some(observer);
+    }
+    
+    /// Make a custom hash function available to `store_with_hasher` and verification
+    ///
+    /// Register the same hashers every time the store is opened, or files
+    /// stored under them can't be verified.
+    pub fn register_hasher(&mut self, hasher: Arc<dyn FileHasher>) -> Result<()> {
+        self.hashers.register(hasher)
+    }
+    
+    /// The hashers this engine knows by name
+    pub fn hashers(&self) -> &HasherRegistry {
+        &self.hashers
+    }
+    
+    /// Load files into the cache ahead of a burst of reads
+    ///
+    /// Returns how many of `hashes` were loaded; missing ones and ones
+    /// already cached are skipped. Files are read in order, each once, and
+    /// cached until the next one would take what this call loaded past the
+    /// cache capacity. That one is read but not cached, so warming never
+    /// evicts files it loaded itself and a long list never reads much more
+    /// than the cache holds. Files with an expiry are read but, as always,
+    /// left out of the cache. With the cache disabled this only checks the
+    /// files can be read.
+    pub fn warm(&self, hashes: &[String]) -> Result<usize> {
+        let mut budget = self.lock_cache().capacity();
+        let mut loaded = 0;
+        for id in hashes {
+            let hash = bare_hash(id)?;
+            if self.cache_enabled && self.lock_cache().contains_key(hash) {
+                continue;
+            }
+            
+            let (data, cacheable) = match self.read_from_db(hash, 1) {
+                Ok(read) => read,
+                Err(StorageError::HashNotFound(_)) => continue,
+                Err(e) => return Err(e),
+            };
+            self.record_miss(data.len());
+            if self.cache_enabled && cacheable {
+                if data.len() > budget {
+                    break;
+                }
+                budget -= data.len();
+                self.cache_insert(hash, &data);
+            }
+            loaded += 1;
+        }
+        Ok(loaded)
+    }
+    
+    /// Retrieve a file one chunk at a time without buffering the whole payload
+    pub fn retrieve_stream(&self, hash: &str) -> Result<ChunkStream<'_>> {
+        let hash = bare_hash(hash)?;
+        
+        let cached = if self.cache_enabled {
+            self.lock_cache().get(hash).cloned()
+        } else {
+            None
+        };
+        
+        let source = if let Some(data) = cached {
+            self.record_hit(data.len());
+            StreamSource::Whole(Some(data))
+        } else if let Some(metadata) = self.live_metadata(hash)? {
+            metadata.check_chunk_list()?;
+            self.record_miss(metadata.size);
+            StreamSource::Chunked(Box::new(metadata))
+        } else {
+            let data = self.read_simple(hash)?;
+            self.record_miss(data.as_ref().map_or(0, Vec::len));
+            match data {
+                Some(data) => StreamSource::Whole(Some(data)),
+                None => return Err(StorageError::HashNotFound(hash.to_string())),
+            }
+        };
+        
+        Ok(ChunkStream {
+            engine: self,
+            source,
+            next: 0,
+            yielded: 0,
+        })
+    }
+    
+    /// Write a file to `writer` chunk by chunk, returning the number of bytes written
+    pub fn retrieve_to_writer<W: Write>(&self, hash: &str, writer: W) -> Result<u64> {
+        self.retrieve_to_writer_with_progress(hash, writer, |_, _| {})
+    }
+    
+    /// `retrieve_to_writer`, calling `progress(bytes_done, bytes_total)` after each chunk is written
+    ///
+    /// Files stored whole or served from the cache are written in one piece,
+    /// so they get a single call.
+    pub fn retrieve_to_writer_with_progress<W: Write>(&self, hash: &str, writer: W, progress: impl FnMut(u64, u64)) -> Result<u64> {
+        copy_stream(self.retrieve_stream(hash)?, writer, progress)
+    }
+    
+    /// Read a file into the front of `buf`, returning the number of bytes written
+    ///
+    /// Chunks are copied in as they are read, so the file is never held
+    /// twice. `buf` must be at least `size_of(hash)` bytes long; a shorter
+    /// one fails before anything is written.
+    pub fn retrieve_into(&self, hash: &str, buf: &mut [u8]) -> Result<usize> {
+        let size = self.size_of(hash)?;
+        if size > buf.len() {
+            return Err(StorageError::IOError(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("buffer of {} bytes is too small for a {}-byte file", buf.len(), size),
+            )));
+        }
+        
+        let written = self.retrieve_to_writer(hash, buf)?;
+        Ok(written as usize)
+    }
+    
+    /// Retrieve a file, recomputing its hash to detect on-disk corruption
+    ///
+    /// Chunked files are checked chunk by chunk against the hashes recorded in
+    /// their metadata and then as a whole. Simple files are checked with the
+    /// algorithm from their info record, or for older files without one, an
+    /// algorithm inferred from the length of the hash. A prefixed identifier
+    /// must name the algorithm the file was stored with. The cache is bypassed
+    /// so the bytes on disk are what get verified.
+    pub fn retrieve_verified(&self, hash: &str) -> Result<Vec<u8>> {
+        self.retrieve_verified_with(hash, 1)
+    }
+    
+    /// Retrieve a file the caller expects to have been hashed with `expected_algorithm`
+    ///
+    /// Fails with `AlgorithmMismatch` if the file was stored under another
+    /// algorithm, which usually means the caller computed the address wrongly.
+    /// Values stored before algorithms were recorded are checked against the
+    /// algorithm that reproduces their hash.
+    pub fn retrieve_as(&self, hash: &str, expected_algorithm: HashAlgorithm) -> Result<Vec<u8>> {
+        let metadata = self.get_metadata(hash)?
+            .ok_or_else(|| StorageError::HashNotFound(hash.to_string()))?;
+        
+        let found = HashAlgorithm::from_str(&metadata.algorithm)?;
+        if found != expected_algorithm {
+            return Err(StorageError::AlgorithmMismatch {
+                expected: expected_algorithm,
+                found,
+            });
+        }
+        
+        self.retrieve(hash)
+    }
+    
+    fn retrieve_verified_with(&self, hash: &str, concurrency: usize) -> Result<Vec<u8>> {
+        let (hash, named_algorithm) = parse_identifier(hash)?;
+        
+        if let Some(metadata) = self.live_metadata(hash)? {
+            let algorithm = HashAlgorithm::from_str(&metadata.algorithm)?;
+            check_named_algorithm(hash, named_algorithm, algorithm)?;
+            
+            let data = self.read_chunks(&metadata, Some(algorithm), concurrency)?;
+            
+            let actual = combine_chunk_hashes(&metadata.chunks, metadata.chunk_size, metadata.hash_scheme, algorithm, self.hash_key.as_ref())?;
+            if actual != hash {
+                return Err(StorageError::CorruptData {
+                    expected: hash.to_string(),
+                    actual,
+                    chunk: None,
+                });
+            }
+            
+            Ok(data)
+        } else {
+            let data = self.read_simple(hash)?
+                .ok_or_else(|| StorageError::HashNotFound(hash.to_string()))?;
+            
+            let info = self.load_metadata(&format!("info:{}", hash))?;
+            if let Some(hasher) = info.as_ref().and_then(|info| self.hashers.custom(&info.algorithm)) {
+                let actual = hasher.hash(&data);
+                if actual != hash {
+                    return Err(StorageError::CorruptData {
+                        expected: hash.to_string(),
+                        actual,
+                        chunk: None,
+                    });
+                }
+                return Ok(data);
+            }
+            let output_len = info.as_ref().and_then(|info| info.output_len);
+            let candidates = match (info, named_algorithm) {
+                (Some(info), _) => {
+                    let algorithm = HashAlgorithm::from_str(&info.algorithm)?;
+                    check_named_algorithm(hash, named_algorithm, algorithm)?;
+                    vec![algorithm]
+                },
+                (None, Some(algorithm)) => vec![algorithm],
+                (None, None) => HashAlgorithm::candidates_for_hash(hash),
+            };
+            if candidates.is_empty() {
+                return Err(StorageError::InvalidAlgorithm(format!("cannot infer algorithm for hash {}", hash)));
+            }
+            
+            let mut actual = String::new();
+            for algorithm in candidates {
+                actual = match self.hash_with_len(&data, algorithm, output_len) {
+                    Ok(actual) => actual,
+                    // Unkeyed-only algorithms can't have produced a keyed address
+                    Err(_) => continue,
+                };
+                if actual == hash {
+                    return Ok(data);
+                }
+            }
+            
+            Err(StorageError::CorruptData {
+                expected: hash.to_string(),
+                actual,
+                chunk: None,
+            })
+        }
+    }
+    
+    /// Look up a file's metadata without reading its contents
+    ///
+    /// Chunked files return their stored metadata record. Simple files return
+    /// their info record if they have one; otherwise a minimal record is
+    /// synthesized from the stored value, with the algorithm worked out from
+    /// the hash and a timestamp of 0 since the store time was never recorded.
+    pub fn get_metadata(&self, hash: &str) -> Result<Option<FileMetadata>> {
+        let hash = bare_hash(hash)?;
+        
+        if let Some(metadata) = self.load_metadata(&format!("meta:{}", hash))? {
+            return Ok(Some(metadata));
+        }
+        if let Some(info) = self.load_metadata(&format!("info:{}", hash))? {
+            return Ok(Some(info));
+        }
+        
+        let value = match self.read_simple(hash)? {
+            Some(value) => value,
+            None => return Ok(None),
+        };
+        
+        // Best effort: the first algorithm that reproduces the hash, or the
+        // first one with a matching digest length if none do
+        let candidates = HashAlgorithm::candidates_for_hash(hash);
+        let algorithm = candidates.iter()
+            .find(|algorithm| self.hash(&value, **algorithm).is_ok_and(|actual| actual == hash))
+            .or(candidates.first())
+            .map(|algorithm| algorithm.as_str().to_string())
+            .unwrap_or_default();
+        
+        Ok(Some(FileMetadata {
+            hash: hash.to_string(),
+            algorithm,
+            size: value.len(),
+            chunk_size: 0,
+            chunks: Vec::new(),
+            timestamp: 0,
+            compression: None,
+            chunking: ChunkingStrategy::Fixed,
+            layout: ChunkLayout::ContentAddressed,
+            hash_scheme: FileHashScheme::LengthPrefixed,
+            expires_at: None,
+            content_type: None,
+            tags: Vec::new(),
+            attrs: HashMap::new(),
+            output_len: None,
+        }))
+    }
+    
+    /// The chunk hashes a file is made of, for comparing against a remote copy
+    ///
+    /// A simple file is its own single chunk. Together with `has_chunk` this
+    /// lets a sync client send only the chunks the other side lacks, then
+    /// finish with `commit_chunked`.
+    pub fn chunk_list(&self, hash: &str) -> Result<Vec<String>> {
+        let hash = bare_hash(hash)?;
+        
+        if let Some(metadata) = self.live_metadata(hash)? {
+            return Ok(metadata.chunks);
+        }
+        if self.exists(hash)? {
+            return Ok(vec![hash.to_string()]);
+        }
+        Err(StorageError::HashNotFound(hash.to_string()))
+    }
+    
+    /// Inclusion proof for chunk `chunk_index` of a file stored with `StoreOptions::merkle`
+    ///
+    /// Returns sibling hashes from the chunk up to the root, which
+    /// `verify_chunk_proof` checks against the file hash, so a client can
+    /// trust one downloaded chunk without fetching the rest.
+    pub fn chunk_proof(&self, hash: &str, chunk_index: usize) -> Result<Vec<String>> {
+        let hash = bare_hash(hash)?;
+        
+        let Some(metadata) = self.live_metadata(hash)? else {
+            if self.exists(hash)? {
+                return Err(StorageError::ChunkingError(format!("{} is stored whole and has no chunk proofs", hash)));
+            }
+            return Err(StorageError::HashNotFound(hash.to_string()));
+        };
+        if metadata.hash_scheme != FileHashScheme::Merkle {
+            return Err(StorageError::ChunkingError(format!("{} was not stored with a Merkle hash", hash)));
+        }
+        if chunk_index >= metadata.chunks.len() {
+            return Err(StorageError::ChunkingError(format!(
+                "chunk {} is out of range for {} chunks", chunk_index, metadata.chunks.len()
+            )));
+        }
+        
+        let algorithm = HashAlgorithm::from_str(&metadata.algorithm)?;
+        merkle::proof(&metadata.chunks, chunk_index, algorithm, self.hash_key.as_ref())
+    }
+    
+    /// Whether a chunk, or a simple file, is stored under `chunk_hash`
+    ///
+    /// Chunks of files stored before deduplication are kept per file rather
+    /// than under their own hash, so they are not found here.
+    pub fn has_chunk(&self, chunk_hash: &str) -> Result<bool> {
+        let chunk_hash = bare_hash(chunk_hash)?;
+        
+        let chunk_key = format!("chunk:{}", chunk_hash);
+        if self.db.key_may_exist(chunk_key.as_bytes())
+            && self.db.get_pinned(chunk_key.as_bytes())?.is_some()
+        {
+            return Ok(true);
+        }
+        self.exists(chunk_hash)
+    }
+    
+    /// The attributes a file was stored with, empty if it has none
+    pub fn get_attrs(&self, hash: &str) -> Result<HashMap<String, String>> {
+        match self.get_metadata(hash)? {
+            Some(metadata) => Ok(metadata.attrs),
+            None => Err(StorageError::HashNotFound(bare_hash(hash)?.to_string())),
+        }
+    }
+    
+    /// Size of a stored file in bytes, without reassembling or decompressing it
+    pub fn size_of(&self, hash: &str) -> Result<usize> {
+        let hash = bare_hash(hash)?;
+        
+        if self.cache_enabled {
+            if let Some(data) = self.lock_cache().get(hash) {
+                return Ok(data.len());
+            }
+        }
+        
+        if let Some(metadata) = self.load_metadata(&format!("meta:{}", hash))? {
+            return Ok(metadata.size);
+        }
+        if let Some(info) = self.load_metadata(&format!("info:{}", hash))? {
+            return Ok(info.size);
+        }
+        
+        // Uncompressed simple value; encryption adds a fixed nonce and tag
+        match self.db.get_pinned(hash.as_bytes())? {
+            Some(value) if self.cipher.is_some() => Ok(value.len().saturating_sub(NONCE_LEN + TAG_LEN)),
+            Some(value) => Ok(value.len()),
+            None => Err(StorageError::HashNotFound(hash.to_string())),
+        }
+    }
+    
+    /// Work out the hash `store_with_options` would return, without writing anything
+    ///
+    /// Returns the hash and whether it is already stored, so a client can
+    /// skip uploading content the store already has.
+    pub fn would_store(&self, data: &[u8], algorithm: HashAlgorithm, chunk_size: usize) -> Result<(String, bool)> {
+        let identifier = self.address_of(data, algorithm, chunk_size)?;
+        let exists = self.exists(&identifier)?;
+        
+        Ok((identifier, exists))
+    }
+    
+    /// The identifier `store_with_options` would return, without touching the database
+    pub(crate) fn address_of(&self, data: &[u8], algorithm: HashAlgorithm, chunk_size: usize) -> Result<String> {
+        self.check_chunk_size(chunk_size)?;
+        
+        let hash = if self.should_chunk(data.len() as u64, chunk_size) {
+            hash_chunks(data.chunks(chunk_size), chunk_size, algorithm, self.hash_key.as_ref())?.1
+        } else {
+            self.hash(data, algorithm)?
+        };
+        Ok(self.identifier(hash, algorithm))
+    }
+    
+    /// Check whether a file is stored without reading its contents
+    ///
+    /// With `StorageConfig::existence_filter`, most absent files are ruled
+    /// out in memory before the database is read.
+    pub fn exists(&self, hash: &str) -> Result<bool> {
+        let hash = bare_hash(hash)?;
+        
+        let Some(filter) = self.db.existence_filter() else {
+            return self.exists_in_db(hash);
+        };
+        if !filter.may_contain(hash.as_bytes()) {
+            return Ok(false);
+        }
+        
+        let exists = self.exists_in_db(hash)?;
+        if !exists {
+            filter.record_false_positive();
+        }
+        Ok(exists)
+    }
+    
+    /// Counts of `exists` lookups the existence filter answered, `None` without one
+    pub fn existence_filter_metrics(&self) -> Option<ExistenceFilterMetrics> {
+        self.db.existence_filter().map(|filter| filter.metrics())
+    }
+    
+    /// The authoritative half of `exists`, past any existence filter
+    fn exists_in_db(&self, hash: &str) -> Result<bool> {
+        if self.cache_enabled && self.lock_cache().contains_key(hash) {
+            return Ok(true);
+        }
+        
+        // key_may_exist never gives false negatives, so it can rule keys out cheaply
+        let metadata_key = format!("meta:{}", hash);
+        if self.db.key_may_exist(metadata_key.as_bytes())
+            && self.live_metadata(hash)?.is_some()
+        {
+            return Ok(true);
+        }
+        
+        if self.db.key_may_exist(hash.as_bytes())
+            && self.db.get_pinned(hash.as_bytes())?.is_some()
+        {
+            let info = self.load_metadata(&format!("info:{}", hash))?;
+            return Ok(!info.is_some_and(|info| self.is_expired(&info)));
+        }
+        
+        Ok(false)
+    }
+    
+    /// Delete a file by its hash, returning whether anything was removed
+    ///
+    /// Pinned files are refused with `StorageError::Pinned`. Deleting takes
+    /// the write lock exclusively: `pin` holds it shared, so no pin can land
+    /// between the pin check and the delete's write, and a store running
+    /// alongside can't take a reference on a chunk body this delete is about
+    /// to remove.
+    pub fn delete(&self, hash: &str) -> Result<bool> {
+        self.check_writable("delete")?;
+        let hash = bare_hash(hash)?;
+        
+        let _write_guard = self.exclusive_write_guard()?;
+        let metadata_key = format!("meta:{}", hash);
+        let metadata = self.load_metadata(&metadata_key)?;
+        
+        if self.pin_count(hash)? > 0 {
+            return Err(StorageError::Pinned(hash.to_string()));
+        }
+        
+        // Evict from cache
+        self.cache_remove(hash);
+        
+        if let Some(metadata) = metadata {
+            // Chunked file - the metadata and its chunk references go in one batch
+            let mut batch = self.db.batch();
+            batch.delete(metadata_key.as_bytes());
+            
+            match metadata.layout {
+                ChunkLayout::Indexed => {
+                    // One range delete covers every index, and any stray chunk
+                    // past the end of the list, whatever order the keys sort in
+                    batch.delete_prefix(format!("chunk:{}:", metadata.hash).as_bytes());
+                },
+                ChunkLayout::ContentAddressed => {
+                    // Release one reference per occurrence; remove bodies nobody
+                    // uses. The exclusive lock keeps the counts still meanwhile.
+                    let mut released: HashMap<&str, u64> = HashMap::new();
+                    for chunk_hash in &metadata.chunks {
+                        *released.entry(chunk_hash.as_str()).or_insert(0) += 1;
+                    }
+                    
+                    for (chunk_hash, count) in released {
+                        let refcount_key = format!("chunkref:{}", chunk_hash);
+                        let remaining = self.chunk_refcount(chunk_hash)?.saturating_sub(count);
+                        if remaining == 0 {
+                            batch.delete(format!("chunk:{}", chunk_hash).as_bytes());
+                            batch.delete(refcount_key.as_bytes());
+                        } else {
+                            batch.put(refcount_key.as_bytes(), remaining.to_le_bytes());
+                        }
+                    }
+                },
+            }
+            
+            self.db.write(batch)?;
+            Ok(true)
+        } else if self.db.get_pinned(hash.as_bytes())?.is_some() {
+            // Simple file
+            let mut batch = self.db.batch();
+            batch.delete(hash.as_bytes());
+            batch.delete(format!("info:{}", hash).as_bytes());
+            self.db.write(batch)?;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+    
+    /// Re-store a file under a different hash algorithm, returning its new hash
+    ///
+    /// The content keeps its chunk size, chunking strategy and compression.
+    /// The old entry stays in place unless `remove_old` is set.
+    pub fn rehash(&self, old_hash: &str, new_algorithm: HashAlgorithm, remove_old: bool) -> Result<String> {
+        self.check_writable("rehash")?;
+        let old_hash = bare_hash(old_hash)?;
+        // Migrations rehash many files once each, so leave the cache to the working set
+        let data = self.retrieve_uncached(old_hash)?;
+        let metadata = self.get_metadata(old_hash)?
+            .ok_or_else(|| StorageError::HashNotFound(old_hash.to_string()))?;
+        
+        let compression = match metadata.compression.as_deref() {
+            Some(codec) => Compression::from_str(codec)?,
+            None => Compression::None,
+        };
+        let options = StoreOptions {
+            algorithm: new_algorithm,
+            chunk_size: metadata.chunk_size,
+            compression,
+            chunking: metadata.chunking,
+            // Whatever lifetime the old file had left carries over
+            ttl_secs: metadata.expires_at.map(|expires_at| expires_at.saturating_sub((self.clock)())),
+            content_type: metadata.content_type.clone(),
+            tags: metadata.tags.clone(),
+            attrs: metadata.attrs.clone(),
+            // A length chosen for the old algorithm need not suit the new one
+            output_len: None,
+            merkle: metadata.hash_scheme == FileHashScheme::Merkle,
+            durability: None,
+            auto_blake3_threshold: None,
+        };
+        let new_hash = self.store_with(&data, &options)?;
+        
+        if remove_old && bare_hash(&new_hash)? != old_hash {
+            self.delete(old_hash)?;
+        }
+        
+        Ok(new_hash)
+    }
+    
+    /// Take a pin on a stored file, returning the new pin count
+    ///
+    /// A file with a positive pin count cannot be deleted or collected. Each
+    /// logical dataset sharing a blob should hold its own pin and release it
+    /// with `unpin` when it no longer needs the data.
+    ///
+    /// The count is updated with a RocksDB merge rather than read and
+    /// rewritten, so concurrent pins and unpins of one file never lose an
+    /// update. The count returned is read just after, so it may include
+    /// other threads' changes.
+    ///
+    /// Pinning takes the write lock shared like any write; `delete` holds it
+    /// exclusively from its pin check to its write, so a pin never lands on
+    /// a file that is being deleted.
+    pub fn pin(&self, hash: &str) -> Result<u64> {
+        self.check_writable("pin")?;
+        let hash = bare_hash(hash)?;
+        
+        let _write_guard = self.write_guard()?;
+        
+        if !self.exists(hash)? {
+            return Err(StorageError::HashNotFound(hash.to_string()));
+        }
+        
+        self.db.add_to_counter(format!("refcount:{}", hash).as_bytes(), 1)?;
+        self.pin_count(hash)
+    }
+    
+    /// Release a pin taken with `pin`, returning the remaining pin count
+    ///
+    /// The release is merged into the count like a pin, never read and
+    /// rewritten. Releasing a file with no pins leaves the count at zero,
+    /// and counts released to zero are removed by the next `gc`.
+    pub fn unpin(&self, hash: &str) -> Result<u64> {
+        self.check_writable("unpin")?;
+        let hash = bare_hash(hash)?;
+        
+        let _write_guard = self.write_guard()?;
+        
+        self.db.add_to_counter(format!("refcount:{}", hash).as_bytes(), -1)?;
+        self.pin_count(hash)
+    }
+    
+    /// Number of pins currently held on a hash
+    pub fn pin_count(&self, hash: &str) -> Result<u64> {
+        let hash = bare_hash(hash)?;
+        
+        read_counter(&self.db, &format!("refcount:{}", hash))
+    }
+    
+    /// Point the alias `name` at a stored file, replacing any earlier target
+    ///
+    /// Aliases are mutable, human-readable names for immutable content, much
+    /// like git tags. They don't keep their file alive: a file deleted or
+    /// collected while aliased makes the alias resolve to a missing hash.
+    ///
+    /// Names must be non-empty and may not contain `/`, which record keys
+    /// keep for namespaces.
+    pub fn set_alias(&self, name: &str, hash: &str) -> Result<()> {
+        self.check_writable("set_alias")?;
+        let key = alias_key(name)?;
+        
+        let _write_guard = self.write_guard()?;
+        
+        if !self.exists(hash)? {
+            return Err(StorageError::HashNotFound(hash.to_string()));
+        }
+        
+        self.db.put(key.as_bytes(), hash.as_bytes())
+    }
+    
+    /// The hash the alias `name` points at, as it was given to `set_alias`
+    pub fn resolve_alias(&self, name: &str) -> Result<String> {
+        match self.db.get(alias_key(name)?.as_bytes())? {
+            Some(hash) => Ok(String::from_utf8_lossy(&hash).into_owned()),
+            None => Err(StorageError::AliasNotFound(name.to_string())),
+        }
+    }
+    
+    /// Retrieve the file the alias `name` points at
+    pub fn retrieve_by_alias(&self, name: &str) -> Result<Vec<u8>> {
+        self.retrieve(&self.resolve_alias(name)?)
+    }
+    
+    /// Remove an alias, returning whether it existed; the file is untouched
+    pub fn remove_alias(&self, name: &str) -> Result<bool> {
+        self.check_writable("remove_alias")?;
+        let key = alias_key(name)?;
+        
+        let _write_guard = self.write_guard()?;
+        
+        if self.db.get_pinned(key.as_bytes())?.is_none() {
+            return Ok(false);
+        }
+        
+        self.db.delete(key.as_bytes())?;
+        Ok(true)
+    }
+    
+    /// Every alias and the hash it points at, ordered by name
+    pub fn list_aliases(&self) -> Result<Vec<(String, String)>> {
+        self.db.scan_prefix(b"alias:")
+            .map(|item| {
+                let (key, value) = item?;
+                Ok((
+                    String::from_utf8_lossy(&key["alias:".len()..]).into_owned(),
+                    String::from_utf8_lossy(&value).into_owned(),
+                ))
+            })
+            .collect()
+    }
+    
+    /// List the hash of every stored file
+    pub fn list_hashes(&self) -> Result<Vec<String>> {
+        self.iter_hashes("").collect()
+    }
+    
+    /// Lazily iterate over stored file hashes starting with `prefix`
+    ///
+    /// Chunked files are found through their `meta:` records and simple files
+    /// through the values column family, so neither walks chunk bodies.
+    /// Nothing is buffered, so this is suitable for paging through large
+    /// stores.
+    pub fn iter_hashes<'a>(&'a self, prefix: &str) -> impl Iterator<Item = Result<String>> + 'a {
+        self.scan_hashes(prefix, &ScanCursor::default())
+            .map(|item| item.map(|(_, hash)| hash))
+    }
+    
+    /// `iter_hashes` resumed from `cursor`, pairing each hash with the cursor that follows it
+    fn scan_hashes<'a>(&'a self, prefix: &str, cursor: &ScanCursor) -> impl Iterator<Item = Result<(ScanCursor, String)>> + 'a {
+        let meta_prefix = format!("meta:{}", prefix);
+        let chunked = (!cursor.simple).then(|| {
+            self.db.scan_prefix_from(meta_prefix.as_bytes(), &cursor.resume(false, meta_prefix.as_bytes()))
+                .map(|item| item.map(|(key, _)| {
+                    let hash = String::from_utf8_lossy(&key["meta:".len()..]).into_owned();
+                    (ScanCursor::after(false, key), hash)
+                }))
+        }).into_iter().flatten();
+        
+        let simple = self.db.scan_prefix_from(prefix.as_bytes(), &cursor.resume(true, prefix.as_bytes()))
+            .filter_map(|item| match item {
+                Ok((key, _)) if key.contains(&b':') => None,
+                Ok((key, _)) => {
+                    let hash = String::from_utf8_lossy(&key).into_owned();
+                    Some(Ok((ScanCursor::after(true, key), hash)))
+                },
+                Err(e) => Some(Err(e)),
+            });
+        
+        chunked.chain(simple)
+    }
+    
+    /// Lazily iterate over the metadata of every live file
+    ///
+    /// Chunked files come from their `meta:` records and simple files from
+    /// their `info:` records, so no file contents are read. Simple files
+    /// stored before info records existed get minimal metadata: the stored
+    /// size, a zero timestamp and an algorithm guessed from the hash length.
+    pub fn iter_files<'a>(&'a self) -> impl Iterator<Item = Result<FileMetadata>> + 'a {
+        self.scan_files(&ScanCursor::default())
+            .map(|item| item.map(|(_, metadata)| metadata))
+    }
+    
+    /// `iter_files` resumed from `cursor`, pairing each file with the cursor that follows it
+    fn scan_files<'a>(&'a self, cursor: &ScanCursor) -> impl Iterator<Item = Result<(ScanCursor, FileMetadata)>> + 'a {
+        let chunked = (!cursor.simple).then(|| {
+            self.db.scan_prefix_from(b"meta:", &cursor.resume(false, b"meta:"))
+                .map(|item| {
+                    let (key, value) = item?;
+                    let metadata = metadata_format::decode(&value)
+                        .map_err(|e| StorageError::SerializationError(format!("{}: {}", String::from_utf8_lossy(&key), e)))?;
+                    Ok((ScanCursor::after(false, key), metadata))
+                })
+        }).into_iter().flatten();
+        
+        let simple = self.db.scan_values_from(&cursor.resume(true, b""))
+            .map(|key| {
+                let key = key?;
+                let hash = String::from_utf8_lossy(&key).into_owned();
+                let next = ScanCursor::after(true, key);
+                if let Some(info) = self.load_metadata(&format!("info:{}", hash))? {
+                    return Ok((next, info));
+                }
+                
+                let size = self.db.get_pinned(hash.as_bytes())?.map_or(0, |value| value.len());
+                let algorithm = HashAlgorithm::candidates_for_hash(&hash).first()
+                    .map(|algorithm| algorithm.as_str().to_string())
+                    .unwrap_or_default();
+                let mut info = simple_info(&hash, HashAlgorithm::default(), size, None);
+                info.algorithm = algorithm;
+                info.timestamp = 0;
+                Ok((next, info))
+            });
+        
+        chunked.chain(simple)
+            .filter(|item| item.as_ref().map_or(true, |(_, metadata)| !self.is_expired(metadata)))
+    }
+    
+    /// Hashes of every live file carrying `tag`
+    ///
+    /// Scans all metadata records, so this takes time proportional to the
+    /// number of stored files.
+    pub fn find_by_tag(&self, tag: &str) -> Result<Vec<String>> {
+        let mut hashes = Vec::new();
+        
+        for prefix in [b"meta:".as_slice(), b"info:".as_slice()] {
+            for item in self.db.scan_prefix(prefix) {
+                let (_, value) = item?;
+                let metadata: FileMetadata = metadata_format::decode(&value)
+                    .map_err(|e| StorageError::SerializationError(e.to_string()))?;
+                
+                if metadata.tags.iter().any(|t| t == tag) && !self.is_expired(&metadata) {
+                    hashes.push(metadata.hash);
+                }
+            }
+        }
+        
+        Ok(hashes)
+    }
+    
+    /// Check every stored file against its hash, reporting all problems found
+    ///
+    /// Each chunked file has every chunk read and checked against its chunk
+    /// hash, and its chunk list checked against the file hash. Simple files
+    /// with an info record are read and rehashed whole. Problems are collected
+    /// rather than returned as errors, so one bad file doesn't hide the rest.
+    /// Expired files are skipped, as are simple files written before info
+    /// records existed.
+    pub fn fsck(&self) -> Result<FsckReport> {
+        // Shared with writers, but keeps gc from removing chunks mid-scan
+        let _write_guard = self.write_guard()?;
+        let mut report = FsckReport::default();
+        
+        for item in self.db.scan_prefix(b"meta:") {
+            let (key, value) = item?;
+            let hash = String::from_utf8_lossy(&key["meta:".len()..]).into_owned();
+            match metadata_format::decode(&value) {
+                Ok(metadata) if self.is_expired(&metadata) => {},
+                Ok(metadata) => self.check_chunked(&hash, &metadata, &mut report),
+                Err(e) => report.add(&hash, FsckProblem::Unreadable(format!("metadata: {}", e))),
+            }
+        }
+        
+        for item in self.db.scan_prefix(b"info:") {
+            let (key, value) = item?;
+            let hash = String::from_utf8_lossy(&key["info:".len()..]).into_owned();
+            match metadata_format::decode(&value) {
+                Ok(info) if self.is_expired(&info) => {},
+                Ok(info) => self.check_simple(&hash, &info, &mut report),
+                Err(e) => report.add(&hash, FsckProblem::Unreadable(format!("info record: {}", e))),
+            }
+        }
+        
+        Ok(report)
+    }
+    
+    /// Make every completed write durable now
+    ///
+    /// Syncs the write-ahead log and flushes memtables to disk, so everything
+    /// stored so far survives power loss. Cheaper than `sync_writes` when
+    /// durability only matters at a few points, e.g. after a critical store.
+    pub fn flush(&self) -> Result<()> {
+        self.check_writable("flush")?;
+        self.db.flush()
+    }
+    
+    /// Compact the whole database, reclaiming space held by deleted records
+    ///
+    /// Deletes only write tombstones, so disk usage doesn't drop after a large
+    /// `delete` or `gc` pass until compaction catches up. This forces it to
+    /// happen now, and blocks until it has finished.
+    pub fn compact(&self) -> Result<()> {
+        self.check_writable("compact")?;
+        self.db.compact();
+        Ok(())
+    }
+    
+    /// Bring a secondary up to date with its primary, see `open_as_secondary`
+    ///
+    /// Also empties this engine's cache, which may still hold files the
+    /// primary has deleted since. Other engines on the secondary, from
+    /// `namespace`, keep their own caches, so catch each of them up too.
+    /// Fails on an engine that isn't a secondary.
+    ///
+    /// With an existence filter, a catch-up that finds new writes rescans
+    /// the keys of every namespace to refill the filters, which costs about
+    /// as much as opening the store. Catch up at an interval rather than
+    /// before every read.
+    pub fn catch_up_with_primary(&self) -> Result<()> {
+        self.db.catch_up_with_primary()?;
+        self.lock_cache().clear();
+        Ok(())
+    }
+    
+    /// The path and settings this engine was opened with, for diagnostics
+    pub fn info(&self) -> EngineInfo {
+        EngineInfo {
+            path: self.db.path().to_path_buf(),
+            namespace: self.db.namespace().map(str::to_string),
+            read_only: self.read_only,
+            cache_enabled: self.cache_enabled,
+            cache_capacity: self.lock_cache().capacity(),
+            compression: self.compression,
+            default_algorithm: HashAlgorithm::Blake3,
+            default_chunk_size: self.largest_default_chunk_size(),
+            auto_chunk_threshold: self.auto_chunk_threshold,
+            chunk_threshold: self.chunk_threshold,
+            max_value_bytes: self.max_value_bytes,
+            encrypted: self.cipher.is_some(),
+            keyed_hashes: self.hash_key.is_some(),
+            prefixed_hashes: self.prefixed_hashes,
+            verify_existing: self.verify_existing,
+            sync_writes: self.db.sync_writes(),
+        }
+    }
+    
+    /// Count the files and chunks in the store
+    ///
+    /// Walks every metadata record, chunk and simple value, so this takes time
+    /// proportional to the size of the store. Simple files written before info
+    /// records existed are counted at their stored (possibly compressed) size.
+    pub fn stats(&self) -> Result<StorageStats> {
+        let mut stats = StorageStats::default();
+        
+        for item in self.db.scan_prefix(b"meta:") {
+            let (key, value) = item?;
+            let metadata: FileMetadata = metadata_format::decode(&value)
+                .map_err(|e| StorageError::SerializationError(format!("{}: {}", String::from_utf8_lossy(&key), e)))?;
+            stats.chunked_files += 1;
+            stats.logical_bytes += metadata.size as u64;
+        }
+        
+        for item in self.db.scan_family(records::CF_CHUNKS) {
+            item?;
+            stats.total_chunks += 1;
+        }
+        
+        for item in self.db.scan_family(records::CF_VALUES) {
+            let (key, value) = item?;
+            let hash = String::from_utf8_lossy(&key);
+            let size = match self.load_metadata(&format!("info:{}", hash))? {
+                Some(info) => info.size,
+                None => value.len(),
+            };
+            stats.simple_files += 1;
+            stats.logical_bytes += size as u64;
+        }
+        
+        stats.estimated_live_bytes = self.db.property_int_value("rocksdb.estimate-live-data-size")?;
+        
+        Ok(stats)
+    }
+    
+    /// Estimate how much deduplicating chunk bodies would save
+    ///
+    /// Reads and hashes every chunk of every chunked file, so it takes time
+    /// proportional to the store's size, but writes nothing. Per-file chunks
+    /// written before deduplication and shared chunks stored under different
+    /// algorithms are both compared by content.
+    pub fn dedup_report(&self) -> Result<DedupReport> {
+        let mut report = DedupReport::default();
+        let mut seen_keys: HashSet<String> = HashSet::new();
+        let mut seen_content: HashSet<[u8; 32]> = HashSet::new();
+        
+        for item in self.db.scan_prefix(b"meta:") {
+            let (key, value) = item?;
+            let metadata: FileMetadata = metadata_format::decode(&value)
+                .map_err(|e| StorageError::SerializationError(format!("{}: {}", String::from_utf8_lossy(&key), e)))?;
+            
+            for i in 0..metadata.chunks.len() {
+                // A shared chunk is one record however many files use it
+                if !seen_keys.insert(metadata.chunk_key(i)) {
+                    continue;
+                }
+                let Some(chunk) = self.read_chunk(&metadata, i)? else {
+                    continue;
+                };
+                
+                report.chunks_scanned += 1;
+                report.current_bytes += chunk.len() as u64;
+                if seen_content.insert(*blake3::hash(&chunk).as_bytes()) {
+                    report.unique_bytes += chunk.len() as u64;
+                } else {
+                    report.duplicate_chunks += 1;
+                }
+            }
+        }
+        
+        Ok(report)
+    }
+    
+    /// Group simple files that hold identical content under different hashes
+    ///
+    /// The same bytes stored under two algorithms (or output lengths) get two
+    /// independent addresses and are stored twice. Each returned group lists
+    /// the hashes of one such content, sorted, and only groups of two or more
+    /// are returned. Reads every simple file, so it takes time proportional
+    /// to the store's size, but writes nothing. Chunked files share chunks
+    /// by content already and are not included.
+    pub fn find_cross_algorithm_duplicates(&self) -> Result<Vec<Vec<String>>> {
+        let mut by_content: HashMap<(usize, [u8; 32]), Vec<String>> = HashMap::new();
+        
+        for item in self.db.scan_family(records::CF_VALUES) {
+            let (key, value) = item?;
+            let hash = String::from_utf8_lossy(&key).into_owned();
+            let info = self.load_metadata(&format!("info:{}", hash))?;
+            let Some((data, _)) = self.decode_simple(value.into_vec(), info)? else {
+                continue;
+            };
+            
+            by_content.entry((data.len(), *blake3::hash(&data).as_bytes()))
+                .or_default()
+                .push(hash);
+        }
+        
+        let mut groups: Vec<Vec<String>> = by_content.into_values()
+            .filter(|hashes| hashes.len() > 1)
+            .collect();
+        for group in &mut groups {
+            group.sort();
+        }
+        groups.sort();
+        Ok(groups)
+    }
+    
+    /// Write the whole store to a single archive file, returning its size in bytes
+    ///
+    /// The archive is read from a snapshot, so it is a consistent point-in-time
+    /// copy even while other threads keep writing. Values are copied as
+    /// stored, so an archive of an encrypted store needs the same key to read.
+    pub fn export_archive<P: AsRef<Path>>(&self, path: P) -> Result<u64> {
+        archive::write_archive(&self.db, BufWriter::new(File::create(path)?))
+    }
+    
+    /// Restore an archive written by `export_archive`, returning the number of records restored
+    ///
+    /// The store must be empty, so records are never mixed with an
+    /// existing store's reference counts.
+    pub fn import_archive<P: AsRef<Path>>(&self, path: P) -> Result<u64> {
+        self.check_writable("import_archive")?;
+        let _write_guard = self.exclusive_write_guard()?;
+        
+        if !self.db.is_empty()? {
+            return Err(StorageError::SerializationError("archives can only be restored into an empty store".to_string()));
+        }
+        
+        let restored = archive::read_archive(&self.db, BufReader::new(File::open(path)?))?;
+        self.lock_cache().clear();
+        Ok(restored)
+    }
+    
+    /// Delete every file, chunk and pin in this engine's namespace
+    ///
+    /// Records are removed with range deletes rather than one by one, which
+    /// suits resetting test fixtures and caches. Other namespaces in the same
+    /// database are left alone. Disk space comes back as compaction runs.
+    pub fn clear(&self) -> Result<()> {
+        self.check_writable("clear")?;
+        let _write_guard = self.exclusive_write_guard()?;
+        
+        self.db.clear()?;
+        self.lock_cache().clear();
+        Ok(())
+    }
+    
+    /// Rewrite metadata records left in JSON by older versions in the current layout
+    ///
+    /// JSON records stay readable without this, but only records in the
+    /// current layout are byte-for-byte reproducible. Returns the number of
+    /// records rewritten; records already current are left alone.
+    pub fn migrate_metadata(&self) -> Result<usize> {
+        self.check_writable("migrate_metadata")?;
+        // Exclusive, so no store rewrites a record between reading and replacing it
+        let _write_guard = self.exclusive_write_guard()?;
+        
+        let mut rewritten = 0;
+        let mut batch = self.db.batch();
+        for prefix in [b"meta:".as_slice(), b"info:".as_slice()] {
+            for item in self.db.scan_prefix(prefix) {
+                let (key, value) = item?;
+                if metadata_format::is_current(&value) {
+                    continue;
+                }
+                
+                let metadata = metadata_format::decode(&value)
+                    .map_err(|e| StorageError::SerializationError(format!("{}: {}", String::from_utf8_lossy(&key), e)))?;
+                batch.put(&key, serialize_metadata(&metadata)?);
+                rewritten += 1;
+                
+                if rewritten % METADATA_MIGRATION_BATCH == 0 {
+                    self.db.write(std::mem::replace(&mut batch, self.db.batch()))?;
+                }
+            }
+        }
+        self.db.write(batch)?;
+        
+        Ok(rewritten)
+    }
+    
+    /// Recreate the lost metadata record of a file whose indexed chunks survive
+    ///
+    /// Only files written before deduplication keep their chunks under
+    /// `chunk:{file_hash}:{index}`, which gives their order back; shared
+    /// content-addressed chunks record neither file nor position, so files
+    /// stored since can't be rebuilt this way. Run this before `gc`, which
+    /// removes chunks no metadata references.
+    ///
+    /// The chunks are read in index order and hashed with `algorithm`, and
+    /// the file hash recomputed for each hash scheme and compression the file
+    /// could have been written with. The record is written only if one of
+    /// them reproduces `file_hash`; otherwise nothing changes and
+    /// `CorruptData` reports the hash the chunks give. Tags, attrs and any
+    /// lifetime the file had are lost with the old record. A file whose
+    /// record is still there is left alone, so its tags, attrs and lifetime
+    /// are never lost to a needless rebuild.
+    pub fn rebuild_metadata(&self, file_hash: &str, algorithm: HashAlgorithm, chunk_size: usize) -> Result<()> {
+        self.check_writable("rebuild_metadata")?;
+        let file_hash = bare_hash(file_hash)?;
+        
+        let _write_guard = self.write_guard()?;
+        
+        if self.db.get_pinned(format!("meta:{}", file_hash).as_bytes())?.is_some() {
+            return Ok(());
+        }
+        
+        // Keys sort as strings, so `:10` comes before `:2`
+        let prefix = format!("chunk:{}:", file_hash);
+        let mut indexed = Vec::new();
+        for item in self.db.scan_prefix(prefix.as_bytes()) {
+            let (key, value) = item?;
+            let index = std::str::from_utf8(&key[prefix.len()..]).ok()
+                .and_then(|index| index.parse::<usize>().ok())
+                .ok_or_else(|| StorageError::ChunkingError(format!("unexpected chunk key {}", String::from_utf8_lossy(&key))))?;
+            indexed.push((index, self.unseal(value.into_vec())?));
+        }
+        if indexed.is_empty() {
+            return Err(StorageError::HashNotFound(file_hash.to_string()));
+        }
+        indexed.sort_by_key(|(index, _)| *index);
+        if let Some(missing) = indexed.iter().enumerate().position(|(expected, (index, _))| expected != *index) {
+            return Err(StorageError::ChunkingError(format!("chunk {} of {} is missing", missing, file_hash)));
+        }
+        
+        let mut actual = None;
+        for codec in [None, Some(COMPRESSION_ZSTD)] {
+            // Compression was per file, so every chunk must decode the same way
+            let Ok(chunks) = indexed.iter()
+                .map(|(_, stored)| decompress(stored.clone(), codec))
+                .collect::<Result<Vec<_>>>() else {
+                continue;
+            };
+            let chunk_hashes = chunks.iter()
+                .map(|chunk| self.hash(chunk, algorithm))
+                .collect::<Result<Vec<_>>>()?;
+            
+            for scheme in [FileHashScheme::LengthPrefixed, FileHashScheme::Joined, FileHashScheme::Merkle] {
+                let hash = combine_chunk_hashes(&chunk_hashes, chunk_size, scheme, algorithm, self.hash_key.as_ref())?;
+                if hash != file_hash {
+                    actual.get_or_insert(hash);
+                    continue;
+                }
+                
+                // Chunks of uneven length came from content-defined chunking
+                let fixed = chunks[..chunks.len() - 1].iter().all(|chunk| chunk.len() == chunk_size);
+                let metadata = FileMetadata {
+                    hash,
+                    algorithm: algorithm.as_str().to_string(),
+                    size: chunks.iter().map(Vec::len).sum(),
+                    chunk_size,
+                    chunks: chunk_hashes,
+                    timestamp: current_timestamp(),
+                    compression: codec.map(str::to_string),
+                    chunking: if fixed { ChunkingStrategy::Fixed } else { ChunkingStrategy::content_defined(chunk_size) },
+                    layout: ChunkLayout::Indexed,
+                    hash_scheme: scheme,
+                    expires_at: None,
+                    content_type: None,
+                    tags: Vec::new(),
+                    attrs: HashMap::new(),
+                    output_len: None,
+                };
+                return self.db.put(format!("meta:{}", file_hash).as_bytes(), serialize_metadata(&metadata)?);
+            }
+        }
+        
+        Err(StorageError::CorruptData {
+            expected: file_hash.to_string(),
+            actual: actual.unwrap_or_default(),
+            chunk: None,
+        })
+    }
+    
+    /// Remove chunk records that no metadata references
+    ///
+    /// Orphans are left behind when a process dies between writing chunks and
+    /// writing their metadata. Reference counts of shared chunks are rebuilt
+    /// from the metadata as well, since the same crash leaves them too high,
+    /// and pin counts released down to zero are dropped. Expired files are
+    /// removed along with any chunks only they used, unless they are pinned.
+    /// Reads proceed normally while this runs; writes wait for it to finish.
+    pub fn gc(&self) -> Result<GcReport> {
+        self.check_writable("gc")?;
+        let _write_guard = self.exclusive_write_guard()?;
+        let mut report = GcReport::default();
+        let mut batch = self.db.batch();
+        
+        // Build the set of live chunk keys and the references each shared chunk should hold
+        let mut live_keys: HashSet<Vec<u8>> = HashSet::new();
+        let mut references: HashMap<String, u64> = HashMap::new();
+        
+        for item in self.db.scan_prefix(b"meta:") {
+            let (key, value) = item?;
+            let metadata: FileMetadata = metadata_format::decode(&value)
+                .map_err(|e| StorageError::SerializationError(e.to_string()))?;
+            
+            // Chunks of an expired file count as unreferenced from here on
+            if self.is_expired(&metadata) && self.pin_count(&metadata.hash)? == 0 {
+                batch.delete(&key);
+                report.files_expired += 1;
+                continue;
+            }
+            
+            for (i, chunk_hash) in metadata.chunks.iter().enumerate() {
+                live_keys.insert(metadata.chunk_key(i).into_bytes());
+                if metadata.layout == ChunkLayout::ContentAddressed {
+                    *references.entry(chunk_hash.clone()).or_insert(0) += 1;
+                }
+            }
+        }
+        
+        for item in self.db.scan_prefix(b"info:") {
+            let (key, value) = item?;
+            let info: FileMetadata = metadata_format::decode(&value)
+                .map_err(|e| StorageError::SerializationError(e.to_string()))?;
+            
+            if self.is_expired(&info) && self.pin_count(&info.hash)? == 0 {
+                batch.delete(info.hash.as_bytes());
+                batch.delete(&key);
+                self.cache_remove(&info.hash);
+                report.files_expired += 1;
+            }
+        }
+        
+        for item in self.db.scan_prefix(b"chunk:") {
+            let (key, value) = item?;
+            report.chunks_scanned += 1;
+            
+            if !live_keys.contains(key.as_ref()) {
+                batch.delete(&key);
+                report.chunks_removed += 1;
+                report.bytes_reclaimed += value.len() as u64;
+            }
+        }
+        
+        for item in self.db.scan_prefix(b"chunkref:") {
+            let (key, _) = item?;
+            let chunk_hash = String::from_utf8_lossy(&key["chunkref:".len()..]).into_owned();
+            let expected = references.remove(&chunk_hash).unwrap_or(0);
+            
+            if expected == 0 {
+                batch.delete(&key);
+                report.refcounts_repaired += 1;
+            } else if self.chunk_refcount(&chunk_hash)? != expected {
+                batch.put(&key, expected.to_le_bytes());
+                report.refcounts_repaired += 1;
+            }
+        }
+        
+        // Live chunks whose reference count record went missing
+        for (chunk_hash, expected) in references {
+            batch.put(format!("chunkref:{}", chunk_hash).as_bytes(), expected.to_le_bytes());
+            report.refcounts_repaired += 1;
+        }
+        
+        // Pin counts every pin has been released from
+        for item in self.db.scan_prefix(b"refcount:") {
+            let (key, _) = item?;
+            let hash = String::from_utf8_lossy(&key["refcount:".len()..]).into_owned();
+            if self.pin_count(&hash)? == 0 {
+                batch.delete(&key);
+            }
+        }
+        
+        self.db.write(batch)?;
+        
+        Ok(report)
+    }
+    
+    /// Read and decode chunk `index` of a chunked file
+    fn read_chunk(&self, metadata: &FileMetadata, index: usize) -> Result<Option<Vec<u8>>> {
+        let stored = match self.db.get(metadata.chunk_key(index).as_bytes())? {
+            Some(stored) => self.unseal(stored)?,
+            None => return Ok(None),
+        };
+        
+        match metadata.layout {
+            ChunkLayout::Indexed => decompress(stored, metadata.compression.as_deref()).map(Some),
+            ChunkLayout::ContentAddressed => decode_chunk(stored).map(Some),
+        }
+    }
+    
+    /// Check a chunked file's chunks and file hash for `fsck`
+    fn check_chunked(&self, hash: &str, metadata: &FileMetadata, report: &mut FsckReport) {
+        report.files_checked += 1;
+        
+        let algorithm = match HashAlgorithm::from_str(&metadata.algorithm) {
+            Ok(algorithm) => algorithm,
+            Err(e) => return report.add(hash, FsckProblem::Unreadable(e.to_string())),
+        };
+        
+        for (index, expected) in metadata.chunks.iter().enumerate() {
+            let chunk = match self.read_chunk(metadata, index) {
+                Ok(Some(chunk)) => chunk,
+                Ok(None) => {
+                    report.add(hash, FsckProblem::MissingChunk { index });
+                    continue;
+                },
+                Err(e) => {
+                    report.add(hash, FsckProblem::Unreadable(format!("chunk {}: {}", index, e)));
+                    continue;
+                },
+            };
+            report.chunks_checked += 1;
+            
+            match self.hash(&chunk, algorithm) {
+                Ok(actual) if &actual != expected => report.add(hash, FsckProblem::ChunkHashMismatch { index, actual }),
+                Ok(_) => {},
+                Err(e) => report.add(hash, FsckProblem::Unreadable(format!("chunk {}: {}", index, e))),
+            }
+        }
+        
+        match combine_chunk_hashes(&metadata.chunks, metadata.chunk_size, metadata.hash_scheme, algorithm, self.hash_key.as_ref()) {
+            Ok(actual) if actual != hash => report.add(hash, FsckProblem::FileHashMismatch { actual }),
+            Ok(_) => {},
+            Err(e) => report.add(hash, FsckProblem::Unreadable(e.to_string())),
+        }
+    }
+    
+    /// Check a simple file's value against its hash for `fsck`
+    fn check_simple(&self, hash: &str, info: &FileMetadata, report: &mut FsckReport) {
+        report.files_checked += 1;
+        
+        let data = match self.read_simple(hash) {
+            Ok(Some(data)) => data,
+            Ok(None) => return report.add(hash, FsckProblem::MissingValue),
+            Err(e) => return report.add(hash, FsckProblem::Unreadable(e.to_string())),
+        };
+        
+        let actual = self.hash_as_recorded(&data, info);
+        match actual {
+            Ok(actual) if actual != hash => report.add(hash, FsckProblem::FileHashMismatch { actual }),
+            Ok(_) => {},
+            Err(e) => report.add(hash, FsckProblem::Unreadable(e.to_string())),
+        }
+    }
+    
+    /// Read and concatenate every chunk of a chunked file
+    ///
+    /// With `verify` set, each chunk is checked against its recorded hash.
+    /// A `concurrency` above 1 splits the chunks between that many scoped
+    /// threads; results are put back in order before joining. Either way a
+    /// failure is reported for the lowest failing chunk.
+    fn read_chunks(&self, metadata: &FileMetadata, verify: Option<HashAlgorithm>, concurrency: usize) -> Result<Vec<u8>> {
+        let read = |i: usize| -> Result<Vec<u8>> {
+            let chunk = self.read_chunk(metadata, i)?
+                .ok_or_else(|| StorageError::ChunkingError(format!("Chunk {} not found", i)))?;
+            
+            if let Some(algorithm) = verify {
+                let actual = self.hash(&chunk, algorithm)?;
+                if actual != metadata.chunks[i] {
+                    return Err(StorageError::CorruptData {
+                        expected: metadata.chunks[i].clone(),
+                        actual,
+                        chunk: Some(i),
+                    });
+                }
+            }
+            Ok(chunk)
+        };
+        
+        metadata.check_chunk_list()?;
+        let count = metadata.chunks.len();
+        let workers = concurrency.clamp(1, count.max(1));
+        let mut data = Vec::with_capacity(metadata.size.min(MAX_REASSEMBLY_RESERVE));
+        
+        if workers == 1 {
+            for i in 0..count {
+                data.extend_from_slice(&read(i)?);
+                // Chunks overrunning the recorded size can't add up to it later
+                if data.len() > metadata.size {
+                    break;
+                }
+            }
+            metadata.check_reassembled(data.len())?;
+            return Ok(data);
+        }
+        
+        // Worker w reads chunks w, w + workers, w + 2 * workers, ... and stops
+        // at the first chunk past the lowest failure so far, so a bad file isn't
+        // read to the end. Every chunk below the final lowest failure is still
+        // read, so the in-order pass below reaches that failure first.
+        let read = &read;
+        let first_failure = &AtomicUsize::new(usize::MAX);
+        let mut slots: Vec<Option<Result<Vec<u8>>>> = (0..count).map(|_| None).collect();
+        std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..workers)
+                .map(|w| scope.spawn(move || {
+                    (w..count).step_by(workers)
+                        .map_while(|i| {
+                            if i > first_failure.load(Ordering::Relaxed) {
+                                return None;
+                            }
+                            let result = read(i);
+                            if result.is_err() {
+                                first_failure.fetch_min(i, Ordering::Relaxed);
+                            }
+                            Some((i, result))
+                        })
+                        .collect::<Vec<_>>()
+                }))
+                .collect();
+            
+            for handle in handles {
+                let results = handle.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic));
+                for (i, result) in results {
+                    slots[i] = Some(result);
+                }
+            }
+        });
+        
+        for (i, slot) in slots.into_iter().enumerate() {
+            // Only chunks past a failure go unread, and that failure returns first
+            let chunk = match slot {
+                Some(result) => result?,
+                None => return Err(StorageError::ChunkingError(format!("Chunk {} not read", i))),
+            };
+            data.extend_from_slice(&chunk);
+        }
+        metadata.check_reassembled(data.len())?;
+        Ok(data)
+    }
+    
+    /// Read `offset..end` of a file cut into fixed-size chunks
+    ///
+    /// Returns `None` if a chunk turns out not to have the recorded size, as
+    /// with chunk lists committed from uneven uploads, so the caller can fall
+    /// back to walking the chunks.
+    fn read_fixed_range(&self, metadata: &FileMetadata, offset: usize, end: usize) -> Result<Option<Vec<u8>>> {
+        let chunk_size = metadata.chunk_size;
+        let mut data = Vec::with_capacity(end - offset);
+        
+        for i in offset / chunk_size..=(end - 1) / chunk_size {
+            let chunk = self.read_chunk(metadata, i)?
+                .ok_or_else(|| StorageError::ChunkingError(format!("Chunk {} not found", i)))?;
+            let is_last = i + 1 == metadata.chunks.len();
+            if chunk.len() != chunk_size && !(is_last && chunk.len() < chunk_size) {
+                return Ok(None);
+            }
+            
+            let chunk_start = i * chunk_size;
+            let from = offset.saturating_sub(chunk_start);
+            let to = (end - chunk_start).min(chunk.len());
+            if from > to {
+                return Ok(None);
+            }
+            data.extend_from_slice(&chunk[from..to]);
+        }
+        
+        if data.len() != end - offset {
+            return Ok(None);
+        }
+        Ok(Some(data))
+    }
+    
+    /// Number of file references held on a content-addressed chunk
+    fn chunk_refcount(&self, chunk_hash: &str) -> Result<u64> {
+        read_counter(&self.db, &format!("chunkref:{}", chunk_hash))
+    }
+    
+    /// Read and deserialize a metadata record
+    fn load_metadata(&self, key: &str) -> Result<Option<FileMetadata>> {
+        match self.db.get(key.as_bytes())? {
+            Some(bytes) => metadata_format::decode(&bytes)
+                .map(Some)
+                .map_err(|e| StorageError::SerializationError(e.to_string())),
+            None => Ok(None),
+        }
+    }
+    
+    /// Read, decrypt and decompress a simple file's value
+    fn read_simple(&self, hash: &str) -> Result<Option<Vec<u8>>> {
+        Ok(self.read_simple_with_info(hash)?.map(|(data, _)| data))
+    }
+    
+    /// Read a simple file's value along with its info record, if it has one
+    ///
+    /// Expired files read as missing.
+    fn read_simple_with_info(&self, hash: &str) -> Result<Option<(Vec<u8>, Option<FileMetadata>)>> {
+        let stored = match self.db.get(hash.as_bytes())? {
+            Some(stored) => stored,
+            None => return Ok(None),
+        };
+        
+        let info = self.load_metadata(&format!("info:{}", hash))?;
+        self.decode_simple(stored, info)
+    }
+    
+    /// Decrypt and decompress a simple file's stored value, or `None` if it has expired
+    fn decode_simple(&self, stored: Vec<u8>, info: Option<FileMetadata>) -> Result<Option<(Vec<u8>, Option<FileMetadata>)>> {
+        match info {
+            Some(info) if self.is_expired(&info) => Ok(None),
+            Some(info) => Ok(Some((decompress(self.unseal(stored)?, info.compression.as_deref())?, Some(info)))),
+            None => Ok(Some((self.unseal(stored)?, None))),
+        }
+    }
+    
+    /// A chunked file's metadata, unless it has expired
+    fn live_metadata(&self, hash: &str) -> Result<Option<FileMetadata>> {
+        Ok(self.load_metadata(&format!("meta:{}", hash))?
+            .filter(|metadata| !self.is_expired(metadata)))
+    }
+    
+    /// Carry a stored record's attributes over to a repeated store of the same content
+    ///
+    /// The longer lifetime wins, tags accumulate, new attrs are merged over
+    /// the old ones and a new content type replaces the old one. Attributes
+    /// of an expired record are dropped.
+    fn inherit_attributes(&self, metadata: &mut FileMetadata, existing: &FileMetadata) {
+        if self.is_expired(existing) {
+            return;
+        }
+        
+        metadata.expires_at = longer_lifetime(existing.expires_at, metadata.expires_at);
+        if metadata.content_type.is_none() {
+            metadata.content_type = existing.content_type.clone();
+        }
+        
+        let mut tags = existing.tags.clone();
+        for tag in metadata.tags.drain(..) {
+            if !tags.contains(&tag) {
+                tags.push(tag);
+            }
+        }
+        metadata.tags = tags;
+        
+        let mut attrs = existing.attrs.clone();
+        attrs.extend(metadata.attrs.drain());
+        metadata.attrs = attrs;
+    }
+    
+    fn is_expired(&self, metadata: &FileMetadata) -> bool {
+        metadata.expires_at.is_some_and(|expires_at| expires_at <= (self.clock)())
+    }
+    
+    /// Commit a chunked file's chunks, references and metadata
+    ///
+    /// The caller must hold the write lock.
+    fn store_chunked(&self, mut chunked_file: ChunkedFile, compression: Compression, durability: Option<Durability>) -> Result<(String, StoreOutcome)> {
+        chunked_file.metadata.compression = compression.codec().map(str::to_string);
+        
+        // Identical content is already stored and already holds its chunk
+        // references; only its lifetime, content type, tags and attrs may change
+        let metadata_key = format!("meta:{}", chunked_file.metadata.hash);
+        if let Some(existing) = self.load_metadata(&metadata_key)? {
+            let mut metadata = chunked_file.metadata;
+            self.inherit_attributes(&mut metadata, &existing);
+            
+            if metadata.expires_at != existing.expires_at
+                || metadata.content_type != existing.content_type
+                || metadata.tags != existing.tags
+                || metadata.attrs != existing.attrs
+            {
+                let updated = FileMetadata {
+                    expires_at: metadata.expires_at,
+                    content_type: metadata.content_type,
+                    tags: metadata.tags,
+                    attrs: metadata.attrs,
+                    ..existing
+                };
+                let mut batch = self.db.batch();
+                batch.put(metadata_key.as_bytes(), serialize_metadata(&updated)?);
+                self.db.write_with(batch, durability)?;
+            }
+            return Ok((metadata.hash, StoreOutcome::AlreadyPresent));
+        }
+        
+        // Count how often each chunk occurs in this file
+        let mut references: HashMap<&str, u64> = HashMap::new();
+        for chunk_hash in &chunked_file.metadata.chunks {
+            *references.entry(chunk_hash.as_str()).or_insert(0) += 1;
+        }
+        
+        // References and metadata are committed in one batch so a failure
+        // part way through leaves no trace of the file. With the `parallel`
+        // feature new bodies are written ahead of it, and a failure can
+        // leave some behind unreferenced for `gc` to remove.
+        let mut batch = self.db.batch();
+        
+        // Store each distinct chunk body once and take references on it
+        let mut new_chunks = Vec::new();
+        for (chunk_hash, chunk) in chunked_file.metadata.chunks.iter().zip(&chunked_file.chunks) {
+            let Some(count) = references.remove(chunk_hash.as_str()) else {
+                continue;
+            };
+            
+            // Concurrent stores may both write a new body; it is the same bytes
+            if self.chunk_refcount(chunk_hash)? == 0 {
+                new_chunks.push((chunk_hash.as_str(), chunk.as_slice()));
+            }
+            batch.add_to_counter(format!("chunkref:{}", chunk_hash).as_bytes(), count as i64);
+        }
+        #[cfg(not(feature = "parallel"))]
+        self.stage_new_chunks(&new_chunks, compression, &mut batch)?;
+        #[cfg(feature = "parallel")]
+        self.write_new_chunks(&new_chunks, compression)?;
+        
+        batch.put(metadata_key.as_bytes(), serialize_metadata(&chunked_file.metadata)?);
+        self.db.write_with(batch, durability)?;
+        
+        Ok((chunked_file.metadata.hash, StoreOutcome::Written))
+    }
+    
+    /// Add the bodies of chunks not stored yet to the file's batch
+    #[cfg(not(feature = "parallel"))]
+    fn stage_new_chunks(&self, chunks: &[(&str, &[u8])], compression: Compression, batch: &mut RecordBatch<'_>) -> Result<()> {
+        for (chunk_hash, chunk) in chunks {
+            batch.put(format!("chunk:{}", chunk_hash).as_bytes(), self.seal(encode_chunk(chunk, compression)?.into())?);
+        }
+        Ok(())
+    }
+    
+    /// Compress, encrypt and write the bodies of chunks not stored yet across the rayon pool
+    ///
+    /// Each body is its own write, running alongside the others. They must
+    /// all land before the batch with the file's references and metadata is
+    /// written, so a body is never referenced before it is stored. A store
+    /// failing in between leaves bodies nothing references; `gc` removes them.
+    #[cfg(feature = "parallel")]
+    fn write_new_chunks(&self, chunks: &[(&str, &[u8])], compression: Compression) -> Result<()> {
+        chunks.par_iter().try_for_each(|(chunk_hash, chunk)| {
+            self.db.put(format!("chunk:{}", chunk_hash).as_bytes(), self.seal(encode_chunk(chunk, compression)?.into())?)
+        })
+    }
+    
+    /// Add a simple file's value and info record to `batch`, returning the info it ends up with
+    /// and whether the content is new
+    ///
+    /// Content that is already stored keeps its value and info record, only
+    /// merging in new attributes, so storing it again writes nothing unless
+    /// those change. With `verify_existing` the stored bytes are first checked
+    /// against `data`.
+    fn stage_simple(&self, batch: &mut RecordBatch<'_>, data: &[u8], mut info: FileMetadata, compression: Compression) -> Result<(FileMetadata, StoreOutcome)> {
+        let info_key = format!("info:{}", info.hash);
+        
+        if let Some(existing) = self.load_metadata(&info_key)?.filter(|existing| !self.is_expired(existing)) {
+            if let Some(stored) = self.db.get(info.hash.as_bytes())? {
+                if self.verify_existing {
+                    self.check_existing(data, stored, &existing)?;
+                }
+                
+                self.inherit_attributes(&mut info, &existing);
+                if info.expires_at == existing.expires_at
+                    && info.content_type == existing.content_type
+                    && info.tags == existing.tags
+                    && info.attrs == existing.attrs
+                {
+                    return Ok((existing, StoreOutcome::AlreadyPresent));
+                }
+                
+                let updated = FileMetadata {
+                    expires_at: info.expires_at,
+                    content_type: info.content_type,
+                    tags: info.tags,
+                    attrs: info.attrs,
+                    ..existing
+                };
+                batch.put(info_key.as_bytes(), serialize_metadata(&updated)?);
+                return Ok((updated, StoreOutcome::AlreadyPresent));
+            }
+        }
+        
+        if data.len() > self.max_value_bytes {
+            return Err(StorageError::ValueTooLarge {
+                size: data.len(),
+                limit: self.max_value_bytes,
+            });
+        }
+        batch.put(info.hash.as_bytes(), self.seal(compression.compress(data)?)?);
+        batch.put(info_key.as_bytes(), serialize_metadata(&info)?);
+        Ok((info, StoreOutcome::Written))
+    }
+    
+    /// Check that the value stored under a hash really holds `data`
+    fn check_existing(&self, data: &[u8], stored: Vec<u8>, existing: &FileMetadata) -> Result<()> {
+        let stored = match self.decode_simple(stored, Some(existing.clone()))? {
+            Some((stored, _)) => stored,
+            None => return Ok(()),
+        };
+        if stored == data {
+            return Ok(());
+        }
+        
+        Err(StorageError::CorruptData {
+            expected: existing.hash.clone(),
+            actual: self.hash_as_recorded(&stored, existing)?,
+            chunk: None,
+        })
+    }
+    
+    /// Write a chunk body unless one is already stored under its hash
+    fn write_chunk_body(&self, chunk_hash: &str, data: &[u8]) -> Result<()> {
+        let chunk_key = format!("chunk:{}", chunk_hash);
+        if self.db.get_pinned(chunk_key.as_bytes())?.is_none() {
+            self.db.put(chunk_key.as_bytes(), self.seal(encode_chunk(data, self.compression)?.into())?)?;
+        }
+        Ok(())
+    }
+    
+    /// Whether a file of `len` bytes is split into chunks of `chunk_size`
+    ///
+    /// A file exactly at the threshold is stored whole.
+    fn should_chunk(&self, len: u64, chunk_size: usize) -> bool {
+        chunk_size > 0 && len > self.whole_limit(chunk_size) as u64
+    }
+    
+    /// Longest file stored whole when chunking at `chunk_size` is allowed
+    ///
+    /// The chunk threshold, but never past `max_value_bytes`.
+    fn whole_limit(&self, chunk_size: usize) -> usize {
+        self.chunk_threshold.unwrap_or(chunk_size).max(chunk_size).min(self.max_value_bytes)
+    }
+    
+    /// Take the write lock shared, as every write does
+    ///
+    /// Writes that timed out earlier are waited for first, so they land
+    /// before anything done under the lock from here on.
+    fn write_guard(&self) -> Result<RwLockReadGuard<'_, ()>> {
+        let guard = self.write_lock.read().unwrap_or_else(PoisonError::into_inner);
+        self.db.await_abandoned_writes()?;
+        Ok(guard)
+    }
+    
+    /// Take the write lock exclusively, as `gc` and other whole-store operations do
+    fn exclusive_write_guard(&self) -> Result<RwLockWriteGuard<'_, ()>> {
+        let guard = self.write_lock.write().unwrap_or_else(PoisonError::into_inner);
+        self.db.await_abandoned_writes()?;
+        Ok(guard)
+    }
+    
+    /// Lock the cache, recovering it if another thread panicked while holding it
+    ///
+    /// Cache entries are only ever inserted or removed whole, so a poisoned
+    /// cache is still consistent and one failed call shouldn't take every
+    /// later call down with it.
+    fn lock_cache(&self) -> MutexGuard<'_, FileCache> {
+        self.cache.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+    
+    /// Cache a file's contents, unless the cache is disabled
+    fn cache_insert(&self, hash: &str, data: &[u8]) {
+        if self.cache_enabled {
+            self.lock_cache().insert(hash.to_string(), data.to_vec());
+        }
+    }
+    
+    fn cache_remove(&self, hash: &str) {
+        if self.cache_enabled {
+            self.lock_cache().remove(hash);
+        }
+    }
+    
+    fn record_hit(&self, bytes: usize) {
+        self.metrics.hits.fetch_add(1, Ordering::Relaxed);
+        self.metrics.bytes_from_cache.fetch_add(bytes as u64, Ordering::Relaxed);
+        if let Some(observer) = &self.cache_observer {
+            observer.hit(bytes as u64);
+        }
+    }
+    
+    fn record_miss(&self, bytes: usize) {
+        self.metrics.misses.fetch_add(1, Ordering::Relaxed);
+        self.metrics.bytes_from_db.fetch_add(bytes as u64, Ordering::Relaxed);
+        if let Some(observer) = &self.cache_observer {
+            observer.miss(bytes as u64);
+        }
+    }
+    
+    /// Refuse `operation` on a read-only engine
+    fn check_writable(&self, operation: &str) -> Result<()> {
+        if self.read_only {
+            return Err(StorageError::ReadOnly(operation.to_string()));
+        }
+        Ok(())
+    }
+    
+    /// The identifier a store returns for `hash`
+    fn identifier(&self, hash: String, algorithm: HashAlgorithm) -> String {
+        if self.prefixed_hashes {
+            format!("{}:{}", algorithm.as_str(), hash)
+        } else {
+            hash
+        }
+    }
+    
+    /// Hash with the engine's key, if it has one
+    fn hash(&self, data: &[u8], algorithm: HashAlgorithm) -> Result<String> {
+        calculate_keyed_hash(data, algorithm, self.hash_key.as_ref())
+    }
+    
+    /// Hash with the engine's key and a digest length other than the default
+    fn hash_with_len(&self, data: &[u8], algorithm: HashAlgorithm, output_len: Option<usize>) -> Result<String> {
+        Ok(hex::encode(self.hash_bytes_with_len(data, algorithm, output_len)?))
+    }
+    
+    /// Raw digest of `hash_with_len`
+    fn hash_bytes_with_len(&self, data: &[u8], algorithm: HashAlgorithm, output_len: Option<usize>) -> Result<Vec<u8>> {
+        let mut hasher = match output_len {
+            Some(output_len) => Hasher::with_output_len(algorithm, self.hash_key.as_ref(), output_len)?,
+            None => Hasher::keyed(algorithm, self.hash_key.as_ref())?,
+        };
+        hasher.update(data);
+        Ok(hasher.finalize_bytes())
+    }
+    
+    /// Hash the way the file described by `info` was hashed
+    fn hash_as_recorded(&self, data: &[u8], info: &FileMetadata) -> Result<String> {
+        if let Some(hasher) = self.hashers.custom(&info.algorithm) {
+            return Ok(hasher.hash(data));
+        }
+        self.hash_with_len(data, HashAlgorithm::from_str(&info.algorithm)?, info.output_len)
+    }
+    
+    /// Encrypt a value for writing when encryption is enabled
+    fn seal<'a>(&self, value: Cow<'a, [u8]>) -> Result<Cow<'a, [u8]>> {
+        let cipher = match &self.cipher {
+            Some(cipher) => cipher,
+            None => return Ok(value),
+        };
+        
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = cipher.encrypt(&nonce, &*value)
+            .map_err(|e| StorageError::EncryptionError(e.to_string()))?;
+        
+        let mut sealed = Vec::with_capacity(NONCE_LEN + ciphertext.len());
+        sealed.extend_from_slice(&nonce);
+        sealed.extend_from_slice(&ciphertext);
+        Ok(Cow::Owned(sealed))
+    }
+    
+    /// Decrypt a value written by `seal`
+    fn unseal(&self, stored: Vec<u8>) -> Result<Vec<u8>> {
+        let cipher = match &self.cipher {
+            Some(cipher) => cipher,
+            None => return Ok(stored),
+        };
+        
+        if stored.len() < NONCE_LEN {
+            return Err(StorageError::EncryptionError("value too short to hold a nonce".to_string()));
+        }
+        let (nonce, ciphertext) = stored.split_at(NONCE_LEN);
+        cipher.decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|e| StorageError::EncryptionError(e.to_string()))
+    }
+}
+
+/// Chunk data into smaller pieces and hash them
+///
+/// `chunk_size` must already have been validated against `MIN_CHUNK_SIZE`.
+/// Empty input is refused, as a file with no chunks would take the hash of
+/// an empty chunk list instead of the hash of its (empty) content.
+fn chunk_data(data: &[u8], chunk_size: usize, algorithm: HashAlgorithm, strategy: &ChunkingStrategy, key: Option<&[u8; 32]>) -> Result<ChunkedFile> {
+    if data.is_empty() {
+        return Err(StorageError::ChunkingError("empty content is stored whole, never chunked".to_string()));
+    }
+    let mut chunks = Vec::new();
+    
+    // Split the data into chunks
+    let chunk_size = match *strategy {
+        ChunkingStrategy::Fixed => {
+            for chunk in data.chunks(chunk_size) {
+                chunks.push(chunk.to_vec());
+            }
+            chunk_size
+        },
+        ChunkingStrategy::ContentDefined { min_size, avg_size, max_size } => {
+            if min_size == 0 || min_size > avg_size || avg_size > max_size {
+                return Err(StorageError::ChunkingError(format!(
+                    "invalid content-defined bounds: min {} avg {} max {}", min_size, avg_size, max_size
+                )));
+            }
+            
+            let mut offset = 0;
+            for len in chunking::chunk_lengths(data, min_size, avg_size, max_size) {
+                chunks.push(data[offset..offset + len].to_vec());
+                offset += len;
+            }
+            avg_size
+        },
+    };
+    
+    build_chunked_file(chunks, chunk_size, algorithm, strategy, key)
+}
+
+/// Hash already split chunks and describe them
+fn build_chunked_file(chunks: Vec<Vec<u8>>, chunk_size: usize, algorithm: HashAlgorithm, strategy: &ChunkingStrategy, key: Option<&[u8; 32]>) -> Result<ChunkedFile> {
+    let (chunk_hashes, file_hash) = hash_chunks(chunks.iter().map(Vec::as_slice), chunk_size, algorithm, key)?;
+    
+    let metadata = FileMetadata {
+        hash: file_hash,
+        algorithm: algorithm.as_str().to_string(),
+        size: chunks.iter().map(Vec::len).sum(),
+        chunk_size,
+        chunks: chunk_hashes,
+        timestamp: current_timestamp(),
+        compression: None,
+        chunking: *strategy,
+        layout: ChunkLayout::ContentAddressed,
+        hash_scheme: FileHashScheme::LengthPrefixed,
+        expires_at: None,
+        content_type: None,
+        tags: Vec::new(),
+        attrs: HashMap::new(),
+        output_len: None,
+    };
+    
+    Ok(ChunkedFile { metadata, chunks })
+}
+
+/// Hash each chunk and combine the results, returning the chunk hashes and the file hash
+fn hash_chunks<'a>(chunks: impl IntoIterator<Item = &'a [u8]>, chunk_size: usize, algorithm: HashAlgorithm, key: Option<&[u8; 32]>) -> Result<(Vec<String>, String)> {
+    #[cfg(not(feature = "parallel"))]
+    let chunk_hashes = chunks.into_iter()
+        .map(|chunk| calculate_keyed_hash(chunk, algorithm, key))
+        .collect::<Result<Vec<_>>>()?;
+    #[cfg(feature = "parallel")]
+    let chunk_hashes = chunks.into_iter()
+        .collect::<Vec<_>>()
+        .par_iter()
+        .map(|chunk| calculate_keyed_hash(chunk, algorithm, key))
+        .collect::<Result<Vec<_>>>()?;
+    let file_hash = combine_chunk_hashes(&chunk_hashes, chunk_size, FileHashScheme::LengthPrefixed, algorithm, key)?;
+    
+    Ok((chunk_hashes, file_hash))
+}
+
+/// The part of `data` from `offset` for up to `len` bytes
+fn slice_range(data: &[u8], offset: usize, len: usize) -> &[u8] {
+    let start = offset.min(data.len());
+    let end = offset.saturating_add(len).min(data.len());
+    &data[start..end]
+}
+
+/// Write every piece of a stream to `writer`, returning the number of bytes written
+///
+/// `progress` gets the bytes written so far and the file size after each piece.
+fn copy_stream<W: Write>(stream: ChunkStream<'_>, mut writer: W, mut progress: impl FnMut(u64, u64)) -> Result<u64> {
+    let mut written = 0u64;
+    let total = stream.total_len();
+    
+    for chunk in stream {
+        let chunk = chunk?;
+        writer.write_all(&chunk)?;
+        written += chunk.len() as u64;
+        progress(written, total);
+    }
+    writer.flush()?;
+    
+    Ok(written)
+}
+
+/// Reject nonzero chunk sizes below `MIN_CHUNK_SIZE`
+///
+/// Rejected up front rather than silently replaced, so the chunking decision
+/// and the recorded metadata always use the caller's size.
+fn check_min_chunk_size(chunk_size: usize) -> Result<()> {
+    if chunk_size > 0 && chunk_size < MIN_CHUNK_SIZE {
+        return Err(StorageError::ChunkingError(format!(
+            "chunk size {} is below the minimum of {} bytes", chunk_size, MIN_CHUNK_SIZE
+        )));
+    }
+    Ok(())
+}
+
+/// Read a little-endian u64 counter record, treating a missing record as 0
+fn read_counter(db: &Records, key: &str) -> Result<u64> {
+    match db.get(key.as_bytes())? {
+        Some(bytes) => {
+            let bytes: [u8; 8] = bytes.as_slice().try_into()
+                .map_err(|_| StorageError::SerializationError(format!("invalid counter record {}", key)))?;
+            Ok(u64::from_le_bytes(bytes))
+        },
+        None => Ok(0),
+    }
+}
+
+/// The record key of the alias `name`
+///
+/// Names must be non-empty and may not contain `/`, which record keys keep
+/// for namespaces.
+fn alias_key(name: &str) -> Result<String> {
+    if name.is_empty() || name.contains('/') {
+        return Err(StorageError::InvalidAlias(name.to_string()));
+    }
+    Ok(format!("alias:{}", name))
+}
+
+/// The later of two expiry times, where `None` means never
+fn longer_lifetime(a: Option<u64>, b: Option<u64>) -> Option<u64> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.max(b)),
+        _ => None,
+    }
+}
+
+/// Info record for a simple file
+fn simple_info(hash: &str, algorithm: HashAlgorithm, size: usize, codec: Option<&str>) -> FileMetadata {
+    FileMetadata {
+        hash: hash.to_string(),
+        algorithm: algorithm.as_str().to_string(),
+        size,
+        chunk_size: 0,
+        chunks: Vec::new(),
+        timestamp: current_timestamp(),
+        compression: codec.map(str::to_string),
+        chunking: ChunkingStrategy::Fixed,
+        layout: ChunkLayout::ContentAddressed,
+        hash_scheme: FileHashScheme::LengthPrefixed,
+        expires_at: None,
+        content_type: None,
+        tags: Vec::new(),
+        attrs: HashMap::new(),
+        output_len: None,
+    }
+}
+
+fn serialize_metadata(metadata: &FileMetadata) -> Result<Vec<u8>> {
+    metadata_format::encode(metadata).map_err(StorageError::SerializationError)
+}
+
+/// Seconds since the Unix epoch
+fn current_timestamp() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// Create the file hash of a chunked file from its chunk hashes
+///
+/// The length-prefixed form commits to the exact chunk list and chunk size,
+/// so no two different chunkings can produce the same input to the hash.
+fn combine_chunk_hashes(chunk_hashes: &[String], chunk_size: usize, scheme: FileHashScheme, algorithm: HashAlgorithm, key: Option<&[u8; 32]>) -> Result<String> {
+    let combined_data = match scheme {
+        FileHashScheme::Merkle => return merkle::root(chunk_hashes, algorithm, key),
+        FileHashScheme::Joined => chunk_hashes.join("|").into_bytes(),
+        FileHashScheme::LengthPrefixed => {
+            let mut combined = Vec::with_capacity(32 + chunk_hashes.len() * 68);
+            combined.extend_from_slice(CHUNKED_HASH_DOMAIN);
+            combined.extend_from_slice(&(chunk_hashes.len() as u64).to_le_bytes());
+            combined.extend_from_slice(&(chunk_size as u64).to_le_bytes());
+            
+            for chunk_hash in chunk_hashes {
+                let raw = hex::decode(chunk_hash)
+                    .map_err(|e| StorageError::SerializationError(format!("invalid chunk hash {}: {}", chunk_hash, e)))?;
+                combined.extend_from_slice(&(raw.len() as u32).to_le_bytes());
+                combined.extend_from_slice(&raw);
+            }
+            combined
+        },
+    };
+    
+    calculate_keyed_hash(&combined_data, algorithm, key)
+}
+
+/// Split an identifier into its bare hash and the algorithm its prefix names
+///
+/// Identifiers are either a bare hex hash or `algorithm:hex`, as returned
+/// by an engine with `StorageConfig::prefixed_hashes` set. Records are always
+/// keyed by the bare hash. A `/` would let the identifier name another
+/// namespace's record, so it is rejected with `InvalidHash`.
+pub fn parse_identifier(id: &str) -> Result<(&str, Option<HashAlgorithm>)> {
+    if id.contains('/') {
+        return Err(StorageError::InvalidHash(id.to_string()));
+    }
+    
+    match id.split_once(':') {
+        Some((algorithm, hash)) => Ok((hash, Some(HashAlgorithm::from_str(algorithm)?))),
+        None => Ok((id, None)),
+    }
+}
+
+/// The bare hash of an identifier, prefixed or not
+fn bare_hash(id: &str) -> Result<&str> {
+    Ok(parse_identifier(id)?.0)
+}
+
+/// The bare hash of a caller-supplied chunk identifier, which must be hex
+fn bare_chunk_hash(id: &str) -> Result<&str> {
+    let hash = bare_hash(id)?;
+    if hash.is_empty() || !hash.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        return Err(StorageError::InvalidHash(id.to_string()));
+    }
+    Ok(hash)
+}
+
+/// Reject an identifier whose prefix names a different algorithm than the file was stored with
+fn check_named_algorithm(hash: &str, named: Option<HashAlgorithm>, stored: HashAlgorithm) -> Result<()> {
+    match named {
+        Some(named) if named != stored => Err(StorageError::InvalidAlgorithm(format!(
+            "{} was stored with {}, not {}", hash, stored.as_str(), named.as_str()
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Calculate hash using the default algorithm (blake3)
+pub fn calculate_hash(data: &[u8]) -> String {
+    calculate_hash_with_algorithm(data, HashAlgorithm::Blake3)
+}
+
+/// Calculate hash using the specified algorithm
+pub fn calculate_hash_with_algorithm(data: &[u8], algorithm: HashAlgorithm) -> String {
+    let mut hasher = Hasher::new(algorithm);
+    hasher.update(data);
+    hasher.finalize()
+}
+
+/// Calculate the raw digest bytes using the specified algorithm
+///
+/// Skips the hex encoding `calculate_hash_with_algorithm` does, for callers
+/// that need the digest itself, e.g. as a Merkle tree leaf.
+pub fn calculate_hash_bytes(data: &[u8], algorithm: HashAlgorithm) -> Vec<u8> {
+    let mut hasher = Hasher::new(algorithm);
+    hasher.update(data);
+    hasher.finalize_bytes()
+}
+
+/// Check that `data` hashes to `claimed` under `algorithm`
+///
+/// For vetting a hash a client claims for uploaded bytes before storing or
+/// serving them under it. `claimed` may carry an algorithm prefix, which must
+/// then name `algorithm`. This is the address of a file stored whole; see
+/// `verify_chunked_hash` for chunked files.
+pub fn verify_hash(data: &[u8], claimed: &str, algorithm: HashAlgorithm) -> bool {
+    match parse_identifier(claimed) {
+        Ok((hash, named)) if named.is_none_or(|named| named == algorithm) => {
+            calculate_hash_with_algorithm(data, algorithm).eq_ignore_ascii_case(hash)
+        },
+        _ => false,
+    }
+}
+
+/// Check that `data` stored in fixed-size chunks of `chunk_size` gets the file hash `claimed`
+///
+/// Follows `store_with_options`: data no longer than `chunk_size` is stored
+/// whole and checked as by `verify_hash`. Both the default file hash and the
+/// Merkle root of `StoreOptions::merkle` are accepted.
+pub fn verify_chunked_hash(data: &[u8], claimed: &str, algorithm: HashAlgorithm, chunk_size: usize) -> Result<bool> {
+    check_min_chunk_size(chunk_size)?;
+    if chunk_size == 0 || data.len() <= chunk_size {
+        return Ok(verify_hash(data, claimed, algorithm));
+    }
+    
+    let (hash, named) = parse_identifier(claimed)?;
+    if matches!(named, Some(named) if named != algorithm) {
+        return Ok(false);
+    }
+    let (chunk_hashes, file_hash) = hash_chunks(data.chunks(chunk_size), chunk_size, algorithm, None)?;
+    
+    Ok(file_hash.eq_ignore_ascii_case(hash)
+        || merkle::root(&chunk_hashes, algorithm, None)?.eq_ignore_ascii_case(hash))
+}
+
+/// Calculate a hash, keyed when `key` is given
+///
+/// Keyed hashing is supported for Blake3 and Blake2b. The same data hashes
+/// differently under different keys, so addresses from one key are
+/// meaningless under another.
+pub fn calculate_keyed_hash(data: &[u8], algorithm: HashAlgorithm, key: Option<&[u8; 32]>) -> Result<String> {
+    let mut hasher = Hasher::keyed(algorithm, key)?;
+    hasher.update(data);
+    Ok(hasher.finalize())
+}
+
+/// Incremental hasher for any supported algorithm
+///
+/// Feeding data in pieces gives the same hex digest as hashing it in one go,
+/// so streams can be hashed without holding them in memory. Also implements
+/// `Write`, so `std::io::copy` can feed it from a reader.
+pub struct Hasher {
+    state: HasherState,
+    output_len: usize,
+}
+
+enum HasherState {
+    Blake3(Box<blake3::Hasher>),
+    Blake2b(Blake2b512),
+    Blake2bVar(Blake2bVar),
+    Blake2bKeyed(Box<Blake2bMac512>),
+    Keccak256(Keccak256),
+    Sha256(Sha256),
+}
+
+impl Hasher {
+    pub fn new(algorithm: HashAlgorithm) -> Self {
+        let state = match algorithm {
+            HashAlgorithm::Blake3 => HasherState::Blake3(Box::new(blake3::Hasher::new())),
+            HashAlgorithm::Blake2b => HasherState::Blake2b(Blake2b512::new()),
+            HashAlgorithm::Keccak256 => HasherState::Keccak256(Keccak256::new()),
+            HashAlgorithm::Sha256 => HasherState::Sha256(Sha256::new()),
+        };
+        Hasher {
+            state,
+            output_len: algorithm.default_output_len(),
+        }
+    }
+    
+    /// A hasher keyed with `key`, or unkeyed when it is `None`
+    ///
+    /// Only Blake3 and Blake2b support keys.
+    pub fn keyed(algorithm: HashAlgorithm, key: Option<&[u8; 32]>) -> Result<Self> {
+        let Some(key) = key else {
+            return Ok(Self::new(algorithm));
+        };
+        
+        let state = match algorithm {
+            HashAlgorithm::Blake3 => HasherState::Blake3(Box::new(blake3::Hasher::new_keyed(key))),
+            HashAlgorithm::Blake2b => {
+                let mac = <Blake2bMac512 as Mac>::new_from_slice(key)
+                    .map_err(|e| StorageError::InvalidAlgorithm(e.to_string()))?;
+                HasherState::Blake2bKeyed(Box::new(mac))
+            },
+            HashAlgorithm::Keccak256 | HashAlgorithm::Sha256 => return Err(StorageError::InvalidAlgorithm(format!(
+                "{} does not support keyed hashing", algorithm.as_str()
+            ))),
+        };
+        Ok(Hasher {
+            state,
+            output_len: algorithm.default_output_len(),
+        })
+    }
+    
+    /// A hasher producing `output_len`-byte digests, keyed as in `keyed`
+    ///
+    /// Blake3 extends its output and Blake2b uses its variable-length form,
+    /// which is a different function from truncating the 64-byte digest.
+    /// Keyed Blake2b only produces 64-byte digests.
+    pub fn with_output_len(algorithm: HashAlgorithm, key: Option<&[u8; 32]>, output_len: usize) -> Result<Self> {
+        algorithm.check_output_len(output_len)?;
+        let mut hasher = Self::keyed(algorithm, key)?;
+        
+        if algorithm == HashAlgorithm::Blake2b && output_len != algorithm.default_output_len() {
+            if key.is_some() {
+                return Err(StorageError::InvalidAlgorithm("keyed blake2b only produces 64-byte digests".to_string()));
+            }
+            let var = Blake2bVar::new(output_len)
+                .map_err(|e| StorageError::InvalidAlgorithm(e.to_string()))?;
+            hasher.state = HasherState::Blake2bVar(var);
+        }
+        hasher.output_len = output_len;
+        
+        Ok(hasher)
+    }
+    
+    pub fn update(&mut self, data: &[u8]) {
+        match &mut self.state {
+            HasherState::Blake3(hasher) => {
+                #[cfg(feature = "parallel")]
+                if data.len() >= PARALLEL_HASH_THRESHOLD {
+                    hasher.update_rayon(data);
+                    return;
+                }
+                hasher.update(data);
+            },
+            HasherState::Blake2b(hasher) => Digest::update(hasher, data),
+            HasherState::Blake2bVar(hasher) => Update::update(hasher, data),
+            HasherState::Blake2bKeyed(mac) => Mac::update(mac.as_mut(), data),
+            HasherState::Keccak256(hasher) => Digest::update(hasher, data),
+            HasherState::Sha256(hasher) => Digest::update(hasher, data),
+        }
+    }
+    
+    /// The hex digest of everything fed in so far
+    pub fn finalize(self) -> String {
+        hex::encode(self.finalize_bytes())
+    }
+    
+    /// The raw digest of everything fed in so far
+    pub fn finalize_bytes(self) -> Vec<u8> {
+        match self.state {
+            HasherState::Blake3(hasher) => {
+                let mut output = vec![0u8; self.output_len];
+                hasher.finalize_xof().fill(&mut output);
+                output
+            },
+            HasherState::Blake2b(hasher) => Digest::finalize(hasher).to_vec(),
+            HasherState::Blake2bVar(hasher) => {
+                let mut output = vec![0u8; self.output_len];
+                // The buffer matches the length the hasher was created with
+                VariableOutput::finalize_variable(hasher, &mut output).expect("output buffer has the requested length");
+                output
+            },
+            HasherState::Blake2bKeyed(mac) => Mac::finalize(*mac).into_bytes().to_vec(),
+            HasherState::Keccak256(hasher) => Digest::finalize(hasher).to_vec(),
+            HasherState::Sha256(hasher) => Digest::finalize(hasher).to_vec(),
+        }
+    }
+}
+
+impl Write for Hasher {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+    
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Where a scan by `scan_hashes` or `scan_files` resumes
+///
+/// Chunked files are listed before simple files; `after` is the record key
+/// last returned from the current listing.
+#[derive(Clone, Debug, Default)]
+struct ScanCursor {
+    simple: bool,
+    after: Option<Box<[u8]>>,
+}
+
+impl ScanCursor {
+    fn after(simple: bool, key: Box<[u8]>) -> Self {
+        ScanCursor { simple, after: Some(key) }
+    }
+    
+    /// The key to seek to in one listing: just past the last key returned from it, else `first`
+    fn resume(&self, simple: bool, first: &[u8]) -> Vec<u8> {
+        match &self.after {
+            Some(key) if self.simple == simple => [&key[..], &[0u8][..]].concat(),
+            _ => first.to_vec(),
+        }
+    }
+}
+
+/// The engine a `BlobWriter` writes to, borrowed or shared
+enum EngineHandle<'a> {
+    Borrowed(&'a StorageEngine),
+    Shared(Arc<StorageEngine>),
+}
+
+impl std::ops::Deref for EngineHandle<'_> {
+    type Target = StorageEngine;
+    
+    fn deref(&self) -> &StorageEngine {
+        match self {
+            EngineHandle::Borrowed(engine) => engine,
+            EngineHandle::Shared(engine) => engine,
+        }
+    }
+}
+
+/// A file built from a sequence of writes, created by `StorageEngine::create_blob`
+///
+/// Input is buffered until it passes the chunking threshold; from then on
+/// each full chunk is written as soon as it fills, so only the current chunk
+/// and the chunk hash list are held. Nothing is visible under a hash until
+/// `finish`. Dropping the writer without finishing leaves any chunks already
+/// written unreferenced for `gc` to remove.
+pub struct BlobWriter<'a> {
+    engine: EngineHandle<'a>,
+    algorithm: HashAlgorithm,
+    chunk_size: usize,
+    threshold: usize,
+    buffer: Vec<u8>,
+    chunk_hashes: Vec<String>,
+    size: usize,
+    chunking: bool,
+}
+
+impl BlobWriter<'_> {
+    /// Append `buf` to the file
+    pub fn write(&mut self, buf: &[u8]) -> Result<()> {
+        self.buffer.extend_from_slice(buf);
+        self.size += buf.len();
+        
+        if !self.chunking && self.buffer.len() > self.threshold {
+            self.chunking = true;
+        }
+        if self.chunking {
+            let full = self.buffer.len() / self.chunk_size * self.chunk_size;
+            let pending = self.buffer.split_off(full);
+            let filled = std::mem::replace(&mut self.buffer, pending);
+            for chunk in filled.chunks(self.chunk_size) {
+                self.write_chunk(chunk)?;
+            }
+        }
+        Ok(())
+    }
+    
+    /// Bytes written so far
+    pub fn len(&self) -> usize {
+        self.size
+    }
+    
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+    
+    /// Record the file and return its hash
+    ///
+    /// Fails with `ChunkingError` if a `gc` run removed chunks written
+    /// earlier, since nothing referenced them until now.
+    pub fn finish(mut self) -> Result<String> {
+        if !self.chunking {
+            return self.engine.store_with_options(&self.buffer, self.algorithm, self.chunk_size);
+        }
+        
+        if !self.buffer.is_empty() {
+            let last = std::mem::take(&mut self.buffer);
+            self.write_chunk(&last)?;
+        }
+        
+        let engine = &*self.engine;
+        let _write_guard = engine.write_guard()?;
+        
+        let mut missing = Vec::new();
+        for chunk_hash in &self.chunk_hashes {
+            if engine.db.get_pinned(format!("chunk:{}", chunk_hash).as_bytes())?.is_none() {
+                missing.push(chunk_hash.as_str());
+            }
+        }
+        if !missing.is_empty() {
+            return Err(StorageError::ChunkingError(format!("chunks removed before finish: {}", missing.join(", "))));
+        }
+        
+        let hash = engine.record_chunked(&self.chunk_hashes, self.chunk_size, self.size, self.algorithm)?;
+        Ok(engine.identifier(hash, self.algorithm))
+    }
+    
+    fn write_chunk(&mut self, chunk: &[u8]) -> Result<()> {
+        let chunk_hash = self.engine.hash(chunk, self.algorithm)?;
+        
+        let _write_guard = self.engine.write_guard()?;
+        self.engine.write_chunk_body(&chunk_hash, chunk)?;
+        
+        self.chunk_hashes.push(chunk_hash);
+        Ok(())
+    }
+}
+
+// Python exceptions
+//
+// SvdbError derives from IOError, which every engine failure used to raise, so
+// existing `except IOError` handlers keep working.
+pyo3::create_exception!(svdb_core, SvdbError, pyo3::exceptions::PyIOError, "Base class for storage engine errors");
+pyo3::create_exception!(svdb_core, SvdbNotFound, SvdbError, "No file is stored under the hash");
+pyo3::create_exception!(svdb_core, SvdbCorrupt, SvdbError, "Stored data does not match its hash");
+pyo3::create_exception!(svdb_core, SvdbInvalidAlgorithm, SvdbError, "Unknown or unsupported hash algorithm");
+pyo3::create_exception!(svdb_core, SvdbDatabaseLocked, SvdbError, "Another process holds the database lock");
+pyo3::create_exception!(svdb_core, SvdbDatabaseCorrupt, SvdbError, "The database files are damaged and cannot be opened");
+pyo3::create_exception!(svdb_core, SvdbPermissionDenied, SvdbError, "The database directory is not accessible");
+pyo3::create_exception!(svdb_core, SvdbDatabaseNotFound, SvdbError, "No database exists at the path and creating one was not allowed");
+pyo3::create_exception!(svdb_core, SvdbValueTooLarge, SvdbError, "The value is too large to store whole; store it with a chunk size");
+pyo3::create_exception!(svdb_core, SvdbTimeout, SvdbError, "A write did not finish within the configured timeout");
+pyo3::create_exception!(svdb_core, SvdbInvalidAlias, SvdbError, "Alias names must be non-empty and may not contain '/'");
+
+impl From<StorageError> for PyErr {
+    fn from(err: StorageError) -> PyErr {
+        let message = err.to_string();
+        match err {
+            StorageError::HashNotFound(_) | StorageError::AliasNotFound(_) => SvdbNotFound::new_err(message),
+            StorageError::CorruptData { .. } | StorageError::InconsistentMetadata { .. } => SvdbCorrupt::new_err(message),
+            StorageError::InvalidAlgorithm(_) | StorageError::AlgorithmMismatch { .. } => SvdbInvalidAlgorithm::new_err(message),
+            StorageError::DatabaseLocked(_) => SvdbDatabaseLocked::new_err(message),
+            StorageError::DatabaseCorrupt(_) => SvdbDatabaseCorrupt::new_err(message),
+            StorageError::PermissionDenied(_) => SvdbPermissionDenied::new_err(message),
+            StorageError::DatabaseNotFound(_) => SvdbDatabaseNotFound::new_err(message),
+            StorageError::ValueTooLarge { .. } => SvdbValueTooLarge::new_err(message),
+            StorageError::Timeout(_) => SvdbTimeout::new_err(message),
+            StorageError::InvalidAlias(_) => SvdbInvalidAlias::new_err(message),
+            _ => SvdbError::new_err(message),
+        }
+    }
+}
+
+// Python module
+#[pymodule]
+fn svdb_core(py: Python, m: &PyModule) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(py_store_file, m)?)?;
+    m.add_function(wrap_pyfunction!(py_retrieve_file, m)?)?;
+    m.add_function(wrap_pyfunction!(py_calculate_hash, m)?)?;
+    m.add_function(wrap_pyfunction!(py_store_file_with_options, m)?)?;
+    m.add_function(wrap_pyfunction!(py_calculate_hash_with_algorithm, m)?)?;
+    m.add_function(wrap_pyfunction!(py_calculate_hash_bytes, m)?)?;
+    m.add_function(wrap_pyfunction!(py_verify_hash, m)?)?;
+    m.add_function(wrap_pyfunction!(py_verify_chunk_proof, m)?)?;
+    m.add_function(wrap_pyfunction!(py_delete_file, m)?)?;
+    m.add_function(wrap_pyfunction!(py_exists, m)?)?;
+    m.add_function(wrap_pyfunction!(py_chunk_list, m)?)?;
+    m.add_function(wrap_pyfunction!(py_has_chunk, m)?)?;
+    m.add_function(wrap_pyfunction!(py_retrieve_file_verified, m)?)?;
+    m.add_function(wrap_pyfunction!(py_retrieve_file_as, m)?)?;
+    m.add_function(wrap_pyfunction!(py_store_batch, m)?)?;
+    m.add_function(wrap_pyfunction!(py_gc, m)?)?;
+    m.add_function(wrap_pyfunction!(py_get_metadata, m)?)?;
+    m.add_function(wrap_pyfunction!(py_pin, m)?)?;
+    m.add_function(wrap_pyfunction!(py_unpin, m)?)?;
+    m.add_function(wrap_pyfunction!(py_size_of, m)?)?;
+    m.add_function(wrap_pyfunction!(py_export, m)?)?;
+    m.add_function(wrap_pyfunction!(py_import, m)?)?;
+    m.add_function(wrap_pyfunction!(py_would_store, m)?)?;
+    m.add_function(wrap_pyfunction!(py_put_chunk, m)?)?;
+    m.add_function(wrap_pyfunction!(py_commit_chunked, m)?)?;
+    m.add_function(wrap_pyfunction!(py_stats, m)?)?;
+    m.add_function(wrap_pyfunction!(py_store_with_ttl, m)?)?;
+    m.add_function(wrap_pyfunction!(py_store_with_metadata, m)?)?;
+    m.add_function(wrap_pyfunction!(py_find_by_tag, m)?)?;
+    m.add_function(wrap_pyfunction!(py_compact, m)?)?;
+    m.add_function(wrap_pyfunction!(py_export_archive, m)?)?;
+    m.add_function(wrap_pyfunction!(py_import_archive, m)?)?;
+    m.add_function(wrap_pyfunction!(py_clear, m)?)?;
+    m.add_function(wrap_pyfunction!(py_find_cross_algorithm_duplicates, m)?)?;
+    m.add_function(wrap_pyfunction!(py_fsck, m)?)?;
+    m.add_function(wrap_pyfunction!(py_dedup_report, m)?)?;
+    m.add_function(wrap_pyfunction!(py_retrieve_batch, m)?)?;
+    m.add_function(wrap_pyfunction!(py_flush, m)?)?;
+    m.add_function(wrap_pyfunction!(py_retrieve_range, m)?)?;
+    m.add("SvdbError", py.get_type::<SvdbError>())?;
+    m.add("SvdbNotFound", py.get_type::<SvdbNotFound>())?;
+    m.add("SvdbCorrupt", py.get_type::<SvdbCorrupt>())?;
+    m.add("SvdbInvalidAlgorithm", py.get_type::<SvdbInvalidAlgorithm>())?;
+    m.add("SvdbDatabaseLocked", py.get_type::<SvdbDatabaseLocked>())?;
+    m.add("SvdbDatabaseCorrupt", py.get_type::<SvdbDatabaseCorrupt>())?;
+    m.add("SvdbPermissionDenied", py.get_type::<SvdbPermissionDenied>())?;
+    m.add("SvdbDatabaseNotFound", py.get_type::<SvdbDatabaseNotFound>())?;
+    m.add("SvdbValueTooLarge", py.get_type::<SvdbValueTooLarge>())?;
+    m.add("SvdbTimeout", py.get_type::<SvdbTimeout>())?;
+    m.add("SvdbInvalidAlias", py.get_type::<SvdbInvalidAlias>())?;
+    m.add_class::<SvdbEngine>()?;
+    m.add_class::<HashIterator>()?;
+    m.add_class::<FileIterator>()?;
+    m.add_class::<SvdbBlobWriter>()?;
+    Ok(())
+}
+
+/// Python handle that opens the database once and reuses it for every call
+#[pyclass(name = "SvdbEngine")]
+struct SvdbEngine {
+    /// `None` once closed
+    engine: Option<Arc<StorageEngine>>,
+}
+
+impl SvdbEngine {
+    /// The open engine, or an error once `close` has been called
+    fn engine(&self) -> PyResult<&Arc<StorageEngine>> {
+        self.engine.as_ref()
+            .ok_or_else(|| SvdbError::new_err("engine is closed"))
+    }
+}
+
+#[pymethods]
+impl SvdbEngine {
+    #[new]
+    #[allow(clippy::too_many_arguments)]
+    #[pyo3(signature = (db_path, compression=None, hash_key=None, prefixed_hashes=false, read_only=false, namespace=None, cache=true, cache_capacity=None, encryption_key=None, create=true, existence_filter=None, false_positive_rate=0.01, secondary_path=None))]
+    fn new(
+        db_path: &str,
+        compression: Option<&str>,
+        hash_key: Option<&PyBytes>,
+        prefixed_hashes: bool,
+        read_only: bool,
+        namespace: Option<String>,
+        cache: bool,
+        cache_capacity: Option<usize>,
+        encryption_key: Option<&PyBytes>,
+        create: bool,
+        existence_filter: Option<usize>,
+        false_positive_rate: f64,
+        secondary_path: Option<&str>
+    ) -> PyResult<Self> {
+        let compression = compression
+            .map(Compression::from_str)
+            .transpose()
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(e.to_string()))?
+            .unwrap_or_default();
+        let hash_key = hash_key.map(parse_hash_key).transpose()?;
+        
+        let mut builder = StorageEngine::builder()
+            .config(StorageConfig {
+                hash_key,
+                prefixed_hashes,
+                cache_capacity,
+                existence_filter: existence_filter.map(|expected_files| ExistenceFilterConfig {
+                    expected_files,
+                    false_positive_rate,
+                }),
+                ..Default::default()
+            })
+            .compression(compression)
+            .cache(cache)
+            .read_only(read_only)
+            .create(create);
+        if let Some(namespace) = namespace {
+            builder = builder.namespace(namespace);
+        }
+        if let Some(secondary_path) = secondary_path {
+            builder = builder.secondary(secondary_path);
+        }
+        if let Some(key) = encryption_key {
+            let key = key.as_bytes().try_into()
+                .map_err(|_| PyErr::new::<pyo3::exceptions::PyValueError, _>("encryption key must be 32 bytes"))?;
+            builder = builder.encryption(EncryptionConfig::new(key));
+        }
+        let engine = builder.open(db_path)
+            .map_err(PyErr::from)?;
+        
+        Ok(SvdbEngine {
+            engine: Some(Arc::new(engine)),
+        })
+    }
+    
+    /// Another namespace of this engine's database, sharing its open handle
+    fn namespace(&self, namespace: &str) -> PyResult<SvdbEngine> {
+        let engine = self.engine()?.namespace(namespace)
+            .map_err(PyErr::from)?;
+        
+        Ok(SvdbEngine {
+            engine: Some(Arc::new(engine)),
+        })
+    }
+    
+    fn store(&self, py_data: &PyBytes) -> PyResult<String> {
+        self.engine()?.store(py_data.as_bytes())
+            .map_err(PyErr::from)
+    }
+    
+    /// `durability` is "buffered", "wal" or "fsync"; by default the engine's `sync_writes` applies
+    /// `auto_blake3_threshold` hashes larger inputs with blake3 instead of `algorithm`
+    #[pyo3(signature = (py_data, algorithm, chunk_size, compression=None, content_defined=false, output_len=None, durability=None, merkle=false, auto_blake3_threshold=None))]
+    #[allow(clippy::too_many_arguments)]
+    fn store_with_options(
+        &self,
+        py_data: &PyBytes,
+        algorithm: &str,
+        chunk_size: usize,
+        compression: Option<&str>,
+        content_defined: bool,
+        output_len: Option<usize>,
+        durability: Option<&str>,
+        merkle: bool,
+        auto_blake3_threshold: Option<usize>
+    ) -> PyResult<String> {
+        let algorithm = HashAlgorithm::from_str(algorithm)
+            .map_err(PyErr::from)?;
+        let compression = match compression {
+            Some(name) => Compression::from_str(name)
+                .map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(e.to_string()))?,
+            None => self.engine()?.compression,
+        };
+        
+        let chunking = if content_defined {
+            ChunkingStrategy::content_defined(chunk_size)
+        } else {
+            ChunkingStrategy::Fixed
+        };
+        
+        let durability = durability.map(parse_durability).transpose()?;
+        
+        let options = StoreOptions { algorithm, chunk_size, compression, chunking, output_len, merkle, durability, auto_blake3_threshold, ..Default::default() };
+        self.engine()?.store_with(py_data.as_bytes(), &options)
+            .map_err(PyErr::from)
+    }
+    
+    /// Store a file, returning its hash and whether its content was new
+    #[pyo3(signature = (py_data, algorithm="blake3", chunk_size=0))]
+    fn store_reporting(&self, py_data: &PyBytes, algorithm: &str, chunk_size: usize) -> PyResult<(String, bool)> {
+        let algorithm = HashAlgorithm::from_str(algorithm)
+            .map_err(PyErr::from)?;
+        let engine = self.engine()?;
+        
+        let options = StoreOptions { algorithm, chunk_size, compression: engine.compression, ..Default::default() };
+        let (hash, outcome) = engine.store_reporting(py_data.as_bytes(), &options)
+            .map_err(PyErr::from)?;
+        Ok((hash, outcome == StoreOutcome::Written))
+    }
+    
+    #[pyo3(signature = (py_data, ttl_secs, algorithm="blake3", chunk_size=0))]
+    fn store_with_ttl(&self, py_data: &PyBytes, ttl_secs: u64, algorithm: &str, chunk_size: usize) -> PyResult<String> {
+        let algo = HashAlgorithm::from_str(algorithm)
+            .map_err(PyErr::from)?;
+        
+        self.engine()?.store_with_ttl(py_data.as_bytes(), algo, chunk_size, ttl_secs)
+            .map_err(PyErr::from)
+    }
+    
+    #[pyo3(signature = (py_data, content_type=None, tags=Vec::new(), algorithm="blake3", chunk_size=0))]
+    fn store_with_metadata(
+        &self,
+        py_data: &PyBytes,
+        content_type: Option<&str>,
+        tags: Vec<&str>,
+        algorithm: &str,
+        chunk_size: usize,
+    ) -> PyResult<String> {
+        let algo = HashAlgorithm::from_str(algorithm)
+            .map_err(PyErr::from)?;
+        
+        self.engine()?.store_with_metadata(py_data.as_bytes(), algo, chunk_size, content_type, &tags)
+            .map_err(PyErr::from)
+    }
+    
+    #[pyo3(signature = (py_data, attrs, algorithm="blake3", chunk_size=0))]
+    fn store_with_attrs(&self, py_data: &PyBytes, attrs: HashMap<String, String>, algorithm: &str, chunk_size: usize) -> PyResult<String> {
+        let algo = HashAlgorithm::from_str(algorithm)
+            .map_err(PyErr::from)?;
+        
+        self.engine()?.store_with_attrs(py_data.as_bytes(), algo, chunk_size, attrs)
+            .map_err(PyErr::from)
+    }
+    
+    fn get_attrs(&self, hash: &str) -> PyResult<HashMap<String, String>> {
+        self.engine()?.get_attrs(hash)
+            .map_err(PyErr::from)
+    }
+    
+    fn find_by_tag(&self, tag: &str) -> PyResult<Vec<String>> {
+        self.engine()?.find_by_tag(tag)
+            .map_err(PyErr::from)
+    }
+    
+    fn store_batch(&self, items: Vec<&PyBytes>) -> PyResult<Vec<String>> {
+        let items: Vec<&[u8]> = items.iter().map(|item| item.as_bytes()).collect();
+        
+        self.engine()?.store_batch(&items)
+            .map_err(PyErr::from)
+    }
+    
+    /// Read a file straight into a writable buffer such as a `bytearray`,
+    /// returning the number of bytes written
+    ///
+    /// Skips the `bytes` copy `retrieve` makes, which matters for large
+    /// files. Size the buffer with `size_of`.
+    fn retrieve_into(&self, hash: &str, buffer: &PyAny) -> PyResult<usize> {
+        let buffer = PyBuffer::<u8>::get(buffer)?;
+        if buffer.readonly() || !buffer.is_c_contiguous() {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>("buffer must be writable and contiguous"));
+        }
+        
+        // SAFETY: the buffer is writable and contiguous, `buffer` keeps the
+        // exported view alive until it is dropped, and the GIL is held
+        // throughout so no Python code can touch the memory meanwhile
+        let target = unsafe { std::slice::from_raw_parts_mut(buffer.buf_ptr() as *mut u8, buffer.len_bytes()) };
+        self.engine()?.retrieve_into(hash, target)
+            .map_err(PyErr::from)
+    }
+    
+    /// Read a file without consulting or filling the cache, for one-shot scans
+    fn retrieve_uncached(&self, py: Python, hash: &str) -> PyResult<Py<PyBytes>> {
+        self.engine()?.retrieve_uncached(hash)
+            .map(|data| PyBytes::new(py, &data).into())
+            .map_err(PyErr::from)
+    }
+    
+    fn retrieve(&self, py: Python, hash: &str) -> PyResult<Py<PyBytes>> {
+        self.engine()?.retrieve(hash)
+            .map(|data| PyBytes::new(py, &data).into())
+            .map_err(PyErr::from)
+    }
+    
+    /// Awaitable `store` for asyncio code: `await engine.store_async(data)`
+    ///
+    /// The data is copied while holding the GIL; hashing and writing run on
+    /// a blocking thread without it, so the event loop keeps going.
+    #[cfg(feature = "python-async")]
+    fn store_async<'p>(&self, py: Python<'p>, py_data: &PyBytes) -> PyResult<&'p PyAny> {
+        let engine = AsyncStorageEngine::from(Arc::clone(self.engine()?));
+        let data = py_data.as_bytes().to_vec();
+        pyo3_asyncio::tokio::future_into_py(py, async move {
+            engine.store(data).await.map_err(PyErr::from)
+        })
+    }
+    
+    /// Awaitable `retrieve` for asyncio code, reading without holding the GIL
+    #[cfg(feature = "python-async")]
+    fn retrieve_async<'p>(&self, py: Python<'p>, hash: String) -> PyResult<&'p PyAny> {
+        let engine = AsyncStorageEngine::from(Arc::clone(self.engine()?));
+        pyo3_asyncio::tokio::future_into_py(py, async move {
+            let data = engine.retrieve(&hash).await.map_err(PyErr::from)?;
+            Python::with_gil(|py| Ok(PyBytes::new(py, &data).to_object(py)))
+        })
+    }
+    
+    /// Cache hit and miss counts since the engine was opened
+    fn cache_metrics(&self, py: Python) -> PyResult<PyObject> {
+        let metrics = self.engine()?.cache_metrics();
+        
+        let dict = PyDict::new(py);
+        dict.set_item("hits", metrics.hits)?;
+        dict.set_item("misses", metrics.misses)?;
+        dict.set_item("bytes_from_cache", metrics.bytes_from_cache)?;
+        dict.set_item("bytes_from_db", metrics.bytes_from_db)?;
+        Ok(dict.to_object(py))
+    }
+    
+    /// Existence filter counts, or None if the engine was opened without one
+    fn existence_filter_metrics(&self, py: Python) -> PyResult<PyObject> {
+        let Some(metrics) = self.engine()?.existence_filter_metrics() else {
+            return Ok(py.None());
+        };
+        
+        let dict = PyDict::new(py);
+        dict.set_item("hits", metrics.hits)?;
+        dict.set_item("misses", metrics.misses)?;
+        dict.set_item("false_positives", metrics.false_positives)?;
+        dict.set_item("bits", metrics.bits)?;
+        Ok(dict.to_object(py))
+    }
+    
+    /// Retrieve a file reading up to `concurrency` chunks at once, optionally verifying each
+    #[pyo3(signature = (hash, concurrency=4, verify=false))]
+    fn retrieve_parallel(&self, py: Python, hash: &str, concurrency: usize, verify: bool) -> PyResult<Py<PyBytes>> {
+        let options = RetrieveOptions { verify, concurrency };
+        self.engine()?.retrieve_with(hash, &options)
+            .map(|data| PyBytes::new(py, &data).into())
+            .map_err(PyErr::from)
+    }
+    
+    /// Read `length` bytes starting at `offset`, fetching only the chunks involved
+    fn retrieve_range(&self, py: Python, hash: &str, offset: usize, length: usize) -> PyResult<Py<PyBytes>> {
+        self.engine()?.retrieve_range(hash, offset, length)
+            .map(|data| PyBytes::new(py, &data).into())
+            .map_err(PyErr::from)
+    }
+    
+    /// Retrieve many files at once; missing ones come back as `None`
+    fn retrieve_batch(&self, py: Python, hashes: Vec<String>) -> PyResult<Vec<Option<Py<PyBytes>>>> {
+        batch_to_py(py, self.engine()?.retrieve_batch(&hashes))
+    }
+    
+    /// Write a file straight to `path` without holding it in memory
+    fn retrieve_to_file(&self, hash: &str, path: &str) -> PyResult<u64> {
+        self.export_file(hash, path, None)
+    }
+    
+    /// Write a file to `path`, calling `progress(bytes_done, bytes_total)` per chunk if given
+    #[pyo3(signature = (hash, path, progress=None))]
+    fn export_file(&self, hash: &str, path: &str, progress: Option<&PyAny>) -> PyResult<u64> {
+        let mut callback_error = None;
+        let written = self.engine()?.export_with_progress(hash, path, |done, total| {
+            report_progress(progress, &mut callback_error, done, total)
+        })
+            .map_err(PyErr::from)?;
+        
+        callback_error.map_or(Ok(written), Err)
+    }
+    
+    /// Store the file at `path`, calling `progress(bytes_done, bytes_total)` per chunk if given
+    #[pyo3(signature = (path, algorithm="blake3", chunk_size=0, progress=None))]
+    fn import_file(&self, path: &str, algorithm: &str, chunk_size: usize, progress: Option<&PyAny>) -> PyResult<String> {
+        let algo = HashAlgorithm::from_str(algorithm)
+            .map_err(PyErr::from)?;
+        
+        let mut callback_error = None;
+        let hash = self.engine()?.import_with_progress(path, algo, chunk_size, |done, total| {
+            report_progress(progress, &mut callback_error, done, total)
+        })
+            .map_err(PyErr::from)?;
+        
+        callback_error.map_or(Ok(hash), Err)
+    }
+    
+    fn retrieve_verified(&self, py: Python, hash: &str) -> PyResult<Py<PyBytes>> {
+        self.engine()?.retrieve_verified(hash)
+            .map(|data| PyBytes::new(py, &data).into())
+            .map_err(PyErr::from)
+    }
+    
+    /// Retrieve a file, raising `SvdbInvalidAlgorithm` if it was stored under another algorithm
+    fn retrieve_as(&self, py: Python, hash: &str, algorithm: &str) -> PyResult<Py<PyBytes>> {
+        let algorithm = HashAlgorithm::from_str(algorithm)
+            .map_err(PyErr::from)?;
+        
+        self.engine()?.retrieve_as(hash, algorithm)
+            .map(|data| PyBytes::new(py, &data).into())
+            .map_err(PyErr::from)
+    }
+    
+    /// Load files into this engine's cache up to its capacity, returning how many were loaded
+    fn warm(&self, hashes: Vec<String>) -> PyResult<usize> {
+        self.engine()?.warm(&hashes)
+            .map_err(PyErr::from)
+    }
+    
+    fn size_of(&self, hash: &str) -> PyResult<usize> {
+        self.engine()?.size_of(hash)
+            .map_err(PyErr::from)
+    }
+    
+    fn exists(&self, hash: &str) -> PyResult<bool> {
+        self.engine()?.exists(hash)
+            .map_err(PyErr::from)
+    }
+    
+    /// Chunk hashes making up a file; a simple file is its own single chunk
+    fn chunk_list(&self, hash: &str) -> PyResult<Vec<String>> {
+        self.engine()?.chunk_list(hash)
+            .map_err(PyErr::from)
+    }
+    
+    fn has_chunk(&self, chunk_hash: &str) -> PyResult<bool> {
+        self.engine()?.has_chunk(chunk_hash)
+            .map_err(PyErr::from)
+    }
+    
+    /// Sibling hashes proving a chunk belongs to a file stored with `merkle=True`
+    fn chunk_proof(&self, hash: &str, chunk_index: usize) -> PyResult<Vec<String>> {
+        self.engine()?.chunk_proof(hash, chunk_index)
+            .map_err(PyErr::from)
+    }
+    
+    #[pyo3(signature = (py_data, algorithm="blake3"))]
+    fn put_chunk(&self, py_data: &PyBytes, algorithm: &str) -> PyResult<String> {
+        let algo = HashAlgorithm::from_str(algorithm)
+            .map_err(PyErr::from)?;
+        
+        self.engine()?.put_chunk(py_data.as_bytes(), algo)
+            .map_err(PyErr::from)
+    }
+    
+    /// Recreate a lost metadata record from the file's surviving indexed chunks
+    #[pyo3(signature = (hash, algorithm="blake3", chunk_size=DEFAULT_CHUNK_SIZE))]
+    fn rebuild_metadata(&self, hash: &str, algorithm: &str, chunk_size: usize) -> PyResult<()> {
+        let algo = HashAlgorithm::from_str(algorithm)
+            .map_err(PyErr::from)?;
+        
+        self.engine()?.rebuild_metadata(hash, algo, chunk_size)
+            .map_err(PyErr::from)
+    }
+    
+    #[pyo3(signature = (chunk_hashes, algorithm="blake3"))]
+    fn commit_chunked(&self, chunk_hashes: Vec<String>, algorithm: &str) -> PyResult<String> {
+        let algo = HashAlgorithm::from_str(algorithm)
+            .map_err(PyErr::from)?;
+        
+        self.engine()?.commit_chunked(&chunk_hashes, algo)
+            .map_err(PyErr::from)
+    }
+    
+    #[pyo3(signature = (py_data, algorithm="blake3", chunk_size=0))]
+    fn would_store(&self, py_data: &PyBytes, algorithm: &str, chunk_size: usize) -> PyResult<(String, bool)> {
+        let algo = HashAlgorithm::from_str(algorithm)
+            .map_err(PyErr::from)?;
+        
+        self.engine()?.would_store(py_data.as_bytes(), algo, chunk_size)
+            .map_err(PyErr::from)
+    }
+    
+    fn delete(&self, hash: &str) -> PyResult<bool> {
+        self.engine()?.delete(hash)
+            .map_err(PyErr::from)
+    }
+    
+    #[pyo3(signature = (old_hash, algorithm, remove_old=false))]
+    fn rehash(&self, old_hash: &str, algorithm: &str, remove_old: bool) -> PyResult<String> {
+        let algorithm = HashAlgorithm::from_str(algorithm)
+            .map_err(PyErr::from)?;
+        
+        self.engine()?.rehash(old_hash, algorithm, remove_old)
+            .map_err(PyErr::from)
+    }
+    
+    fn pin(&self, hash: &str) -> PyResult<u64> {
+        self.engine()?.pin(hash)
+            .map_err(PyErr::from)
+    }
+    
+    fn unpin(&self, hash: &str) -> PyResult<u64> {
+        self.engine()?.unpin(hash)
+            .map_err(PyErr::from)
+    }
+    
+    /// Point a human-readable name at a stored hash, like a git tag
+    fn set_alias(&self, name: &str, hash: &str) -> PyResult<()> {
+        self.engine()?.set_alias(name, hash)
+            .map_err(PyErr::from)
+    }
+    
+    fn resolve_alias(&self, name: &str) -> PyResult<String> {
+        self.engine()?.resolve_alias(name)
+            .map_err(PyErr::from)
+    }
+    
+    fn retrieve_by_alias(&self, py: Python, name: &str) -> PyResult<Py<PyBytes>> {
+        self.engine()?.retrieve_by_alias(name)
+            .map(|data| PyBytes::new(py, &data).into())
+            .map_err(PyErr::from)
+    }
+    
+    fn remove_alias(&self, name: &str) -> PyResult<bool> {
+        self.engine()?.remove_alias(name)
+            .map_err(PyErr::from)
+    }
+    
+    /// Every alias as a dict of name to hash
+    fn list_aliases(&self, py: Python) -> PyResult<PyObject> {
+        let aliases = self.engine()?.list_aliases()
+            .map_err(PyErr::from)?;
+        
+        let dict = PyDict::new(py);
+        for (name, hash) in aliases {
+            dict.set_item(name, hash)?;
+        }
+        Ok(dict.into())
+    }
+    
+    fn stats(&self, py: Python) -> PyResult<PyObject> {
+        let stats = self.engine()?.stats()
+            .map_err(PyErr::from)?;
+        
+        stats_to_dict(py, &stats)
+    }
+    
+    /// The path and settings this engine was opened with
+    fn info(&self, py: Python) -> PyResult<PyObject> {
+        info_to_dict(py, &self.engine()?.info())
+    }
+    
+    fn flush(&self) -> PyResult<()> {
+        self.engine()?.flush()
+            .map_err(PyErr::from)
+    }
+    
+    fn fsck(&self, py: Python) -> PyResult<PyObject> {
+        let report = self.engine()?.fsck()
+            .map_err(PyErr::from)?;
+        
+        fsck_report_to_dict(py, &report)
+    }
+    
+    /// Estimate the savings from deduplicating chunks, without changing anything
+    fn dedup_report(&self, py: Python) -> PyResult<PyObject> {
+        let report = self.engine()?.dedup_report()
+            .map_err(PyErr::from)?;
+        
+        dedup_report_to_dict(py, &report)
+    }
+    
+    fn compact(&self) -> PyResult<()> {
+        self.engine()?.compact()
+            .map_err(PyErr::from)
+    }
+    
+    /// Pick up the primary's writes, on an engine opened with secondary_path
+    fn catch_up_with_primary(&self) -> PyResult<()> {
+        self.engine()?.catch_up_with_primary()
+            .map_err(PyErr::from)
+    }
+    
+    /// Back the whole store up to one file, returning its size in bytes
+    fn export_archive(&self, path: &str) -> PyResult<u64> {
+        self.engine()?.export_archive(path)
+            .map_err(PyErr::from)
+    }
+    
+    /// Restore a backup into this empty store, returning the number of records restored
+    fn import_archive(&self, path: &str) -> PyResult<u64> {
+        self.engine()?.import_archive(path)
+            .map_err(PyErr::from)
+    }
+    
+    /// Delete everything in this engine's namespace
+    fn clear(&self) -> PyResult<()> {
+        self.engine()?.clear()
+            .map_err(PyErr::from)
+    }
+    
+    /// Hashes of simple files holding identical content, grouped
+    fn find_cross_algorithm_duplicates(&self) -> PyResult<Vec<Vec<String>>> {
+        self.engine()?.find_cross_algorithm_duplicates()
+            .map_err(PyErr::from)
+    }
+    
+    /// Rewrite metadata left in JSON by older versions, returning how many records changed
+    fn migrate_metadata(&self) -> PyResult<usize> {
+        self.engine()?.migrate_metadata()
+            .map_err(PyErr::from)
+    }
+    
+    /// Store a file again in chunks of `chunk_size`, returning its new hash
+    fn rechunk(&self, hash: &str, chunk_size: usize) -> PyResult<String> {
+        self.engine()?.rechunk(hash, chunk_size)
+            .map_err(PyErr::from)
+    }
+    
+    fn gc(&self, py: Python) -> PyResult<PyObject> {
+        let report = self.engine()?.gc()
+            .map_err(PyErr::from)?;
+        
+        gc_report_to_dict(py, &report)
+    }
+    
+    fn get_metadata(&self, py: Python, hash: &str) -> PyResult<Option<PyObject>> {
+        let metadata = self.engine()?.get_metadata(hash)
+            .map_err(PyErr::from)?;
+        
+        metadata.map(|metadata| metadata_to_dict(py, &metadata)).transpose()
+    }
+    
+    /// Lazily iterate over stored file hashes, optionally filtered by prefix
+    #[pyo3(signature = (prefix=""))]
+    fn iter_hashes(&self, prefix: &str) -> PyResult<HashIterator> {
+        Ok(HashIterator::new(self.engine()?.clone(), prefix))
+    }
+    
+    /// Lazily iterate over `(hash, size, algorithm, timestamp)` for every live file
+    fn iter_files(&self) -> PyResult<FileIterator> {
+        Ok(FileIterator::new(self.engine()?.clone()))
+    }
+    
+    /// Release the database; later calls raise `SvdbError`
+    ///
+    /// The database closes once any iterators from `iter_hashes` are gone too.
+    fn close(&mut self) {
+        self.engine = None;
+    }
+    
+    #[getter]
+    fn closed(&self) -> bool {
+        self.engine.is_none()
+    }
+    
+    fn __enter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+    
+    fn __exit__(&mut self, _exc_type: Option<&PyAny>, _exc_value: Option<&PyAny>, _traceback: Option<&PyAny>) -> bool {
+        self.close();
+        false
+    }
+    
+    /// Open a writable file-like object; `close()` records the file and sets its `hash`
+    #[pyo3(signature = (algorithm="blake3", chunk_size=DEFAULT_CHUNK_SIZE))]
+    fn create_blob(&self, algorithm: &str, chunk_size: usize) -> PyResult<SvdbBlobWriter> {
+        let algorithm = HashAlgorithm::from_str(algorithm)
+            .map_err(PyErr::from)?;
+        
+        SvdbBlobWriter::new(self.engine()?.clone(), algorithm, chunk_size)
+    }
+}
+
+/// Python file-like object that builds a file from successive writes
+#[pyclass(unsendable)]
+struct SvdbBlobWriter {
+    writer: Option<BlobWriter<'static>>,
+    hash: Option<String>,
+}
+
+impl SvdbBlobWriter {
+    fn new(engine: Arc<StorageEngine>, algorithm: HashAlgorithm, chunk_size: usize) -> PyResult<Self> {
+        let writer = engine.create_shared_blob(algorithm, chunk_size)
+            .map_err(PyErr::from)?;
+        
+        Ok(SvdbBlobWriter {
+            writer: Some(writer),
+            hash: None,
+        })
+    }
+    
+    fn writer(&mut self) -> PyResult<&mut BlobWriter<'static>> {
+        self.writer.as_mut()
+            .ok_or_else(|| PyErr::new::<pyo3::exceptions::PyValueError, _>("I/O operation on closed blob"))
+    }
+}
+
+#[pymethods]
+impl SvdbBlobWriter {
+    fn write(&mut self, py_data: &PyBytes) -> PyResult<usize> {
+        let data = py_data.as_bytes();
+        self.writer()?.write(data)
+            .map_err(PyErr::from)?;
+        Ok(data.len())
+    }
+    
+    fn writable(&self) -> bool {
+        true
+    }
+    
+    fn tell(&mut self) -> PyResult<usize> {
+        Ok(self.writer()?.len())
+    }
+    
+    /// Record the file and return its hash; later calls return the same hash
+    fn finish(&mut self) -> PyResult<String> {
+        if let Some(writer) = self.writer.take() {
+            self.hash = Some(writer.finish().map_err(PyErr::from)?);
+        }
+        self.hash.clone()
+            .ok_or_else(|| PyErr::new::<pyo3::exceptions::PyValueError, _>("blob was abandoned"))
+    }
+    
+    fn close(&mut self) -> PyResult<()> {
+        if self.writer.is_some() {
+            self.finish()?;
+        }
+        Ok(())
+    }
+    
+    /// Hash of the finished file, or `None` until it is closed
+    #[getter]
+    fn hash(&self) -> Option<String> {
+        self.hash.clone()
+    }
+    
+    #[getter]
+    fn closed(&self) -> bool {
+        self.writer.is_none()
+    }
+    
+    fn __enter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+    
+    /// Finish on a clean exit; abandon the blob if the block raised
+    fn __exit__(&mut self, exc_type: Option<&PyAny>, _exc_value: Option<&PyAny>, _traceback: Option<&PyAny>) -> PyResult<bool> {
+        if exc_type.is_some() {
+            self.writer = None;
+        } else {
+            self.close()?;
+        }
+        Ok(false)
+    }
+}
+
+/// Python iterator over stored hashes
+///
+/// Keeps a cursor rather than an open RocksDB iterator, seeking past the last
+/// hash returned on each call, so it owns everything it refers to.
+#[pyclass(unsendable)]
+struct HashIterator {
+    engine: Arc<StorageEngine>,
+    prefix: String,
+    // `None` once the scan has ended
+    cursor: Option<ScanCursor>,
+}
+
+impl HashIterator {
+    fn new(engine: Arc<StorageEngine>, prefix: &str) -> Self {
+        HashIterator {
+            engine,
+            prefix: prefix.to_string(),
+            cursor: Some(ScanCursor::default()),
+        }
+    }
+}
+
+#[pymethods]
+impl HashIterator {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+    
+    fn __next__(mut slf: PyRefMut<'_, Self>) -> PyResult<Option<String>> {
+        let Some(cursor) = slf.cursor.take() else {
+            return Ok(None);
+        };
+        let next = slf.engine.scan_hashes(&slf.prefix, &cursor).next().transpose()?;
+        
+        Ok(next.map(|(cursor, hash)| {
+            slf.cursor = Some(cursor);
+            hash
+        }))
+    }
+}
+
+/// Python iterator over file metadata, resuming from a cursor like `HashIterator`
+#[pyclass(unsendable)]
+struct FileIterator {
+    engine: Arc<StorageEngine>,
+    // `None` once the scan has ended
+    cursor: Option<ScanCursor>,
+}
+
+impl FileIterator {
+    fn new(engine: Arc<StorageEngine>) -> Self {
+        FileIterator {
+            engine,
+            cursor: Some(ScanCursor::default()),
+        }
+    }
+}
+
+#[pymethods]
+impl FileIterator {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+    
+    fn __next__(mut slf: PyRefMut<'_, Self>) -> PyResult<Option<(String, usize, String, u64)>> {
+        let Some(cursor) = slf.cursor.take() else {
+            return Ok(None);
+        };
+        let next = slf.engine.scan_files(&cursor).next().transpose()?;
+        
+        Ok(next.map(|(cursor, metadata)| {
+            slf.cursor = Some(cursor);
+            (metadata.hash, metadata.size, metadata.algorithm, metadata.timestamp)
+        }))
+    }
+}
+
+fn metadata_to_dict(py: Python, metadata: &FileMetadata) -> PyResult<PyObject> {
+    let dict = PyDict::new(py);
+    dict.set_item("hash", &metadata.hash)?;
+    dict.set_item("algorithm", &metadata.algorithm)?;
+    dict.set_item("size", metadata.size)?;
+    dict.set_item("chunk_size", metadata.chunk_size)?;
+    dict.set_item("chunks", &metadata.chunks)?;
+    dict.set_item("timestamp", metadata.timestamp)?;
+    dict.set_item("compression", &metadata.compression)?;
+    dict.set_item("expires_at", metadata.expires_at)?;
+    dict.set_item("content_type", &metadata.content_type)?;
+    dict.set_item("tags", &metadata.tags)?;
+    dict.set_item("attrs", &metadata.attrs)?;
+    let chunking = match metadata.chunking {
+        ChunkingStrategy::Fixed => "fixed",
+        ChunkingStrategy::ContentDefined { .. } => "content_defined",
+    };
+    dict.set_item("chunking", chunking)?;
+    Ok(dict.to_object(py))
+}
+
+fn gc_report_to_dict(py: Python, report: &GcReport) -> PyResult<PyObject> {
+    let dict = PyDict::new(py);
+    dict.set_item("chunks_scanned", report.chunks_scanned)?;
+    dict.set_item("chunks_removed", report.chunks_removed)?;
+    dict.set_item("bytes_reclaimed", report.bytes_reclaimed)?;
+    dict.set_item("refcounts_repaired", report.refcounts_repaired)?;
+    dict.set_item("files_expired", report.files_expired)?;
+    Ok(dict.to_object(py))
+}
+
+fn fsck_report_to_dict(py: Python, report: &FsckReport) -> PyResult<PyObject> {
+    let findings = PyList::empty(py);
+    for finding in &report.findings {
+        let entry = PyDict::new(py);
+        entry.set_item("hash", &finding.hash)?;
+        match &finding.problem {
+            FsckProblem::MissingChunk { index } => {
+                entry.set_item("problem", "missing_chunk")?;
+                entry.set_item("chunk", index)?;
+            },
+            FsckProblem::ChunkHashMismatch { index, actual } => {
+                entry.set_item("problem", "chunk_hash_mismatch")?;
+                entry.set_item("chunk", index)?;
+                entry.set_item("actual", actual)?;
+            },
+            FsckProblem::FileHashMismatch { actual } => {
+                entry.set_item("problem", "file_hash_mismatch")?;
+                entry.set_item("actual", actual)?;
+            },
+            FsckProblem::MissingValue => entry.set_item("problem", "missing_value")?,
+            FsckProblem::Unreadable(detail) => {
+                entry.set_item("problem", "unreadable")?;
+                entry.set_item("detail", detail)?;
+            },
+        }
+        findings.append(entry)?;
+    }
+    
+    let dict = PyDict::new(py);
+    dict.set_item("files_checked", report.files_checked)?;
+    dict.set_item("chunks_checked", report.chunks_checked)?;
+    dict.set_item("findings", findings)?;
+    Ok(dict.to_object(py))
+}
+
+fn dedup_report_to_dict(py: Python, report: &DedupReport) -> PyResult<PyObject> {
+    let dict = PyDict::new(py);
+    dict.set_item("chunks_scanned", report.chunks_scanned)?;
+    dict.set_item("duplicate_chunks", report.duplicate_chunks)?;
+    dict.set_item("current_bytes", report.current_bytes)?;
+    dict.set_item("unique_bytes", report.unique_bytes)?;
+    dict.set_item("savings_ratio", report.savings_ratio())?;
+    Ok(dict.to_object(py))
+}
+
+fn stats_to_dict(py: Python, stats: &StorageStats) -> PyResult<PyObject> {
+    let dict = PyDict::new(py);
+    dict.set_item("simple_files", stats.simple_files)?;
+    dict.set_item("chunked_files", stats.chunked_files)?;
+    dict.set_item("total_chunks", stats.total_chunks)?;
+    dict.set_item("logical_bytes", stats.logical_bytes)?;
+    dict.set_item("estimated_live_bytes", stats.estimated_live_bytes)?;
+    Ok(dict.to_object(py))
+}
+
+fn info_to_dict(py: Python, info: &EngineInfo) -> PyResult<PyObject> {
+    let dict = PyDict::new(py);
+    dict.set_item("path", info.path.to_string_lossy())?;
+    dict.set_item("namespace", &info.namespace)?;
+    dict.set_item("read_only", info.read_only)?;
+    dict.set_item("cache_enabled", info.cache_enabled)?;
+    dict.set_item("cache_capacity", info.cache_capacity)?;
+    dict.set_item("compression", info.compression.codec().unwrap_or(COMPRESSION_NONE))?;
+    dict.set_item("default_algorithm", info.default_algorithm.as_str())?;
+    dict.set_item("default_chunk_size", info.default_chunk_size)?;
+    dict.set_item("auto_chunk_threshold", info.auto_chunk_threshold)?;
+    dict.set_item("chunk_threshold", info.chunk_threshold)?;
+    dict.set_item("max_value_bytes", info.max_value_bytes)?;
+    dict.set_item("encrypted", info.encrypted)?;
+    dict.set_item("keyed_hashes", info.keyed_hashes)?;
+    dict.set_item("prefixed_hashes", info.prefixed_hashes)?;
+    dict.set_item("verify_existing", info.verify_existing)?;
+    dict.set_item("sync_writes", info.sync_writes)?;
+    Ok(dict.to_object(py))
+}
+
+// Python bindings
+//
+// The free functions below open the database on every call and are kept for
+// backwards compatibility only. They are slow and lose the cache between
+// calls; use `SvdbEngine` for anything that touches the store repeatedly.
+
+/// Store a file (slow: reopens the database, prefer `SvdbEngine.store`)
+#[pyfunction]
+fn py_store_file(_py: Python, db_path: &str, py_data: &PyBytes) -> PyResult<String> {
+    let data = py_data.as_bytes();
+    
+    let engine = StorageEngine::new(db_path)
+        .map_err(PyErr::from)?;
+    
+    engine.store(data)
+        .map_err(PyErr::from)
+}
+
+/// Store a file with options (slow: reopens the database, prefer `SvdbEngine.store_with_options`)
+#[pyfunction]
+#[pyo3(signature = (db_path, py_data, algorithm, chunk_size, compression=None))]
+fn py_store_file_with_options(
+    _py: Python, 
+    db_path: &str, 
+    py_data: &PyBytes,
+    algorithm: &str,
+    chunk_size: usize,
+    compression: Option<&str>
+) -> PyResult<String> {
+    let data = py_data.as_bytes();
+    
+    let algorithm = HashAlgorithm::from_str(algorithm)
+        .map_err(PyErr::from)?;
+    let compression = compression
+        .map(Compression::from_str)
+        .transpose()
+        .map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(e.to_string()))?
+        .unwrap_or_default();
+    
+    let engine = StorageEngine::new(db_path)
+        .map_err(PyErr::from)?;
+    
+    engine.store_with(data, &StoreOptions { algorithm, chunk_size, compression, ..Default::default() })
+        .map_err(PyErr::from)
+}
+
+/// Store a file that expires (slow: reopens the database, prefer `SvdbEngine.store_with_ttl`)
+#[pyfunction]
+#[pyo3(signature = (db_path, py_data, ttl_secs, algorithm="blake3", chunk_size=0))]
+fn py_store_with_ttl(_py: Python, db_path: &str, py_data: &PyBytes, ttl_secs: u64, algorithm: &str, chunk_size: usize) -> PyResult<String> {
+    let algo = HashAlgorithm::from_str(algorithm)
+        .map_err(PyErr::from)?;
+    let engine = StorageEngine::new(db_path)
+        .map_err(PyErr::from)?;
+    
+    engine.store_with_ttl(py_data.as_bytes(), algo, chunk_size, ttl_secs)
+        .map_err(PyErr::from)
+}
+
+/// Store a file with a content type and tags (slow: reopens the database, prefer `SvdbEngine.store_with_metadata`)
+#[pyfunction]
+#[pyo3(signature = (db_path, py_data, content_type=None, tags=Vec::new(), algorithm="blake3", chunk_size=0))]
+fn py_store_with_metadata(
+    _py: Python,
+    db_path: &str,
+    py_data: &PyBytes,
+    content_type: Option<&str>,
+    tags: Vec<&str>,
+    algorithm: &str,
+    chunk_size: usize,
+) -> PyResult<String> {
+    let algo = HashAlgorithm::from_str(algorithm)
+        .map_err(PyErr::from)?;
+    let engine = StorageEngine::new(db_path)
+        .map_err(PyErr::from)?;
+    
+    engine.store_with_metadata(py_data.as_bytes(), algo, chunk_size, content_type, &tags)
+        .map_err(PyErr::from)
+}
+
+/// Find files carrying a tag (slow: reopens the database, prefer `SvdbEngine.find_by_tag`)
+#[pyfunction]
+fn py_find_by_tag(_py: Python, db_path: &str, tag: &str) -> PyResult<Vec<String>> {
+    let engine = StorageEngine::new(db_path)
+        .map_err(PyErr::from)?;
+    
+    engine.find_by_tag(tag)
+        .map_err(PyErr::from)
+}
+
+/// Store a list of files in one write (slow: reopens the database, prefer `SvdbEngine.store_batch`)
+#[pyfunction]
+fn py_store_batch(_py: Python, db_path: &str, items: Vec<&PyBytes>) -> PyResult<Vec<String>> {
+    let items: Vec<&[u8]> = items.iter().map(|item| item.as_bytes()).collect();
+    
+    let engine = StorageEngine::new(db_path)
+        .map_err(PyErr::from)?;
+    
+    engine.store_batch(&items)
+        .map_err(PyErr::from)
+}
+
+/// Retrieve a file (slow: reopens the database, prefer `SvdbEngine.retrieve`)
+#[pyfunction]
+fn py_retrieve_file(py: Python, db_path: &str, hash: &str) -> PyResult<Py<PyBytes>> {
+    let engine = StorageEngine::new(db_path)
+        .map_err(PyErr::from)?;
+    
+    engine.retrieve(hash)
+        .map(|data| PyBytes::new(py, &data).into())
+        .map_err(PyErr::from)
+}
+
+/// Read part of a file (slow: reopens the database, prefer `SvdbEngine.retrieve_range`)
+#[pyfunction]
+fn py_retrieve_range(py: Python, db_path: &str, hash: &str, offset: usize, length: usize) -> PyResult<Py<PyBytes>> {
+    let engine = StorageEngine::new(db_path)
+        .map_err(PyErr::from)?;
+    
+    engine.retrieve_range(hash, offset, length)
+        .map(|data| PyBytes::new(py, &data).into())
+        .map_err(PyErr::from)
+}
+
+/// Retrieve many files (slow: reopens the database, prefer `SvdbEngine.retrieve_batch`)
+#[pyfunction]
+fn py_retrieve_batch(py: Python, db_path: &str, hashes: Vec<String>) -> PyResult<Vec<Option<Py<PyBytes>>>> {
+    let engine = StorageEngine::new(db_path)
+        .map_err(PyErr::from)?;
+    
+    batch_to_py(py, engine.retrieve_batch(&hashes))
+}
+
+/// Convert `retrieve_batch` results, mapping missing files to `None`
+fn batch_to_py(py: Python, results: Vec<Result<Vec<u8>>>) -> PyResult<Vec<Option<Py<PyBytes>>>> {
+    results.into_iter()
+        .map(|result| match result {
+            Ok(data) => Ok(Some(PyBytes::new(py, &data).into())),
+            Err(StorageError::HashNotFound(_)) => Ok(None),
+            Err(e) => Err(PyErr::from(e)),
+        })
+        .collect()
+}
+
+/// Delete a file (slow: reopens the database, prefer `SvdbEngine.delete`)
+#[pyfunction]
+fn py_delete_file(_py: Python, db_path: &str, hash: &str) -> PyResult<bool> {
+    let engine = StorageEngine::new(db_path)
+        .map_err(PyErr::from)?;
+    
+    engine.delete(hash)
+        .map_err(PyErr::from)
+}
+
+/// Retrieve a file and verify its hash (slow: reopens the database, prefer `SvdbEngine.retrieve_verified`)
+#[pyfunction]
+fn py_retrieve_file_verified(py: Python, db_path: &str, hash: &str) -> PyResult<Py<PyBytes>> {
+    let engine = StorageEngine::new(db_path)
+        .map_err(PyErr::from)?;
+    
+    engine.retrieve_verified(hash)
+        .map(|data| PyBytes::new(py, &data).into())
+        .map_err(PyErr::from)
+}
+
+/// Retrieve a file stored under a known algorithm (slow: reopens the database, prefer `SvdbEngine.retrieve_as`)
+#[pyfunction]
+fn py_retrieve_file_as(py: Python, db_path: &str, hash: &str, algorithm: &str) -> PyResult<Py<PyBytes>> {
+    let algorithm = HashAlgorithm::from_str(algorithm)
+        .map_err(PyErr::from)?;
+    let engine = StorageEngine::new(db_path)
+        .map_err(PyErr::from)?;
+    
+    engine.retrieve_as(hash, algorithm)
+        .map(|data| PyBytes::new(py, &data).into())
+        .map_err(PyErr::from)
+}
+
+/// Write a stored file to disk (slow: reopens the database, prefer `SvdbEngine.export_file`)
+#[pyfunction]
+fn py_export(_py: Python, db_path: &str, hash: &str, path: &str) -> PyResult<u64> {
+    let engine = StorageEngine::new(db_path)
+        .map_err(PyErr::from)?;
+    
+    engine.export(hash, path)
+        .map_err(PyErr::from)
+}
+
+/// Store a file from disk (slow: reopens the database, prefer `SvdbEngine.import_file`)
+#[pyfunction]
+#[pyo3(signature = (db_path, path, algorithm="blake3", chunk_size=0))]
+fn py_import(_py: Python, db_path: &str, path: &str, algorithm: &str, chunk_size: usize) -> PyResult<String> {
+    let algo = HashAlgorithm::from_str(algorithm)
+        .map_err(PyErr::from)?;
+    let engine = StorageEngine::new(db_path)
+        .map_err(PyErr::from)?;
+    
+    engine.import(path, algo, chunk_size)
+        .map_err(PyErr::from)
+}
+
+/// Size of a stored file (slow: reopens the database, prefer `SvdbEngine.size_of`)
+#[pyfunction]
+fn py_size_of(_py: Python, db_path: &str, hash: &str) -> PyResult<usize> {
+    let engine = StorageEngine::new(db_path)
+        .map_err(PyErr::from)?;
+    
+    engine.size_of(hash)
+        .map_err(PyErr::from)
+}
+
+/// Check whether a file exists (slow: reopens the database, prefer `SvdbEngine.exists`)
+#[pyfunction]
+fn py_exists(_py: Python, db_path: &str, hash: &str) -> PyResult<bool> {
+    let engine = StorageEngine::new(db_path)
+        .map_err(PyErr::from)?;
+    
+    engine.exists(hash)
+        .map_err(PyErr::from)
+}
+
+/// List the chunk hashes of a file (slow: reopens the database, prefer `SvdbEngine.chunk_list`)
+#[pyfunction]
+fn py_chunk_list(_py: Python, db_path: &str, hash: &str) -> PyResult<Vec<String>> {
+    let engine = StorageEngine::new(db_path)
+        .map_err(PyErr::from)?;
+    
+    engine.chunk_list(hash)
+        .map_err(PyErr::from)
+}
+
+/// Check whether a chunk is stored (slow: reopens the database, prefer `SvdbEngine.has_chunk`)
+#[pyfunction]
+fn py_has_chunk(_py: Python, db_path: &str, chunk_hash: &str) -> PyResult<bool> {
+    let engine = StorageEngine::new(db_path)
+        .map_err(PyErr::from)?;
+    
+    engine.has_chunk(chunk_hash)
+        .map_err(PyErr::from)
+}
+
+/// Store one chunk of a resumable upload (slow: reopens the database, prefer `SvdbEngine.put_chunk`)
+#[pyfunction]
+#[pyo3(signature = (db_path, py_data, algorithm="blake3"))]
+fn py_put_chunk(_py: Python, db_path: &str, py_data: &PyBytes, algorithm: &str) -> PyResult<String> {
+    let algo = HashAlgorithm::from_str(algorithm)
+        .map_err(PyErr::from)?;
+    let engine = StorageEngine::new(db_path)
+        .map_err(PyErr::from)?;
+    
+    engine.put_chunk(py_data.as_bytes(), algo)
+        .map_err(PyErr::from)
+}
+
+/// Finish a resumable upload (slow: reopens the database, prefer `SvdbEngine.commit_chunked`)
+#[pyfunction]
+#[pyo3(signature = (db_path, chunk_hashes, algorithm="blake3"))]
+fn py_commit_chunked(_py: Python, db_path: &str, chunk_hashes: Vec<String>, algorithm: &str) -> PyResult<String> {
+    let algo = HashAlgorithm::from_str(algorithm)
+        .map_err(PyErr::from)?;
+    let engine = StorageEngine::new(db_path)
+        .map_err(PyErr::from)?;
+    
+    engine.commit_chunked(&chunk_hashes, algo)
+        .map_err(PyErr::from)
+}
+
+/// Hash data as a store would and report whether it is already stored (slow: reopens the database, prefer `SvdbEngine.would_store`)
+#[pyfunction]
+#[pyo3(signature = (db_path, py_data, algorithm="blake3", chunk_size=0))]
+fn py_would_store(_py: Python, db_path: &str, py_data: &PyBytes, algorithm: &str, chunk_size: usize) -> PyResult<(String, bool)> {
+    let algo = HashAlgorithm::from_str(algorithm)
+        .map_err(PyErr::from)?;
+    let engine = StorageEngine::new(db_path)
+        .map_err(PyErr::from)?;
+    
+    engine.would_store(py_data.as_bytes(), algo, chunk_size)
+        .map_err(PyErr::from)
+}
+
+/// Count the files and chunks in a store (slow: reopens the database, prefer `SvdbEngine.stats`)
+#[pyfunction]
+fn py_stats(py: Python, db_path: &str) -> PyResult<PyObject> {
+    let engine = StorageEngine::new(db_path)
+        .map_err(PyErr::from)?;
+    
+    let stats = engine.stats()
+        .map_err(PyErr::from)?;
+    
+    stats_to_dict(py, &stats)
+}
+
+/// Flush the database to disk (slow: reopens the database, prefer `SvdbEngine.flush`)
+#[pyfunction]
+fn py_flush(_py: Python, db_path: &str) -> PyResult<()> {
+    let engine = StorageEngine::new(db_path)
+        .map_err(PyErr::from)?;
+    
+    engine.flush()
+        .map_err(PyErr::from)
+}
+
+/// Compact the database (slow: reopens the database, prefer `SvdbEngine.compact`)
+#[pyfunction]
+fn py_compact(_py: Python, db_path: &str) -> PyResult<()> {
+    let engine = StorageEngine::new(db_path)
+        .map_err(PyErr::from)?;
+    
+    engine.compact()
+        .map_err(PyErr::from)
+}
+
+/// Back a store up to one file (slow: reopens the database, prefer `SvdbEngine.export_archive`)
+#[pyfunction]
+fn py_export_archive(_py: Python, db_path: &str, path: &str) -> PyResult<u64> {
+    let engine = StorageEngine::new(db_path)
+        .map_err(PyErr::from)?;
+    
+    engine.export_archive(path)
+        .map_err(PyErr::from)
+}
+
+/// Restore a backup into an empty store (slow: reopens the database, prefer `SvdbEngine.import_archive`)
+#[pyfunction]
+fn py_import_archive(_py: Python, db_path: &str, path: &str) -> PyResult<u64> {
+    let engine = StorageEngine::new(db_path)
+        .map_err(PyErr::from)?;
+    
+    engine.import_archive(path)
+        .map_err(PyErr::from)
+}
+
+/// Group simple files holding identical content (slow: reopens the database, prefer `SvdbEngine.find_cross_algorithm_duplicates`)
+#[pyfunction]
+fn py_find_cross_algorithm_duplicates(_py: Python, db_path: &str) -> PyResult<Vec<Vec<String>>> {
+    let engine = StorageEngine::new(db_path)
+        .map_err(PyErr::from)?;
+    
+    engine.find_cross_algorithm_duplicates()
+        .map_err(PyErr::from)
+}
+
+/// Delete everything in a store (slow: reopens the database, prefer `SvdbEngine.clear`)
+#[pyfunction]
+fn py_clear(_py: Python, db_path: &str) -> PyResult<()> {
+    let engine = StorageEngine::new(db_path)
+        .map_err(PyErr::from)?;
+    
+    engine.clear()
+        .map_err(PyErr::from)
+}
+
+/// Check every stored file for corruption (slow: reopens the database, prefer `SvdbEngine.fsck`)
+#[pyfunction]
+fn py_fsck(py: Python, db_path: &str) -> PyResult<PyObject> {
+    let engine = StorageEngine::new(db_path)
+        .map_err(PyErr::from)?;
+    
+    let report = engine.fsck()
+        .map_err(PyErr::from)?;
+    
+    fsck_report_to_dict(py, &report)
+}
+
+/// Estimate chunk deduplication savings (slow: reopens the database, prefer `SvdbEngine.dedup_report`)
+#[pyfunction]
+fn py_dedup_report(py: Python, db_path: &str) -> PyResult<PyObject> {
+    let engine = StorageEngine::new(db_path)
+        .map_err(PyErr::from)?;
+    
+    let report = engine.dedup_report()
+        .map_err(PyErr::from)?;
+    
+    dedup_report_to_dict(py, &report)
+}
+
+/// Garbage-collect orphaned chunks (slow: reopens the database, prefer `SvdbEngine.gc`)
+#[pyfunction]
+fn py_gc(py: Python, db_path: &str) -> PyResult<PyObject> {
+    let engine = StorageEngine::new(db_path)
+        .map_err(PyErr::from)?;
+    
+    let report = engine.gc()
+        .map_err(PyErr::from)?;
+    
+    gc_report_to_dict(py, &report)
+}
+
+/// Look up a file's metadata (slow: reopens the database, prefer `SvdbEngine.get_metadata`)
+#[pyfunction]
+fn py_get_metadata(py: Python, db_path: &str, hash: &str) -> PyResult<Option<PyObject>> {
+    let engine = StorageEngine::new(db_path)
+        .map_err(PyErr::from)?;
+    
+    let metadata = engine.get_metadata(hash)
+        .map_err(PyErr::from)?;
+    
+    metadata.map(|metadata| metadata_to_dict(py, &metadata)).transpose()
+}
+
+/// Pin a file (slow: reopens the database, prefer `SvdbEngine.pin`)
+#[pyfunction]
+fn py_pin(_py: Python, db_path: &str, hash: &str) -> PyResult<u64> {
+    let engine = StorageEngine::new(db_path)
+        .map_err(PyErr::from)?;
+    
+    engine.pin(hash)
+        .map_err(PyErr::from)
+}
+
+/// Unpin a file (slow: reopens the database, prefer `SvdbEngine.unpin`)
+#[pyfunction]
+fn py_unpin(_py: Python, db_path: &str, hash: &str) -> PyResult<u64> {
+    let engine = StorageEngine::new(db_path)
+        .map_err(PyErr::from)?;
+    
+    engine.unpin(hash)
+        .map_err(PyErr::from)
+}
+
+#[pyfunction]
+fn py_calculate_hash(_py: Python, py_data: &PyBytes) -> String {
+    let data = py_data.as_bytes();
+    calculate_hash(data)
+}
+
+#[pyfunction]
+#[pyo3(signature = (py_data, algorithm, key=None, output_len=None))]
+fn py_calculate_hash_with_algorithm(_py: Python, py_data: &PyBytes, algorithm: &str, key: Option<&PyBytes>, output_len: Option<usize>) -> PyResult<String> {
+    let data = py_data.as_bytes();
+    let algo = HashAlgorithm::from_str(algorithm)
+        .map_err(PyErr::from)?;
+    let key = key.map(parse_hash_key).transpose()?;
+    
+    let hash = match output_len {
+        Some(output_len) => Hasher::with_output_len(algo, key.as_ref(), output_len).map(|mut hasher| {
+            hasher.update(data);
+            hasher.finalize()
+        }),
+        None => calculate_keyed_hash(data, algo, key.as_ref()),
+    };
+    hash.map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(e.to_string()))
+}
+
+/// Check a proof from `SvdbEngine.chunk_proof` against a file hash
+#[pyfunction]
+#[pyo3(signature = (root, chunk_hash, index, chunk_count, proof, algorithm="blake3", key=None))]
+#[allow(clippy::too_many_arguments)]
+fn py_verify_chunk_proof(
+    _py: Python,
+    root: &str,
+    chunk_hash: &str,
+    index: usize,
+    chunk_count: usize,
+    proof: Vec<String>,
+    algorithm: &str,
+    key: Option<&PyBytes>
+) -> PyResult<bool> {
+    let algo = HashAlgorithm::from_str(algorithm)
+        .map_err(PyErr::from)?;
+    let key = key.map(parse_hash_key).transpose()?;
+    
+    verify_chunk_proof(root, chunk_hash, index, chunk_count, &proof, algo, key.as_ref())
+        .map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(e.to_string()))
+}
+
+/// Check that `py_data` hashes to `claimed`, chunked as `store_with_options` would with `chunk_size`
+#[pyfunction]
+#[pyo3(signature = (py_data, claimed, algorithm="blake3", chunk_size=0))]
+fn py_verify_hash(_py: Python, py_data: &PyBytes, claimed: &str, algorithm: &str, chunk_size: usize) -> PyResult<bool> {
+    let algo = HashAlgorithm::from_str(algorithm)
+        .map_err(PyErr::from)?;
+    
+    verify_chunked_hash(py_data.as_bytes(), claimed, algo, chunk_size)
+        .map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(e.to_string()))
+}
+
+/// Calculate the raw digest of `py_data` as bytes rather than hex
+#[pyfunction]
+#[pyo3(signature = (py_data, algorithm="blake3"))]
+fn py_calculate_hash_bytes(py: Python, py_data: &PyBytes, algorithm: &str) -> PyResult<Py<PyBytes>> {
+    let algo = HashAlgorithm::from_str(algorithm)
+        .map_err(PyErr::from)?;
+    
+    Ok(PyBytes::new(py, &calculate_hash_bytes(py_data.as_bytes(), algo)).into())
+}
+
+/// Pass progress to a Python callable, if one was given
+///
+/// Engine calls run with the GIL held, so the callable is invoked directly.
+/// The operation can't be interrupted halfway, so the first exception the
+/// callable raises is kept in `error`, to be raised once the operation is
+/// done, and the callable isn't called again.
+fn report_progress(progress: Option<&PyAny>, error: &mut Option<PyErr>, done: u64, total: u64) {
+    if let (Some(progress), None) = (progress, &*error) {
+        if let Err(e) = progress.call1((done, total)) {
+            *error = Some(e);
+        }
+    }
+}
+
+fn parse_durability(name: &str) -> PyResult<Durability> {
+    Durability::from_str(name)
+        .map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(e.to_string()))
+}
+
+/// Hash keys are exactly 32 bytes
+fn parse_hash_key(key: &PyBytes) -> PyResult<[u8; 32]> {
+    key.as_bytes().try_into()
+        .map_err(|_| PyErr::new::<pyo3::exceptions::PyValueError, _>("hash key must be 32 bytes"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+    
+    /// `len` bytes of a repeating pattern that neither compresses to nothing nor dedups within a chunk
+    fn patterned(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i % 251) as u8).collect()
+    }
+    
+    #[test]
+    fn test_storage_engine() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let engine = StorageEngine::new(temp_dir.path())?;
+        
+        let test_data = b"Hello, SVDB!";
+        let hash = engine.store(test_data)?;
+        
+        let retrieved = engine.retrieve(&hash)?;
+        assert_eq!(retrieved, test_data);
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_calculate_hash() {
+        let data = b"Hello, SVDB!";
+        let hash = calculate_hash(data);
+        assert!(!hash.is_empty());
+        
+        // Hash should be deterministic
+        let hash2 = calculate_hash(data);
+        assert_eq!(hash, hash2);
+    }
+    
+    #[test]
+    fn test_calculate_hash_with_algorithm() {
+        let data = b"Hello, SVDB!";
+        
+        // Test all algorithms
+        let hash_blake3 = calculate_hash_with_algorithm(data, HashAlgorithm::Blake3);
+        let hash_blake2b = calculate_hash_with_algorithm(data, HashAlgorithm::Blake2b);
+        let hash_keccak = calculate_hash_with_algorithm(data, HashAlgorithm::Keccak256);
+        
+        // All should produce non-empty results
+        assert!(!hash_blake3.is_empty());
+        assert!(!hash_blake2b.is_empty());
+        assert!(!hash_keccak.is_empty());
+        
+        // Different algorithms should produce different hashes
+        assert_ne!(hash_blake3, hash_blake2b);
+        assert_ne!(hash_blake3, hash_keccak);
+        assert_ne!(hash_blake2b, hash_keccak);
+    }
+    
+    #[test]
+    fn test_sha256() {
+        let data = b"Hello, SVDB!";
+        let hash_sha256 = calculate_hash_with_algorithm(data, HashAlgorithm::Sha256);
+        
+        assert!(!hash_sha256.is_empty());
+        assert_ne!(hash_sha256, calculate_hash_with_algorithm(data, HashAlgorithm::Blake3));
+        assert_ne!(hash_sha256, calculate_hash_with_algorithm(data, HashAlgorithm::Keccak256));
+        
+        // Known SHA-256 test vector
+        assert_eq!(
+            calculate_hash_with_algorithm(b"abc", HashAlgorithm::Sha256),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert!(matches!(HashAlgorithm::from_str("sha256"), Ok(HashAlgorithm::Sha256)));
+    }
+    
+    #[test]
+    fn test_chunking() -> Result<()> {
+        // Generate a larger test file
+        let large_data = vec![0u8; 5 * 1024 * 1024]; // 5MB
+        let chunk_size = 1024 * 1024; // 1MB
+        
+        // Chunk the data
+        let chunked = chunk_data(&large_data, chunk_size, HashAlgorithm::Blake3, &ChunkingStrategy::Fixed, None)?;
+        
+        // Should have 5 chunks
+        assert_eq!(chunked.chunks.len(), 5);
+        assert_eq!(chunked.metadata.chunks.len(), 5);
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_store_retrieve_chunked() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let engine = StorageEngine::new(temp_dir.path())?;
+        
+        // Generate test data larger than chunk size
+        let large_data = vec![1u8; 3 * 1024 * 1024]; // 3MB
+        let chunk_size = 1024 * 1024; // 1MB chunks
+        
+        // Store with chunking
+        let hash = engine.store_with_options(&large_data, HashAlgorithm::Blake3, chunk_size)?;
+        
+        // Retrieve
+        let retrieved = engine.retrieve(&hash)?;
+        
+        // Verify
+        assert_eq!(retrieved, large_data);
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_delete() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let engine = StorageEngine::new(temp_dir.path())?;
+        
+        // Simple file
+        let hash = engine.store(b"delete me")?;
+        assert!(engine.delete(&hash)?);
+        assert!(engine.retrieve(&hash).is_err());
+        assert!(!engine.delete(&hash)?);
+        
+        // Chunked file
+        let large_data = vec![2u8; 3 * 1024 * 1024];
+        let hash = engine.store_with_options(&large_data, HashAlgorithm::Blake3, 1024 * 1024)?;
+        let metadata = engine.load_metadata(&format!("meta:{}", hash))?.unwrap();
+        engine.retrieve(&hash)?;
+        assert!(engine.delete(&hash)?);
+        assert!(engine.retrieve(&hash).is_err());
+        assert!(engine.db.get(metadata.chunk_key(0).as_bytes())?.is_none());
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_exists() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let engine = StorageEngine::new(temp_dir.path())?;
+        
+        let hash = engine.store(b"present")?;
+        assert!(engine.exists(&hash)?);
+        assert!(!engine.exists(&calculate_hash(b"absent"))?);
+        
+        let large_data = vec![3u8; 2 * 1024 * 1024];
+        let chunked_hash = engine.store_with_options(&large_data, HashAlgorithm::Blake3, 1024 * 1024)?;
+        assert!(engine.exists(&chunked_hash)?);
+        
+        engine.delete(&chunked_hash)?;
+        assert!(!engine.exists(&chunked_hash)?);
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_retrieve_verified() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let engine = StorageEngine::new(temp_dir.path())?;
+        
+        // Intact files verify under every algorithm
+        for algorithm in [HashAlgorithm::Blake3, HashAlgorithm::Blake2b, HashAlgorithm::Keccak256, HashAlgorithm::Sha256] {
+            let hash = engine.store_with_options(b"verify me", algorithm, 0)?;
+            assert_eq!(engine.retrieve_verified(&hash)?, b"verify me");
+        }
+        
+        // Corrupt a simple file on disk
+        let hash = engine.store(b"original")?;
+        engine.db.put(hash.as_bytes(), b"bitrot")?;
+        match engine.retrieve_verified(&hash) {
+            Err(StorageError::CorruptData { expected, chunk, .. }) => {
+                assert_eq!(expected, hash);
+                assert_eq!(chunk, None);
+            },
+            other => panic!("expected CorruptData, got {:?}", other),
+        }
+        
+        // Corrupt the second chunk of a chunked file
+        let large_data = patterned(3 * 1024 * 1024);
+        let hash = engine.store_with_options(&large_data, HashAlgorithm::Blake3, 1024 * 1024)?;
+        assert_eq!(engine.retrieve_verified(&hash)?, large_data);
+        let metadata = engine.load_metadata(&format!("meta:{}", hash))?.unwrap();
+        engine.db.put(metadata.chunk_key(1).as_bytes(), encode_chunk(&[5u8; 1024], Compression::None)?)?;
+        match engine.retrieve_verified(&hash) {
+            Err(StorageError::CorruptData { chunk, .. }) => assert_eq!(chunk, Some(1)),
+            other => panic!("expected CorruptData, got {:?}", other),
+        }
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_store_retrieve_sha256() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let engine = StorageEngine::new(temp_dir.path())?;
+        
+        let hash = engine.store_with_options(b"Hello, SVDB!", HashAlgorithm::Sha256, 0)?;
+        assert_eq!(hash, calculate_hash_with_algorithm(b"Hello, SVDB!", HashAlgorithm::Sha256));
+        assert_eq!(engine.retrieve(&hash)?, b"Hello, SVDB!");
+        
+        let large_data = vec![6u8; 2 * 1024 * 1024 + 17];
+        let hash = engine.store_with_options(&large_data, HashAlgorithm::Sha256, 1024 * 1024)?;
+        assert_eq!(engine.retrieve_verified(&hash)?, large_data);
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_zstd_compression() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let engine = StorageEngine::with_compression(temp_dir.path(), Compression::Zstd)?;
+        
+        // Simple file: stored compressed, retrieved raw
+        let log_data = b"INFO request served\n".repeat(1000);
+        let hash = engine.store(&log_data)?;
+        let stored = engine.db.get(hash.as_bytes())?.unwrap();
+        assert!(stored.len() < log_data.len());
+        engine.cache.lock().unwrap().clear();
+        assert_eq!(engine.retrieve(&hash)?, log_data);
+        assert_eq!(engine.retrieve_verified(&hash)?, log_data);
+        
+        // Chunked file
+        let large_data = b"WARN disk almost full\n".repeat(200_000);
+        let hash = engine.store_with_options(&large_data, HashAlgorithm::Blake3, 1024 * 1024)?;
+        let metadata = engine.load_metadata(&format!("meta:{}", hash))?.unwrap();
+        assert_eq!(metadata.compression.as_deref(), Some("zstd"));
+        assert_eq!(metadata.size, large_data.len());
+        engine.cache.lock().unwrap().clear();
+        assert_eq!(engine.retrieve(&hash)?, large_data);
+        
+        // Per-call override stores raw
+        let options = StoreOptions { compression: Compression::None, ..Default::default() };
+        let hash = engine.store_with(b"raw bytes", &options)?;
+        assert_eq!(engine.db.get(hash.as_bytes())?.unwrap(), b"raw bytes");
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_content_defined_chunking_dedup() -> Result<()> {
+        use rand::{rngs::StdRng, RngCore, SeedableRng};
+        
+        let mut data = vec![0u8; 8 * 1024 * 1024];
+        StdRng::seed_from_u64(42).fill_bytes(&mut data);
+        let strategy = ChunkingStrategy::content_defined(64 * 1024);
+        
+        let original = chunk_data(&data, 64 * 1024, HashAlgorithm::Blake3, &strategy, None)?;
+        
+        // Insert one byte at the front
+        let mut shifted = vec![0xAB];
+        shifted.extend_from_slice(&data);
+        let edited = chunk_data(&shifted, 64 * 1024, HashAlgorithm::Blake3, &strategy, None)?;
+        
+        let before: std::collections::HashSet<_> = original.metadata.chunks.iter().collect();
+        let unchanged = edited.metadata.chunks.iter().filter(|h| before.contains(h)).count();
+        assert!(unchanged * 10 >= original.metadata.chunks.len() * 9);
+        
+        // Fixed-size chunking loses every chunk to the same edit
+        let fixed = ChunkingStrategy::Fixed;
+        let original = chunk_data(&data, 64 * 1024, HashAlgorithm::Blake3, &fixed, None)?;
+        let edited = chunk_data(&shifted, 64 * 1024, HashAlgorithm::Blake3, &fixed, None)?;
+        let before: std::collections::HashSet<_> = original.metadata.chunks.iter().collect();
+        assert_eq!(edited.metadata.chunks.iter().filter(|h| before.contains(h)).count(), 0);
+        
+        // Chunks respect the bounds and round-trip through the engine
+        if let ChunkingStrategy::ContentDefined { min_size, max_size, .. } = strategy {
+            let chunked = chunk_data(&data, 64 * 1024, HashAlgorithm::Blake3, &strategy, None)?;
+            let (last, rest) = chunked.chunks.split_last().unwrap();
+            assert!(last.len() <= max_size);
+            assert!(rest.iter().all(|c| c.len() >= min_size && c.len() <= max_size));
+        }
+        
+        let temp_dir = tempdir()?;
+        let engine = StorageEngine::new(temp_dir.path())?;
+        let options = StoreOptions { chunk_size: 64 * 1024, chunking: strategy, ..Default::default() };
+        let hash = engine.store_with(&data, &options)?;
+        let metadata = engine.load_metadata(&format!("meta:{}", hash))?.unwrap();
+        assert_eq!(metadata.chunking, strategy);
+        engine.cache.lock().unwrap().clear();
+        assert_eq!(engine.retrieve(&hash)?, data);
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_chunk_deduplication() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let engine = StorageEngine::new(temp_dir.path())?;
+        let chunk_size = 1024 * 1024;
+        
+        let count_chunk_bodies = |engine: &StorageEngine| {
+            engine.db.scan_prefix(b"chunk:").count()
+        };
+        
+        // Two files sharing their first two chunks
+        let shared = patterned(2 * chunk_size);
+        let mut first = shared.clone();
+        first.extend(vec![1u8; chunk_size]);
+        let mut second = shared.clone();
+        second.extend(vec![2u8; chunk_size]);
+        
+        let first_hash = engine.store_with_options(&first, HashAlgorithm::Blake3, chunk_size)?;
+        let second_hash = engine.store_with_options(&second, HashAlgorithm::Blake3, chunk_size)?;
+        assert_eq!(count_chunk_bodies(&engine), 4);
+        
+        // Storing the same file again takes no extra references
+        engine.store_with_options(&first, HashAlgorithm::Blake3, chunk_size)?;
+        assert_eq!(count_chunk_bodies(&engine), 4);
+        
+        // Deleting one file keeps the chunks the other still uses
+        assert!(engine.delete(&first_hash)?);
+        assert_eq!(count_chunk_bodies(&engine), 3);
+        engine.cache.lock().unwrap().clear();
+        assert_eq!(engine.retrieve(&second_hash)?, second);
+        
+        assert!(engine.delete(&second_hash)?);
+        assert_eq!(count_chunk_bodies(&engine), 0);
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_store_batch() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let engine = StorageEngine::new(temp_dir.path())?;
+        
+        let items: Vec<Vec<u8>> = (0..100).map(|i| format!("item {}", i).into_bytes()).collect();
+        let refs: Vec<&[u8]> = items.iter().map(|item| item.as_slice()).collect();
+        let hashes = engine.store_batch(&refs)?;
+        
+        assert_eq!(hashes.len(), items.len());
+        engine.cache.lock().unwrap().clear();
+        for (hash, item) in hashes.iter().zip(&items) {
+            assert_eq!(hash, &calculate_hash(item));
+            assert_eq!(&engine.retrieve(hash)?, item);
+        }
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_retrieve_stream() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let engine = StorageEngine::new(temp_dir.path())?;
+        
+        let large_data = patterned(3 * 1024 * 1024 + 5);
+        let hash = engine.store_with_options(&large_data, HashAlgorithm::Blake3, 1024 * 1024)?;
+        
+        let pieces: Vec<Vec<u8>> = engine.retrieve_stream(&hash)?.collect::<Result<_>>()?;
+        assert_eq!(pieces.len(), 4);
+        assert_eq!(pieces.concat(), large_data);
+        
+        let mut out = Vec::new();
+        assert_eq!(engine.retrieve_to_writer(&hash, &mut out)?, large_data.len() as u64);
+        assert_eq!(out, large_data);
+        
+        // Simple files come back as one piece
+        let hash = engine.store(b"small")?;
+        let pieces: Vec<Vec<u8>> = engine.retrieve_stream(&hash)?.collect::<Result<_>>()?;
+        assert_eq!(pieces, vec![b"small".to_vec()]);
+        
+        assert!(engine.retrieve_stream("missing").is_err());
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_gc_removes_orphans() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let engine = StorageEngine::new(temp_dir.path())?;
+        
+        let large_data = patterned(3 * 1024 * 1024);
+        let hash = engine.store_with_options(&large_data, HashAlgorithm::Blake3, 1024 * 1024)?;
+        
+        // Nothing to collect in a consistent store
+        let report = engine.gc()?;
+        assert_eq!(report.chunks_scanned, 3);
+        assert_eq!(report.chunks_removed, 0);
+        assert_eq!(report.refcounts_repaired, 0);
+        
+        // Simulate a crash after the chunks were written but before the metadata
+        engine.db.put(b"chunk:orphan", encode_chunk(b"orphan body", Compression::None)?)?;
+        engine.db.put(b"chunkref:orphan", 1u64.to_le_bytes())?;
+        
+        let report = engine.gc()?;
+        assert_eq!(report.chunks_scanned, 4);
+        assert_eq!(report.chunks_removed, 1);
+        assert_eq!(report.bytes_reclaimed, 12);
+        assert_eq!(report.refcounts_repaired, 1);
+        assert!(engine.db.get(b"chunk:orphan")?.is_none());
+        
+        engine.cache.lock().unwrap().clear();
+        assert_eq!(engine.retrieve(&hash)?, large_data);
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_chunked_store_is_atomic() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let engine = StorageEngine::new(temp_dir.path())?;
+        
+        let large_data = patterned(3 * 1024 * 1024);
+        let chunked = chunk_data(&large_data, 1024 * 1024, HashAlgorithm::Blake3, &ChunkingStrategy::Fixed, None)?;
+        
+        // A malformed reference count on the last chunk makes the store fail part way through
+        let last_chunk = &chunked.metadata.chunks[2];
+        engine.db.put(format!("chunkref:{}", last_chunk).as_bytes(), b"bad")?;
+        assert!(engine.store_with_options(&large_data, HashAlgorithm::Blake3, 1024 * 1024).is_err());
+        
+        // Nothing from the failed store is visible
+        assert!(engine.db.get(format!("meta:{}", chunked.metadata.hash).as_bytes())?.is_none());
+        assert!(engine.db.get(format!("chunk:{}", chunked.metadata.chunks[0]).as_bytes())?.is_none());
+        assert!(!engine.exists(&chunked.metadata.hash)?);
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_with_config() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let config = StorageConfig {
+            compression: Some(DBCompressionType::None),
+            block_cache_bytes: Some(8 * 1024 * 1024),
+            write_buffer_size: Some(4 * 1024 * 1024),
+            max_background_jobs: Some(2),
+            value_compression: Compression::Zstd,
+            hash_key: None,
+            chunk_threshold: None,
+            auto_chunk_threshold: None,
+            max_value_bytes: None,
+            prefixed_hashes: false,
+            verify_existing: false,
+            sync_writes: false,
+            namespace: None,
+            disable_cache: false,
+            cache_capacity: None,
+            retry: Some(RetryPolicy::default()),
+            existence_filter: None,
+            write_timeout: None,
+        };
+        let engine = StorageEngine::with_config(temp_dir.path(), config)?;
+        
+        let hash = engine.store(b"tuned")?;
+        engine.cache.lock().unwrap().clear();
+        assert_eq!(engine.retrieve(&hash)?, b"tuned");
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_list_hashes() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let engine = StorageEngine::new(temp_dir.path())?;
+        
+        let simple = engine.store(b"simple")?;
+        let compressed = engine.store_with(b"compressed", &StoreOptions {
+            algorithm: HashAlgorithm::Keccak256,
+            compression: Compression::Zstd,
+            ..Default::default()
+        })?;
+        let large_data = patterned(2 * 1024 * 1024);
+        let chunked = engine.store_with_options(&large_data, HashAlgorithm::Blake3, 1024 * 1024)?;
+        
+        let mut listed = engine.list_hashes()?;
+        listed.sort();
+        let mut expected = vec![simple.clone(), compressed, chunked.clone()];
+        expected.sort();
+        assert_eq!(listed, expected);
+        
+        // Prefix filtering
+        let prefixed: Vec<String> = engine.iter_hashes(&chunked[..8]).collect::<Result<_>>()?;
+        assert_eq!(prefixed, vec![chunked]);
+        
+        engine.delete(&simple)?;
+        assert_eq!(engine.list_hashes()?.len(), 2);
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_get_metadata() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let engine = StorageEngine::new(temp_dir.path())?;
+        
+        let large_data = patterned(2 * 1024 * 1024 + 10);
+        let hash = engine.store_with_options(&large_data, HashAlgorithm::Blake2b, 1024 * 1024)?;
+        let metadata = engine.get_metadata(&hash)?.unwrap();
+        assert_eq!(metadata.algorithm, "blake2b");
+        assert_eq!(metadata.size, large_data.len());
+        assert_eq!(metadata.chunks.len(), 3);
+        
+        // Simple files report their info record
+        let hash = engine.store_with_options(b"simple", HashAlgorithm::Sha256, 0)?;
+        let metadata = engine.get_metadata(&hash)?.unwrap();
+        assert_eq!(metadata.algorithm, "sha256");
+        assert_eq!(metadata.size, 6);
+        assert!(metadata.chunks.is_empty());
+        
+        assert!(engine.get_metadata("missing")?.is_none());
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_chunk_size_below_minimum() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let engine = StorageEngine::new(temp_dir.path())?;
+        
+        let data = vec![7u8; 4096];
+        match engine.store_with_options(&data, HashAlgorithm::Blake3, 512) {
+            Err(StorageError::ChunkingError(_)) => {},
+            other => panic!("expected ChunkingError, got {:?}", other),
+        }
+        
+        // The minimum itself is accepted and recorded as given
+        let hash = engine.store_with_options(&data, HashAlgorithm::Blake3, MIN_CHUNK_SIZE)?;
+        let metadata = engine.get_metadata(&hash)?.unwrap();
+        assert_eq!(metadata.chunk_size, MIN_CHUNK_SIZE);
+        assert_eq!(metadata.chunks.len(), 4);
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_encryption_at_rest() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let encryption = EncryptionConfig::new([9u8; 32]);
+        let engine = StorageEngine::with_encryption(temp_dir.path(), StorageConfig::default(), encryption)?;
+        
+        // Simple file: on-disk bytes differ from the plaintext
+        let secret = b"top secret document".to_vec();
+        let hash = engine.store(&secret)?;
+        assert_eq!(hash, calculate_hash(&secret));
+        let stored = engine.db.get(hash.as_bytes())?.unwrap();
+        assert_ne!(stored, secret);
+        assert!(!stored.windows(secret.len()).any(|window| window == secret.as_slice()));
+        engine.cache.lock().unwrap().clear();
+        assert_eq!(engine.retrieve(&hash)?, secret);
+        assert_eq!(engine.retrieve_verified(&hash)?, secret);
+        
+        // Chunked file
+        let large_data = patterned(2 * 1024 * 1024 + 3);
+        let hash = engine.store_with_options(&large_data, HashAlgorithm::Blake3, 1024 * 1024)?;
+        engine.cache.lock().unwrap().clear();
+        assert_eq!(engine.retrieve(&hash)?, large_data);
+        
+        // A different key cannot read the values
+        drop(engine);
+        let other = EncryptionConfig::new([1u8; 32]);
+        let engine = StorageEngine::with_encryption(temp_dir.path(), StorageConfig::default(), other)?;
+        assert!(matches!(engine.retrieve(&hash), Err(StorageError::EncryptionError(_))));
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_pin_blocks_delete() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let mut engine = StorageEngine::new(temp_dir.path())?;
+        
+        let hash = engine.store(b"shared blob")?;
+        assert_eq!(engine.pin(&hash)?, 1);
+        assert_eq!(engine.pin(&hash)?, 2);
+        assert!(matches!(engine.delete(&hash), Err(StorageError::Pinned(_))));
+        
+        assert_eq!(engine.unpin(&hash)?, 1);
+        assert!(matches!(engine.delete(&hash), Err(StorageError::Pinned(_))));
+        assert_eq!(engine.unpin(&hash)?, 0);
+        assert!(engine.delete(&hash)?);
+        
+        // Pins require the hash to exist
+        assert!(matches!(engine.pin(&hash), Err(StorageError::HashNotFound(_))));
+        
+        // gc keeps a pinned file and its chunks, even past its expiry
+        let kept = engine.store_with_ttl(&patterned(3 * 1024), HashAlgorithm::Blake3, 1024, 10)?;
+        assert_eq!(engine.pin(&kept)?, 1);
+        engine.clock = || u64::MAX;
+        let report = engine.gc()?;
+        assert_eq!((report.files_expired, report.chunks_removed), (0, 0));
+        assert!(engine.db.get_pinned(format!("meta:{}", kept).as_bytes())?.is_some());
+        
+        // ... until the pin is released
+        assert_eq!(engine.unpin(&kept)?, 0);
+        let report = engine.gc()?;
+        assert_eq!((report.files_expired, report.chunks_removed), (1, 3));
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_pin_races_delete() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let engine = StorageEngine::new(temp_dir.path())?;
+        
+        for i in 0..200u32 {
+            let hash = engine.store(&i.to_le_bytes())?;
+            let (pinned, deleted) = std::thread::scope(|scope| {
+                let pinning = scope.spawn(|| engine.pin(&hash));
+                let deleted = engine.delete(&hash);
+                (pinning.join().unwrap(), deleted)
+            });
+            
+            // Exactly one side wins, and a pin always finds its file
+            match (pinned, deleted) {
+                (Ok(1), Err(StorageError::Pinned(_))) => {
+                    assert!(engine.exists(&hash)?);
+                    assert_eq!(engine.unpin(&hash)?, 0);
+                },
+                (Err(StorageError::HashNotFound(_)), Ok(true)) => {
+                    assert!(!engine.exists(&hash)?);
+                    assert_eq!(engine.pin_count(&hash)?, 0);
+                },
+                (pinned, deleted) => panic!("pin {:?} raced delete {:?}", pinned, deleted),
+            }
+        }
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_rehash() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let engine = StorageEngine::new(temp_dir.path())?;
+        
+        // Simple file, keeping the old entry
+        let old_hash = engine.store_with_options(b"migrate me", HashAlgorithm::Keccak256, 0)?;
+        let new_hash = engine.rehash(&old_hash, HashAlgorithm::Blake3, false)?;
+        assert_eq!(new_hash, calculate_hash(b"migrate me"));
+        assert_eq!(engine.retrieve(&new_hash)?, b"migrate me");
+        assert!(engine.exists(&old_hash)?);
+        
+        // Chunked file, removing the old entry
+        let large_data = patterned(2 * 1024 * 1024 + 1);
+        let old_hash = engine.store_with_options(&large_data, HashAlgorithm::Keccak256, 1024 * 1024)?;
+        let new_hash = engine.rehash(&old_hash, HashAlgorithm::Blake3, true)?;
+        assert!(!engine.exists(&old_hash)?);
+        let metadata = engine.get_metadata(&new_hash)?.unwrap();
+        assert_eq!(metadata.algorithm, "blake3");
+        assert_eq!(metadata.chunk_size, 1024 * 1024);
+        assert_eq!(engine.retrieve_verified(&new_hash)?, large_data);
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_size_of() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let engine = StorageEngine::new(temp_dir.path())?;
+        
+        let hash = engine.store(b"twelve bytes")?;
+        engine.cache.lock().unwrap().clear();
+        assert_eq!(engine.size_of(&hash)?, 12);
+        
+        let compressed = engine.store_with(&[0u8; 5000], &StoreOptions {
+            compression: Compression::Zstd,
+            ..Default::default()
+        })?;
+        engine.cache.lock().unwrap().clear();
+        assert_eq!(engine.size_of(&compressed)?, 5000);
+        
+        let large_data = vec![1u8; 3 * 1024 * 1024 + 7];
+        let chunked = engine.store_with_options(&large_data, HashAlgorithm::Blake3, 1024 * 1024)?;
+        assert_eq!(engine.size_of(&chunked)?, large_data.len());
+        
+        assert!(engine.size_of("missing").is_err());
+        
+        // Encrypted values report the plaintext length
+        drop(engine);
+        let encrypted_dir = tempdir()?;
+        let engine = StorageEngine::with_encryption(
+            encrypted_dir.path(),
+            StorageConfig::default(),
+            EncryptionConfig::new([3u8; 32]),
+        )?;
+        let hash = engine.store(b"twelve bytes")?;
+        engine.cache.lock().unwrap().clear();
+        assert_eq!(engine.size_of(&hash)?, 12);
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_keyed_hashing() -> Result<()> {
+        let data = b"tenant scoped content";
+        let key_a = [1u8; 32];
+        let key_b = [2u8; 32];
+        
+        let plain = calculate_hash(data);
+        let keyed_a = calculate_keyed_hash(data, HashAlgorithm::Blake3, Some(&key_a))?;
+        let keyed_b = calculate_keyed_hash(data, HashAlgorithm::Blake3, Some(&key_b))?;
+        assert_ne!(plain, keyed_a);
+        assert_ne!(keyed_a, keyed_b);
+        
+        let blake2b_a = calculate_keyed_hash(data, HashAlgorithm::Blake2b, Some(&key_a))?;
+        assert_eq!(blake2b_a.len(), 128);
+        assert_ne!(blake2b_a, calculate_hash_with_algorithm(data, HashAlgorithm::Blake2b));
+        assert!(calculate_keyed_hash(data, HashAlgorithm::Sha256, Some(&key_a)).is_err());
+        
+        let temp_dir = tempdir()?;
+        let engine = StorageEngine::with_config(temp_dir.path(), StorageConfig {
+            hash_key: Some(key_a),
+            ..Default::default()
+        })?;
+        
+        let hash = engine.store(data)?;
+        assert_eq!(hash, keyed_a);
+        assert!(!engine.exists(&plain)?);
+        engine.cache.lock().unwrap().clear();
+        assert_eq!(engine.retrieve_verified(&hash)?, data);
+        
+        // Chunked files are keyed too
+        let large_data = patterned(4 * 1024 * 1024);
+        let chunked = engine.store_with_options(&large_data, HashAlgorithm::Blake2b, 1024 * 1024)?;
+        assert_eq!(engine.retrieve_verified(&chunked)?, large_data);
+        assert!(engine.store_with_options(data, HashAlgorithm::Keccak256, 0).is_err());
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_simple_file_records_algorithm() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let engine = StorageEngine::new(temp_dir.path())?;
+        
+        // Keccak256 and Blake3 digests have the same length, so only the
+        // info record can tell them apart
+        let hash = engine.store_with_options(b"keccak simple", HashAlgorithm::Keccak256, 0)?;
+        let metadata = engine.get_metadata(&hash)?.unwrap();
+        assert_eq!(metadata.algorithm, "keccak256");
+        assert_eq!(metadata.size, 13);
+        assert!(metadata.timestamp > 0);
+        assert!(metadata.compression.is_none());
+        
+        engine.cache.lock().unwrap().clear();
+        assert_eq!(engine.retrieve_verified(&hash)?, b"keccak simple");
+        
+        // Files stored before info records existed still get a synthesized one
+        engine.db.delete(format!("info:{}", hash).as_bytes())?;
+        let metadata = engine.get_metadata(&hash)?.unwrap();
+        assert_eq!(metadata.algorithm, "keccak256");
+        assert_eq!(metadata.timestamp, 0);
+        
+        assert!(engine.delete(&hash)?);
+        assert!(engine.db.get(format!("info:{}", hash).as_bytes())?.is_none());
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_import_export() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let engine = StorageEngine::new(temp_dir.path().join("db"))?;
+        
+        let large_data = patterned(3 * 1024 * 1024 + 100);
+        let source = temp_dir.path().join("source.bin");
+        std::fs::write(&source, &large_data)?;
+        
+        // Importing chunk by chunk gives the same address as storing in memory
+        let hash = engine.import(&source, HashAlgorithm::Blake3, 1024 * 1024)?;
+        assert_eq!(hash, engine.store_with_options(&large_data, HashAlgorithm::Blake3, 1024 * 1024)?);
+        assert_eq!(engine.get_metadata(&hash)?.unwrap().chunks.len(), 4);
+        
+        let target = temp_dir.path().join("target.bin");
+        assert_eq!(engine.export(&hash, &target)?, large_data.len() as u64);
+        assert_eq!(std::fs::read(&target)?, large_data);
+        
+        let small = temp_dir.path().join("small.txt");
+        std::fs::write(&small, b"small file")?;
+        let hash = engine.import(&small, HashAlgorithm::Blake3, 0)?;
+        assert_eq!(hash, calculate_hash(b"small file"));
+        
+        let missing = temp_dir.path().join("missing.bin");
+        assert!(engine.export("missing", &missing).is_err());
+        assert!(!missing.exists());
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_would_store() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let engine = StorageEngine::new(temp_dir.path())?;
+        
+        let large_data = patterned(2 * 1024 * 1024 + 10);
+        let (predicted, exists) = engine.would_store(&large_data, HashAlgorithm::Keccak256, 1024 * 1024)?;
+        assert!(!exists);
+        assert!(engine.get_metadata(&predicted)?.is_none());
+        
+        let hash = engine.store_with_options(&large_data, HashAlgorithm::Keccak256, 1024 * 1024)?;
+        assert_eq!(hash, predicted);
+        assert_eq!(engine.would_store(&large_data, HashAlgorithm::Keccak256, 1024 * 1024)?, (hash, true));
+        
+        let (predicted, exists) = engine.would_store(b"small", HashAlgorithm::Blake3, 1024 * 1024)?;
+        assert_eq!(predicted, calculate_hash(b"small"));
+        assert!(!exists);
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_chunked_file_hash_commits_to_chunking() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let engine = StorageEngine::new(temp_dir.path())?;
+        
+        let data = patterned(4 * 1024 * 1024);
+        let hash_1m = engine.store_with_options(&data, HashAlgorithm::Blake3, 1024 * 1024)?;
+        let hash_2m = engine.store_with_options(&data, HashAlgorithm::Blake3, 2 * 1024 * 1024)?;
+        assert_ne!(hash_1m, hash_2m);
+        assert_eq!(engine.would_store(&data, HashAlgorithm::Blake3, 1024 * 1024)?.0, hash_1m);
+        
+        let metadata = engine.get_metadata(&hash_1m)?.unwrap();
+        assert_eq!(metadata.hash_scheme, FileHashScheme::LengthPrefixed);
+        assert_ne!(hash_1m, combine_chunk_hashes(&metadata.chunks, metadata.chunk_size, FileHashScheme::Joined, HashAlgorithm::Blake3, None)?);
+        assert_eq!(engine.retrieve_verified(&hash_1m)?, data);
+        
+        // Records written before the scheme was recorded still verify
+        let legacy_hash = combine_chunk_hashes(&metadata.chunks, metadata.chunk_size, FileHashScheme::Joined, HashAlgorithm::Blake3, None)?;
+        let mut legacy: serde_json::Value = serde_json::to_value(&metadata).unwrap();
+        legacy["hash"] = serde_json::Value::String(legacy_hash.clone());
+        legacy.as_object_mut().unwrap().remove("hash_scheme");
+        engine.db.put(format!("meta:{}", legacy_hash).as_bytes(), serde_json::to_vec(&legacy).unwrap())?;
+        assert_eq!(engine.retrieve_verified(&legacy_hash)?, data);
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_put_and_commit_chunks() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let engine = StorageEngine::new(temp_dir.path())?;
+        
+        let data = patterned(3 * 1024 * 1024 + 10);
+        let chunk_hashes = data.chunks(1024 * 1024)
+            .map(|chunk| engine.put_chunk(chunk, HashAlgorithm::Blake3))
+            .collect::<Result<Vec<_>>>()?;
+        
+        let mut with_missing = chunk_hashes.clone();
+        with_missing.push(calculate_hash(b"never uploaded"));
+        match engine.commit_chunked(&with_missing, HashAlgorithm::Blake3) {
+            Err(StorageError::ChunkingError(message)) => assert!(message.contains(&with_missing[4])),
+            other => panic!("expected ChunkingError, got {:?}", other),
+        }
+        
+        // Committed uploads match a direct store of the same bytes
+        let hash = engine.commit_chunked(&chunk_hashes, HashAlgorithm::Blake3)?;
+        assert_eq!(engine.would_store(&data, HashAlgorithm::Blake3, 1024 * 1024)?, (hash.clone(), true));
+        assert_eq!(engine.get_metadata(&hash)?.unwrap().size, data.len());
+        assert_eq!(engine.retrieve_verified(&hash)?, data);
+        
+        assert_eq!(engine.commit_chunked(&chunk_hashes, HashAlgorithm::Blake3)?, hash);
+        assert_eq!(engine.chunk_refcount(&chunk_hashes[0])?, 1);
+        
+        // Chunk hashes that aren't hex never reach a record key
+        for bad in ["", "not-hex", "../chunk", "blake3:"] {
+            let mut with_bad = chunk_hashes.clone();
+            with_bad[1] = bad.to_string();
+            assert!(matches!(engine.commit_chunked(&with_bad, HashAlgorithm::Blake3), Err(StorageError::InvalidHash(_))));
+        }
+        assert!(matches!(engine.commit_chunked(&["zz".to_string()], HashAlgorithm::Blake3), Err(StorageError::InvalidHash(_))));
+        let prefixed: Vec<_> = chunk_hashes.iter().map(|chunk_hash| format!("blake3:{}", chunk_hash)).collect();
+        assert_eq!(engine.commit_chunked(&prefixed, HashAlgorithm::Blake3)?, hash);
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_stats() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let engine = StorageEngine::new(temp_dir.path())?;
+        assert_eq!(engine.stats()?.simple_files, 0);
+        
+        engine.store(b"first")?;
+        engine.store_with(&[0u8; 4096], &StoreOptions {
+            compression: Compression::Zstd,
+            ..Default::default()
+        })?;
+        let large_data = patterned(2 * 1024 * 1024 + 10);
+        engine.store_with_options(&large_data, HashAlgorithm::Blake3, 1024 * 1024)?;
+        
+        let stats = engine.stats()?;
+        assert_eq!(stats.simple_files, 2);
+        assert_eq!(stats.chunked_files, 1);
+        assert_eq!(stats.total_chunks, 3);
+        assert_eq!(stats.logical_bytes, 5 + 4096 + large_data.len() as u64);
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_chunk_threshold() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let engine = StorageEngine::with_config(temp_dir.path(), StorageConfig {
+            chunk_threshold: Some(4 * 1024 * 1024),
+            ..Default::default()
+        })?;
+        
+        // Below the threshold a small chunk size is ignored
+        let data = patterned(3 * 1024 * 1024);
+        let hash = engine.store_with_options(&data, HashAlgorithm::Blake3, 64 * 1024)?;
+        assert_eq!(hash, calculate_hash(&data));
+        assert!(engine.get_metadata(&hash)?.unwrap().chunks.is_empty());
+        assert_eq!(engine.would_store(&data, HashAlgorithm::Blake3, 64 * 1024)?, (hash, true));
+        
+        // Above it the requested chunk size is used
+        let data = patterned(5 * 1024 * 1024);
+        let hash = engine.store_with_options(&data, HashAlgorithm::Blake3, 1024 * 1024)?;
+        let metadata = engine.get_metadata(&hash)?.unwrap();
+        assert_eq!(metadata.chunk_size, 1024 * 1024);
+        assert_eq!(metadata.chunks.len(), 5);
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_poisoned_cache_is_recovered() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let engine = Arc::new(StorageEngine::new(temp_dir.path())?);
+        let hash = engine.store(b"survives a panic")?;
+        
+        let poisoner = engine.clone();
+        let result = std::thread::spawn(move || {
+            let _cache = poisoner.cache.lock().unwrap();
+            panic!("poison the cache");
+        }).join();
+        assert!(result.is_err());
+        assert!(engine.cache.is_poisoned());
+        
+        assert_eq!(engine.retrieve(&hash)?, b"survives a panic");
+        let hash = engine.store(b"still writable")?;
+        assert_eq!(engine.retrieve(&hash)?, b"still writable");
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_column_family_migration() -> Result<()> {
+        let temp_dir = tempdir()?;
+        
+        // A store laid out the old way, with everything in the default family
+        let large_data = patterned(2 * 1024 * 1024 + 10);
+        let chunked = chunk_data(&large_data, 1024 * 1024, HashAlgorithm::Blake3, &ChunkingStrategy::Fixed, None)?;
+        {
+            let mut opts = Options::default();
+            opts.create_if_missing(true);
+            let db = rocksdb::DB::open(&opts, temp_dir.path())?;
+            db.put(calculate_hash(b"old simple"), b"old simple")?;
+            for (chunk_hash, chunk) in chunked.metadata.chunks.iter().zip(&chunked.chunks) {
+                db.put(format!("chunk:{}", chunk_hash), encode_chunk(chunk, Compression::None)?)?;
+                db.put(format!("chunkref:{}", chunk_hash), 1u64.to_le_bytes())?;
+            }
+            db.put(format!("meta:{}", chunked.metadata.hash), serialize_metadata(&chunked.metadata)?)?;
+        }
+        
+        let engine = StorageEngine::new(temp_dir.path())?;
+        assert_eq!(engine.retrieve(&calculate_hash(b"old simple"))?, b"old simple");
+        assert_eq!(engine.retrieve_verified(&chunked.metadata.hash)?, large_data);
+        assert_eq!(engine.list_hashes()?.len(), 2);
+        
+        let stats = engine.stats()?;
+        assert_eq!((stats.simple_files, stats.chunked_files, stats.total_chunks), (1, 1, 3));
+        assert_eq!(engine.gc()?.chunks_removed, 0);
+        
+        Ok(())
+    }
+    
+    static TEST_CLOCK: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(1_000);
+    
+    fn test_clock() -> u64 {
+        TEST_CLOCK.load(std::sync::atomic::Ordering::SeqCst)
+    }
+    
+    #[test]
+    fn test_ttl_expiry() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let mut engine = StorageEngine::new(temp_dir.path())?;
+        engine.clock = test_clock;
+        
+        let simple = engine.store_with_ttl(b"ephemeral", HashAlgorithm::Blake3, 0, 10)?;
+        let large_data = patterned(2 * 1024 * 1024 + 10);
+        let chunked = engine.store_with_ttl(&large_data, HashAlgorithm::Blake3, 1024 * 1024, 10)?;
+        let permanent = engine.store(b"permanent")?;
+        assert_eq!(engine.get_metadata(&simple)?.unwrap().expires_at, Some(1_010));
+        assert_eq!(engine.retrieve(&simple)?, b"ephemeral");
+        assert_eq!(engine.retrieve(&chunked)?, large_data);
+        
+        TEST_CLOCK.store(1_010, std::sync::atomic::Ordering::SeqCst);
+        assert!(matches!(engine.retrieve(&simple), Err(StorageError::HashNotFound(_))));
+        assert!(matches!(engine.retrieve(&chunked), Err(StorageError::HashNotFound(_))));
+        assert!(!engine.exists(&simple)?);
+        assert!(!engine.exists(&chunked)?);
+        
+        let report = engine.gc()?;
+        assert_eq!(report.files_expired, 2);
+        assert_eq!(report.chunks_removed, 3);
+        assert!(engine.get_metadata(&simple)?.is_none());
+        assert!(engine.get_metadata(&chunked)?.is_none());
+        assert_eq!(engine.retrieve(&permanent)?, b"permanent");
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_content_type_and_tags() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let engine = StorageEngine::new(temp_dir.path())?;
+        
+        let image = engine.store_with_metadata(b"png bytes", HashAlgorithm::Blake3, 0, Some("image/png"), &["thumbnail"])?;
+        let large_data = patterned(2 * 1024 * 1024 + 10);
+        let video = engine.store_with_metadata(&large_data, HashAlgorithm::Blake3, 1024 * 1024, Some("video/mp4"), &["upload"])?;
+        engine.store(b"untagged")?;
+        
+        let metadata = engine.get_metadata(&image)?.unwrap();
+        assert_eq!(metadata.content_type.as_deref(), Some("image/png"));
+        assert_eq!(metadata.tags, vec!["thumbnail"]);
+        
+        // Storing again adds tags and keeps the content type
+        engine.store_with_metadata(&large_data, HashAlgorithm::Blake3, 1024 * 1024, None, &["thumbnail"])?;
+        let metadata = engine.get_metadata(&video)?.unwrap();
+        assert_eq!(metadata.content_type.as_deref(), Some("video/mp4"));
+        assert_eq!(metadata.tags, vec!["upload", "thumbnail"]);
+        
+        let mut tagged = engine.find_by_tag("thumbnail")?;
+        tagged.sort();
+        let mut expected = vec![image, video.clone()];
+        expected.sort();
+        assert_eq!(tagged, expected);
+        assert_eq!(engine.find_by_tag("upload")?, vec![video]);
+        assert!(engine.find_by_tag("missing")?.is_empty());
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_compact() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let engine = StorageEngine::new(temp_dir.path())?;
+        
+        let hashes = (0..100u32)
+            .map(|i| engine.store(&i.to_le_bytes().repeat(256)))
+            .collect::<Result<Vec<_>>>()?;
+        for hash in &hashes[..50] {
+            engine.delete(hash)?;
+        }
+        
+        engine.compact()?;
+        
+        assert!(!engine.exists(&hashes[0])?);
+        assert_eq!(engine.retrieve(&hashes[99])?, 99u32.to_le_bytes().repeat(256));
+        assert_eq!(engine.list_hashes()?.len(), 50);
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_store_reader() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let engine = StorageEngine::new(temp_dir.path())?;
+        
+        let large_data = patterned(3 * 1024 * 1024 + 100);
+        let hash = engine.store_reader(large_data.as_slice(), HashAlgorithm::Blake3, 1024 * 1024)?;
+        assert_eq!(engine.get_metadata(&hash)?.unwrap().chunks.len(), 4);
+        assert_eq!(engine.retrieve(&hash)?, large_data);
+        
+        // Streaming and in-memory stores agree, and storing again takes no new references
+        assert_eq!(hash, engine.store_with_options(&large_data, HashAlgorithm::Blake3, 1024 * 1024)?);
+        let first_chunk = engine.get_metadata(&hash)?.unwrap().chunks[0].clone();
+        assert_eq!(engine.chunk_refcount(&first_chunk)?, 1);
+        
+        // Input that ends exactly at the chunk size is not chunked
+        let exact = &large_data[..1024 * 1024];
+        let hash = engine.store_reader(exact, HashAlgorithm::Blake3, 1024 * 1024)?;
+        assert_eq!(hash, calculate_hash(exact));
+        
+        let hash = engine.store_reader(&b"small file"[..], HashAlgorithm::Sha256, 0)?;
+        assert_eq!(hash, calculate_hash_with_algorithm(b"small file", HashAlgorithm::Sha256));
+        assert_eq!(engine.retrieve(&hash)?, b"small file");
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_empty_file_round_trip() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let algorithms = [HashAlgorithm::Blake3, HashAlgorithm::Blake2b, HashAlgorithm::Keccak256, HashAlgorithm::Sha256];
+        
+        for compression in [Compression::None, Compression::Zstd] {
+            let path = temp_dir.path().join(format!("{:?}", compression));
+            let mut hashes = Vec::new();
+            {
+                let engine = StorageEngine::with_compression(&path, compression)?;
+                for algorithm in algorithms {
+                    // A chunk size never applies to empty input
+                    let hash = engine.store_with_options(b"", algorithm, 1024 * 1024)?;
+                    assert_eq!(hash, calculate_hash_with_algorithm(b"", algorithm));
+                    assert_eq!(engine.retrieve(&hash)?, Vec::<u8>::new());
+                    hashes.push(hash);
+                }
+            }
+            
+            // Reopen so every read goes to disk instead of the cache
+            let engine = StorageEngine::with_compression(&path, compression)?;
+            for hash in &hashes {
+                assert!(engine.exists(hash)?);
+                assert_eq!(engine.size_of(hash)?, 0);
+                assert_eq!(engine.get_metadata(hash)?.unwrap().size, 0);
+                assert_eq!(engine.retrieve_verified(hash)?, Vec::<u8>::new());
+                assert_eq!(engine.retrieve_stream(hash)?.count(), 0);
+                assert_eq!(engine.retrieve(hash)?, Vec::<u8>::new());
+            }
+            assert!(matches!(engine.retrieve(&calculate_hash(b"missing")), Err(StorageError::HashNotFound(_))));
+        }
+        
+        let engine = StorageEngine::with_encryption(temp_dir.path().join("encrypted"), StorageConfig::default(), EncryptionConfig::new([5u8; 32]))?;
+        let hash = engine.store(b"")?;
+        engine.lock_cache().clear();
+        assert_eq!(engine.size_of(&hash)?, 0);
+        assert_eq!(engine.retrieve_verified(&hash)?, Vec::<u8>::new());
+        
+        assert_eq!(engine.store_reader(&b""[..], HashAlgorithm::Blake3, 1024 * 1024)?, calculate_hash(b""));
+        assert!(matches!(engine.commit_chunked(&[], HashAlgorithm::Blake3), Err(StorageError::ChunkingError(_))));
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_warm() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let engine = StorageEngine::new(temp_dir.path())?;
+        
+        let large_data = patterned(3 * 1024 * 1024);
+        let hashes = vec![
+            engine.store(b"small file")?,
+            engine.store_with_options(&large_data, HashAlgorithm::Blake3, 1024 * 1024)?,
+            calculate_hash(b"never stored"),
+        ];
+        engine.lock_cache().clear();
+        
+        assert_eq!(engine.warm(&hashes)?, 2);
+        let mut cache = engine.lock_cache();
+        assert_eq!(cache.get(&hashes[0]).map(Vec::as_slice), Some(&b"small file"[..]));
+        assert_eq!(cache.get(&hashes[1]), Some(&large_data));
+        assert!(!cache.contains_key(&hashes[2]));
+        drop(cache);
+        
+        // Files already cached aren't read again
+        let misses = engine.cache_metrics().misses;
+        assert_eq!(engine.warm(&hashes)?, 0);
+        assert_eq!(engine.cache_metrics().misses, misses);
+        
+        // Warming stops at the cache capacity instead of evicting what it loaded
+        let small_dir = tempdir()?;
+        let small = StorageEngine::builder()
+            .cache_capacity(2500)
+            .open(small_dir.path())?;
+        let hashes: Vec<String> = (0..4u8)
+            .map(|i| small.store(&[i; 1000]))
+            .collect::<Result<_>>()?;
+        small.lock_cache().clear();
+        assert_eq!(small.warm(&hashes)?, 2);
+        let cache = small.lock_cache();
+        assert!(cache.contains_key(&hashes[0]) && cache.contains_key(&hashes[1]));
+        assert!(!cache.contains_key(&hashes[2]) && !cache.contains_key(&hashes[3]));
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_chunk_writes_with_shared_chunks() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let engine = StorageEngine::with_encryption(
+            temp_dir.path(),
+            StorageConfig { value_compression: Compression::Zstd, ..Default::default() },
+            EncryptionConfig::new([7u8; 32]),
+        )?;
+        
+        // Two identical halves share their chunks, and a second file shares some more
+        let half = patterned(4 * 1024 * 1024);
+        let data = [half.as_slice(), half.as_slice()].concat();
+        let hash = engine.store_with_options(&data, HashAlgorithm::Blake3, 1024 * 1024)?;
+        let other = engine.store_with_options(&[half.as_slice(), b"tail"].concat(), HashAlgorithm::Blake3, 1024 * 1024)?;
+        
+        let metadata = engine.get_metadata(&hash)?.unwrap();
+        assert_eq!(metadata.chunks.len(), 8);
+        assert_eq!(engine.chunk_refcount(&metadata.chunks[0])?, 3);
+        
+        engine.lock_cache().clear();
+        assert_eq!(engine.retrieve_verified(&hash)?, data);
+        assert!(engine.delete(&other)?);
+        assert_eq!(engine.gc()?.chunks_removed, 0);
+        assert_eq!(engine.retrieve_verified(&hash)?, data);
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_prefixed_hashes() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let engine = StorageEngine::with_config(temp_dir.path(), StorageConfig {
+            prefixed_hashes: true,
+            ..Default::default()
+        })?;
+        
+        let id = engine.store_with_options(b"prefixed", HashAlgorithm::Sha256, 0)?;
+        let bare = calculate_hash_with_algorithm(b"prefixed", HashAlgorithm::Sha256);
+        assert_eq!(id, format!("sha256:{}", bare));
+        assert_eq!(parse_identifier(&id)?, (bare.as_str(), Some(HashAlgorithm::Sha256)));
+        
+        // Prefixed and bare forms find the same record
+        engine.lock_cache().clear();
+        assert_eq!(engine.retrieve_verified(&id)?, b"prefixed");
+        assert_eq!(engine.retrieve(&bare)?, b"prefixed");
+        assert_eq!(engine.size_of(&id)?, 8);
+        
+        // The prefix has to match the algorithm the file was stored with
+        assert!(matches!(engine.retrieve_verified(&format!("keccak256:{}", bare)), Err(StorageError::InvalidAlgorithm(_))));
+        assert!(matches!(engine.retrieve(&format!("md5:{}", bare)), Err(StorageError::InvalidAlgorithm(_))));
+        
+        let large_data = patterned(3 * 1024 * 1024);
+        let id = engine.store_with_options(&large_data, HashAlgorithm::Blake3, 1024 * 1024)?;
+        assert!(id.starts_with("blake3:"));
+        assert_eq!(engine.retrieve_verified(&id)?, large_data);
+        assert!(engine.delete(&id)?);
+        assert!(!engine.exists(&id)?);
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_fsck() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let engine = StorageEngine::new(temp_dir.path())?;
+        
+        let large_data = patterned(3 * 1024 * 1024);
+        let chunked = engine.store_with_options(&large_data, HashAlgorithm::Blake3, 1024 * 1024)?;
+        let simple = engine.store(b"simple file")?;
+        let untouched = engine.store(b"untouched")?;
+        
+        let report = engine.fsck()?;
+        assert!(report.is_clean());
+        assert_eq!(report.files_checked, 3);
+        assert_eq!(report.chunks_checked, 3);
+        
+        // Damage one chunk, drop another and overwrite a simple value
+        let chunks = engine.get_metadata(&chunked)?.unwrap().chunks;
+        engine.db.put(format!("chunk:{}", chunks[0]).as_bytes(), encode_chunk(b"garbage", Compression::None)?)?;
+        engine.db.delete(format!("chunk:{}", chunks[2]).as_bytes())?;
+        engine.db.put(simple.as_bytes(), b"tampered")?;
+        
+        let report = engine.fsck()?;
+        assert_eq!(report.files_checked, 3);
+        assert_eq!(report.findings, vec![
+            FsckFinding {
+                hash: chunked.clone(),
+                problem: FsckProblem::ChunkHashMismatch { index: 0, actual: calculate_hash(b"garbage") },
+            },
+            FsckFinding {
+                hash: chunked.clone(),
+                problem: FsckProblem::MissingChunk { index: 2 },
+            },
+            FsckFinding {
+                hash: simple.clone(),
+                problem: FsckProblem::FileHashMismatch { actual: calculate_hash(b"tampered") },
+            },
+        ]);
+        assert!(report.findings.iter().all(|finding| finding.hash != untouched));
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_incremental_hasher() -> Result<()> {
+        let data = patterned(200_000);
+        let key = [4u8; 32];
+        
+        // Published known answers, so the hasher isn't only checked against our own one-shot hashing
+        let vectors = [
+            (HashAlgorithm::Blake3, "", "af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262"),
+            (HashAlgorithm::Blake3, "abc", "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85"),
+            (HashAlgorithm::Blake2b, "", "786a02f742015903c6c6fd852552d272912f4740e15847618a86e217f71f5419d25e1031afee585313896444934eb04b903a685b1448b755d56f701afe9be2ce"),
+            (HashAlgorithm::Blake2b, "abc", "ba80a53f981c4d0d6a2797b69f12f6e94c212f14685ac4b74b12bb6fdbffa2d17d87c5392aab792dc252d5de4533cc9518d38aa8dbf1925ab92386edd4009923"),
+            (HashAlgorithm::Keccak256, "", "c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470"),
+            (HashAlgorithm::Keccak256, "abc", "4e03657aea45a94fc7d47ba826c8d667c0d1e6e33a64a036ec44f58fa12d6c45"),
+            (HashAlgorithm::Sha256, "", "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"),
+            (HashAlgorithm::Sha256, "abc", "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"),
+        ];
+        for (algorithm, input, expected) in vectors {
+            let mut hasher = Hasher::new(algorithm);
+            for byte in input.as_bytes() {
+                hasher.update(std::slice::from_ref(byte));
+            }
+            assert_eq!(hasher.finalize(), expected, "{} of {:?}", algorithm.as_str(), input);
+            assert_eq!(calculate_hash_with_algorithm(input.as_bytes(), algorithm), expected);
+        }
+        
+        for algorithm in [HashAlgorithm::Blake3, HashAlgorithm::Blake2b, HashAlgorithm::Keccak256, HashAlgorithm::Sha256] {
+            for piece in [1, 7, 4096, 65_537, data.len()] {
+                let mut hasher = Hasher::new(algorithm);
+                hasher.update(b"");
+                for chunk in data.chunks(piece) {
+                    hasher.update(chunk);
+                }
+                assert_eq!(hasher.finalize(), calculate_hash_with_algorithm(&data, algorithm));
+            }
+            
+            let mut hasher = Hasher::new(algorithm);
+            std::io::copy(&mut data.as_slice(), &mut hasher)?;
+            assert_eq!(hasher.finalize(), calculate_hash_with_algorithm(&data, algorithm));
+            
+            assert_eq!(Hasher::new(algorithm).finalize(), calculate_hash_with_algorithm(b"", algorithm));
+        }
+        
+        for algorithm in [HashAlgorithm::Blake3, HashAlgorithm::Blake2b] {
+            let mut hasher = Hasher::keyed(algorithm, Some(&key))?;
+            for chunk in data.chunks(1000) {
+                hasher.update(chunk);
+            }
+            let keyed = hasher.finalize();
+            assert_eq!(keyed, calculate_keyed_hash(&data, algorithm, Some(&key))?);
+            assert_ne!(keyed, calculate_hash_with_algorithm(&data, algorithm));
+        }
+        assert!(Hasher::keyed(HashAlgorithm::Sha256, Some(&key)).is_err());
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_retrieve_batch() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let engine = StorageEngine::with_compression(temp_dir.path(), Compression::Zstd)?;
+        
+        let large_data = patterned(3 * 1024 * 1024);
+        let hashes = vec![
+            engine.store(b"first")?,
+            calculate_hash(b"missing"),
+            engine.store_with_options(&large_data, HashAlgorithm::Blake3, 1024 * 1024)?,
+            engine.store(b"")?,
+            engine.store(b"first")?,
+        ];
+        engine.lock_cache().clear();
+        
+        let results = engine.retrieve_batch(&hashes);
+        assert_eq!(results.len(), 5);
+        assert_eq!(results[0].as_ref().unwrap(), b"first");
+        assert!(matches!(results[1], Err(StorageError::HashNotFound(_))));
+        assert_eq!(results[2].as_ref().unwrap(), &large_data);
+        assert_eq!(results[3].as_ref().unwrap(), b"");
+        assert_eq!(results[4].as_ref().unwrap(), b"first");
+        
+        // Hits were cached, so a second batch is served from the cache
+        assert!(engine.lock_cache().contains_key(&hashes[0]));
+        assert!(engine.lock_cache().contains_key(&hashes[2]));
+        assert_eq!(engine.retrieve_batch(&hashes[..1])[0].as_ref().unwrap(), b"first");
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_read_only() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let large_data = patterned(3 * 1024 * 1024);
+        let (simple, chunked) = {
+            let engine = StorageEngine::new(temp_dir.path())?;
+            (engine.store(b"read me")?, engine.store_with_options(&large_data, HashAlgorithm::Blake3, 1024 * 1024)?)
+        };
+        
+        // A read-only handle can sit alongside the writer
+        let writer = StorageEngine::new(temp_dir.path())?;
+        let reader = StorageEngine::open_read_only(temp_dir.path())?;
+        
+        assert_eq!(reader.retrieve(&simple)?, b"read me");
+        assert!(reader.lock_cache().contains_key(&simple));
+        assert_eq!(reader.retrieve_verified(&chunked)?, large_data);
+        assert!(reader.fsck()?.is_clean());
+        
+        assert!(matches!(reader.store(b"nope"), Err(StorageError::ReadOnly(_))));
+        assert!(matches!(reader.delete(&simple), Err(StorageError::ReadOnly(_))));
+        assert!(matches!(reader.gc(), Err(StorageError::ReadOnly(_))));
+        assert!(matches!(reader.compact(), Err(StorageError::ReadOnly(_))));
+        assert!(matches!(reader.pin(&simple), Err(StorageError::ReadOnly(_))));
+        assert!(writer.exists(&simple)?);
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_cache_metrics() -> Result<()> {
+        #[derive(Default)]
+        struct Recorder {
+            hits: AtomicU64,
+            missed_bytes: AtomicU64,
+        }
+        
+        impl CacheObserver for Recorder {
+            fn hit(&self, _bytes: u64) {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+            }
+            
+            fn miss(&self, bytes: u64) {
+                self.missed_bytes.fetch_add(bytes, Ordering::Relaxed);
+            }
+        }
+        
+        let temp_dir = tempdir()?;
+        let mut engine = StorageEngine::new(temp_dir.path())?;
+        let recorder = Arc::new(Recorder::default());
+        engine.set_cache_observer(recorder.clone());
+        
+        let hash = engine.store(b"metrics")?;
+        engine.retrieve(&hash)?;
+        engine.lock_cache().clear();
+        engine.retrieve(&hash)?;
+        engine.retrieve(&hash)?;
+        assert!(engine.retrieve(&calculate_hash(b"missing")).is_err());
+        
+        assert_eq!(engine.cache_metrics(), CacheMetrics {
+            hits: 2,
+            misses: 2,
+            bytes_from_cache: 14,
+            bytes_from_db: 7,
+        });
+        assert_eq!(recorder.hits.load(Ordering::Relaxed), 2);
+        assert_eq!(recorder.missed_bytes.load(Ordering::Relaxed), 7);
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_parallel_retrieve() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let engine = StorageEngine::new(temp_dir.path())?;
+        
+        let large_data = patterned(5 * 1024 * 1024 + 17);
+        let hash = engine.store_with_options(&large_data, HashAlgorithm::Blake3, 1024 * 1024)?;
+        
+        for concurrency in [1, 2, 4, 64] {
+            for verify in [false, true] {
+                engine.lock_cache().clear();
+                assert_eq!(engine.retrieve_with(&hash, &RetrieveOptions { verify, concurrency })?, large_data);
+            }
+        }
+        
+        // A damaged chunk is reported by index however the reads were spread
+        let chunks = engine.get_metadata(&hash)?.unwrap().chunks;
+        engine.db.put(format!("chunk:{}", chunks[3]).as_bytes(), encode_chunk(b"damaged", Compression::None)?)?;
+        let result = engine.retrieve_with(&hash, &RetrieveOptions { verify: true, concurrency: 4 });
+        assert!(matches!(result, Err(StorageError::CorruptData { chunk: Some(3), .. })));
+        
+        Ok(())
+    }
+    
+    /// Compare serial and parallel Blake3 hashing of one large buffer; run with
+    /// `cargo test --release --features parallel -- --ignored`
+    #[test]
+    #[ignore]
+    fn bench_parallel_hash() {
+        let data: Vec<u8> = (0..1024 * 1024 * 1024).map(|i| (i % 251) as u8 ^ (i >> 20) as u8).collect();
+        
+        let start = std::time::Instant::now();
+        let serial = blake3::hash(&data).to_hex().to_string();
+        println!("serial: {:.2?}", start.elapsed());
+        
+        let start = std::time::Instant::now();
+        let hash = calculate_hash(&data);
+        println!("calculate_hash (parallel feature {}): {:.2?}", cfg!(feature = "parallel"), start.elapsed());
+        assert_eq!(hash, serial);
+    }
+    
+    /// Compare serial and parallel chunk reads; run with `cargo test --release -- --ignored`
+    #[test]
+    #[ignore]
+    fn bench_parallel_retrieve() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let engine = StorageEngine::new(temp_dir.path())?;
+        
+        let data: Vec<u8> = (0..256 * 1024 * 1024).map(|i| (i % 251) as u8 ^ (i >> 20) as u8).collect();
+        let hash = engine.store_with_options(&data, HashAlgorithm::Blake3, 1024 * 1024)?;
+        
+        for concurrency in [1, 2, 4, 8] {
+            for verify in [false, true] {
+                engine.lock_cache().clear();
+                let start = std::time::Instant::now();
+                let read = engine.retrieve_with(&hash, &RetrieveOptions { verify, concurrency })?;
+                println!("concurrency {} verify {}: {:.2?}", concurrency, verify, start.elapsed());
+                assert_eq!(read.len(), data.len());
+            }
+        }
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_restore_skips_write() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let engine = StorageEngine::with_encryption(
+            temp_dir.path(),
+            StorageConfig { verify_existing: true, ..Default::default() },
+            EncryptionConfig::new([8u8; 32]),
+        )?;
+        
+        // Every write draws a fresh nonce, so unchanged ciphertext means no write happened
+        let hash = engine.store(b"stored once")?;
+        let sealed = engine.db.get(hash.as_bytes())?.unwrap();
+        let info = engine.db.get(format!("info:{}", hash).as_bytes())?.unwrap();
+        
+        assert_eq!(engine.store(b"stored once")?, hash);
+        assert_eq!(engine.store_batch(&[b"stored once"])?, vec![hash.clone()]);
+        assert_eq!(engine.db.get(hash.as_bytes())?.unwrap(), sealed);
+        assert_eq!(engine.db.get(format!("info:{}", hash).as_bytes())?.unwrap(), info);
+        
+        // New attributes rewrite the info record but still not the value
+        engine.store_with_metadata(b"stored once", HashAlgorithm::Blake3, 0, None, &["tagged"])?;
+        assert_eq!(engine.db.get(hash.as_bytes())?.unwrap(), sealed);
+        assert_eq!(engine.get_metadata(&hash)?.unwrap().tags, vec!["tagged".to_string()]);
+        
+        // A value that no longer matches its hash is caught instead of trusted
+        let other = StorageEngine::with_encryption(temp_dir.path().join("other"), StorageConfig::default(), EncryptionConfig::new([8u8; 32]))?;
+        let tampered = other.store(b"different bytes")?;
+        engine.db.put(hash.as_bytes(), other.db.get(tampered.as_bytes())?.unwrap())?;
+        assert!(matches!(engine.store(b"stored once"), Err(StorageError::CorruptData { .. })));
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_flush_and_sync_writes() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let hash = {
+            let engine = StorageEngine::with_config(temp_dir.path(), StorageConfig {
+                sync_writes: true,
+                ..Default::default()
+            })?;
+            let hash = engine.store(b"synced")?;
+            assert!(engine.delete(&engine.store(b"deleted")?)?);
+            engine.flush()?;
+            hash
+        };
+        
+        let engine = StorageEngine::new(temp_dir.path())?;
+        assert_eq!(engine.retrieve(&hash)?, b"synced");
+        assert!(!engine.exists(&calculate_hash(b"deleted"))?);
+        engine.flush()?;
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_retrieve_range() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let engine = StorageEngine::new(temp_dir.path())?;
+        
+        let data = patterned(3 * 1024 * 1024 + 100);
+        let fixed = engine.store_with_options(&data, HashAlgorithm::Blake3, 1024 * 1024)?;
+        let cdc = engine.store_with(&data, &StoreOptions {
+            algorithm: HashAlgorithm::Blake2b,
+            chunk_size: 64 * 1024,
+            chunking: ChunkingStrategy::content_defined(64 * 1024),
+            ..Default::default()
+        })?;
+        let simple = engine.store(&data[..5000])?;
+        engine.lock_cache().clear();
+        
+        let mb = 1024 * 1024;
+        let ranges = [(0, 10), (mb - 5, 10), (mb, mb), (3 * mb + 90, 50), (5, 3 * mb + 95), (3 * mb + 100, 10), (4 * mb, 1)];
+        for hash in [&fixed, &cdc] {
+            for (offset, len) in ranges {
+                assert_eq!(engine.retrieve_range(hash, offset, len)?, slice_range(&data, offset, len), "{} {}", offset, len);
+            }
+        }
+        assert_eq!(engine.retrieve_range(&simple, 4990, 100)?, &data[4990..5000]);
+        
+        // Only the chunk holding the range is read
+        let metadata = engine.get_metadata(&fixed)?.unwrap();
+        engine.db.delete(metadata.chunk_key(0).as_bytes())?;
+        assert_eq!(engine.retrieve_range(&fixed, 2 * mb + 7, 100)?, &data[2 * mb + 7..2 * mb + 107]);
+        assert!(engine.retrieve_range(&fixed, 0, 1).is_err());
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_algorithm_aliases() {
+        let aliases = [
+            ("BLAKE3", HashAlgorithm::Blake3),
+            ("blake-3", HashAlgorithm::Blake3),
+            ("Blake_3", HashAlgorithm::Blake3),
+            ("b3", HashAlgorithm::Blake3),
+            ("blake2b", HashAlgorithm::Blake2b),
+            ("BLAKE2B-512", HashAlgorithm::Blake2b),
+            ("keccak", HashAlgorithm::Keccak256),
+            ("Keccak-256", HashAlgorithm::Keccak256),
+            ("SHA-256", HashAlgorithm::Sha256),
+            ("sha2-256", HashAlgorithm::Sha256),
+            ("sha_256", HashAlgorithm::Sha256),
+        ];
+        for (name, expected) in aliases {
+            assert_eq!(HashAlgorithm::from_str(name).unwrap(), expected, "{}", name);
+        }
+        for algorithm in HashAlgorithm::ALL {
+            assert_eq!(HashAlgorithm::from_str(algorithm.as_str()).unwrap(), algorithm);
+        }
+        
+        for name in ["blake2", "sha2", "sha3-256", "SHA3", "md5", ""] {
+            let message = HashAlgorithm::from_str(name).unwrap_err().to_string();
+            assert!(message.contains("supported algorithms are blake3, blake2b, keccak256, sha256"), "{}", message);
+        }
+        assert!(HashAlgorithm::from_str("sha3-256").unwrap_err().to_string().contains("not Keccak256"));
+    }
+    
+    #[test]
+    fn test_retrieve_as() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let engine = StorageEngine::new(temp_dir.path())?;
+        
+        let data = patterned(3000);
+        let simple = engine.store_with_options(&data, HashAlgorithm::Keccak256, 0)?;
+        let chunked = engine.store_with_options(&data, HashAlgorithm::Blake2b, 1024)?;
+        
+        assert_eq!(engine.retrieve_as(&simple, HashAlgorithm::Keccak256)?, data);
+        assert_eq!(engine.retrieve_as(&chunked, HashAlgorithm::Blake2b)?, data);
+        assert!(matches!(
+            engine.retrieve_as(&simple, HashAlgorithm::Blake3),
+            Err(StorageError::AlgorithmMismatch { expected: HashAlgorithm::Blake3, found: HashAlgorithm::Keccak256 })
+        ));
+        assert!(matches!(
+            engine.retrieve_as(&chunked, HashAlgorithm::Sha256),
+            Err(StorageError::AlgorithmMismatch { expected: HashAlgorithm::Sha256, found: HashAlgorithm::Blake2b })
+        ));
+        
+        // Legacy values without a recorded algorithm are identified by their hash
+        engine.db.delete(format!("info:{}", simple).as_bytes())?;
+        assert_eq!(engine.retrieve_as(&simple, HashAlgorithm::Keccak256)?, data);
+        assert!(matches!(engine.retrieve_as(&simple, HashAlgorithm::Sha256), Err(StorageError::AlgorithmMismatch { .. })));
+        
+        assert!(matches!(engine.retrieve_as(&"0".repeat(64), HashAlgorithm::Blake3), Err(StorageError::HashNotFound(_))));
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_blob_writer() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let engine = StorageEngine::new(temp_dir.path())?;
+        
+        let data = patterned(10_000);
+        for len in [0, 700, 1024, 1025, 4096, 10_000] {
+            let mut writer = engine.create_blob(HashAlgorithm::Blake3, 1024)?;
+            for piece in data[..len].chunks(333) {
+                writer.write(piece)?;
+            }
+            assert_eq!(writer.len(), len);
+            let hash = writer.finish()?;
+            
+            assert_eq!(hash, engine.store_with_options(&data[..len], HashAlgorithm::Blake3, 1024)?, "{}", len);
+            engine.lock_cache().clear();
+            assert_eq!(engine.retrieve_verified(&hash)?, &data[..len]);
+        }
+        
+        // Abandoned blobs leave only unreferenced chunks behind
+        let mut writer = engine.create_blob(HashAlgorithm::Blake2b, 1024)?;
+        writer.write(&[9u8; 5000])?;
+        drop(writer);
+        assert!(engine.gc()?.chunks_removed > 0);
+        
+        // A shared writer keeps the engine alive on its own
+        let shared = Arc::new(engine);
+        let mut writer = shared.create_shared_blob(HashAlgorithm::Blake3, 1024)?;
+        let expected = shared.store_with_options(&data, HashAlgorithm::Blake3, 1024)?;
+        let engine = Arc::downgrade(&shared);
+        drop(shared);
+        writer.write(&data)?;
+        assert_eq!(writer.finish()?, expected);
+        assert!(engine.upgrade().is_none());
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_open_errors() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let engine = StorageEngine::new(temp_dir.path())?;
+        
+        match StorageEngine::new(temp_dir.path()) {
+            Err(StorageError::DatabaseLocked(path)) => assert_eq!(path, temp_dir.path().display().to_string()),
+            other => panic!("expected DatabaseLocked, got {:?}", other.err()),
+        }
+        drop(engine);
+        
+        std::fs::write(temp_dir.path().join("CURRENT"), b"garbage")?;
+        assert!(matches!(StorageEngine::new(temp_dir.path()), Err(StorageError::DatabaseCorrupt(_))));
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_dedup_report() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let engine = StorageEngine::new(temp_dir.path())?;
+        assert_eq!(engine.dedup_report()?.savings_ratio(), 0.0);
+        
+        // Shared chunks are one record already; the same content under
+        // another algorithm is a second copy
+        let data = patterned(3 * 1024 * 1024);
+        engine.store_with_options(&data, HashAlgorithm::Blake3, 1024 * 1024)?;
+        engine.store_with_options(&data[..2 * 1024 * 1024 + 1], HashAlgorithm::Blake3, 1024 * 1024)?;
+        engine.store_with_options(&data, HashAlgorithm::Blake2b, 1024 * 1024)?;
+        
+        let report = engine.dedup_report()?;
+        assert_eq!(report.chunks_scanned, 7);
+        assert_eq!(report.duplicate_chunks, 3);
+        assert_eq!(report.current_bytes, 2 * data.len() as u64 + 1);
+        assert_eq!(report.unique_bytes, data.len() as u64 + 1);
+        assert!(report.savings_ratio() > 0.49 && report.savings_ratio() < 0.51);
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_output_len() -> Result<()> {
+        let data = b"variable output";
+        
+        // Default lengths hash exactly as before
+        for algorithm in HashAlgorithm::ALL {
+            let mut hasher = Hasher::with_output_len(algorithm, None, algorithm.default_output_len())?;
+            hasher.update(data);
+            assert_eq!(hasher.finalize(), calculate_hash_with_algorithm(data, algorithm));
+        }
+        
+        let mut hasher = Hasher::with_output_len(HashAlgorithm::Blake2b, None, 32)?;
+        hasher.update(data);
+        let blake2b_256 = hasher.finalize();
+        assert_eq!(blake2b_256.len(), 64);
+        assert_ne!(blake2b_256, calculate_hash_with_algorithm(data, HashAlgorithm::Blake2b)[..64]);
+        
+        // Blake3's extended output starts with its default digest
+        let mut hasher = Hasher::with_output_len(HashAlgorithm::Blake3, None, 64)?;
+        hasher.update(data);
+        let blake3_512 = hasher.finalize();
+        assert_eq!(blake3_512[..64], calculate_hash_with_algorithm(data, HashAlgorithm::Blake3));
+        
+        assert!(Hasher::with_output_len(HashAlgorithm::Blake3, None, 8).is_err());
+        assert!(Hasher::with_output_len(HashAlgorithm::Blake2b, None, 65).is_err());
+        assert!(Hasher::with_output_len(HashAlgorithm::Sha256, None, 16).is_err());
+        assert!(Hasher::with_output_len(HashAlgorithm::Blake2b, Some(&[1u8; 32]), 32).is_err());
+        
+        let temp_dir = tempdir()?;
+        let engine = StorageEngine::new(temp_dir.path())?;
+        let options = StoreOptions { algorithm: HashAlgorithm::Blake2b, output_len: Some(32), ..Default::default() };
+        let hash = engine.store_with(data, &options)?;
+        assert_eq!(hash, blake2b_256);
+        assert_eq!(engine.get_metadata(&hash)?.unwrap().output_len, Some(32));
+        engine.lock_cache().clear();
+        assert_eq!(engine.retrieve_verified(&hash)?, data);
+        assert!(engine.fsck()?.is_clean());
+        
+        // Storing again with the recorded length reuses the stored value
+        assert_eq!(engine.store_with(data, &options)?, hash);
+        
+        let chunked = StoreOptions { output_len: Some(32), chunk_size: 1024, ..options };
+        assert!(matches!(engine.store_with(&[0u8; 4096], &chunked), Err(StorageError::ChunkingError(_))));
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_archive_round_trip() -> Result<()> {
+        let source_dir = tempdir()?;
+        let source = StorageEngine::new(source_dir.path())?;
+        
+        let simple = source.store(b"archived")?;
+        let large_data = patterned(3 * 1024 * 1024);
+        let chunked = source.store_with_options(&large_data, HashAlgorithm::Blake3, 1024 * 1024)?;
+        source.pin(&simple)?;
+        
+        let archive_path = source_dir.path().join("backup.svdb");
+        let written = source.export_archive(&archive_path)?;
+        assert_eq!(written, std::fs::metadata(&archive_path)?.len());
+        
+        let target_dir = tempdir()?;
+        let target = StorageEngine::new(target_dir.path())?;
+        assert!(target.import_archive(&archive_path)? > 0);
+        assert_eq!(target.retrieve_verified(&simple)?, b"archived");
+        assert_eq!(target.retrieve_verified(&chunked)?, large_data);
+        assert!(target.fsck()?.is_clean());
+        assert_eq!(target.gc()?.chunks_removed, 0);
+        assert!(matches!(target.delete(&simple), Err(StorageError::Pinned(_))));
+        
+        // Only an empty store can be restored into
+        assert!(matches!(target.import_archive(&archive_path), Err(StorageError::SerializationError(_))));
+        
+        // A truncated archive is reported rather than half-read silently
+        let truncated_path = source_dir.path().join("truncated.svdb");
+        let bytes = std::fs::read(&archive_path)?;
+        std::fs::write(&truncated_path, &bytes[..bytes.len() - 20])?;
+        let empty_dir = tempdir()?;
+        let empty = StorageEngine::new(empty_dir.path())?;
+        assert!(matches!(empty.import_archive(&truncated_path), Err(StorageError::SerializationError(_))));
+        
+        // ... as is a record claiming a huge key, without allocating for it
+        let oversized_path = source_dir.path().join("oversized.svdb");
+        let mut bytes = bytes[..8].to_vec();
+        bytes.extend_from_slice(&(u32::MAX - 1).to_le_bytes());
+        bytes.extend_from_slice(&0u64.to_le_bytes());
+        std::fs::write(&oversized_path, &bytes)?;
+        let empty_dir = tempdir()?;
+        let empty = StorageEngine::new(empty_dir.path())?;
+        assert!(matches!(empty.import_archive(&oversized_path), Err(StorageError::SerializationError(e)) if e.contains("truncated")));
+        
+        // Keys that would land in another namespace are refused
+        let foreign_path = source_dir.path().join("foreign.svdb");
+        let mut bytes = bytes[..8].to_vec();
+        let key = format!("alpha/{}", simple);
+        bytes.extend_from_slice(&(key.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&1u64.to_le_bytes());
+        bytes.extend_from_slice(key.as_bytes());
+        bytes.push(0);
+        bytes.extend_from_slice(&u32::MAX.to_le_bytes());
+        bytes.extend_from_slice(&1u64.to_le_bytes());
+        std::fs::write(&foreign_path, &bytes)?;
+        assert!(matches!(empty.import_archive(&foreign_path), Err(StorageError::SerializationError(e)) if e.contains("outside")));
+        assert!(!empty.namespace("alpha")?.exists(&simple)?);
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_namespaces() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let default = StorageEngine::new(temp_dir.path())?;
+        let alpha = default.namespace("alpha")?;
+        let beta = default.namespace("beta")?;
+        
+        let shared = b"in every namespace";
+        let large_data = patterned(3 * 1024 * 1024);
+        let hash = alpha.store(shared)?;
+        let chunked = alpha.store_with_options(&large_data, HashAlgorithm::Blake3, 1024 * 1024)?;
+        default.store(b"unnamed only")?;
+        
+        assert!(!beta.exists(&hash)?);
+        assert!(matches!(beta.retrieve(&chunked), Err(StorageError::HashNotFound(_))));
+        assert!(!default.exists(&chunked)?);
+        assert_eq!(alpha.list_hashes()?.len(), 2);
+        assert_eq!(default.list_hashes()?.len(), 1);
+        assert_eq!(alpha.stats()?.total_chunks, 3);
+        assert_eq!(default.stats()?.total_chunks, 0);
+        
+        // Storing the same content elsewhere makes an independent copy
+        assert_eq!(beta.store(shared)?, hash);
+        assert!(beta.delete(&hash)?);
+        assert_eq!(alpha.retrieve(&hash)?, shared);
+        
+        // gc in one namespace leaves another's chunks alone
+        let report = beta.gc()?;
+        assert_eq!(report.chunks_scanned, 0);
+        assert_eq!(default.gc()?.chunks_removed, 0);
+        assert_eq!(alpha.retrieve_verified(&chunked)?, large_data);
+        
+        // A namespaced key can't be reached by spelling it out from the unnamed namespace
+        let spelled = format!("alpha/{}", hash);
+        assert!(matches!(default.retrieve(&spelled), Err(StorageError::InvalidHash(_))));
+        assert!(matches!(default.exists(&spelled), Err(StorageError::InvalidHash(_))));
+        assert!(matches!(default.delete(&spelled), Err(StorageError::InvalidHash(_))));
+        assert_eq!(alpha.retrieve(&hash)?, shared);
+        
+        assert!(matches!(default.namespace("a/b"), Err(StorageError::InvalidNamespace(_))));
+        assert!(matches!(default.namespace(""), Err(StorageError::InvalidNamespace(_))));
+        assert!(matches!(default.namespace("a\0b"), Err(StorageError::InvalidNamespace(_))));
+        assert!(matches!(default.namespace("\0svdb\0meta"), Err(StorageError::InvalidNamespace(_))));
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_tampered_chunk_list() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let engine = StorageEngine::new(temp_dir.path())?;
+        
+        let data = patterned(3 * 1024 * 1024);
+        let hash = engine.store_with_options(&data, HashAlgorithm::Blake3, 1024 * 1024)?;
+        let metadata = engine.get_metadata(&hash)?.unwrap();
+        engine.lock_cache().clear();
+        
+        let tamper = |metadata: &FileMetadata| -> Result<()> {
+            engine.db.put(format!("meta:{}", hash).as_bytes(), serialize_metadata(metadata)?)?;
+            engine.lock_cache().clear();
+            Ok(())
+        };
+        
+        // A truncated chunk list no longer adds up to the recorded size
+        let mut truncated = metadata.clone();
+        truncated.chunks.pop();
+        tamper(&truncated)?;
+        assert!(matches!(engine.retrieve(&hash), Err(StorageError::CorruptData { chunk: None, .. })));
+        assert!(matches!(engine.retrieve_with(&hash, &RetrieveOptions { verify: false, concurrency: 4 }), Err(StorageError::CorruptData { chunk: None, .. })));
+        let streamed: Result<Vec<_>> = engine.retrieve_stream(&hash)?.collect();
+        assert!(matches!(streamed, Err(StorageError::CorruptData { chunk: None, .. })));
+        
+        // A repeated chunk overruns it
+        let mut repeated = metadata.clone();
+        repeated.chunks.push(repeated.chunks[0].clone());
+        tamper(&repeated)?;
+        assert!(matches!(engine.retrieve(&hash), Err(StorageError::CorruptData { chunk: None, .. })));
+        
+        // An emptied list is caught before reading anything
+        let mut emptied = metadata.clone();
+        emptied.chunks.clear();
+        tamper(&emptied)?;
+        assert!(matches!(engine.retrieve_stream(&hash), Err(StorageError::InconsistentMetadata { .. })));
+        
+        tamper(&metadata)?;
+        assert_eq!(engine.retrieve(&hash)?, data);
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_iter_files() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let engine = StorageEngine::new(temp_dir.path())?;
+        assert_eq!(engine.iter_files().count(), 0);
+        
+        let simple = engine.store_with_options(b"keccak file", HashAlgorithm::Keccak256, 0)?;
+        let large_data = patterned(2 * 1024 * 1024 + 1);
+        let chunked = engine.store_with_options(&large_data, HashAlgorithm::Blake2b, 1024 * 1024)?;
+        let legacy = engine.store(b"stored before info records")?;
+        engine.db.delete(format!("info:{}", legacy).as_bytes())?;
+        engine.store_with_ttl(b"expires", HashAlgorithm::Blake3, 0, 0)?;
+        
+        let mut files: Vec<FileMetadata> = engine.iter_files().collect::<Result<_>>()?;
+        files.sort_by_key(|metadata| metadata.size);
+        let summary: Vec<(&str, usize, &str)> = files.iter()
+            .map(|metadata| (metadata.hash.as_str(), metadata.size, metadata.algorithm.as_str()))
+            .collect();
+        assert_eq!(summary, vec![
+            (simple.as_str(), 11, "keccak256"),
+            (legacy.as_str(), 26, "blake3"),
+            (chunked.as_str(), large_data.len(), "blake2b"),
+        ]);
+        assert_eq!(files[1].timestamp, 0);
+        assert!(files[0].timestamp > 0);
+        
+        // Resuming from a cursor one step at a time, as the Python iterators
+        // do, visits the same files and hashes
+        let mut cursor = ScanCursor::default();
+        let mut stepped = Vec::new();
+        while let Some((next, metadata)) = engine.scan_files(&cursor).next().transpose()? {
+            stepped.push(metadata.hash);
+            cursor = next;
+        }
+        let listed: Vec<String> = engine.iter_files().map(|metadata| metadata.map(|metadata| metadata.hash)).collect::<Result<_>>()?;
+        assert_eq!(stepped, listed);
+        
+        let mut cursor = ScanCursor::default();
+        let mut stepped = Vec::new();
+        while let Some((next, hash)) = engine.scan_hashes("", &cursor).next().transpose()? {
+            stepped.push(hash);
+            cursor = next;
+        }
+        assert_eq!(stepped, engine.list_hashes()?);
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_chunk_list_sync() -> Result<()> {
+        let source_dir = tempdir()?;
+        let source = StorageEngine::new(source_dir.path())?;
+        let target_dir = tempdir()?;
+        let target = StorageEngine::new(target_dir.path())?;
+        
+        let data = patterned(3 * 1024 * 1024);
+        let hash = source.store_with_options(&data, HashAlgorithm::Blake3, 1024 * 1024)?;
+        let chunks = source.chunk_list(&hash)?;
+        assert_eq!(chunks.len(), 3);
+        assert!(chunks.iter().all(|chunk| source.has_chunk(chunk).unwrap()));
+        
+        // The target already holds the first chunk, so only the rest are sent
+        target.put_chunk(&data[..1024 * 1024], HashAlgorithm::Blake3)?;
+        let missing: Vec<usize> = (0..chunks.len())
+            .filter(|i| !target.has_chunk(&chunks[*i]).unwrap())
+            .collect();
+        assert_eq!(missing, vec![1, 2]);
+        for i in missing {
+            target.put_chunk(&data[i * 1024 * 1024..(i + 1) * 1024 * 1024], HashAlgorithm::Blake3)?;
+        }
+        assert_eq!(target.commit_chunked(&chunks, HashAlgorithm::Blake3)?, hash);
+        assert_eq!(target.retrieve_verified(&hash)?, data);
+        
+        let simple = source.store(b"single chunk")?;
+        assert_eq!(source.chunk_list(&simple)?, vec![simple.clone()]);
+        assert!(source.has_chunk(&simple)?);
+        assert!(!target.has_chunk(&simple)?);
+        assert!(matches!(target.chunk_list(&simple), Err(StorageError::HashNotFound(_))));
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_store_durability() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let engine = StorageEngine::new(temp_dir.path())?;
+        
+        let data = patterned(200_000);
+        let mut hashes = Vec::new();
+        for durability in [Durability::Buffered, Durability::Wal, Durability::Fsync] {
+            for chunk_size in [0, 64 * 1024] {
+                let mut content = data.clone();
+                content.push(durability as u8);
+                let options = StoreOptions { chunk_size, durability: Some(durability), ..Default::default() };
+                hashes.push((engine.store_with(&content, &options)?, content));
+            }
+        }
+        
+        // Buffered writes skip the log, so only a flush makes them survive a reopen
+        engine.flush()?;
+        drop(engine);
+        let engine = StorageEngine::new(temp_dir.path())?;
+        for (hash, content) in &hashes {
+            assert_eq!(&engine.retrieve_verified(hash)?, content);
+        }
+        
+        assert!(matches!(Durability::from_str("FSYNC"), Ok(Durability::Fsync)));
+        assert!(matches!(Durability::from_str("sync"), Err(StorageError::InvalidDurability(_))));
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_raw_digests() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let engine = StorageEngine::new(temp_dir.path())?;
+        
+        for algorithm in HashAlgorithm::ALL {
+            let digest = calculate_hash_bytes(b"raw digest", algorithm);
+            assert_eq!(hex::encode(&digest), calculate_hash_with_algorithm(b"raw digest", algorithm));
+        }
+        
+        let data = patterned(300_000);
+        let digest = engine.store_raw(&data, HashAlgorithm::Sha256, 0)?;
+        assert_eq!(digest, calculate_hash_bytes(&data, HashAlgorithm::Sha256));
+        assert_eq!(engine.retrieve_raw(&digest)?, data);
+        
+        let chunked = engine.store_raw(&data, HashAlgorithm::Blake3, 64 * 1024)?;
+        assert_eq!(chunked.len(), 32);
+        assert_eq!(engine.retrieve_raw(&chunked)?, data);
+        assert!(matches!(engine.retrieve_raw(&[0u8; 32]), Err(StorageError::HashNotFound(_))));
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_merkle_chunk_proofs() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let engine = StorageEngine::new(temp_dir.path())?;
+        
+        // Five chunks leave an unpaired node on the first two levels
+        let data = patterned(5 * 64 * 1024);
+        let options = StoreOptions { chunk_size: 64 * 1024, merkle: true, ..Default::default() };
+        let hash = engine.store_with(&data, &options)?;
+        let flat = engine.store_with_options(&data, HashAlgorithm::Blake3, 64 * 1024)?;
+        assert_ne!(hash, flat);
+        
+        let chunks = engine.chunk_list(&hash)?;
+        assert_eq!(chunks.len(), 5);
+        for (index, chunk_hash) in chunks.iter().enumerate() {
+            let proof = engine.chunk_proof(&hash, index)?;
+            assert!(verify_chunk_proof(&hash, chunk_hash, index, chunks.len(), &proof, HashAlgorithm::Blake3, None)?);
+            
+            let other = (index + 1) % chunks.len();
+            assert!(!verify_chunk_proof(&hash, &chunks[other], index, chunks.len(), &proof, HashAlgorithm::Blake3, None)?);
+            assert!(!verify_chunk_proof(&flat, chunk_hash, index, chunks.len(), &proof, HashAlgorithm::Blake3, None)?);
+        }
+        
+        engine.lock_cache().clear();
+        assert_eq!(engine.retrieve_verified(&hash)?, data);
+        assert!(engine.fsck()?.is_clean());
+        
+        assert!(matches!(engine.chunk_proof(&hash, 5), Err(StorageError::ChunkingError(_))));
+        assert!(matches!(engine.chunk_proof(&flat, 0), Err(StorageError::ChunkingError(_))));
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_without_cache() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let engine = StorageEngine::without_cache(temp_dir.path())?;
+        
+        let data = patterned(300_000);
+        let simple = engine.store(b"never cached")?;
+        let chunked = engine.store_with_options(&data, HashAlgorithm::Blake3, 64 * 1024)?;
+        let batch = engine.store_batch(&[b"batched".as_slice()])?;
+        
+        assert_eq!(engine.retrieve(&simple)?, b"never cached");
+        assert_eq!(engine.retrieve(&chunked)?, data);
+        assert_eq!(engine.retrieve_range(&chunked, 1000, 10)?, &data[1000..1010]);
+        assert_eq!(engine.retrieve_batch(&batch)[0].as_deref().ok(), Some(&b"batched"[..]));
+        assert_eq!(engine.warm(&[simple.clone(), chunked.clone()])?, 2);
+        assert!(engine.lock_cache().is_empty());
+        
+        let metrics = engine.cache_metrics();
+        assert_eq!(metrics.hits, 0);
+        assert!(metrics.misses >= 4);
+        
+        engine.delete(&simple)?;
+        assert!(!engine.exists(&simple)?);
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_chunk_size_boundary() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let engine = StorageEngine::new(temp_dir.path())?;
+        let chunk_size = 64 * 1024;
+        
+        for (len, chunk_lens) in [
+            (chunk_size, vec![]),
+            (chunk_size + 1, vec![chunk_size, 1]),
+            (chunk_size + 100, vec![chunk_size, 100]),
+            (2 * chunk_size, vec![chunk_size, chunk_size]),
+        ] {
+            let data = patterned(len);
+            let hash = engine.store_with_options(&data, HashAlgorithm::Blake3, chunk_size)?;
+            
+            let metadata = engine.get_metadata(&hash)?.unwrap();
+            assert_eq!(metadata.chunks.len(), chunk_lens.len(), "{} bytes", len);
+            assert_eq!(metadata.size, len);
+            for (index, chunk_len) in chunk_lens.iter().enumerate() {
+                let chunk = engine.db.get(metadata.chunk_key(index).as_bytes())?.unwrap();
+                // Chunk bodies carry a one-byte codec tag
+                assert_eq!(chunk.len(), chunk_len + 1);
+            }
+            
+            // Streaming stores draw the same boundary and give the same hash
+            assert_eq!(engine.store_reader(data.as_slice(), HashAlgorithm::Blake3, chunk_size)?, hash);
+            assert_eq!(engine.would_store(&data, HashAlgorithm::Blake3, chunk_size)?, (hash.clone(), true));
+            engine.lock_cache().clear();
+            assert_eq!(engine.retrieve_verified(&hash)?, data);
+        }
+        
+        // A threshold keeps a file just over the chunk size whole
+        let threshold_dir = tempdir()?;
+        let engine = StorageEngine::with_config(threshold_dir.path(), StorageConfig {
+            chunk_threshold: Some(chunk_size + 100),
+            ..Default::default()
+        })?;
+        let data = vec![7u8; chunk_size + 1];
+        let hash = engine.store_with_options(&data, HashAlgorithm::Blake3, chunk_size)?;
+        assert!(engine.get_metadata(&hash)?.unwrap().chunks.is_empty());
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_retrieve_into() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let engine = StorageEngine::new(temp_dir.path())?;
+        
+        let data = patterned(300_000);
+        let chunked = engine.store_with_options(&data, HashAlgorithm::Blake3, 64 * 1024)?;
+        let simple = engine.store(b"fits")?;
+        engine.lock_cache().clear();
+        
+        let mut buf = vec![0u8; data.len()];
+        assert_eq!(engine.retrieve_into(&chunked, &mut buf)?, data.len());
+        assert_eq!(buf, data);
+        
+        let mut buf = vec![0xffu8; 8];
+        assert_eq!(engine.retrieve_into(&simple, &mut buf)?, 4);
+        assert_eq!(&buf, b"fits\xff\xff\xff\xff");
+        
+        let mut small = vec![0u8; data.len() - 1];
+        assert!(matches!(engine.retrieve_into(&chunked, &mut small), Err(StorageError::IOError(_))));
+        assert!(small.iter().all(|byte| *byte == 0));
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_store_reporting() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let engine = StorageEngine::new(temp_dir.path())?;
+        
+        let data = patterned(300_000);
+        for chunk_size in [0, 64 * 1024] {
+            let options = StoreOptions { chunk_size, ..Default::default() };
+            let (hash, outcome) = engine.store_reporting(&data, &options)?;
+            assert_eq!(outcome, StoreOutcome::Written);
+            
+            let (again, outcome) = engine.store_reporting(&data, &options)?;
+            assert_eq!((again, outcome), (hash.clone(), StoreOutcome::AlreadyPresent));
+            
+            // New tags update the record but the content was already there
+            let tagged = StoreOptions { tags: vec!["seen".to_string()], ..options.clone() };
+            assert_eq!(engine.store_reporting(&data, &tagged)?.1, StoreOutcome::AlreadyPresent);
+            
+            engine.delete(&hash)?;
+            assert_eq!(engine.store_reporting(&data, &options)?.1, StoreOutcome::Written);
+        }
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_delete_indexed_chunks_by_range() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let engine = StorageEngine::new(temp_dir.path())?;
+        let neighbour = engine.store_with_options(&[9u8; 4096], HashAlgorithm::Blake3, 1024)?;
+        
+        // A file written before deduplication, with more than ten chunks so
+        // `chunk:{hash}:10` sorts before `chunk:{hash}:2`
+        let data = patterned(12 * 1024);
+        let mut metadata = chunk_data(&data, 1024, HashAlgorithm::Blake3, &ChunkingStrategy::Fixed, None)?.metadata;
+        metadata.layout = ChunkLayout::Indexed;
+        for (index, chunk) in data.chunks(1024).enumerate() {
+            engine.db.put(metadata.chunk_key(index).as_bytes(), chunk)?;
+        }
+        engine.db.put(format!("chunk:{}:12", metadata.hash).as_bytes(), b"stray")?;
+        engine.db.put(format!("meta:{}", metadata.hash).as_bytes(), serialize_metadata(&metadata)?)?;
+        assert_eq!(engine.retrieve(&metadata.hash)?, data);
+        
+        assert!(engine.delete(&metadata.hash)?);
+        let prefix = format!("chunk:{}:", metadata.hash);
+        assert_eq!(engine.db.scan_prefix(prefix.as_bytes()).count(), 0);
+        assert!(!engine.exists(&metadata.hash)?);
+        
+        engine.lock_cache().clear();
+        assert_eq!(engine.retrieve_verified(&neighbour)?, vec![9u8; 4096]);
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_clear() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let engine = StorageEngine::new(temp_dir.path())?;
+        let alpha = engine.namespace("alpha")?;
+        let beta = engine.namespace("beta")?;
+        
+        let data = patterned(300_000);
+        let hash = engine.store_with_options(&data, HashAlgorithm::Blake3, 64 * 1024)?;
+        let simple = engine.store(b"unnamed")?;
+        engine.pin(&simple)?;
+        let in_alpha = alpha.store(b"alpha")?;
+        let in_beta = beta.store_with_options(&data, HashAlgorithm::Blake3, 64 * 1024)?;
+        
+        alpha.clear()?;
+        assert!(!alpha.exists(&in_alpha)?);
+        assert!(alpha.db.is_empty()?);
+        assert!(engine.exists(&hash)? && beta.exists(&in_beta)?);
+        
+        // The unnamed namespace steps around the named ones
+        engine.clear()?;
+        assert!(!engine.exists(&hash)? && !engine.exists(&simple)?);
+        assert!(engine.db.is_empty()?);
+        let stats = engine.stats()?;
+        assert_eq!(stats.simple_files + stats.chunked_files, 0);
+        beta.lock_cache().clear();
+        assert_eq!(beta.retrieve_verified(&in_beta)?, data);
+        
+        drop((alpha, beta, engine));
+        let engine = StorageEngine::new(temp_dir.path())?;
+        assert!(!engine.exists(&hash)?);
+        assert_eq!(engine.store_with_options(&data, HashAlgorithm::Blake3, 64 * 1024)?, hash);
+        assert_eq!(engine.retrieve_verified(&hash)?, data);
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_mixed_algorithm_retrieve() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let engine = StorageEngine::new(temp_dir.path())?;
+        
+        let data = patterned(200_000);
+        let mut stored = Vec::new();
+        for algorithm in HashAlgorithm::ALL {
+            for chunk_size in [0, 64 * 1024] {
+                let mut content = data.clone();
+                content.extend_from_slice(algorithm.as_str().as_bytes());
+                let hash = engine.store_with_options(&content, algorithm, chunk_size)?;
+                stored.push((hash, algorithm, content));
+            }
+        }
+        // Simple files stored before info records existed carry no algorithm
+        engine.db.delete(format!("info:{}", stored[2].0).as_bytes())?;
+        
+        // A reader that only has the hash strings, and nothing cached
+        drop(engine);
+        let engine = StorageEngine::new(temp_dir.path())?;
+        for (hash, algorithm, content) in &stored {
+            assert_eq!(&engine.retrieve(hash)?, content, "{}", algorithm.as_str());
+            assert_eq!(&engine.retrieve_verified(hash)?, content, "{}", algorithm.as_str());
+            assert_eq!(engine.get_metadata(hash)?.unwrap().algorithm, algorithm.as_str());
+        }
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_large_input_hash_matches_serial() {
+        for len in [PARALLEL_HASH_THRESHOLD - 1, PARALLEL_HASH_THRESHOLD, 3 * PARALLEL_HASH_THRESHOLD + 17] {
+            let data = patterned(len);
+            assert_eq!(calculate_hash(&data), blake3::hash(&data).to_hex().to_string());
+            
+            let mut hasher = Hasher::new(HashAlgorithm::Blake3);
+            hasher.update(&data[..len / 2]);
+            hasher.update(&data[len / 2..]);
+            assert_eq!(hasher.finalize(), calculate_hash(&data));
+        }
+    }
+    
+    #[test]
+    fn test_attrs() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let engine = StorageEngine::new(temp_dir.path())?;
+        
+        let data = patterned(300_000);
+        for chunk_size in [0, 64 * 1024] {
+            let attrs = HashMap::from([
+                ("filename".to_string(), "report.pdf".to_string()),
+                ("creator".to_string(), "ingest".to_string()),
+            ]);
+            let hash = engine.store_with_attrs(&data, HashAlgorithm::Blake3, chunk_size, attrs.clone())?;
+            assert_eq!(engine.get_attrs(&hash)?, attrs);
+            
+            // Storing again merges new values over the old ones
+            let renamed = HashMap::from([("filename".to_string(), "final.pdf".to_string())]);
+            assert_eq!(engine.store_with_attrs(&data, HashAlgorithm::Blake3, chunk_size, renamed)?, hash);
+            let merged = engine.get_attrs(&hash)?;
+            assert_eq!(merged["filename"], "final.pdf");
+            assert_eq!(merged["creator"], "ingest");
+            
+            engine.delete(&hash)?;
+        }
+        
+        let plain = engine.store(b"no attrs")?;
+        assert!(engine.get_attrs(&plain)?.is_empty());
+        assert!(matches!(engine.get_attrs(&calculate_hash(b"missing")), Err(StorageError::HashNotFound(_))));
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_store_auto_chunks_large_files() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let engine = StorageEngine::with_config(temp_dir.path(), StorageConfig {
+            auto_chunk_threshold: Some(2 * DEFAULT_CHUNK_SIZE),
+            ..Default::default()
+        })?;
+        
+        let at_threshold = vec![1u8; 2 * DEFAULT_CHUNK_SIZE];
+        let hash = engine.store(&at_threshold)?;
+        assert!(engine.get_metadata(&hash)?.unwrap().chunks.is_empty());
+        
+        let data = patterned(2 * DEFAULT_CHUNK_SIZE + 1);
+        let hash = engine.store(&data)?;
+        let metadata = engine.get_metadata(&hash)?.unwrap();
+        assert_eq!(metadata.chunks.len(), 3);
+        assert_eq!(metadata.chunk_size, DEFAULT_CHUNK_SIZE);
+        assert_eq!(hash, engine.store_with_options(&data, HashAlgorithm::Blake3, DEFAULT_CHUNK_SIZE)?);
+        engine.lock_cache().clear();
+        assert_eq!(engine.retrieve_verified(&hash)?, data);
+        
+        // The default threshold applies without configuration
+        drop(engine);
+        let engine = StorageEngine::new(temp_dir.path())?;
+        assert_eq!(engine.auto_chunk_threshold, AUTO_CHUNK_THRESHOLD);
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_retrieve_uncached() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let engine = StorageEngine::new(temp_dir.path())?;
+        
+        let data = patterned(300_000);
+        let chunked = engine.store_with_options(&data, HashAlgorithm::Blake3, 64 * 1024)?;
+        let simple = engine.store(b"scanned once")?;
+        engine.lock_cache().clear();
+        
+        assert_eq!(engine.retrieve_uncached(&chunked)?, data);
+        assert_eq!(engine.retrieve_uncached(&simple)?, b"scanned once");
+        assert!(engine.lock_cache().is_empty());
+        assert_eq!(engine.cache_metrics().misses, 0);
+        
+        // A stale cache entry is not consulted
+        engine.lock_cache().insert(simple.clone(), b"stale".to_vec());
+        assert_eq!(engine.retrieve_uncached(&simple)?, b"scanned once");
+        
+        let rehashed = engine.rehash(&chunked, HashAlgorithm::Sha256, false)?;
+        assert!(!engine.lock_cache().contains_key(&chunked));
+        assert_eq!(engine.retrieve_verified(&rehashed)?, data);
+        assert!(matches!(engine.retrieve_uncached(&calculate_hash(b"missing")), Err(StorageError::HashNotFound(_))));
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_builder() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let engine = StorageEngine::builder()
+            .compression(Compression::Zstd)
+            .cache(false)
+            .namespace("built")
+            .encryption(EncryptionConfig::new([4u8; 32]))
+            .open(temp_dir.path())?;
+        
+        let hash = engine.store(&[0u8; 10_000])?;
+        assert!(engine.lock_cache().is_empty());
+        assert_eq!(engine.get_metadata(&hash)?.unwrap().compression.as_deref(), Some("zstd"));
+        drop(engine);
+        
+        let reader = StorageEngine::builder()
+            .namespace("built")
+            .encryption(EncryptionConfig::new([4u8; 32]))
+            .read_only(true)
+            .open(temp_dir.path())?;
+        assert_eq!(reader.retrieve(&hash)?, vec![0u8; 10_000]);
+        assert!(matches!(reader.store(b"nope"), Err(StorageError::ReadOnly(_))));
+        drop(reader);
+        
+        // Other namespaces don't see the file
+        let unnamed = StorageEngine::builder().open(temp_dir.path())?;
+        assert!(!unnamed.exists(&hash)?);
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_verify_hash() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let engine = StorageEngine::new(temp_dir.path())?;
+        let data = patterned(200_000);
+        
+        let hash = engine.store_with_options(&data, HashAlgorithm::Keccak256, 0)?;
+        assert!(verify_hash(&data, &hash, HashAlgorithm::Keccak256));
+        assert!(verify_hash(&data, &hash.to_uppercase(), HashAlgorithm::Keccak256));
+        assert!(verify_hash(&data, &format!("keccak256:{}", hash), HashAlgorithm::Keccak256));
+        assert!(!verify_hash(&data, &format!("blake3:{}", hash), HashAlgorithm::Keccak256));
+        assert!(!verify_hash(&data, &hash, HashAlgorithm::Blake3));
+        assert!(!verify_hash(&data[1..], &hash, HashAlgorithm::Keccak256));
+        
+        let chunked = engine.store_with_options(&data, HashAlgorithm::Blake3, 64 * 1024)?;
+        assert!(verify_chunked_hash(&data, &chunked, HashAlgorithm::Blake3, 64 * 1024)?);
+        assert!(!verify_chunked_hash(&data, &chunked, HashAlgorithm::Blake3, 32 * 1024)?);
+        assert!(!verify_hash(&data, &chunked, HashAlgorithm::Blake3));
+        
+        let options = StoreOptions { chunk_size: 64 * 1024, merkle: true, ..Default::default() };
+        let merkle = engine.store_with(&data, &options)?;
+        assert!(verify_chunked_hash(&data, &merkle, HashAlgorithm::Blake3, 64 * 1024)?);
+        
+        // Small data is stored whole whatever the chunk size
+        let small = engine.store(b"small")?;
+        assert!(verify_chunked_hash(b"small", &small, HashAlgorithm::Blake3, 64 * 1024)?);
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_custom_hasher() -> Result<()> {
+        struct Xor;
+        
+        impl FileHasher for Xor {
+            fn name(&self) -> &str {
+                "xor8"
+            }
+            
+            fn hash(&self, data: &[u8]) -> String {
+                let mut digest = [0u8; 8];
+                for (i, byte) in data.iter().enumerate() {
+                    digest[i % 8] ^= byte;
+                }
+                hex::encode(digest)
+            }
+        }
+        
+        let temp_dir = tempdir()?;
+        let mut engine = StorageEngine::new(temp_dir.path())?;
+        assert!(matches!(engine.store_with_hasher(b"data", "xor8"), Err(StorageError::InvalidAlgorithm(_))));
+        
+        engine.register_hasher(Arc::new(Xor))?;
+        assert!(engine.register_hasher(Arc::new(Xor)).is_err());
+        assert_eq!(engine.hashers().from_str("xor8")?.name(), "xor8");
+        assert_eq!(engine.hashers().from_str("SHA-256")?.name(), "sha256");
+        
+        let data = patterned(1000);
+        let hash = engine.store_with_hasher(&data, "xor8")?;
+        assert_eq!(hash, Xor.hash(&data));
+        assert_eq!(engine.get_metadata(&hash)?.unwrap().algorithm, "xor8");
+        engine.lock_cache().clear();
+        assert_eq!(engine.retrieve(&hash)?, data);
+        assert_eq!(engine.retrieve_verified(&hash)?, data);
+        
+        // Built-in names go through the usual path
+        assert_eq!(engine.store_with_hasher(&data, "keccak")?, calculate_hash_with_algorithm(&data, HashAlgorithm::Keccak256));
+        
+        // A value that no longer matches its custom hash is caught
+        engine.db.put(hash.as_bytes(), &data[1..])?;
+        engine.lock_cache().clear();
+        assert!(matches!(engine.retrieve_verified(&hash), Err(StorageError::CorruptData { .. })));
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_retry_transient_errors() {
+        use rocksdb::ErrorKind;
+        
+        let policy = Some(RetryPolicy { attempts: 3, backoff: Duration::ZERO });
+        let run = |policy: Option<RetryPolicy>, failures: Vec<ErrorKind>| {
+            let mut failures = failures.into_iter();
+            let mut calls = 0;
+            let result = records::with_retry(policy, ErrorKind::clone, || {
+                calls += 1;
+                failures.next().map_or(Ok("done"), Err)
+            });
+            (result, calls)
+        };
+        
+        // Transient failures are retried until one succeeds
+        assert_eq!(run(policy, vec![ErrorKind::Busy, ErrorKind::TryAgain]), (Ok("done"), 3));
+        
+        // ... but only up to the attempt limit, returning the last error
+        assert_eq!(run(policy, vec![ErrorKind::Busy, ErrorKind::TimedOut, ErrorKind::Busy]), (Err(ErrorKind::Busy), 3));
+        
+        // Other failures are returned straight away
+        assert_eq!(run(policy, vec![ErrorKind::Corruption]), (Err(ErrorKind::Corruption), 1));
+        assert_eq!(run(policy, vec![ErrorKind::NotFound]), (Err(ErrorKind::NotFound), 1));
+        
+        // Without a policy every call is tried once
+        assert_eq!(run(None, vec![ErrorKind::Busy]), (Err(ErrorKind::Busy), 1));
+        
+        // Backoff doubles up to a cap, so many attempts never wait for minutes
+        let policy = RetryPolicy { attempts: 20, backoff: Duration::from_millis(10) };
+        let waits: Vec<_> = std::iter::successors(Some(policy.backoff), |&wait| Some(records::next_backoff(wait, policy)))
+            .take(20)
+            .collect();
+        assert_eq!(waits[1], Duration::from_millis(20));
+        assert_eq!(waits[19], Duration::from_secs(1));
+        let long = RetryPolicy { attempts: 3, backoff: Duration::from_secs(5) };
+        assert_eq!(records::next_backoff(long.backoff, long), long.backoff);
+    }
+    
+    #[test]
+    fn test_progress_callbacks() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let engine = StorageEngine::new(temp_dir.path())?;
+        let data = patterned(5 * 1024 * 1024 + 512 * 1024);
+        let total = data.len() as u64;
+        
+        let check = |calls: &[(u64, u64)], count: usize| {
+            assert_eq!(calls.len(), count);
+            assert!(calls.windows(2).all(|pair| pair[0].0 < pair[1].0));
+            assert!(calls.iter().all(|&(done, of)| done <= of));
+            assert_eq!(calls.last(), Some(&(total, total)));
+        };
+        
+        let mut calls = Vec::new();
+        let hash = engine.store_reader_with_progress(data.as_slice(), HashAlgorithm::Blake3, 1024 * 1024, total, |done, of| calls.push((done, of)))?;
+        check(&calls, 6);
+        
+        // An unknown length is reported as the bytes read so far
+        let mut calls = Vec::new();
+        engine.store_reader_with_progress(data.as_slice(), HashAlgorithm::Blake3, 1024 * 1024, 0, |done, of| calls.push((done, of)))?;
+        assert!(calls.iter().all(|&(done, of)| done == of));
+        check(&calls, 6);
+        
+        engine.lock_cache().clear();
+        let mut calls = Vec::new();
+        let mut out = Vec::new();
+        engine.retrieve_to_writer_with_progress(&hash, &mut out, |done, of| calls.push((done, of)))?;
+        assert_eq!(out, data);
+        check(&calls, 6);
+        
+        // A file stored whole is reported once
+        let mut calls = Vec::new();
+        engine.store_reader_with_progress(&b"small"[..], HashAlgorithm::Blake3, 1024 * 1024, 5, |done, of| calls.push((done, of)))?;
+        assert_eq!(calls, vec![(5, 5)]);
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_metadata_records_are_canonical() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let engine = StorageEngine::new(temp_dir.path())?;
+        let data = patterned(200_000);
+        let hash = engine.store_with_options(&data, HashAlgorithm::Blake3, 64 * 1024)?;
+        let mut metadata = engine.get_metadata(&hash)?.unwrap();
+        
+        // Attribute insertion order doesn't change the encoding
+        let names: Vec<String> = (0..32).map(|i| format!("attr{}", i)).collect();
+        metadata.attrs = names.iter().map(|name| (name.clone(), name.to_uppercase())).collect();
+        let mut reversed = metadata.clone();
+        reversed.attrs = names.iter().rev().map(|name| (name.clone(), name.to_uppercase())).collect();
+        let record = serialize_metadata(&metadata)?;
+        assert_eq!(record, serialize_metadata(&reversed)?);
+        assert!(record.starts_with(b"SVM\x01"));
+        assert_eq!(metadata_format::decode(&record).unwrap().attrs, metadata.attrs);
+        
+        // A later layout is detected rather than misread
+        let mut future = record.clone();
+        future[3] = 2;
+        assert!(metadata_format::decode(&future).is_err());
+        
+        // JSON records from older versions still read, and migrate in place
+        let meta_key = format!("meta:{}", hash);
+        engine.db.put(meta_key.as_bytes(), serde_json::to_vec(&metadata).unwrap())?;
+        assert_eq!(engine.get_metadata(&hash)?.unwrap().attrs, metadata.attrs);
+        
+        assert_eq!(engine.migrate_metadata()?, 1);
+        assert_eq!(engine.db.get(meta_key.as_bytes())?.unwrap(), record);
+        assert_eq!(engine.migrate_metadata()?, 0);
+        engine.lock_cache().clear();
+        assert_eq!(engine.retrieve(&hash)?, data);
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_find_cross_algorithm_duplicates() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let engine = StorageEngine::new(temp_dir.path())?;
+        let data = patterned(10_000);
+        
+        assert!(engine.find_cross_algorithm_duplicates()?.is_empty());
+        
+        let blake3 = engine.store_with_options(&data, HashAlgorithm::Blake3, 0)?;
+        let keccak = engine.store_with_options(&data, HashAlgorithm::Keccak256, 0)?;
+        let sha256 = engine.store_with_options(&data, HashAlgorithm::Sha256, 0)?;
+        engine.store_with_options(&data[1..], HashAlgorithm::Blake2b, 0)?;
+        let other = engine.store_with_options(b"other", HashAlgorithm::Blake3, 0)?;
+        let other_keccak = engine.store_with_options(b"other", HashAlgorithm::Keccak256, 0)?;
+        // Chunked files are left out
+        engine.store_with_options(&data, HashAlgorithm::Blake2b, 1024)?;
+        
+        let mut expected = vec![vec![blake3, keccak, sha256], vec![other, other_keccak]];
+        for group in &mut expected {
+            group.sort();
+        }
+        expected.sort();
+        assert_eq!(engine.find_cross_algorithm_duplicates()?, expected);
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_open_existing() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let path = temp_dir.path().join("store");
+        
+        assert!(matches!(StorageEngine::open_existing(&path), Err(StorageError::DatabaseNotFound(_))));
+        assert!(!path.exists());
+        
+        let hash = StorageEngine::new(&path)?.store(b"kept")?;
+        let engine = StorageEngine::open_existing(&path)?;
+        assert_eq!(engine.retrieve(&hash)?, b"kept");
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_verified_retrieve_stops_at_first_bad_chunk() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let engine = StorageEngine::new(temp_dir.path())?;
+        let data = patterned(10 * 1024);
+        let hash = engine.store_with_options(&data, HashAlgorithm::Blake3, 1024)?;
+        let metadata = engine.get_metadata(&hash)?.unwrap();
+        assert_eq!(metadata.chunks.len(), 10);
         
-        // Hash should be deterministic
-        let hash2 = calculate_hash(data);
-        assert_eq!(hash, hash2);
+        engine.db.put(metadata.chunk_key(1).as_bytes(), encode_chunk(&[5u8; 1024], Compression::None)?)?;
+        engine.db.put(metadata.chunk_key(6).as_bytes(), encode_chunk(&[6u8; 1024], Compression::None)?)?;
+        
+        // Concurrent reads may fetch chunks past the failure, but always
+        // report the lowest bad chunk, whichever thread fails first
+        for concurrency in [2, 3, 4, 8] {
+            let parallel = RetrieveOptions { verify: true, concurrency };
+            for _ in 0..20 {
+                assert!(matches!(engine.retrieve_with(&hash, &parallel), Err(StorageError::CorruptData { chunk: Some(1), .. })));
+            }
+        }
+        
+        // Reading in order stops at the bad chunk: with the later chunks gone,
+        // reading them at all would fail differently
+        for i in 2..10 {
+            engine.db.delete(metadata.chunk_key(i).as_bytes())?;
+        }
+        assert!(matches!(engine.retrieve_verified(&hash), Err(StorageError::CorruptData { chunk: Some(1), .. })));
+        
+        Ok(())
     }
     
     #[test]
-    fn test_calculate_hash_with_algorithm() {
-        let data = b"Hello, SVDB!";
+    fn test_cache_capacity() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let engine = StorageEngine::builder()
+            .cache_capacity(3000)
+            .open(temp_dir.path())?;
+        assert_eq!(engine.info().cache_capacity, 3000);
         
-        // Test all algorithms
-        let hash_blake3 = calculate_hash_with_algorithm(data, HashAlgorithm::Blake3);
-        let hash_blake2b = calculate_hash_with_algorithm(data, HashAlgorithm::Blake2b);
-        let hash_keccak = calculate_hash_with_algorithm(data, HashAlgorithm::Keccak256);
+        let hashes: Vec<String> = (0..3u8)
+            .map(|i| engine.store(&[i; 1000]))
+            .collect::<Result<_>>()?;
+        let large = engine.store(&[9u8; 4000])?;
+        engine.lock_cache().clear();
+        for hash in &hashes {
+            engine.retrieve(hash)?;
+        }
         
-        // All should produce non-empty results
-        assert!(!hash_blake3.is_empty());
-        assert!(!hash_blake2b.is_empty());
-        assert!(!hash_keccak.is_empty());
+        // Reading the first file again makes the second the least recently used
+        engine.retrieve(&hashes[0])?;
+        let fourth = engine.store(&[3u8; 1000])?;
+        engine.retrieve(&fourth)?;
+        {
+            let cache = engine.lock_cache();
+            assert!(cache.contains_key(&hashes[0]) && cache.contains_key(&hashes[2]) && cache.contains_key(&fourth));
+            assert!(!cache.contains_key(&hashes[1]));
+            assert_eq!(cache.bytes(), 3000);
+        }
         
-        // Different algorithms should produce different hashes
-        assert_ne!(hash_blake3, hash_blake2b);
-        assert_ne!(hash_blake3, hash_keccak);
-        assert_ne!(hash_blake2b, hash_keccak);
+        // A file bigger than the whole cache is served without evicting anything
+        assert_eq!(engine.retrieve(&large)?, vec![9u8; 4000]);
+        let cache = engine.lock_cache();
+        assert!(!cache.contains_key(&large));
+        assert_eq!(cache.bytes(), 3000);
+        
+        Ok(())
     }
     
     #[test]
-    fn test_chunking() -> Result<()> {
-        // Generate a larger test file
-        let large_data = vec![0u8; 5 * 1024 * 1024]; // 5MB
-        let chunk_size = 1024 * 1024; // 1MB
+    fn test_info() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let engine = StorageEngine::builder()
+            .compression(Compression::Zstd)
+            .cache(false)
+            .namespace("diag")
+            .open(temp_dir.path())?;
         
-        // Chunk the data
-        let chunked = chunk_data(&large_data, chunk_size, HashAlgorithm::Blake3)?;
+        let info = engine.info();
+        assert_eq!(info.path, temp_dir.path());
+        assert_eq!(info.namespace.as_deref(), Some("diag"));
+        assert_eq!(info.compression, Compression::Zstd);
+        assert_eq!(info.default_algorithm, HashAlgorithm::Blake3);
+        assert_eq!(info.auto_chunk_threshold, AUTO_CHUNK_THRESHOLD);
+        assert!(!info.cache_enabled && !info.read_only && !info.encrypted && !info.keyed_hashes);
         
-        // Should have 5 chunks
-        assert_eq!(chunked.chunks.len(), 5);
-        assert_eq!(chunked.metadata.chunks.len(), 5);
+        assert_eq!(info.cache_capacity, DEFAULT_CACHE_CAPACITY);
+        assert_eq!(engine.namespace("other")?.info().namespace.as_deref(), Some("other"));
         
         Ok(())
     }
     
     #[test]
-    fn test_store_retrieve_chunked() -> Result<()> {
+    fn test_copy_to() -> Result<()> {
+        let source_dir = tempdir()?;
+        let dest_dir = tempdir()?;
+        let source = StorageEngine::new(source_dir.path())?;
+        let dest = StorageEngine::builder()
+            .compression(Compression::Zstd)
+            .encryption(EncryptionConfig::new([9u8; 32]))
+            .open(dest_dir.path())?;
+        let data = patterned(300_000);
+        
+        let options = StoreOptions {
+            chunk_size: 64 * 1024,
+            tags: vec!["copied".to_string()],
+            ..Default::default()
+        };
+        let chunked = source.store_with(&data, &options)?;
+        let simple = source.store_with_options(b"simple file", HashAlgorithm::Keccak256, 0)?;
+        
+        source.copy_to(&chunked, &dest)?;
+        source.copy_to(&simple, &dest)?;
+        assert!(matches!(source.copy_to("missing", &dest), Err(StorageError::HashNotFound(_))));
+        let keyed_dir = tempdir()?;
+        let keyed = StorageEngine::with_config(keyed_dir.path(), StorageConfig {
+            hash_key: Some([3u8; 32]),
+            ..Default::default()
+        })?;
+        assert!(matches!(source.copy_to(&simple, &keyed), Err(StorageError::ConfigMismatch(_))));
+        
+        assert_eq!(dest.retrieve_verified(&chunked)?, data);
+        assert_eq!(dest.retrieve_verified(&simple)?, b"simple file");
+        let metadata = dest.get_metadata(&chunked)?.unwrap();
+        assert_eq!(metadata.tags, vec!["copied".to_string()]);
+        assert_eq!(metadata.compression.as_deref(), Some("zstd"));
+        assert_eq!(dest.get_metadata(&simple)?.unwrap().algorithm, "keccak256");
+        
+        // Copying again takes no extra chunk references, so one delete frees the chunks
+        source.copy_to(&chunked, &dest)?;
+        assert!(dest.delete(&chunked)?);
+        assert!(metadata.chunks.iter().all(|chunk_hash| !dest.has_chunk(chunk_hash).unwrap()));
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_reserved_prefix_migration() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let families = ["default", "metadata", "chunks", "values"];
+        let mut opts = Options::default();
+        opts.create_if_missing(true);
+        opts.create_missing_column_families(true);
+        
+        // A store laid out with column families but bare internal keys
+        let large_data = patterned(3 * 1024);
+        let chunked = chunk_data(&large_data, 1024, HashAlgorithm::Blake3, &ChunkingStrategy::Fixed, None)?;
+        {
+            let db = rocksdb::DB::open_cf(&opts, temp_dir.path(), families)?;
+            let (default, metadata, chunks, values) = (
+                db.cf_handle("default").unwrap(),
+                db.cf_handle("metadata").unwrap(),
+                db.cf_handle("chunks").unwrap(),
+                db.cf_handle("values").unwrap(),
+            );
+            db.put_cf(&default, "schema:column-families", b"1")?;
+            db.put_cf(&values, calculate_hash(b"old simple"), b"old simple")?;
+            for (chunk_hash, chunk) in chunked.metadata.chunks.iter().zip(&chunked.chunks) {
+                db.put_cf(&chunks, format!("chunk:{}", chunk_hash), encode_chunk(chunk, Compression::None)?)?;
+                db.put_cf(&default, format!("chunkref:{}", chunk_hash), 1u64.to_le_bytes())?;
+            }
+            db.put_cf(&metadata, format!("meta:{}", chunked.metadata.hash), serialize_metadata(&chunked.metadata)?)?;
+            db.put_cf(&metadata, format!("alpha/meta:{}", chunked.metadata.hash), serialize_metadata(&chunked.metadata)?)?;
+        }
+        
+        {
+            let engine = StorageEngine::new(temp_dir.path())?;
+            assert_eq!(engine.retrieve(&calculate_hash(b"old simple"))?, b"old simple");
+            assert_eq!(engine.retrieve_verified(&chunked.metadata.hash)?, large_data);
+            assert_eq!(engine.list_hashes()?.len(), 2);
+            assert_eq!(engine.gc()?.chunks_removed, 0);
+            assert_eq!(engine.namespace("alpha")?.get_metadata(&chunked.metadata.hash)?.unwrap().hash, chunked.metadata.hash);
+        }
+        
+        // Every internal record now sits behind the reserved prefix; values don't
+        let db = rocksdb::DB::open_cf(&opts, temp_dir.path(), families)?;
+        for family in ["default", "metadata", "chunks"] {
+            for item in db.iterator_cf(&db.cf_handle(family).unwrap(), rocksdb::IteratorMode::Start) {
+                let (key, _) = item?;
+                let key = key.strip_prefix(b"alpha/".as_slice()).unwrap_or(&key);
+                assert!(key.starts_with(b"\0svdb\0"), "{:?}", String::from_utf8_lossy(key));
+            }
+        }
+        let values: Vec<_> = db.iterator_cf(&db.cf_handle("values").unwrap(), rocksdb::IteratorMode::Start).collect::<std::result::Result<_, _>>()?;
+        assert_eq!(&*values[0].0, calculate_hash(b"old simple").as_bytes());
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_rechunk() -> Result<()> {
         let temp_dir = tempdir()?;
         let engine = StorageEngine::new(temp_dir.path())?;
         
-        // Generate test data larger than chunk size
-        let large_data = vec![1u8; 3 * 1024 * 1024]; // 3MB
-        let chunk_size = 1024 * 1024; // 1MB chunks
+        let data = patterned(10 * 1024 + 7);
+        let hash = engine.store_with(&data, &StoreOptions {
+            chunk_size: 1024,
+            tags: vec!["dataset".to_string()],
+            ..Default::default()
+        })?;
         
-        // Store with chunking
-        let hash = engine.store_with_options(&large_data, HashAlgorithm::Blake3, chunk_size)?;
+        // The chunk size is part of the file hash, so rechunking gives a new one
+        let rechunked = engine.rechunk(&hash, 4096)?;
+        assert_ne!(rechunked, hash);
+        assert_eq!(engine.retrieve_verified(&rechunked)?, data);
+        let metadata = engine.get_metadata(&rechunked)?.unwrap();
+        assert_eq!((metadata.chunk_size, metadata.chunks.len()), (4096, 3));
+        assert_eq!(metadata.tags, vec!["dataset".to_string()]);
         
-        // Retrieve
-        let retrieved = engine.retrieve(&hash)?;
+        // The old file stays until deleted, and the same size gives the same hash
+        assert_eq!(engine.retrieve(&hash)?, data);
+        assert_eq!(engine.rechunk(&rechunked, 4096)?, rechunked);
+        assert!(engine.delete(&hash)?);
+        assert_eq!(engine.retrieve_verified(&rechunked)?, data);
         
-        // Verify
-        assert_eq!(retrieved, large_data);
+        // Content that fits one chunk is stored whole
+        let whole = engine.rechunk(&rechunked, 16 * 1024)?;
+        assert_eq!(whole, calculate_hash(&data));
+        
+        assert!(matches!(engine.rechunk(&hash, 4096), Err(StorageError::HashNotFound(_))));
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_tiny_inputs() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let engine = StorageEngine::new(temp_dir.path())?;
+        let chunk_size = MIN_CHUNK_SIZE;
+        
+        for len in [0, 1, chunk_size - 1] {
+            let data = patterned(len);
+            let expected = calculate_hash(&data);
+            
+            // With or without a chunk size, content that fits one chunk is stored whole
+            for requested in [0, chunk_size] {
+                let hash = engine.store_with_options(&data, HashAlgorithm::Blake3, requested)?;
+                assert_eq!(hash, expected, "{} bytes, chunk size {}", len, requested);
+                assert!(engine.get_metadata(&hash)?.unwrap().chunks.is_empty());
+                assert_eq!(engine.store_reader(data.as_slice(), HashAlgorithm::Blake3, requested)?, expected);
+                engine.lock_cache().clear();
+                assert_eq!(engine.retrieve_verified(&hash)?, data);
+            }
+            
+            if len == 0 {
+                assert!(matches!(
+                    chunk_data(&data, chunk_size, HashAlgorithm::Blake3, &ChunkingStrategy::Fixed, None),
+                    Err(StorageError::ChunkingError(_))
+                ));
+                continue;
+            }
+            
+            // Chunked explicitly, tiny content is a single short chunk that reads back whole
+            let chunked = chunk_data(&data, chunk_size, HashAlgorithm::Blake3, &ChunkingStrategy::Fixed, None)?;
+            assert_eq!((chunked.metadata.chunks.len(), chunked.metadata.size), (1, len));
+            let (hash, _) = engine.store_chunked(chunked, Compression::None, None)?;
+            assert_ne!(hash, expected);
+            assert_eq!(engine.retrieve_verified(&hash)?, data);
+            
+            // A resumable upload of one chunk gives the hash `store` would
+            let chunk_hash = engine.put_chunk(&data, HashAlgorithm::Blake3)?;
+            assert_eq!(engine.commit_chunked(&[chunk_hash], HashAlgorithm::Blake3)?, expected);
+        }
+        
+        // A chunked record with no chunks is refused rather than read as empty content
+        let mut metadata = simple_info(&calculate_hash(b"none"), HashAlgorithm::Blake3, 0, None);
+        metadata.chunk_size = chunk_size;
+        engine.db.put(format!("meta:{}", metadata.hash).as_bytes(), serialize_metadata(&metadata)?)?;
+        assert!(matches!(engine.retrieve(&metadata.hash), Err(StorageError::InconsistentMetadata { .. })));
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_existence_filter() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let config = StorageConfig {
+            existence_filter: Some(ExistenceFilterConfig {
+                expected_files: 1000,
+                false_positive_rate: 0.01,
+            }),
+            ..Default::default()
+        };
+        
+        let mut stored = Vec::new();
+        {
+            let engine = StorageEngine::with_config(temp_dir.path(), config.clone())?;
+            for i in 0..500u32 {
+                stored.push(engine.store(&i.to_le_bytes())?);
+            }
+            let data = patterned(3 * 1024);
+            stored.push(engine.store_with_options(&data, HashAlgorithm::Blake3, 1024)?);
+            stored.push(engine.store_reader(&data[1..], HashAlgorithm::Sha256, 1024)?);
+            
+            // Files written after opening are found without a reopen
+            assert!(stored.iter().all(|hash| engine.exists(hash).unwrap()));
+        }
+        
+        // Reopened, the filter is filled from the stored keys: never a false negative
+        let engine = StorageEngine::with_config(temp_dir.path(), config.clone())?;
+        assert!(stored.iter().all(|hash| engine.exists(hash).unwrap()));
+        assert_eq!(engine.existence_filter_metrics().unwrap().misses, 0);
+        
+        // Absent files are mostly ruled out without reading the database
+        for i in 0..1000u32 {
+            assert!(!engine.exists(&calculate_hash(&(i + 1_000_000).to_le_bytes()))?);
+        }
+        let metrics = engine.existence_filter_metrics().unwrap();
+        assert_eq!(metrics.misses + metrics.false_positives, 1000);
+        assert!(metrics.false_positives < 50, "{:?}", metrics);
+        
+        // Deleted files keep their bits, but the database still has the last word
+        assert!(engine.delete(&stored[0])?);
+        assert!(!engine.exists(&stored[0])?);
+        
+        // Each namespace has its own filter
+        let other = engine.namespace("other")?;
+        assert!(!other.exists(&stored[1])?);
+        assert_eq!(other.existence_filter_metrics().unwrap().hits, 0);
+        
+        // Handles on one namespace share it, so none misses another's files
+        let second = engine.namespace("other")?;
+        let hash = other.store(b"stored through the first handle")?;
+        assert!(second.exists(&hash)?);
+        let hash = second.store(b"stored through the second handle")?;
+        assert!(other.exists(&hash)?);
+        
+        assert!(StorageEngine::new(tempdir()?.path())?.existence_filter_metrics().is_none());
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_concurrent_pins() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let engine = StorageEngine::new(temp_dir.path())?;
+        let hash = engine.store(b"shared by many datasets")?;
+        
+        // Every thread takes three pins and releases two, interleaved with the others
+        std::thread::scope(|scope| {
+            let workers: Vec<_> = (0..16).map(|_| scope.spawn(|| -> Result<()> {
+                for _ in 0..50 {
+                    engine.pin(&hash)?;
+                    engine.pin(&hash)?;
+                    engine.unpin(&hash)?;
+                    engine.pin(&hash)?;
+                    engine.unpin(&hash)?;
+                }
+                Ok(())
+            })).collect();
+            workers.into_iter().try_for_each(|worker| worker.join().unwrap())
+        })?;
+        assert_eq!(engine.pin_count(&hash)?, 16 * 50);
+        
+        // Releasing more pins than were taken stops at zero without swallowing later pins
+        for _ in 0..16 * 50 + 3 {
+            engine.unpin(&hash)?;
+        }
+        assert_eq!(engine.unpin(&hash)?, 0);
+        
+        // Released counts don't outlive the next gc
+        engine.gc()?;
+        assert_eq!(engine.db.scan_prefix(b"refcount:").count(), 0);
+        assert_eq!(engine.unpin(&hash)?, 0);
+        assert_eq!(engine.db.scan_prefix(b"refcount:").count(), 1);
+        engine.gc()?;
+        assert_eq!(engine.db.scan_prefix(b"refcount:").count(), 0);
+        assert_eq!(engine.pin(&hash)?, 1);
+        
+        // A count written whole, as gc repairs do, stays the base for later deltas
+        engine.db.put(format!("refcount:{}", hash).as_bytes(), 5u64.to_le_bytes())?;
+        assert_eq!(engine.unpin(&hash)?, 4);
+        engine.db.compact();
+        drop(engine);
+        assert_eq!(StorageEngine::open_read_only(temp_dir.path())?.pin_count(&hash)?, 4);
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_sharded_engine() -> Result<()> {
+        let temp_dirs: Vec<_> = (0..4).map(|_| tempdir()).collect::<std::io::Result<_>>()?;
+        let open = |dirs: &[tempfile::TempDir]| -> Result<ShardedStorageEngine> {
+            ShardedStorageEngine::new(dirs.iter().map(|dir| StorageEngine::new(dir.path())).collect::<Result<_>>()?)
+        };
+        
+        let mut stored = Vec::new();
+        {
+            let sharded = open(&temp_dirs)?;
+            for i in 0..64u32 {
+                let data = format!("file {}", i).into_bytes();
+                let hash = sharded.store(&data)?;
+                
+                // The leading 64 bits of the hash pick the shard, and only that shard holds the file
+                let index = (u64::from_str_radix(&hash[..16], 16).unwrap() % 4) as usize;
+                assert_eq!(sharded.shard_index(&hash)?, index);
+                for (i, shard) in sharded.shards().iter().enumerate() {
+                    assert_eq!(shard.exists(&hash)?, i == index);
+                }
+                stored.push((hash, data));
+            }
+            
+            let large_data = patterned(3 * 1024);
+            let hash = sharded.store_with_options(&large_data, HashAlgorithm::Blake3, 1024)?;
+            assert_eq!(sharded.shard_for(&hash)?.get_metadata(&hash)?.unwrap().chunks.len(), 3);
+            stored.push((hash, large_data));
+            
+            // Every shard got a share
+            let mut counts = [0; 4];
+            for (hash, _) in &stored {
+                counts[sharded.shard_index(hash)?] += 1;
+            }
+            assert!(counts.iter().all(|count| *count > 0), "{:?}", counts);
+        }
+        
+        // Reopened in the same order, every file is found where it was stored
+        let sharded = open(&temp_dirs)?;
+        for (hash, data) in &stored {
+            assert!(sharded.exists(hash)?);
+            assert_eq!(&sharded.retrieve_verified(hash)?, data);
+        }
+        assert!(sharded.delete(&stored[0].0)?);
+        assert!(!sharded.exists(&stored[0].0)?);
+        assert!(matches!(sharded.retrieve(&stored[0].0), Err(StorageError::HashNotFound(_))));
+        drop(sharded);
+        
+        // A different order or count would reroute files, so it is refused
+        let mut reordered = temp_dirs;
+        reordered.swap(0, 1);
+        assert!(matches!(open(&reordered), Err(StorageError::ShardMismatch(_))));
+        assert!(matches!(open(&reordered[..3]), Err(StorageError::ShardMismatch(_))));
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_rebuild_metadata() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let engine = StorageEngine::new(temp_dir.path())?;
+        
+        // Files written before deduplication, with enough chunks that `:10` sorts before `:2`
+        let data = patterned(12 * 1024 + 5);
+        let mut hashes = Vec::new();
+        for compression in [Compression::None, Compression::Zstd] {
+            let algorithm = if compression == Compression::None { HashAlgorithm::Blake3 } else { HashAlgorithm::Sha256 };
+            let mut metadata = chunk_data(&data, 1024, algorithm, &ChunkingStrategy::Fixed, None)?.metadata;
+            metadata.layout = ChunkLayout::Indexed;
+            metadata.compression = compression.codec().map(str::to_string);
+            for (index, chunk) in data.chunks(1024).enumerate() {
+                engine.db.put(metadata.chunk_key(index).as_bytes(), compression.compress(chunk)?)?;
+            }
+            engine.db.put(format!("meta:{}", metadata.hash).as_bytes(), serialize_metadata(&metadata)?)?;
+            assert_eq!(engine.retrieve(&metadata.hash)?, data);
+            hashes.push((metadata.hash, algorithm));
+        }
+        
+        for (hash, algorithm) in &hashes {
+            // The metadata record is lost: the chunks are still there but unreachable
+            engine.db.delete(format!("meta:{}", hash).as_bytes())?;
+            engine.lock_cache().clear();
+            assert!(matches!(engine.retrieve(hash), Err(StorageError::HashNotFound(_))));
+            
+            // Chunks that don't reproduce the hash leave it unreachable
+            assert!(matches!(engine.rebuild_metadata(hash, *algorithm, 2048), Err(StorageError::CorruptData { .. })));
+            assert!(!engine.exists(hash)?);
+            
+            engine.rebuild_metadata(hash, *algorithm, 1024)?;
+            assert_eq!(engine.retrieve_verified(hash)?, data);
+            assert_eq!(engine.retrieve_range(hash, 5000, 100)?, data[5000..5100]);
+            assert!(engine.fsck()?.is_clean());
+            
+            // A record that is still there is never replaced
+            let mut tagged = engine.get_metadata(hash)?.unwrap();
+            tagged.tags = vec!["keep".to_string()];
+            engine.db.put(format!("meta:{}", hash).as_bytes(), serialize_metadata(&tagged)?)?;
+            engine.rebuild_metadata(hash, *algorithm, 1024)?;
+            assert_eq!(engine.get_metadata(hash)?.unwrap().tags, vec!["keep".to_string()]);
+        }
+        
+        // A lost chunk can't be made up for
+        let (hash, algorithm) = &hashes[0];
+        engine.db.delete(format!("meta:{}", hash).as_bytes())?;
+        engine.db.delete(format!("chunk:{}:3", hash).as_bytes())?;
+        assert!(matches!(engine.rebuild_metadata(hash, *algorithm, 1024), Err(StorageError::ChunkingError(_))));
+        assert!(matches!(engine.rebuild_metadata(&calculate_hash(b"never stored"), *algorithm, 1024), Err(StorageError::HashNotFound(_))));
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_auto_blake3_threshold() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let engine = StorageEngine::new(temp_dir.path())?;
+        let options = StoreOptions {
+            algorithm: HashAlgorithm::Keccak256,
+            chunk_size: 64 * 1024,
+            auto_blake3_threshold: Some(128 * 1024),
+            ..Default::default()
+        };
+        
+        // Small inputs keep the explicit choice
+        let small = engine.store_with(b"tiny", &options)?;
+        assert_eq!(small, calculate_hash_with_algorithm(b"tiny", HashAlgorithm::Keccak256));
+        assert_eq!(engine.get_metadata(&small)?.unwrap().algorithm, "keccak256");
+        
+        // Large ones record the algorithm actually used, never the policy
+        let large_data = patterned(256 * 1024);
+        let large = engine.store_with(&large_data, &options)?;
+        let metadata = engine.get_metadata(&large)?.unwrap();
+        assert_eq!(metadata.algorithm, "blake3");
+        assert_eq!(large, engine.store_with_options(&large_data, HashAlgorithm::Blake3, 64 * 1024)?);
+        engine.lock_cache().clear();
+        assert_eq!(engine.retrieve_verified(&large)?, large_data);
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_max_value_bytes() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let engine = StorageEngine::with_config(temp_dir.path(), StorageConfig {
+            max_value_bytes: Some(64 * 1024),
+            chunk_threshold: Some(1024 * 1024),
+            ..Default::default()
+        })?;
+        let data = patterned(100 * 1024);
+        
+        // Without a chunk size the value can't be split, so it is refused
+        match engine.store_with_options(&data, HashAlgorithm::Blake3, 0) {
+            Err(StorageError::ValueTooLarge { size, limit }) => assert_eq!((size, limit), (100 * 1024, 64 * 1024)),
+            other => panic!("expected ValueTooLarge, got {:?}", other),
+        }
+        assert!(matches!(engine.store_batch(&[&data]), Err(StorageError::ValueTooLarge { .. })));
+        assert!(!engine.exists(&calculate_hash(&data))?);
+        
+        // With one, the limit overrides the chunk threshold, as it does for `store`
+        let hash = engine.store_with_options(&data, HashAlgorithm::Blake3, 16 * 1024)?;
+        assert_eq!(engine.get_metadata(&hash)?.unwrap().chunks.len(), 7);
+        assert_eq!(engine.store_reader(data.as_slice(), HashAlgorithm::Blake3, 16 * 1024)?, hash);
+        let stored = engine.store(&data)?;
+        assert_eq!(engine.get_metadata(&stored)?.unwrap().chunk_size, 64 * 1024);
+        assert_eq!(engine.retrieve(&stored)?, data);
+        
+        // No chunk may be larger than the limit either
+        assert!(matches!(
+            engine.store_with_options(&data, HashAlgorithm::Blake3, 96 * 1024),
+            Err(StorageError::ChunkingError(_))
+        ));
+        
+        // Values up to the limit are stored whole as before
+        let small = engine.store_with_options(&data[..64 * 1024], HashAlgorithm::Blake3, 0)?;
+        assert_eq!(small, calculate_hash(&data[..64 * 1024]));
+        assert_eq!(engine.info().max_value_bytes, 64 * 1024);
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_secondary() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let secondary_dir = tempdir()?;
+        let primary = StorageEngine::with_config(temp_dir.path(), StorageConfig {
+            existence_filter: Some(ExistenceFilterConfig::default()),
+            ..Default::default()
+        })?;
+        let before = primary.store(b"before")?;
+        primary.flush()?;
+        
+        let secondary = StorageEngine::builder()
+            .config(StorageConfig {
+                existence_filter: Some(ExistenceFilterConfig::default()),
+                ..Default::default()
+            })
+            .secondary(secondary_dir.path())
+            .open(temp_dir.path())?;
+        assert_eq!(secondary.retrieve(&before)?, b"before");
+        assert!(secondary.info().read_only);
+        
+        // Writes on the primary only show up once the secondary catches up
+        let data = patterned(3 * 1024 * 1024);
+        let after = primary.store_with_options(&data, HashAlgorithm::Blake3, 1024 * 1024)?;
+        assert!(primary.delete(&before)?);
+        assert!(!secondary.exists(&after)?);
+        
+        secondary.catch_up_with_primary()?;
+        assert!(secondary.exists(&after)?);
+        assert_eq!(secondary.retrieve_verified(&after)?, data);
+        assert!(!secondary.exists(&before)?);
+        
+        // Catching up through one handle refills the filters of every namespace
+        let logs = secondary.namespace("logs")?;
+        let logged = primary.namespace("logs")?.store(b"log line")?;
+        secondary.catch_up_with_primary()?;
+        assert!(logs.exists(&logged)?);
+        logs.catch_up_with_primary()?;
+        assert_eq!(logs.retrieve(&logged)?, b"log line");
+        
+        assert!(matches!(secondary.store(b"nope"), Err(StorageError::ReadOnly(_))));
+        assert!(matches!(secondary.delete(&after), Err(StorageError::ReadOnly(_))));
+        assert!(primary.catch_up_with_primary().is_err());
+        
+        let other_dir = tempdir()?;
+        let plain = StorageEngine::open_as_secondary(temp_dir.path(), other_dir.path())?;
+        assert_eq!(plain.retrieve(&after)?, data);
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_write_timeout() -> Result<()> {
+        let timeout = Duration::from_millis(200);
+        let temp_dir = tempdir()?;
+        let engine = StorageEngine::with_config(temp_dir.path(), StorageConfig {
+            write_timeout: Some(timeout),
+            ..Default::default()
+        })?;
+        assert_eq!(engine.db.with_timeout(timeout, || Ok(7))?, 7);
+        assert!(matches!(
+            engine.db.with_timeout(timeout, || Err::<(), _>(StorageError::HashNotFound("x".to_string()))),
+            Err(StorageError::HashNotFound(_))
+        ));
+        
+        // A store whose write can't start in time is given up on
+        let release = engine.db.stall_write_workers(false);
+        let late = b"written after its store gave up";
+        assert!(matches!(engine.store(late), Err(StorageError::Timeout(t)) if t == timeout));
+        
+        // Until the write lands, gc can't take the lock and nor can another store
+        assert!(matches!(engine.gc(), Err(StorageError::Timeout(_))));
+        assert!(matches!(engine.store(b"queued behind it"), Err(StorageError::Timeout(_))));
+        
+        // Once it lands, the store is complete and everything goes ahead
+        drop(release);
+        engine.gc()?;
+        assert_eq!(engine.retrieve_verified(&calculate_hash(late))?, late);
+        
+        // Writes that finish in time behave as without a timeout
+        let temp_dir = tempdir()?;
+        let engine = StorageEngine::with_config(temp_dir.path(), StorageConfig {
+            write_timeout: Some(Duration::from_secs(30)),
+            ..Default::default()
+        })?;
+        let data = patterned(3 * 1024 * 1024);
+        let hash = engine.store_with_options(&data, HashAlgorithm::Blake3, 1024 * 1024)?;
+        assert_eq!(engine.pin(&hash)?, 1);
+        assert_eq!(engine.unpin(&hash)?, 0);
+        assert_eq!(engine.retrieve_verified(&hash)?, data);
+        assert!(engine.delete(&hash)?);
+        assert!(!engine.exists(&hash)?);
+        
+        // A write finding the queue full waits for room instead of timing out at once
+        let release = engine.db.stall_write_workers(true);
+        let releaser = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(100));
+            drop(release);
+        });
+        let queued = engine.store(b"waited for a free worker")?;
+        releaser.join().unwrap();
+        assert_eq!(engine.retrieve(&queued)?, b"waited for a free worker");
+        
+        Ok(())
+    }
+    
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_parallel_store_failure_leaves_orphans() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let engine = StorageEngine::with_config(temp_dir.path(), StorageConfig {
+            write_timeout: Some(Duration::from_millis(200)),
+            ..Default::default()
+        })?;
+        let data = patterned(3 * 1024 * 1024);
+        let (hash, _) = engine.would_store(&data, HashAlgorithm::Blake3, 1024 * 1024)?;
+        
+        // Chunk bodies are written before the metadata, so the store fails without recording the file
+        let release = engine.db.stall_write_workers(false);
+        assert!(matches!(engine.store_with_options(&data, HashAlgorithm::Blake3, 1024 * 1024), Err(StorageError::Timeout(_))));
+        drop(release);
+        assert!(!engine.exists(&hash)?);
+        
+        // The bodies that did land are unreferenced, and gc removes them
+        let report = engine.gc()?;
+        assert!((1..=3).contains(&report.chunks_removed));
+        assert_eq!(engine.db.scan_prefix(b"chunk:").count(), 0);
+        
+        assert_eq!(engine.store_with_options(&data, HashAlgorithm::Blake3, 1024 * 1024)?, hash);
+        assert_eq!(engine.retrieve_verified(&hash)?, data);
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_aliases() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let engine = StorageEngine::new(temp_dir.path())?;
+        let v1 = engine.store(b"release one")?;
+        let v2 = engine.store(b"release two")?;
+        
+        engine.set_alias("latest", &v1)?;
+        engine.set_alias("v1", &v1)?;
+        assert_eq!(engine.resolve_alias("latest")?, v1);
+        
+        // Moving an alias leaves the content it pointed at alone
+        engine.set_alias("latest", &v2)?;
+        assert_eq!(engine.retrieve_by_alias("latest")?, b"release two");
+        assert_eq!(engine.retrieve_by_alias("v1")?, b"release one");
+        assert_eq!(engine.list_aliases()?, vec![("latest".to_string(), v2.clone()), ("v1".to_string(), v1.clone())]);
+        
+        let missing = calculate_hash(b"never stored");
+        assert!(matches!(engine.set_alias("ghost", &missing), Err(StorageError::HashNotFound(_))));
+        assert!(matches!(engine.resolve_alias("ghost"), Err(StorageError::AliasNotFound(_))));
+        
+        assert!(engine.remove_alias("v1")?);
+        assert!(!engine.remove_alias("v1")?);
+        assert!(matches!(engine.retrieve_by_alias("v1"), Err(StorageError::AliasNotFound(_))));
+        assert!(engine.exists(&v1)?);
+        
+        // Aliases are not files
+        assert_eq!(engine.list_hashes()?.len(), 2);
+        
+        // Names that can't be stored as record keys are refused
+        for name in ["", "a/b", "/"] {
+            assert!(matches!(engine.set_alias(name, &v2), Err(StorageError::InvalidAlias(_))));
+            assert!(matches!(engine.resolve_alias(name), Err(StorageError::InvalidAlias(_))));
+            assert!(matches!(engine.retrieve_by_alias(name), Err(StorageError::InvalidAlias(_))));
+            assert!(matches!(engine.remove_alias(name), Err(StorageError::InvalidAlias(_))));
+        }
+        assert_eq!(engine.list_aliases()?.len(), 1);
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_inflated_size() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let engine = StorageEngine::new(temp_dir.path())?;
+        
+        let data = patterned(3 * 1024 * 1024);
+        let hash = engine.store_with_options(&data, HashAlgorithm::Blake3, 1024 * 1024)?;
+        let metadata = engine.get_metadata(&hash)?.unwrap();
+        
+        let tamper = |size: usize| -> Result<()> {
+            let mut tampered = metadata.clone();
+            tampered.size = size;
+            engine.db.put(format!("meta:{}", hash).as_bytes(), serialize_metadata(&tampered)?)?;
+            engine.lock_cache().clear();
+            Ok(())
+        };
+        
+        // A size no machine could allocate is refused after reading the chunks
+        tamper(usize::MAX / 2)?;
+        assert!(matches!(engine.retrieve(&hash), Err(StorageError::CorruptData { chunk: None, .. })));
+        assert!(matches!(engine.retrieve_with(&hash, &RetrieveOptions { verify: false, concurrency: 4 }), Err(StorageError::CorruptData { chunk: None, .. })));
+        
+        // So is one just a byte off either way
+        tamper(data.len() + 1)?;
+        assert!(matches!(engine.retrieve(&hash), Err(StorageError::CorruptData { chunk: None, .. })));
+        tamper(data.len() - 1)?;
+        assert!(matches!(engine.retrieve(&hash), Err(StorageError::CorruptData { chunk: None, .. })));
+        
+        tamper(data.len())?;
+        assert_eq!(engine.retrieve(&hash)?, data);
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_concurrent_shared_chunks() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let engine = StorageEngine::new(temp_dir.path())?;
+        let chunk_size = MIN_CHUNK_SIZE;
+        let shared = patterned(2 * chunk_size);
+        let keeper = engine.store_with_options(&shared, HashAlgorithm::Blake3, chunk_size)?;
+        let shared_chunks = engine.get_metadata(&keeper)?.unwrap().chunks;
+        
+        // Every thread stores and deletes its own file built on the shared chunks
+        std::thread::scope(|scope| {
+            let workers: Vec<_> = (0..8u8).map(|t| {
+                let (engine, shared) = (&engine, &shared);
+                scope.spawn(move || -> Result<()> {
+                    let mut data = shared.clone();
+                    data.extend(std::iter::repeat_n(t, chunk_size));
+                    for _ in 0..50 {
+                        let hash = engine.store_with_options(&data, HashAlgorithm::Blake3, chunk_size)?;
+                        assert!(engine.delete(&hash)?);
+                    }
+                    Ok(())
+                })
+            }).collect();
+            workers.into_iter().try_for_each(|worker| worker.join().unwrap())
+        })?;
+        
+        // Only the keeper's references remain, and its chunks survived
+        for chunk_hash in &shared_chunks {
+            assert_eq!(engine.chunk_refcount(chunk_hash)?, 1);
+        }
+        engine.lock_cache().clear();
+        assert_eq!(engine.retrieve_verified(&keeper)?, shared);
+        assert!(engine.fsck()?.is_clean());
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_namespace_handles_share_write_lock() -> Result<()> {
+        let temp_dir = tempdir()?;
+        let root = StorageEngine::new(temp_dir.path())?;
+        let writer = root.namespace("shared")?;
+        let collector = root.namespace("shared")?;
+        assert!(Arc::ptr_eq(&writer.write_lock, &collector.write_lock));
+        assert!(!Arc::ptr_eq(&root.write_lock, &writer.write_lock));
+        
+        // A gc through one handle never sees a half-stored file of the other
+        let chunk_size = MIN_CHUNK_SIZE;
+        let shared = patterned(2 * chunk_size);
+        let keeper = writer.store_with_options(&shared, HashAlgorithm::Blake3, chunk_size)?;
+        std::thread::scope(|scope| {
+            let storing = scope.spawn(|| -> Result<()> {
+                for i in 0..200u32 {
+                    let mut data = shared.clone();
+                    data.extend_from_slice(&i.to_le_bytes());
+                    let hash = writer.store_with_options(&data, HashAlgorithm::Blake3, chunk_size)?;
+                    assert!(writer.delete(&hash)?);
+                }
+                Ok(())
+            });
+            for _ in 0..20 {
+                collector.gc()?;
+            }
+            storing.join().unwrap()
+        })?;
+        
+        assert_eq!(collector.retrieve_verified(&keeper)?, shared);
+        assert!(collector.fsck()?.is_clean());
         
         Ok(())
     }
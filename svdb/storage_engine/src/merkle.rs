@@ -0,0 +1,120 @@
+// Merkle trees over chunk hashes
+//
+// A file hashed with `FileHashScheme::Merkle` takes the root of a binary tree
+// whose leaves are its chunk hashes, so a single chunk can be checked against
+// the file hash with about log2(chunks) sibling hashes instead of the whole
+// chunk list. Leaves and inner nodes are hashed under different prefix bytes
+// so a node can never pass for a leaf. A node left without a partner at the
+// end of a level moves up to the next level unchanged.
+
+use crate::{parse_identifier, HashAlgorithm, Hasher, Result, StorageError};
+
+const LEAF_PREFIX: u8 = 0;
+const NODE_PREFIX: u8 = 1;
+
+fn decode(hash: &str) -> Result<Vec<u8>> {
+    hex::decode(hash)
+        .map_err(|e| StorageError::SerializationError(format!("invalid chunk hash {}: {}", hash, e)))
+}
+
+fn leaf(chunk_hash: &str, algorithm: HashAlgorithm, key: Option<&[u8; 32]>) -> Result<Vec<u8>> {
+    let mut hasher = Hasher::keyed(algorithm, key)?;
+    hasher.update(&[LEAF_PREFIX]);
+    hasher.update(&decode(chunk_hash)?);
+    Ok(hasher.finalize_bytes())
+}
+
+fn node(left: &[u8], right: &[u8], algorithm: HashAlgorithm, key: Option<&[u8; 32]>) -> Result<Vec<u8>> {
+    let mut hasher = Hasher::keyed(algorithm, key)?;
+    hasher.update(&[NODE_PREFIX]);
+    hasher.update(left);
+    hasher.update(right);
+    Ok(hasher.finalize_bytes())
+}
+
+/// Every level of the tree, leaves first and the root alone last
+fn levels(chunk_hashes: &[String], algorithm: HashAlgorithm, key: Option<&[u8; 32]>) -> Result<Vec<Vec<Vec<u8>>>> {
+    if chunk_hashes.is_empty() {
+        return Err(StorageError::ChunkingError("a Merkle tree needs at least one chunk".to_string()));
+    }
+
+    let leaves = chunk_hashes.iter()
+        .map(|chunk_hash| leaf(chunk_hash, algorithm, key))
+        .collect::<Result<Vec<_>>>()?;
+    let mut levels = vec![leaves];
+    while let Some(level) = levels.last().filter(|level| level.len() > 1) {
+        let next = level.chunks(2)
+            .map(|pair| match pair {
+                [left, right] => node(left, right, algorithm, key),
+                _ => Ok(pair[0].clone()),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        levels.push(next);
+    }
+    Ok(levels)
+}
+
+/// The hex root of the tree over `chunk_hashes`
+pub(crate) fn root(chunk_hashes: &[String], algorithm: HashAlgorithm, key: Option<&[u8; 32]>) -> Result<String> {
+    let levels = levels(chunk_hashes, algorithm, key)?;
+    Ok(hex::encode(&levels[levels.len() - 1][0]))
+}
+
+/// The sibling hashes from chunk `index` up to the root, lowest first
+///
+/// `index` must be within `chunk_hashes`.
+pub(crate) fn proof(chunk_hashes: &[String], index: usize, algorithm: HashAlgorithm, key: Option<&[u8; 32]>) -> Result<Vec<String>> {
+    let levels = levels(chunk_hashes, algorithm, key)?;
+
+    let mut proof = Vec::new();
+    let mut index = index;
+    for level in &levels[..levels.len() - 1] {
+        if let Some(sibling) = level.get(index ^ 1) {
+            proof.push(hex::encode(sibling));
+        }
+        index /= 2;
+    }
+    Ok(proof)
+}
+
+/// Check a proof from `StorageEngine::chunk_proof` against a file hash
+///
+/// `chunk_hash` is the hash of chunk `index` of a file with `chunk_count`
+/// chunks, as listed by `chunk_list`. `key` must be the engine's hash key,
+/// if it has one. A proof for another chunk, position or file gives `false`.
+pub fn verify_chunk_proof(
+    root: &str,
+    chunk_hash: &str,
+    index: usize,
+    chunk_count: usize,
+    proof: &[String],
+    algorithm: HashAlgorithm,
+    key: Option<&[u8; 32]>,
+) -> Result<bool> {
+    if index >= chunk_count {
+        return Ok(false);
+    }
+    let (root, _) = parse_identifier(root)?;
+
+    let mut hash = leaf(chunk_hash, algorithm, key)?;
+    let mut siblings = proof.iter();
+    let (mut index, mut width) = (index, chunk_count);
+    while width > 1 {
+        // The last node of an odd level has no sibling and moves up as is
+        if index ^ 1 < width {
+            let Some(sibling) = siblings.next() else {
+                return Ok(false);
+            };
+            let sibling = decode(sibling)?;
+            hash = if index % 2 == 0 {
+                node(&hash, &sibling, algorithm, key)?
+            } else {
+                node(&sibling, &hash, algorithm, key)?
+            };
+        }
+        index /= 2;
+        width = width.div_ceil(2);
+    }
+
+    Ok(siblings.next().is_none() && hex::encode(hash) == root)
+}
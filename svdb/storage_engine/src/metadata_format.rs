@@ -0,0 +1,105 @@
+// Metadata record encoding
+//
+// Metadata records were first written as serde_json, whose output for `attrs`
+// follows HashMap iteration order, so the same metadata could be written as
+// different bytes from one run to the next. Records are now a short header
+// followed by bincode of `RecordV1`: fields in declaration order, integers at
+// fixed width and `attrs` sorted by key, so equal metadata always gives
+// byte-identical records. The version byte in the header lets a later layout
+// be told apart from this one.
+//
+// JSON records still decode. They always start with `{`, which can't be
+// mistaken for the header; `StorageEngine::migrate_metadata` rewrites them.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{ChunkLayout, ChunkingStrategy, FileHashScheme, FileMetadata};
+
+const MAGIC: &[u8; 3] = b"SVM";
+const VERSION: u8 = 1;
+
+/// The version 1 record layout. Fields may never be reordered or changed;
+/// a new layout gets a new struct and version.
+#[derive(Serialize, Deserialize)]
+struct RecordV1 {
+    hash: String,
+    algorithm: String,
+    size: u64,
+    chunk_size: u64,
+    chunks: Vec<String>,
+    timestamp: u64,
+    compression: Option<String>,
+    chunking: ChunkingStrategy,
+    layout: ChunkLayout,
+    hash_scheme: FileHashScheme,
+    expires_at: Option<u64>,
+    content_type: Option<String>,
+    tags: Vec<String>,
+    attrs: BTreeMap<String, String>,
+    output_len: Option<u64>,
+}
+
+/// Encode metadata as a current-version record
+pub(crate) fn encode(metadata: &FileMetadata) -> Result<Vec<u8>, String> {
+    let record = RecordV1 {
+        hash: metadata.hash.clone(),
+        algorithm: metadata.algorithm.clone(),
+        size: metadata.size as u64,
+        chunk_size: metadata.chunk_size as u64,
+        chunks: metadata.chunks.clone(),
+        timestamp: metadata.timestamp,
+        compression: metadata.compression.clone(),
+        chunking: metadata.chunking,
+        layout: metadata.layout,
+        hash_scheme: metadata.hash_scheme,
+        expires_at: metadata.expires_at,
+        content_type: metadata.content_type.clone(),
+        tags: metadata.tags.clone(),
+        attrs: metadata.attrs.iter().map(|(k, v)| (k.clone(), v.clone())).collect(),
+        output_len: metadata.output_len.map(|len| len as u64),
+    };
+
+    let mut bytes = MAGIC.to_vec();
+    bytes.push(VERSION);
+    bincode::serialize_into(&mut bytes, &record).map_err(|e| e.to_string())?;
+    Ok(bytes)
+}
+
+/// Decode a record of any version, or a JSON record from before versioning
+pub(crate) fn decode(bytes: &[u8]) -> Result<FileMetadata, String> {
+    let Some(body) = bytes.strip_prefix(MAGIC.as_slice()) else {
+        return serde_json::from_slice(bytes).map_err(|e| e.to_string());
+    };
+
+    match body.split_first() {
+        Some((&VERSION, body)) => {
+            let record: RecordV1 = bincode::deserialize(body).map_err(|e| e.to_string())?;
+            Ok(FileMetadata {
+                hash: record.hash,
+                algorithm: record.algorithm,
+                size: record.size as usize,
+                chunk_size: record.chunk_size as usize,
+                chunks: record.chunks,
+                timestamp: record.timestamp,
+                compression: record.compression,
+                chunking: record.chunking,
+                layout: record.layout,
+                hash_scheme: record.hash_scheme,
+                expires_at: record.expires_at,
+                content_type: record.content_type,
+                tags: record.tags,
+                attrs: record.attrs.into_iter().collect(),
+                output_len: record.output_len.map(|len| len as usize),
+            })
+        }
+        Some((version, _)) => Err(format!("unsupported metadata record version {}", version)),
+        None => Err("metadata record has no version".to_string()),
+    }
+}
+
+/// Whether a record is in the current layout, so `migrate_metadata` can leave it be
+pub(crate) fn is_current(bytes: &[u8]) -> bool {
+    bytes.strip_prefix(MAGIC.as_slice()).and_then(|body| body.first()) == Some(&VERSION)
+}
@@ -0,0 +1,1068 @@
+// Column family routing
+//
+// Records are split across column families by key so that listing and gc only
+// walk the records they care about, and so each family can be tuned on its
+// own. Keys keep their `meta:`/`chunk:` prefixes, which makes the family a pure
+// function of the key and lets callers stay unaware of the split.
+//
+// A namespace gives several logical stores one database. Its records are kept
+// under `{namespace}/` in front of the usual key, added and stripped here, so
+// every scan stays inside the namespace. Record keys never contain `/`
+// (`parse_identifier` turns such identifiers away), which keeps the unnamed
+// namespace clear of the named ones.
+//
+// Internal records (everything outside the values family) are also stored
+// behind `RESERVED_PREFIX`, which starts with a zero byte no hash identifier
+// can contain. A user key can then never land on an internal record's
+// database key, whatever shape identifiers take later. Like the namespace,
+// the prefix is added and stripped here; record keys never carry it.
+
+use std::borrow::Cow;
+use std::collections::{HashMap, VecDeque};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Condvar, Mutex, OnceLock, PoisonError, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use rocksdb::{BoundColumnFamily, ColumnFamilyDescriptor, DBPinnableSlice, Direction, ErrorKind, IteratorMode, MergeOperands, Options, WriteBatch, WriteOptions, DB};
+
+use crate::existence::ExistenceFilter;
+use crate::{Durability, ExistenceFilterConfig, Result, RetryPolicy, StorageError};
+
+/// `meta:` and `info:` records
+pub(crate) const CF_METADATA: &str = "metadata";
+/// `chunk:` bodies
+pub(crate) const CF_CHUNKS: &str = "chunks";
+/// Simple file values, stored under their bare hash
+pub(crate) const CF_VALUES: &str = "values";
+/// Counters and other small bookkeeping records
+const CF_DEFAULT: &str = rocksdb::DEFAULT_COLUMN_FAMILY_NAME;
+
+const FAMILIES: [&str; 4] = [CF_DEFAULT, CF_METADATA, CF_CHUNKS, CF_VALUES];
+
+/// Present once records written before column families were introduced have
+/// been moved out of the default family
+const MIGRATED_KEY: &[u8] = b"schema:column-families";
+
+/// Present once internal records have been moved behind `RESERVED_PREFIX`
+const RESERVED_KEY: &[u8] = b"schema:reserved-prefix";
+
+/// Stored in front of every internal record key, after any namespace
+const RESERVED_PREFIX: &[u8] = b"\0svdb\0";
+
+/// Records moved per write batch while migrating
+const MIGRATION_BATCH: usize = 1000;
+
+/// Name the counter merge operator is registered under
+const COUNTER_MERGE: &str = "svdb.counter";
+
+/// Ends a namespace name at the front of a database key
+const NAMESPACE_SEPARATOR: u8 = b'/';
+
+/// Whether `key` is one a record can have: free of namespace separators and
+/// the reserved prefix, which only this module adds
+pub(crate) fn is_record_key(key: &[u8]) -> bool {
+    !key.contains(&NAMESPACE_SEPARATOR) && !key.starts_with(RESERVED_PREFIX)
+}
+
+/// A record key and its value, as scans yield them
+type Record = (Box<[u8]>, Box<[u8]>);
+
+/// Column family a key belongs to
+pub(crate) fn family_for(key: &[u8]) -> &'static str {
+    if key.starts_with(b"meta:") || key.starts_with(b"info:") {
+        CF_METADATA
+    } else if key.starts_with(b"chunk:") {
+        CF_CHUNKS
+    } else if key.contains(&b':') {
+        CF_DEFAULT
+    } else {
+        CF_VALUES
+    }
+}
+
+/// Apply counter deltas to a little-endian u64 counter, see `Records::add_to_counter`
+///
+/// Deltas apply in write order and the count never drops below zero, so an
+/// extra decrement can't cancel a later increment. A malformed record stops
+/// the merge, which surfaces as an error on read.
+fn merge_counter(_key: &[u8], existing: Option<&[u8]>, operands: &MergeOperands) -> Option<Vec<u8>> {
+    let mut count = match existing {
+        Some(bytes) => u64::from_le_bytes(bytes.try_into().ok()?),
+        None => 0,
+    };
+    for operand in operands {
+        let delta = i64::from_le_bytes(operand.try_into().ok()?);
+        count = count.saturating_add_signed(delta);
+    }
+    Some(count.to_le_bytes().to_vec())
+}
+
+/// Deltas are only combined with the counter they apply to
+///
+/// Summing two deltas alone would lose the clamp at zero between them.
+/// Declining leaves them stacked until a read or compaction reaches the
+/// counter itself.
+fn keep_counter_deltas(_key: &[u8], _existing: Option<&[u8]>, _operands: &MergeOperands) -> Option<Vec<u8>> {
+    None
+}
+
+/// Options every family is opened with
+fn family_options(opts: &Options) -> Options {
+    let mut opts = opts.clone();
+    opts.set_merge_operator(COUNTER_MERGE, merge_counter, keep_counter_deltas);
+    opts
+}
+
+/// Turn the common reasons a database fails to open into their own errors
+///
+/// RocksDB only reports these as message strings, so the message is matched
+/// on; anything unrecognised stays a plain `DBError`.
+fn open_error(err: rocksdb::Error, path: &Path) -> StorageError {
+    let path = path.display().to_string();
+    let message = err.to_string();
+
+    match err.kind() {
+        ErrorKind::Corruption => StorageError::DatabaseCorrupt(format!("{} ({})", path, message)),
+        ErrorKind::IOError if message.contains("Permission denied") => StorageError::PermissionDenied(path),
+        ErrorKind::IOError | ErrorKind::Busy if message.to_lowercase().contains("lock") => StorageError::DatabaseLocked(path),
+        ErrorKind::InvalidArgument if message.contains("does not exist") => StorageError::DatabaseNotFound(path),
+        ErrorKind::IOError if message.contains("No such file or directory") => StorageError::DatabaseNotFound(path),
+        _ => StorageError::DBError(err),
+    }
+}
+
+/// Failures that can succeed when simply tried again, e.g. during a write stall
+///
+/// Corruption, I/O and argument errors would fail the same way every time.
+fn is_transient(kind: ErrorKind) -> bool {
+    matches!(kind, ErrorKind::Busy | ErrorKind::TryAgain | ErrorKind::TimedOut)
+}
+
+/// Longest wait between two tries, unless the policy's first wait is longer
+const MAX_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Wait before the try after one that waited `backoff`
+pub(crate) fn next_backoff(backoff: Duration, policy: RetryPolicy) -> Duration {
+    backoff.saturating_mul(2).min(MAX_BACKOFF.max(policy.backoff))
+}
+
+/// Run `op`, trying transient failures again as `policy` allows
+///
+/// `kind` classifies an error. Anything not transient, or still failing once
+/// the attempts run out, is returned as is.
+pub(crate) fn with_retry<T, E>(
+    policy: Option<RetryPolicy>,
+    kind: impl Fn(&E) -> ErrorKind,
+    mut op: impl FnMut() -> std::result::Result<T, E>,
+) -> std::result::Result<T, E> {
+    let Some(policy) = policy else {
+        return op();
+    };
+
+    let mut backoff = policy.backoff;
+    let mut attempt = 1;
+    loop {
+        match op() {
+            Err(e) if attempt < policy.attempts && is_transient(kind(&e)) => {
+                thread::sleep(backoff);
+                backoff = next_backoff(backoff, policy);
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Most threads running writes that have a timeout
+///
+/// Threads are only started as concurrent writes need them, so the cap
+/// matters once this many writes are stalled at the same time.
+const MAX_WRITE_WORKERS: usize = 64;
+
+/// Writes waiting for a thread once every thread is busy
+const WRITE_QUEUE: usize = 64;
+
+type WriteJob = Box<dyn FnOnce() + Send>;
+
+#[derive(Default)]
+struct WriteQueue {
+    jobs: VecDeque<WriteJob>,
+    workers: usize,
+    /// Workers waiting for a job
+    idle: usize,
+    /// Set once every handle on the database is gone
+    closed: bool,
+}
+
+#[derive(Default)]
+struct WorkerPool {
+    queue: Mutex<WriteQueue>,
+    job_queued: Condvar,
+    slot_freed: Condvar,
+}
+
+impl WorkerPool {
+    fn work(&self) {
+        let mut queue = self.queue.lock().unwrap_or_else(PoisonError::into_inner);
+        loop {
+            if let Some(job) = queue.jobs.pop_front() {
+                drop(queue);
+                self.slot_freed.notify_one();
+                job();
+                queue = self.queue.lock().unwrap_or_else(PoisonError::into_inner);
+            } else if queue.closed {
+                return;
+            } else {
+                queue.idle += 1;
+                queue = self.job_queued.wait(queue).unwrap_or_else(PoisonError::into_inner);
+                queue.idle -= 1;
+            }
+        }
+    }
+}
+
+/// Threads that run writes bounded by a timeout, see `StorageConfig::write_timeout`
+///
+/// A thread is started whenever a write finds none free, up to
+/// `MAX_WRITE_WORKERS`, so the pool grows with the number of concurrent
+/// writes rather than holding them back. The threads stop once every handle
+/// on the database is gone.
+#[derive(Default)]
+struct WriteWorkers {
+    pool: Arc<WorkerPool>,
+}
+
+impl WriteWorkers {
+    /// Queue `job`, waiting until `deadline` for room; the job is handed back if there is none
+    fn submit(&self, job: WriteJob, deadline: Instant) -> std::result::Result<(), WriteJob> {
+        let queue = self.pool.queue.lock().unwrap_or_else(PoisonError::into_inner);
+        let wait = deadline.saturating_duration_since(Instant::now());
+        let (mut queue, _) = self.pool.slot_freed
+            .wait_timeout_while(queue, wait, |queue| queue.jobs.len() >= WRITE_QUEUE)
+            .unwrap_or_else(PoisonError::into_inner);
+        if queue.jobs.len() >= WRITE_QUEUE {
+            return Err(job);
+        }
+
+        queue.jobs.push_back(job);
+        if queue.jobs.len() > queue.idle && queue.workers < MAX_WRITE_WORKERS {
+            queue.workers += 1;
+            let pool = Arc::clone(&self.pool);
+            thread::spawn(move || pool.work());
+        } else {
+            self.pool.job_queued.notify_one();
+        }
+        Ok(())
+    }
+}
+
+impl Drop for WriteWorkers {
+    fn drop(&mut self) {
+        self.pool.queue.lock().unwrap_or_else(PoisonError::into_inner).closed = true;
+        self.pool.job_queued.notify_all();
+    }
+}
+
+/// Progress of a write with a timeout
+enum WriteState {
+    Running,
+    /// The caller timed out and returned; the write still counts in `Shared::abandoned_writes`
+    Abandoned,
+    Done,
+}
+
+/// Handle of column family `name`
+fn family_of<'a>(db: &'a DB, name: &str) -> Arc<BoundColumnFamily<'a>> {
+    // Every family in FAMILIES is created when the database is opened
+    db.cf_handle(name).expect("column family is created on open")
+}
+
+/// State every handle on one database shares, keyed by namespace prefix
+#[derive(Default)]
+struct Shared {
+    write_locks: Mutex<HashMap<Vec<u8>, Arc<RwLock<()>>>>,
+    existence_filters: Mutex<HashMap<Vec<u8>, Arc<ExistenceFilter>>>,
+    /// Sequence number a secondary last refilled every filter at
+    filters_caught_up_at: Mutex<Option<u64>>,
+    /// Started by the first write with a timeout
+    write_workers: OnceLock<WriteWorkers>,
+    /// Writes that timed out but haven't landed yet
+    abandoned_writes: Mutex<usize>,
+    /// Signalled whenever an abandoned write lands
+    abandoned_landed: Condvar,
+}
+
+/// RocksDB handle that routes every key to its column family
+pub(crate) struct Records {
+    db: Arc<DB>,
+    shared: Arc<Shared>,
+    sync_writes: bool,
+    retry: Option<RetryPolicy>,
+    /// See `StorageConfig::write_timeout`
+    write_timeout: Option<Duration>,
+    /// `{namespace}/`, or empty for the unnamed namespace
+    namespace: Vec<u8>,
+    /// Hashes of the files in this namespace, see `StorageConfig::existence_filter`
+    existence: Option<Arc<ExistenceFilter>>,
+}
+
+impl Records {
+    /// Open the database, creating missing families and migrating old records
+    pub(crate) fn open<P: AsRef<Path>>(opts: &Options, path: P) -> Result<Self> {
+        let mut opts = family_options(opts);
+        opts.create_missing_column_families(true);
+
+        let descriptors = FAMILIES.iter()
+            .map(|name| ColumnFamilyDescriptor::new(*name, opts.clone()));
+        let records = Records {
+            db: Arc::new(DB::open_cf_descriptors(&opts, &path, descriptors)
+                .map_err(|e| open_error(e, path.as_ref()))?),
+            shared: Arc::default(),
+            sync_writes: false,
+            retry: None,
+            write_timeout: None,
+            namespace: Vec::new(),
+            existence: None,
+        };
+
+        records.migrate()?;
+        Ok(records)
+    }
+
+    /// Open an existing database without write access
+    pub(crate) fn open_read_only<P: AsRef<Path>>(opts: &Options, path: P) -> Result<Self> {
+        let opts = family_options(opts);
+        let descriptors = FAMILIES.iter()
+            .map(|name| ColumnFamilyDescriptor::new(*name, opts.clone()));
+        let db = DB::open_cf_descriptors_read_only(&opts, &path, descriptors, false)
+            .map_err(|e| open_error(e, path.as_ref()))?;
+        Records::without_writes(db)
+    }
+
+    /// Open a secondary instance following the database at `path`
+    ///
+    /// RocksDB keeps the secondary's own logs under `secondary_path`. The
+    /// secondary sees the primary as of opening, and as of each
+    /// `catch_up_with_primary` after that.
+    pub(crate) fn open_secondary<P: AsRef<Path>>(opts: &Options, path: P, secondary_path: &Path) -> Result<Self> {
+        let mut opts = family_options(opts);
+        // Secondaries must keep every table file open to follow the primary
+        opts.set_max_open_files(-1);
+        let descriptors = FAMILIES.iter()
+            .map(|name| ColumnFamilyDescriptor::new(*name, opts.clone()));
+        let db = DB::open_cf_descriptors_as_secondary(&opts, path.as_ref(), secondary_path, descriptors)
+            .map_err(|e| open_error(e, path.as_ref()))?;
+        Records::without_writes(db)
+    }
+
+    /// Records over a database opened read-only or as a secondary
+    ///
+    /// Records can't be migrated without writing, so the store must already
+    /// have been opened read-write since its current layout was introduced.
+    fn without_writes(db: DB) -> Result<Self> {
+        let records = Records {
+            db: Arc::new(db),
+            shared: Arc::default(),
+            sync_writes: false,
+            retry: None,
+            write_timeout: None,
+            namespace: Vec::new(),
+            existence: None,
+        };
+
+        if records.get_pinned(RESERVED_KEY)?.is_none() {
+            return Err(StorageError::ReadOnly("store must be opened read-write once to migrate it".to_string()));
+        }
+        Ok(records)
+    }
+
+    /// Replay the primary's writes since the last catch-up, on a secondary
+    ///
+    /// Files the primary stored in the meantime are added to the existence
+    /// filter of every namespace, whichever handle catches up; like any
+    /// deleted file, ones it removed keep their bits. RocksDB doesn't say
+    /// which keys arrived, so this rescans each namespace with a filter, but
+    /// only when the primary wrote anything since the last rescan.
+    pub(crate) fn catch_up_with_primary(&self) -> Result<()> {
+        self.db.try_catch_up_with_primary()?;
+
+        let sequence = self.db.latest_sequence_number();
+        let mut caught_up_at = self.shared.filters_caught_up_at.lock().unwrap_or_else(PoisonError::into_inner);
+        if *caught_up_at == Some(sequence) {
+            return Ok(());
+        }
+        let filters: Vec<_> = self.shared.existence_filters.lock().unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .map(|(namespace, filter)| (namespace.clone(), Arc::clone(filter)))
+            .collect();
+        for (namespace, filter) in filters {
+            self.in_namespace(namespace).fill_existence_filter(&filter)?;
+        }
+        *caught_up_at = Some(sequence);
+        Ok(())
+    }
+
+    /// Make every write wait for the write-ahead log to reach disk
+    pub(crate) fn set_sync_writes(&mut self, sync: bool) {
+        self.sync_writes = sync;
+    }
+
+    /// Retry reads and single writes that fail transiently, see `StorageConfig::retry`
+    pub(crate) fn set_retry(&mut self, retry: Option<RetryPolicy>) {
+        self.retry = retry;
+    }
+
+    /// Give up on writes RocksDB hasn't finished after `timeout`, see `StorageConfig::write_timeout`
+    pub(crate) fn set_write_timeout(&mut self, timeout: Option<Duration>) {
+        self.write_timeout = timeout;
+    }
+
+    /// The records of `namespace` in the same database
+    ///
+    /// Names may not contain a zero byte, so none can start like `RESERVED_PREFIX`
+    /// and pass a namespace's keys off as internal records.
+    pub(crate) fn namespaced(&self, namespace: &str) -> Result<Records> {
+        let name = namespace.as_bytes();
+        if name.is_empty()
+            || name.contains(&NAMESPACE_SEPARATOR)
+            || name.contains(&0)
+            || name.starts_with(RESERVED_PREFIX)
+        {
+            return Err(StorageError::InvalidNamespace(namespace.to_string()));
+        }
+
+        let mut prefix = namespace.as_bytes().to_vec();
+        prefix.push(NAMESPACE_SEPARATOR);
+
+        let mut records = self.in_namespace(prefix);
+        if let Some(filter) = &self.existence {
+            records.enable_existence_filter(filter.config())?;
+        }
+        Ok(records)
+    }
+
+    /// These records with the namespace `prefix` instead, and no existence filter yet
+    fn in_namespace(&self, prefix: Vec<u8>) -> Records {
+        Records {
+            db: Arc::clone(&self.db),
+            shared: Arc::clone(&self.shared),
+            sync_writes: self.sync_writes,
+            retry: self.retry,
+            write_timeout: self.write_timeout,
+            namespace: prefix,
+            existence: None,
+        }
+    }
+
+    /// The lock writers in this namespace hold shared and `gc` holds exclusively
+    ///
+    /// Every handle on the namespace gets the same lock, so a collection
+    /// through one handle waits for stores through all of them.
+    pub(crate) fn write_lock(&self) -> Arc<RwLock<()>> {
+        let mut locks = self.shared.write_locks.lock().unwrap_or_else(PoisonError::into_inner);
+        Arc::clone(locks.entry(self.namespace.clone()).or_default())
+    }
+
+    /// Wait for writes that timed out to land, for at most the write timeout
+    ///
+    /// A write that times out keeps running after its caller has released
+    /// the engine's write lock, so whoever takes the lock next waits for it
+    /// here. Otherwise a `gc` could collect chunks a late batch is about to
+    /// reference, or a store could count on a chunk a late delete removes.
+    pub(crate) fn await_abandoned_writes(&self) -> Result<()> {
+        let Some(timeout) = self.write_timeout else {
+            return Ok(());
+        };
+        let abandoned = self.shared.abandoned_writes.lock().unwrap_or_else(PoisonError::into_inner);
+        let (abandoned, _) = self.shared.abandoned_landed
+            .wait_timeout_while(abandoned, timeout, |count| *count > 0)
+            .unwrap_or_else(PoisonError::into_inner);
+        if *abandoned > 0 {
+            return Err(StorageError::Timeout(timeout));
+        }
+        Ok(())
+    }
+
+    /// Fill an existence filter with every file in this namespace, and keep it up to date from now on
+    ///
+    /// Every handle on the namespace shares one filter, so a file stored
+    /// through one handle is never ruled out by another. Only the first
+    /// handle scans the namespace; later ones keep the filter as it is.
+    pub(crate) fn enable_existence_filter(&mut self, config: ExistenceFilterConfig) -> Result<()> {
+        let filter = {
+            let mut filters = self.shared.existence_filters.lock().unwrap_or_else(PoisonError::into_inner);
+            match filters.get(&self.namespace) {
+                Some(filter) => Arc::clone(filter),
+                None => {
+                    let filter = Arc::new(ExistenceFilter::new(config));
+                    self.fill_existence_filter(&filter)?;
+                    filters.insert(self.namespace.clone(), Arc::clone(&filter));
+                    filter
+                }
+            }
+        };
+        self.existence = Some(filter);
+        Ok(())
+    }
+
+    /// Insert every file in this namespace into `filter`
+    fn fill_existence_filter(&self, filter: &ExistenceFilter) -> Result<()> {
+        for item in self.scan_prefix(b"meta:") {
+            let (key, _) = item?;
+            filter.insert(&key[b"meta:".len()..]);
+        }
+        for key in self.scan_family_keys(CF_VALUES) {
+            filter.insert(&key?);
+        }
+        Ok(())
+    }
+
+    pub(crate) fn existence_filter(&self) -> Option<&ExistenceFilter> {
+        self.existence.as_deref()
+    }
+
+    /// Add the file a record belongs to to the existence filter, if it is a file record
+    ///
+    /// Called as records are staged, before they are written, so the filter
+    /// never lags behind the database.
+    fn note_file(&self, key: &[u8]) {
+        let Some(filter) = &self.existence else {
+            return;
+        };
+        if let Some(hash) = key.strip_prefix(b"meta:") {
+            filter.insert(hash);
+        } else if family_for(key) == CF_VALUES {
+            filter.insert(key);
+        }
+    }
+
+    /// Directory the database was opened from
+    pub(crate) fn path(&self) -> &Path {
+        self.db.path()
+    }
+
+    /// Name of this namespace, `None` for the unnamed one
+    pub(crate) fn namespace(&self) -> Option<&str> {
+        let name = self.namespace.strip_suffix(&[NAMESPACE_SEPARATOR])?;
+        // Names come from `&str`, so they are always UTF-8
+        std::str::from_utf8(name).ok()
+    }
+
+    pub(crate) fn sync_writes(&self) -> bool {
+        self.sync_writes
+    }
+
+    /// The database key of a record key in this namespace
+    fn db_key<'k>(&self, key: &'k [u8]) -> Cow<'k, [u8]> {
+        let reserved = if family_for(key) == CF_VALUES { &[][..] } else { RESERVED_PREFIX };
+        if self.namespace.is_empty() && reserved.is_empty() {
+            Cow::Borrowed(key)
+        } else {
+            Cow::Owned([self.namespace.as_slice(), reserved, key].concat())
+        }
+    }
+
+    /// The record key of a database key, if the key belongs to this namespace
+    fn record_key<'k>(&self, db_key: &'k [u8]) -> Option<&'k [u8]> {
+        let key = if self.namespace.is_empty() {
+            (!db_key.contains(&NAMESPACE_SEPARATOR)).then_some(db_key)?
+        } else {
+            db_key.strip_prefix(self.namespace.as_slice())?
+        };
+        Some(key.strip_prefix(RESERVED_PREFIX).unwrap_or(key))
+    }
+
+    /// Iterate over a family's database keys starting with `db_prefix`, as record keys,
+    /// from the first at or after `db_start`
+    fn scan<'a>(&'a self, family: &str, db_prefix: Vec<u8>, db_start: &[u8]) -> impl Iterator<Item = Result<Record>> + 'a {
+        self.db.iterator_cf(&self.family(family), IteratorMode::From(db_start, Direction::Forward))
+            .take_while(move |item| item.as_ref().map_or(true, |(key, _)| key.starts_with(&db_prefix)))
+            .filter_map(move |item| match item {
+                Ok((key, value)) => self.record_key(&key).map(|key| Ok((key.into(), value))),
+                Err(e) => Some(Err(StorageError::from(e))),
+            })
+    }
+
+    fn family(&self, name: &str) -> Arc<BoundColumnFamily<'_>> {
+        family_of(&self.db, name)
+    }
+
+    fn write_options(&self) -> WriteOptions {
+        let mut write_opts = WriteOptions::default();
+        write_opts.set_sync(self.sync_writes);
+        write_opts
+    }
+
+    /// Run a write, bounded by the write timeout if there is one
+    fn timed_write(
+        &self,
+        write_opts: WriteOptions,
+        op: impl FnOnce(&DB, &WriteOptions) -> std::result::Result<(), rocksdb::Error> + Send + 'static,
+    ) -> Result<()> {
+        let Some(timeout) = self.write_timeout else {
+            return Ok(op(&self.db, &write_opts)?);
+        };
+        let db = Arc::clone(&self.db);
+        self.with_timeout(timeout, move || Ok(op(&db, &write_opts)?))
+    }
+
+    /// Run `op` on a write worker, giving up on it after `timeout`
+    ///
+    /// A RocksDB call can't be cancelled, so one that times out carries on in
+    /// the background and may still complete after `Timeout` is returned. It
+    /// counts as abandoned until then, see `await_abandoned_writes`. Waiting
+    /// for room in the queue counts against `timeout` too; a write that
+    /// never gets in returns `Timeout` without running `op`.
+    pub(crate) fn with_timeout<T: Send + 'static>(
+        &self,
+        timeout: Duration,
+        op: impl FnOnce() -> Result<T> + Send + 'static,
+    ) -> Result<T> {
+        let deadline = Instant::now() + timeout;
+        let (sender, receiver) = mpsc::sync_channel(1);
+        let state = Arc::new(Mutex::new(WriteState::Running));
+        let job = {
+            let (state, shared) = (Arc::clone(&state), Arc::clone(&self.shared));
+            Box::new(move || {
+                let result = panic::catch_unwind(AssertUnwindSafe(op))
+                    .unwrap_or_else(|_| Err(std::io::Error::other("write panicked").into()));
+                let mut state = state.lock().unwrap_or_else(PoisonError::into_inner);
+                if let WriteState::Abandoned = *state {
+                    *shared.abandoned_writes.lock().unwrap_or_else(PoisonError::into_inner) -= 1;
+                    shared.abandoned_landed.notify_all();
+                }
+                *state = WriteState::Done;
+                // Nobody is listening any more if the call timed out
+                let _ = sender.send(result);
+            })
+        };
+
+        let workers = self.shared.write_workers.get_or_init(WriteWorkers::default);
+        if workers.submit(job, deadline).is_err() {
+            return Err(StorageError::Timeout(timeout));
+        }
+
+        match receiver.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+            Ok(result) => result,
+            Err(RecvTimeoutError::Timeout) => {
+                let mut state = state.lock().unwrap_or_else(PoisonError::into_inner);
+                if let WriteState::Done = *state {
+                    // Finished between the timeout and taking the state
+                    return receiver.recv().unwrap_or_else(|_| Err(std::io::Error::other("write result lost").into()));
+                }
+                *state = WriteState::Abandoned;
+                *self.shared.abandoned_writes.lock().unwrap_or_else(PoisonError::into_inner) += 1;
+                Err(StorageError::Timeout(timeout))
+            }
+            Err(RecvTimeoutError::Disconnected) => Err(std::io::Error::other("write worker panicked").into()),
+        }
+    }
+
+    /// Keep every write worker busy until the returned sender is dropped, and the queue full too if `fill_queue`
+    #[cfg(test)]
+    pub(crate) fn stall_write_workers(&self, fill_queue: bool) -> mpsc::Sender<()> {
+        let (release, stalled) = mpsc::channel::<()>();
+        let stalled = Arc::new(Mutex::new(stalled));
+        let workers = self.shared.write_workers.get_or_init(WriteWorkers::default);
+        let jobs = MAX_WRITE_WORKERS + if fill_queue { WRITE_QUEUE } else { 0 };
+        for _ in 0..jobs {
+            let stalled = Arc::clone(&stalled);
+            let job: WriteJob = Box::new(move || {
+                let _ = stalled.lock().unwrap_or_else(PoisonError::into_inner).recv();
+            });
+            // Room opens up as the workers start and take their jobs
+            let _ = workers.submit(job, Instant::now() + Duration::from_secs(10));
+        }
+        release
+    }
+
+    pub(crate) fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        let (family, db_key) = (self.family(family_for(key)), self.db_key(key));
+        Ok(with_retry(self.retry, rocksdb::Error::kind, || self.db.get_cf(&family, &db_key))?)
+    }
+
+    pub(crate) fn get_pinned(&self, key: &[u8]) -> Result<Option<DBPinnableSlice<'_>>> {
+        let (family, db_key) = (self.family(family_for(key)), self.db_key(key));
+        Ok(with_retry(self.retry, rocksdb::Error::kind, || self.db.get_pinned_cf(&family, &db_key))?)
+    }
+
+    /// Look up many keys in one call, each in its own column family
+    ///
+    /// A transient failure on any key retries the whole lookup. Once the
+    /// tries run out, each key keeps the result of the last one.
+    pub(crate) fn multi_get(&self, keys: &[Vec<u8>]) -> Vec<Result<Option<Vec<u8>>>> {
+        let families: Vec<_> = keys.iter().map(|key| self.family(family_for(key))).collect();
+        let db_keys: Vec<_> = keys.iter().map(|key| self.db_key(key)).collect();
+        let transient = |result: &std::result::Result<Option<Vec<u8>>, rocksdb::Error>| {
+            result.as_ref().err().map(rocksdb::Error::kind).filter(|kind| is_transient(kind.clone()))
+        };
+        let lookup = || {
+            let results = self.db.multi_get_cf(families.iter().zip(&db_keys));
+            if results.iter().any(|result| transient(result).is_some()) {
+                return Err(results);
+            }
+            Ok(results)
+        };
+        let first_transient = |results: &Vec<_>| results.iter().find_map(transient).expect("a failed lookup has a transient error");
+        with_retry(self.retry, first_transient, lookup)
+            .unwrap_or_else(|results| results)
+            .into_iter()
+            .map(|result| result.map_err(StorageError::from))
+            .collect()
+    }
+
+    pub(crate) fn key_may_exist(&self, key: &[u8]) -> bool {
+        self.db.key_may_exist_cf(&self.family(family_for(key)), self.db_key(key))
+    }
+
+    pub(crate) fn put(&self, key: &[u8], value: impl AsRef<[u8]>) -> Result<()> {
+        self.note_file(key);
+        let (name, retry) = (family_for(key), self.retry);
+        if self.write_timeout.is_none() {
+            let (family, db_key, write_opts) = (self.family(name), self.db_key(key), self.write_options());
+            return Ok(with_retry(retry, rocksdb::Error::kind, || self.db.put_cf_opt(&family, &db_key, value.as_ref(), &write_opts))?);
+        }
+
+        // The write may outlive this call, so it gets its own copies
+        let (db_key, value) = (self.db_key(key).into_owned(), value.as_ref().to_vec());
+        self.timed_write(self.write_options(), move |db, write_opts| {
+            with_retry(retry, rocksdb::Error::kind, || db.put_cf_opt(&family_of(db, name), &db_key, &value, write_opts))
+        })
+    }
+
+    /// Add `delta` to the little-endian u64 counter at `key` without reading it
+    ///
+    /// Concurrent calls never lose an update, and a counter never drops below
+    /// zero. Counters set with `put` stay valid starting points.
+    pub(crate) fn add_to_counter(&self, key: &[u8], delta: i64) -> Result<()> {
+        let (name, db_key, retry) = (family_for(key), self.db_key(key).into_owned(), self.retry);
+        self.timed_write(self.write_options(), move |db, write_opts| {
+            with_retry(retry, rocksdb::Error::kind, || db.merge_cf_opt(&family_of(db, name), &db_key, delta.to_le_bytes(), write_opts))
+        })
+    }
+
+    pub(crate) fn delete(&self, key: &[u8]) -> Result<()> {
+        let (name, db_key, retry) = (family_for(key), self.db_key(key).into_owned(), self.retry);
+        self.timed_write(self.write_options(), move |db, write_opts| {
+            with_retry(retry, rocksdb::Error::kind, || db.delete_cf_opt(&family_of(db, name), &db_key, write_opts))
+        })
+    }
+
+    pub(crate) fn batch(&self) -> RecordBatch<'_> {
+        RecordBatch {
+            records: self,
+            batch: WriteBatch::default(),
+        }
+    }
+
+    pub(crate) fn write(&self, batch: RecordBatch<'_>) -> Result<()> {
+        self.write_batch(batch.batch, self.write_options())
+    }
+
+    /// Write a batch at `durability`, or at the handle's own setting if `None`
+    pub(crate) fn write_with(&self, batch: RecordBatch<'_>, durability: Option<Durability>) -> Result<()> {
+        let Some(durability) = durability else {
+            return self.write(batch);
+        };
+
+        let mut write_opts = WriteOptions::default();
+        match durability {
+            Durability::Buffered => write_opts.disable_wal(true),
+            Durability::Wal => {}
+            Durability::Fsync => write_opts.set_sync(true),
+        }
+        self.write_batch(batch.batch, write_opts)
+    }
+
+    /// Write a batch, retrying transient failures like single writes
+    ///
+    /// RocksDB consumes a batch as it writes it, so with a retry policy each
+    /// try writes a copy rebuilt from the batch's serialized records.
+    fn write_batch(&self, batch: WriteBatch, write_opts: WriteOptions) -> Result<()> {
+        let retry = self.retry;
+        self.timed_write(write_opts, move |db, write_opts| {
+            if retry.is_none() {
+                return db.write_opt(batch, write_opts);
+            }
+            let data = batch.data().to_vec();
+            with_retry(retry, rocksdb::Error::kind, || db.write_opt(WriteBatch::from_data(&data), write_opts))
+        })
+    }
+
+    /// Iterate over every record whose key starts with `prefix`
+    pub(crate) fn scan_prefix<'a>(&'a self, prefix: &[u8]) -> impl Iterator<Item = Result<Record>> + 'a {
+        self.scan_prefix_from(prefix, prefix)
+    }
+
+    /// Iterate over the records whose key starts with `prefix`, from the first at or after `start`
+    pub(crate) fn scan_prefix_from<'a>(&'a self, prefix: &[u8], start: &[u8]) -> impl Iterator<Item = Result<Record>> + 'a {
+        self.scan(family_for(prefix), self.db_key(prefix).into_owned(), &self.db_key(start))
+    }
+
+    /// Iterate over every record in one column family
+    pub(crate) fn scan_family<'a>(&'a self, name: &str) -> impl Iterator<Item = Result<Record>> + 'a {
+        self.scan(name, self.namespace.clone(), &self.namespace)
+    }
+
+    /// Iterate over the keys of one column family without copying its values
+    pub(crate) fn scan_family_keys<'a>(&'a self, name: &str) -> impl Iterator<Item = Result<Box<[u8]>>> + 'a {
+        self.scan_keys(name, &self.namespace)
+    }
+
+    /// Iterate over the simple file keys from the first at or after `start`
+    pub(crate) fn scan_values_from<'a>(&'a self, start: &[u8]) -> impl Iterator<Item = Result<Box<[u8]>>> + 'a {
+        self.scan_keys(CF_VALUES, &self.db_key(start))
+    }
+
+    /// Iterate over a family's keys in this namespace from `db_start`, as record keys
+    fn scan_keys<'a>(&'a self, name: &str, db_start: &[u8]) -> impl Iterator<Item = Result<Box<[u8]>>> + 'a {
+        let mut iter = self.db.raw_iterator_cf(&self.family(name));
+        iter.seek(db_start);
+        let mut done = false;
+
+        std::iter::from_fn(move || {
+            while !done {
+                let Some(db_key) = iter.key() else {
+                    done = true;
+                    return iter.status().err().map(|e| Err(e.into()));
+                };
+                if !db_key.starts_with(&self.namespace) {
+                    done = true;
+                    break;
+                }
+
+                let key = self.record_key(db_key).map(Box::from);
+                iter.next();
+                if let Some(key) = key {
+                    return Some(Ok(key));
+                }
+            }
+            None
+        })
+    }
+
+    /// Visit every record in every family as of a single point in time
+    ///
+    /// Writes made while this runs are not seen, so the records visited
+    /// always form a consistent state of the store.
+    pub(crate) fn for_each_in_snapshot(&self, mut visit: impl FnMut(&[u8], &[u8]) -> Result<()>) -> Result<()> {
+        let snapshot = self.db.snapshot();
+        for family in FAMILIES {
+            let mode = IteratorMode::From(&self.namespace, Direction::Forward);
+            for item in snapshot.iterator_cf(&self.family(family), mode) {
+                let (key, value) = item?;
+                if !key.starts_with(&self.namespace) {
+                    break;
+                }
+                if let Some(key) = self.record_key(&key) {
+                    visit(key, &value)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Delete every record in this namespace
+    ///
+    /// A named namespace is one key range per family, each removed with a
+    /// single range tombstone. Keys of the unnamed namespace interleave with
+    /// named ones, so its keys are walked to find the runs between them,
+    /// which are then removed the same way.
+    pub(crate) fn clear(&self) -> Result<()> {
+        let mut batch = WriteBatch::default();
+
+        for family in FAMILIES {
+            let family = self.family(family);
+            if !self.namespace.is_empty() {
+                batch.delete_range_cf(&family, self.namespace.clone(), prefix_end(&self.namespace));
+                continue;
+            }
+
+            let mut iter = self.db.raw_iterator_cf(&family);
+            iter.seek_to_first();
+            let mut run_start: Option<Vec<u8>> = None;
+            let mut last_key = Vec::new();
+            while let Some(key) = iter.key() {
+                match key.iter().position(|byte| *byte == NAMESPACE_SEPARATOR) {
+                    Some(end) => {
+                        // A named namespace ends the run, and is skipped whole
+                        if let Some(start) = run_start.take() {
+                            batch.delete_range_cf(&family, start, key.to_vec());
+                        }
+                        let next = prefix_end(&key[..=end]);
+                        iter.seek(next);
+                    },
+                    None => {
+                        if run_start.is_none() {
+                            run_start = Some(key.to_vec());
+                        }
+                        last_key.clear();
+                        last_key.extend_from_slice(key);
+                        iter.next();
+                    },
+                }
+            }
+            iter.status()?;
+
+            if let Some(start) = run_start {
+                last_key.push(0);
+                batch.delete_range_cf(&family, start, last_key);
+            }
+        }
+
+        // Clearing the unnamed namespace must not make the next open migrate again
+        if self.namespace.is_empty() {
+            for key in [MIGRATED_KEY, RESERVED_KEY] {
+                batch.put_cf(&self.family(CF_DEFAULT), self.db_key(key), b"1");
+            }
+        }
+        self.write_batch(batch, self.write_options())
+    }
+
+    /// Whether the store holds no records besides its own bookkeeping
+    pub(crate) fn is_empty(&self) -> Result<bool> {
+        for family in FAMILIES {
+            for item in self.scan(family, self.namespace.clone(), &self.namespace) {
+                let (key, _) = item?;
+                if &*key != MIGRATED_KEY && &*key != RESERVED_KEY {
+                    return Ok(false);
+                }
+            }
+        }
+        Ok(true)
+    }
+
+    /// An integer property summed over all column families
+    pub(crate) fn property_int_value(&self, name: &str) -> Result<Option<u64>> {
+        let mut total = None;
+        for family in FAMILIES {
+            if let Some(value) = self.db.property_int_value_cf(&self.family(family), name)? {
+                total = Some(total.unwrap_or(0) + value);
+            }
+        }
+        Ok(total)
+    }
+
+    /// Sync the write-ahead log and flush every memtable to disk
+    pub(crate) fn flush(&self) -> Result<()> {
+        self.db.flush_wal(true)?;
+        for family in FAMILIES {
+            self.db.flush_cf(&self.family(family))?;
+        }
+        Ok(())
+    }
+
+    /// Compact every column family over its whole key range
+    pub(crate) fn compact(&self) {
+        for family in FAMILIES {
+            self.db.compact_range_cf(&self.family(family), None::<&[u8]>, None::<&[u8]>);
+        }
+    }
+
+    /// Bring records written by older versions into the current layout
+    fn migrate(&self) -> Result<()> {
+        if self.get_pinned(RESERVED_KEY)?.is_some() {
+            return Ok(());
+        }
+
+        self.migrate_families()?;
+        self.migrate_reserved()
+    }
+
+    /// Move records written before column families existed into their family
+    fn migrate_families(&self) -> Result<()> {
+        let default = self.family(CF_DEFAULT);
+        // An interrupted `migrate_reserved` may already have moved the marker
+        if self.db.get_pinned_cf(&default, MIGRATED_KEY)?.is_some() || self.get_pinned(MIGRATED_KEY)?.is_some() {
+            return Ok(());
+        }
+
+        let mut batch = WriteBatch::default();
+        for item in self.db.iterator_cf(&default, IteratorMode::Start) {
+            let (key, value) = item?;
+            let family = family_for(&key);
+            if family == CF_DEFAULT {
+                continue;
+            }
+
+            batch.put_cf(&self.family(family), &key, &value);
+            batch.delete_cf(&default, &key);
+            if batch.len() >= MIGRATION_BATCH * 2 {
+                self.db.write(std::mem::take(&mut batch))?;
+            }
+        }
+
+        batch.put_cf(&default, MIGRATED_KEY, b"1");
+        self.db.write(batch)?;
+        Ok(())
+    }
+
+    /// Move internal records of every namespace behind `RESERVED_PREFIX`
+    fn migrate_reserved(&self) -> Result<()> {
+        for family in [CF_DEFAULT, CF_METADATA, CF_CHUNKS] {
+            let family = self.family(family);
+            let mut batch = WriteBatch::default();
+            for item in self.db.iterator_cf(&family, IteratorMode::Start) {
+                let (key, value) = item?;
+                let start = key.iter().position(|byte| *byte == NAMESPACE_SEPARATOR).map_or(0, |end| end + 1);
+                if key[start..].starts_with(RESERVED_PREFIX) {
+                    continue;
+                }
+
+                batch.put_cf(&family, [&key[..start], RESERVED_PREFIX, &key[start..]].concat(), &value);
+                batch.delete_cf(&family, &key);
+                if batch.len() >= MIGRATION_BATCH * 2 {
+                    self.db.write(std::mem::take(&mut batch))?;
+                }
+            }
+            self.db.write(batch)?;
+        }
+
+        self.db.put_cf(&self.family(CF_DEFAULT), self.db_key(RESERVED_KEY), b"1")?;
+        Ok(())
+    }
+}
+
+/// The first key after every key starting with `prefix`
+///
+/// The last byte of `prefix` must not be 0xff.
+fn prefix_end(prefix: &[u8]) -> Vec<u8> {
+    let mut end = prefix.to_vec();
+    *end.last_mut().expect("prefix is not empty") += 1;
+    end
+}
+
+/// Write batch that routes every key to its column family
+pub(crate) struct RecordBatch<'a> {
+    records: &'a Records,
+    batch: WriteBatch,
+}
+
+impl RecordBatch<'_> {
+    pub(crate) fn put(&mut self, key: &[u8], value: impl AsRef<[u8]>) {
+        self.records.note_file(key);
+        self.batch.put_cf(&self.records.family(family_for(key)), self.records.db_key(key), value);
+    }
+
+    /// Add `delta` to a counter when the batch is written, see `Records::add_to_counter`
+    pub(crate) fn add_to_counter(&mut self, key: &[u8], delta: i64) {
+        self.batch.merge_cf(&self.records.family(family_for(key)), self.records.db_key(key), delta.to_le_bytes());
+    }
+
+    pub(crate) fn delete(&mut self, key: &[u8]) {
+        self.batch.delete_cf(&self.records.family(family_for(key)), self.records.db_key(key));
+    }
+
+    /// Delete every record whose key starts with `prefix` with a single range tombstone
+    ///
+    /// Covers the whole prefix whatever order its keys sort in. The last
+    /// byte of `prefix` must not be 0xff.
+    pub(crate) fn delete_prefix(&mut self, prefix: &[u8]) {
+        let start = self.records.db_key(prefix).into_owned();
+        let end = prefix_end(&start);
+        self.batch.delete_range_cf(&self.records.family(family_for(prefix)), start, end);
+    }
+}
@@ -0,0 +1,119 @@
+// Hash-sharded storage
+//
+// Spreads files over several engines, each normally its own RocksDB instance,
+// by the leading 64 bits of their hash modulo the shard count. A file's hash
+// is worked out before it is stored so the store goes straight to its shard;
+// every other call routes on the hash it is given.
+//
+// Changing the shard count would send most hashes to a different shard, so
+// each shard records its index and the count when first used, and a layout
+// that disagrees with the record is refused.
+
+use crate::{bare_hash, HashAlgorithm, Result, StorageEngine, StorageError};
+
+/// Record holding a shard's index and the shard count, as two little-endian u32s
+const LAYOUT_KEY: &[u8] = b"shard:layout";
+
+/// Storage engine spreading files over several engines by hash
+pub struct ShardedStorageEngine {
+    shards: Vec<StorageEngine>,
+}
+
+impl ShardedStorageEngine {
+    /// Route over `shards`, in order, recording the layout in each one
+    ///
+    /// The shards must hash alike (same hash key and chunking thresholds), or a
+    /// file's hash could depend on the shard it lands in. Read-only shards
+    /// are checked against their layout record but never given one.
+    pub fn new(shards: Vec<StorageEngine>) -> Result<Self> {
+        let Some(first) = shards.first() else {
+            return Err(StorageError::ShardMismatch("at least one shard is needed".to_string()));
+        };
+        let hashes_alike = |shard: &StorageEngine| {
+            shard.hash_key == first.hash_key
+                && shard.chunk_threshold == first.chunk_threshold
+                && shard.max_value_bytes == first.max_value_bytes
+                && shard.auto_chunk_threshold == first.auto_chunk_threshold
+        };
+        if !shards.iter().all(hashes_alike) {
+            return Err(StorageError::ShardMismatch("shards must share a hash key and chunking thresholds".to_string()));
+        }
+
+        let count = shards.len() as u32;
+        for (index, shard) in shards.iter().enumerate() {
+            let layout = [(index as u32).to_le_bytes(), count.to_le_bytes()].concat();
+            match shard.db.get(LAYOUT_KEY)? {
+                Some(recorded) if recorded == layout => {},
+                Some(recorded) => {
+                    return Err(StorageError::ShardMismatch(format!(
+                        "shard {} of {} was recorded as {}", index, count, describe_layout(&recorded)
+                    )));
+                },
+                None if shard.read_only => {},
+                None => shard.db.put(LAYOUT_KEY, &layout)?,
+            }
+        }
+
+        Ok(ShardedStorageEngine { shards })
+    }
+
+    pub fn shards(&self) -> &[StorageEngine] {
+        &self.shards
+    }
+
+    /// Index of the shard that holds `hash`
+    pub fn shard_index(&self, hash: &str) -> Result<usize> {
+        let hash = bare_hash(hash)?;
+        let prefix = hash.get(..16).unwrap_or(hash);
+        let prefix = u64::from_str_radix(prefix, 16)
+            .map_err(|_| StorageError::HashNotFound(hash.to_string()))?;
+        Ok((prefix % self.shards.len() as u64) as usize)
+    }
+
+    /// The shard that holds `hash`
+    pub fn shard_for(&self, hash: &str) -> Result<&StorageEngine> {
+        Ok(&self.shards[self.shard_index(hash)?])
+    }
+
+    /// Store a file with default settings, as `StorageEngine::store` does
+    pub fn store(&self, data: &[u8]) -> Result<String> {
+        let chunk_size = self.shards[0].default_chunk_size(data.len());
+        self.store_with_options(data, HashAlgorithm::Blake3, chunk_size)
+    }
+
+    /// Store a file in the shard its hash routes to
+    ///
+    /// The data is hashed once to pick the shard and again by the shard
+    /// while storing.
+    pub fn store_with_options(&self, data: &[u8], algorithm: HashAlgorithm, chunk_size: usize) -> Result<String> {
+        let hash = self.shards[0].address_of(data, algorithm, chunk_size)?;
+        self.shard_for(&hash)?.store_with_options(data, algorithm, chunk_size)
+    }
+
+    pub fn retrieve(&self, hash: &str) -> Result<Vec<u8>> {
+        self.shard_for(hash)?.retrieve(hash)
+    }
+
+    pub fn retrieve_verified(&self, hash: &str) -> Result<Vec<u8>> {
+        self.shard_for(hash)?.retrieve_verified(hash)
+    }
+
+    pub fn delete(&self, hash: &str) -> Result<bool> {
+        self.shard_for(hash)?.delete(hash)
+    }
+
+    pub fn exists(&self, hash: &str) -> Result<bool> {
+        self.shard_for(hash)?.exists(hash)
+    }
+}
+
+fn describe_layout(recorded: &[u8]) -> String {
+    match (recorded.get(..4), recorded.get(4..8)) {
+        (Some(index), Some(count)) => format!(
+            "shard {} of {}",
+            u32::from_le_bytes(index.try_into().expect("4 bytes")),
+            u32::from_le_bytes(count.try_into().expect("4 bytes"))
+        ),
+        _ => "an unreadable layout".to_string(),
+    }
+}
@@ -0,0 +1,30 @@
+#![cfg(feature = "async")]
+
+use svdb_core::{AsyncStorageEngine, HashAlgorithm};
+use tempfile::tempdir;
+
+#[tokio::test]
+async fn test_async_engine_round_trip() -> svdb_core::Result<()> {
+    let temp_dir = tempdir()?;
+    let engine = AsyncStorageEngine::open(temp_dir.path()).await?;
+
+    let hash = engine.store(b"async data".to_vec()).await?;
+    assert!(engine.exists(&hash).await?);
+    assert_eq!(engine.retrieve(&hash).await?, b"async data");
+    assert_eq!(engine.size_of(&hash).await?, 10);
+
+    // Concurrent stores share one database handle
+    let large_data: Vec<u8> = (0..3 * 1024 * 1024).map(|i| (i % 251) as u8).collect();
+    let (small, large) = tokio::join!(
+        engine.store(b"concurrent".to_vec()),
+        engine.store_with_options(large_data.clone(), HashAlgorithm::Blake3, 1024 * 1024),
+    );
+    let (small, large) = (small?, large?);
+    assert_eq!(engine.retrieve(&small).await?, b"concurrent");
+    assert_eq!(engine.retrieve_verified(&large).await?, large_data);
+
+    assert!(engine.delete(&hash).await?);
+    assert!(!engine.exists(&hash).await?);
+
+    Ok(())
+}